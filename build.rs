@@ -0,0 +1,117 @@
+//! Build script providing the build information shown by `rustic version`
+//!
+//! Sets `RUSTIC_GIT_COMMIT`, `RUSTIC_BUILD_DATE` and `RUSTIC_CORE_VERSION` for the compilation of
+//! rustic. Variables already set in the build environment are kept, so packagers building from a
+//! source tarball can provide them; `SOURCE_DATE_EPOCH` is honored for reproducible builds.
+
+use std::{
+    env, fs,
+    path::Path,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+fn main() {
+    for var in [
+        "RUSTIC_GIT_COMMIT",
+        "RUSTIC_BUILD_DATE",
+        "RUSTIC_CORE_VERSION",
+        "SOURCE_DATE_EPOCH",
+    ] {
+        println!("cargo:rerun-if-env-changed={var}");
+    }
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    rerun_if_git_head_changed();
+
+    if env::var_os("RUSTIC_GIT_COMMIT").is_none() {
+        if let Some(commit) = git_commit() {
+            println!("cargo:rustc-env=RUSTIC_GIT_COMMIT={commit}");
+        }
+    }
+
+    if env::var_os("RUSTIC_BUILD_DATE").is_none() {
+        let secs = env::var("SOURCE_DATE_EPOCH")
+            .ok()
+            .and_then(|epoch| epoch.parse().ok())
+            .or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .ok()
+                    .map(|duration| duration.as_secs())
+            });
+        if let Some(secs) = secs {
+            println!("cargo:rustc-env=RUSTIC_BUILD_DATE={}", date(secs));
+        }
+    }
+
+    if env::var_os("RUSTIC_CORE_VERSION").is_none() {
+        if let Some(version) = locked_version("rustic_core") {
+            println!("cargo:rustc-env=RUSTIC_CORE_VERSION={version}");
+        }
+    }
+}
+
+/// Rerun the build script when a new commit is checked out or made
+fn rerun_if_git_head_changed() {
+    let Ok(head) = fs::read_to_string(".git/HEAD") else {
+        return;
+    };
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    if let Some(reference) = head.trim().strip_prefix("ref: ") {
+        println!("cargo:rerun-if-changed=.git/{reference}");
+        println!("cargo:rerun-if-changed=.git/packed-refs");
+    }
+}
+
+/// Get the abbreviated hash of the checked out commit, if building from a git repository
+fn git_commit() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let commit = String::from_utf8(output.stdout).ok()?;
+    Some(commit.trim().to_string()).filter(|commit| !commit.is_empty())
+}
+
+/// Get the version of a dependency from the lock file
+fn locked_version(name: &str) -> Option<String> {
+    let lock =
+        fs::read_to_string(Path::new(&env::var("CARGO_MANIFEST_DIR").ok()?).join("Cargo.lock"))
+            .ok()?;
+    let name_line = format!("name = \"{name}\"");
+    let mut lines = lock.lines();
+    while let Some(line) = lines.next() {
+        if line == name_line {
+            return lines
+                .next()?
+                .strip_prefix("version = \"")?
+                .strip_suffix('"')
+                .map(ToString::to_string);
+        }
+    }
+    None
+}
+
+/// Format seconds since the unix epoch as `YYYY-MM-DD` (UTC)
+///
+/// Uses the `civil_from_days` algorithm from <http://howardhinnant.github.io/date_algorithms.html>
+fn date(secs: u64) -> String {
+    let days = i64::try_from(secs / 86_400).unwrap_or_default() + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = era * 400 + year_of_era + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
@@ -11,7 +11,11 @@ use abscissa_core::{
 use anyhow::Result;
 
 // use crate::helpers::*;
-use crate::{commands::EntryPoint, config::RusticConfig};
+use crate::{
+    commands::EntryPoint,
+    config::RusticConfig,
+    events::{self, Event},
+};
 
 /// Application state
 pub static RUSTIC_APP: AppCell<RusticApp> = AppCell::new();
@@ -112,12 +116,13 @@ impl Application for RusticApp {
 
 impl RusticApp {
     /// Shut down this application gracefully, exiting with given exit code.
-    fn shutdown_with_exitcode(&self, shutdown: Shutdown, exit_code: i32) -> ! {
+    pub(crate) fn shutdown_with_exitcode(&self, shutdown: Shutdown, exit_code: i32) -> ! {
         let result = self.state().components().shutdown(self, shutdown);
         if let Err(e) = result {
             fatal_error(self, &e)
         }
 
+        events::emit(&Event::Finished { exit_code });
         process::exit(exit_code);
     }
 }
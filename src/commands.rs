@@ -1,13 +1,19 @@
 //! Rustic Subcommands
 
+pub(crate) mod acl;
 pub(crate) mod backup;
+pub(crate) mod bench;
+pub(crate) mod cache;
 pub(crate) mod cat;
+#[cfg(feature = "sqlite")]
+pub(crate) mod catalog;
 pub(crate) mod check;
 pub(crate) mod completions;
 pub(crate) mod config;
 pub(crate) mod copy;
 pub(crate) mod diff;
 pub(crate) mod dump;
+pub(crate) mod env;
 pub(crate) mod find;
 pub(crate) mod forget;
 pub(crate) mod init;
@@ -15,6 +21,10 @@ pub(crate) mod key;
 pub(crate) mod list;
 pub(crate) mod ls;
 pub(crate) mod merge;
+#[cfg(feature = "mount")]
+pub(crate) mod mount;
+#[cfg(not(feature = "mount"))]
+pub(crate) mod mount_unavailable;
 pub(crate) mod prune;
 pub(crate) mod repair;
 pub(crate) mod repoinfo;
@@ -25,6 +35,7 @@ pub(crate) mod snapshots;
 pub(crate) mod tag;
 #[cfg(feature = "tui")]
 pub(crate) mod tui;
+pub(crate) mod version;
 #[cfg(feature = "webdav")]
 pub(crate) mod webdav;
 
@@ -33,17 +44,30 @@ use std::fs::File;
 use std::path::PathBuf;
 use std::str::FromStr;
 
+#[cfg(feature = "sqlite")]
+use crate::commands::catalog::CatalogCmd;
+#[cfg(feature = "mount")]
+use crate::commands::mount::MountCmd;
+#[cfg(not(feature = "mount"))]
+use crate::commands::mount_unavailable::MountUnavailableCmd;
 #[cfg(feature = "webdav")]
 use crate::commands::webdav::WebDavCmd;
 use crate::{
     commands::{
-        backup::BackupCmd, cat::CatCmd, check::CheckCmd, completions::CompletionsCmd,
-        config::ConfigCmd, copy::CopyCmd, diff::DiffCmd, dump::DumpCmd, forget::ForgetCmd,
-        init::InitCmd, key::KeyCmd, list::ListCmd, ls::LsCmd, merge::MergeCmd, prune::PruneCmd,
-        repair::RepairCmd, repoinfo::RepoInfoCmd, restore::RestoreCmd, self_update::SelfUpdateCmd,
-        show_config::ShowConfigCmd, snapshots::SnapshotCmd, tag::TagCmd,
+        acl::AclCmd, backup::BackupCmd, bench::BenchCmd, cache::CacheCmd, cat::CatCmd,
+        check::CheckCmd, completions::CompletionsCmd, config::ConfigCmd, copy::CopyCmd,
+        diff::DiffCmd, dump::DumpCmd, env::EnvCmd, forget::ForgetCmd, init::InitCmd, key::KeyCmd,
+        list::ListCmd, ls::LsCmd, merge::MergeCmd, prune::PruneCmd, repair::RepairCmd,
+        repoinfo::RepoInfoCmd, restore::RestoreCmd, self_update::SelfUpdateCmd,
+        show_config::ShowConfigCmd, snapshots::SnapshotCmd, tag::TagCmd, version::VersionCmd,
     },
     config::{progress_options::ProgressOptions, AllRepositoryOptions, RusticConfig},
+    events::{self, Event, EventLogger},
+    helpers::{rotate_log_file, DEFAULT_LOG_MAX_FILES},
+    hot_fallback::with_hot_fallback,
+    logging::{self, JsonLogger, LogFormat},
+    retry::retry_backends,
+    throttle::limit_backends,
     {Application, RUSTIC_APP},
 };
 
@@ -52,18 +76,22 @@ use abscissa_core::{
     FrameworkErrorKind, Runnable, Shutdown,
 };
 use anyhow::{anyhow, Result};
-use clap::builder::{
-    styling::{AnsiColor, Effects},
-    Styles,
+use clap::{
+    builder::{
+        styling::{AnsiColor, Effects},
+        Styles,
+    },
+    CommandFactory,
 };
 use convert_case::{Case, Casing};
 use dialoguer::Password;
 use human_panic::setup_panic;
-use log::{log, warn, Level};
-use rustic_core::{IndexedFull, OpenStatus, ProgressBars, Repository};
-use simplelog::{CombinedLogger, LevelFilter, TermLogger, TerminalMode, WriteLogger};
+use itertools::Itertools;
+use log::{info, log, warn, Level};
+use rustic_core::{Id, IndexedFull, OpenStatus, ProgressBars, Repository, RepositoryBackends};
+use simplelog::{CombinedLogger, LevelFilter, SharedLogger, TermLogger, TerminalMode, WriteLogger};
 
-use self::find::FindCmd;
+use self::{completions::CompleteCmd, find::FindCmd};
 
 pub(super) mod constants {
     pub(super) const MAX_PASSWORD_RETRIES: usize = 5;
@@ -73,19 +101,39 @@ pub(super) mod constants {
 /// Subcommands need to be listed in an enum.
 #[derive(clap::Parser, Command, Debug, Runnable)]
 enum RusticCmd {
+    /// Manage advisory access-control annotations of snapshots
+    Acl(AclCmd),
+
     /// Backup to the repository
     Backup(BackupCmd),
 
+    /// Measure the performance of backup, restore or prune on generated data
+    Bench(BenchCmd),
+
+    /// Show, inspect or clear the local cache
+    Cache(CacheCmd),
+
     /// Show raw data of repository files and blobs
     Cat(CatCmd),
 
-    /// Change the repository configuration
+    /// Build and search a catalog of the files of all snapshots
+    #[cfg(feature = "sqlite")]
+    Catalog(CatalogCmd),
+
+    /// Show or change the repository configuration
     Config(ConfigCmd),
 
     /// Generate shell completions
     Completions(CompletionsCmd),
 
+    /// Complete values depending on the repository, used by the shell completions
+    #[clap(name = "__complete", hide = true)]
+    Complete(CompleteCmd),
+
     /// Check the repository
+    ///
+    /// Exits with code 0 if no errors are found, 1 if errors are found and 2 if the check itself
+    /// failed to run.
     Check(CheckCmd),
 
     /// Copy snapshots to other repositories. Note: The target repositories must be given in the config file!
@@ -98,6 +146,9 @@ enum RusticCmd {
     /// dump the contents of a file in a snapshot to stdout
     Dump(DumpCmd),
 
+    /// Print the environment variables given in the config file
+    Env(EnvCmd),
+
     /// Find in given snapshots
     Find(FindCmd),
 
@@ -119,6 +170,15 @@ enum RusticCmd {
     /// Merge snapshots
     Merge(MergeCmd),
 
+    /// Mount the repository as read-only FUSE filesystem
+    #[cfg(feature = "mount")]
+    Mount(MountCmd),
+
+    /// Mount the repository as read-only FUSE filesystem (not available in this build)
+    #[cfg(not(feature = "mount"))]
+    #[clap(hide = true)]
+    Mount(MountUnavailableCmd),
+
     /// Show a detailed overview of the snapshots within the repository
     Snapshots(SnapshotCmd),
 
@@ -144,6 +204,9 @@ enum RusticCmd {
     /// Change tags of snapshots
     Tag(TagCmd),
 
+    /// Show version, build information and enabled features
+    Version(VersionCmd),
+
     /// Start a webdav server which allows to access the repository
     #[cfg(feature = "webdav")]
     Webdav(WebDavCmd),
@@ -159,7 +222,7 @@ fn styles() -> Styles {
 
 /// Entry point for the application. It needs to be a struct to allow using subcommands!
 #[derive(clap::Parser, Command, Debug)]
-#[command(author, about, name="rustic", styles=styles(), version = option_env!("PROJECT_VERSION").unwrap_or(env!("CARGO_PKG_VERSION")))]
+#[command(author, about, name="rustic", styles=styles(), version = version::VERSION)]
 pub struct EntryPoint {
     #[command(flatten)]
     pub config: RusticConfig,
@@ -173,7 +236,20 @@ impl Runnable for EntryPoint {
         // Set up panic hook for better error messages and logs
         setup_panic!();
 
+        if events::is_enabled() {
+            // the subcommand is the first argument which is a known subcommand
+            let command = Self::command();
+            let name = std::env::args()
+                .skip(1)
+                .find(|arg| command.find_subcommand(arg).is_some())
+                .unwrap_or_default();
+            events::emit(&Event::CommandStarted { command: &name });
+        }
+
         self.commands.run();
+        if let Some(limit) = RUSTIC_APP.config().global.cache_size_limit {
+            cache::limit_cache_size(limit.as_u64());
+        }
         RUSTIC_APP.shutdown(Shutdown::Graceful)
     }
 }
@@ -217,13 +293,28 @@ impl Configurable<RusticConfig> for EntryPoint {
 
         // get global options from command line / env and config file
         if config.global.use_profile.is_empty() {
-            config.merge_profile("rustic", &mut merge_logs, Level::Info)?;
+            if config.global.no_default_config {
+                merge_logs.push((Level::Info, "using no config file.".to_string()));
+            } else {
+                config.merge_profile("rustic", &mut merge_logs, Level::Info)?;
+            }
         } else {
             for profile in &config.global.use_profile.clone() {
                 config.merge_profile(profile, &mut merge_logs, Level::Warn)?;
             }
         }
 
+        config
+            .apply_repository_name(&self.config.repository)
+            .map_err(|e| FrameworkErrorKind::ConfigError.context(e))?;
+
+        // the repository file has lower precedence than --repository / RUSTIC_REPOSITORY
+        let repository_given = self.config.repository.be.repository.is_some();
+        config
+            .repository
+            .apply_repository_file(repository_given)
+            .map_err(|e| FrameworkErrorKind::ConfigError.context(e))?;
+
         // start logger
         let level_filter = match &config.global.log_level {
             Some(level) => LevelFilter::from_str(level)
@@ -233,41 +324,75 @@ impl Configurable<RusticConfig> for EntryPoint {
         let term_config = simplelog::ConfigBuilder::new()
             .set_time_level(LevelFilter::Off)
             .build();
-        match &config.global.log_file {
-            None => TermLogger::init(
+        let global = &config.global;
+        if global.log_file.is_none()
+            && (global.log_max_size.is_some() || global.log_max_files.is_some())
+        {
+            return Err(FrameworkErrorKind::ConfigError
+                .context(anyhow!("log-max-size and log-max-files require log-file"))
+                .into());
+        }
+        let json = global.log_format == Some(LogFormat::Json);
+        // if the system log is not available, don't abort but log to stderr as usual
+        let (system_logger, system_log_error) = match logging::system_logger(global, level_filter) {
+            Ok(logger) => (logger, None),
+            Err(err) => (None, Some(err)),
+        };
+        let mut loggers: Vec<Box<dyn SharedLogger>> = Vec::new();
+        if global.log_file.is_some() || system_logger.is_some() {
+            // only warnings and errors are additionally printed
+            loggers.push(TermLogger::new(
+                level_filter.min(LevelFilter::Warn),
+                term_config,
+                TerminalMode::Stderr,
+                ColorChoice::Auto,
+            ));
+        } else if json {
+            loggers.push(JsonLogger::new(level_filter, std::io::stderr()));
+        } else {
+            loggers.push(TermLogger::new(
                 level_filter,
                 term_config,
                 TerminalMode::Stderr,
                 ColorChoice::Auto,
-            )
-            .map_err(|e| FrameworkErrorKind::ConfigError.context(e))?,
-
-            Some(file) => {
-                let file_config = simplelog::ConfigBuilder::new()
-                    .set_time_format_rfc3339()
-                    .build();
-                let file = File::options()
-                    .create(true)
-                    .append(true)
-                    .open(file)
-                    .map_err(|e| {
-                        FrameworkErrorKind::PathError {
-                            name: Some(file.clone()),
-                        }
-                        .context(e)
-                    })?;
-                let term_logger = TermLogger::new(
-                    level_filter.min(LevelFilter::Warn),
-                    term_config,
-                    TerminalMode::Stderr,
-                    ColorChoice::Auto,
-                );
-                CombinedLogger::init(vec![
-                    term_logger,
-                    WriteLogger::new(level_filter, file_config, file),
-                ])
+            ));
+        }
+        if let Some(file) = &global.log_file {
+            let file_config = simplelog::ConfigBuilder::new()
+                .set_time_format_rfc3339()
+                .build();
+            if global.log_max_size.is_some() || global.log_max_files.is_some() {
+                rotate_log_file(
+                    file,
+                    global.log_max_size,
+                    global.log_max_files.unwrap_or(DEFAULT_LOG_MAX_FILES),
+                )
                 .map_err(|e| FrameworkErrorKind::ConfigError.context(e))?;
             }
+            let file = File::options()
+                .create(true)
+                .append(true)
+                .open(file)
+                .map_err(|e| {
+                    FrameworkErrorKind::PathError {
+                        name: Some(file.clone()),
+                    }
+                    .context(e)
+                })?;
+            if json {
+                loggers.push(JsonLogger::new(level_filter, file));
+            } else {
+                loggers.push(WriteLogger::new(level_filter, file_config, file));
+            }
+        }
+        loggers.extend(system_logger);
+        if let Some(target) = &config.global.event_stream {
+            events::init(target).map_err(|e| FrameworkErrorKind::ConfigError.context(e))?;
+        }
+        loggers.push(Box::new(EventLogger));
+        CombinedLogger::init(loggers).map_err(|e| FrameworkErrorKind::ConfigError.context(e))?;
+        if let Some(err) = system_log_error {
+            warn!("{err:#}, logging to stderr instead");
         }
 
         // display logs from merging
@@ -275,9 +400,13 @@ impl Configurable<RusticConfig> for EntryPoint {
             log!(level, "{}", merge_log);
         }
 
+        config.repository.apply_backend_options();
+
         match &self.commands {
             RusticCmd::Forget(cmd) => cmd.override_config(config),
             RusticCmd::Copy(cmd) => cmd.override_config(config),
+            #[cfg(feature = "mount")]
+            RusticCmd::Mount(cmd) => cmd.override_config(config),
             #[cfg(feature = "webdav")]
             RusticCmd::Webdav(cmd) => cmd.override_config(config),
 
@@ -286,6 +415,27 @@ impl Configurable<RusticConfig> for EntryPoint {
         }
     }
 }
+/// Get the backends with the given options, wrapped by the configured retries, bandwidth limits
+/// and the fallback to the cold repository
+///
+/// Commands accessing files of the repository directly should use these backends instead of
+/// the raw backends of the options.
+///
+/// # Arguments
+///
+/// * `repo_opts` - The repository options
+fn get_backends(repo_opts: &AllRepositoryOptions) -> Result<RepositoryBackends> {
+    let backends = repo_opts.be.to_backends()?;
+    let backends = retry_backends(
+        backends,
+        repo_opts.backend_retries,
+        repo_opts.backend_retry_backoff.map(Into::into),
+        repo_opts.backend_retry_backoff_max.map(Into::into),
+    );
+    let backends = limit_backends(backends, repo_opts.limit_download, repo_opts.limit_upload);
+    Ok(with_hot_fallback(backends))
+}
+
 /// Get the repository with the given options
 ///
 /// # Arguments
@@ -296,8 +446,24 @@ fn get_repository_with_progress<P>(
     repo_opts: &AllRepositoryOptions,
     po: P,
 ) -> Result<Repository<P, ()>> {
-    let backends = repo_opts.be.to_backends()?;
-    let repo = Repository::new_with_progress(&repo_opts.repo, &backends, po)?;
+    let backends = get_backends(repo_opts)?;
+    let repo = Repository::new_with_progress(&repo_opts.repository_options()?, &backends, po)?;
+    Ok(repo)
+}
+
+/// Get the repository with the given options using the given backends
+///
+/// # Arguments
+///
+/// * `repo_opts` - The repository options
+/// * `backends` - The backends, usually the ones of [`get_backends`] with additional wrappers
+///
+fn get_repository_with_backends(
+    repo_opts: &AllRepositoryOptions,
+    backends: &RepositoryBackends,
+) -> Result<Repository<ProgressOptions, ()>> {
+    let po = RUSTIC_APP.config().global.progress_options;
+    let repo = Repository::new_with_progress(&repo_opts.repository_options()?, backends, po)?;
     Ok(repo)
 }
 
@@ -335,10 +501,31 @@ fn open_repository_with_progress<P: Clone>(
     repo_opts: &AllRepositoryOptions,
     po: P,
 ) -> Result<Repository<P, OpenStatus>> {
+    open_repository_from(get_repository_with_progress(repo_opts, po)?)
+}
+
+/// Open the given repository
+///
+/// # Arguments
+///
+/// * `repo` - The repository to open
+///
+/// # Errors
+///
+/// * If the password can't be read or is incorrect
+fn open_repository_from<P: Clone>(repo: Repository<P, ()>) -> Result<Repository<P, OpenStatus>> {
     if RUSTIC_APP.config().global.check_index {
         warn!("Option check-index is not supported and will be ignored!");
     }
-    let repo = get_repository_with_progress(repo_opts, po)?;
+    let repo = open_with_password(repo)?;
+    events::emit(&Event::RepositoryOpened {
+        repository: &repo.name,
+    });
+    Ok(repo)
+}
+
+/// Open the repository using the configured password or by asking for the password
+fn open_with_password<P: Clone>(repo: Repository<P, ()>) -> Result<Repository<P, OpenStatus>> {
     match repo.password()? {
         // if password is given, directly return the result of find_key_in_backend and don't retry
         Some(pass) => {
@@ -389,6 +576,51 @@ fn open_repository_indexed(
     open_repository_indexed_with_progress(repo_opts, po)
 }
 
+/// Determine the packs to warm up before they are read
+///
+/// If warm-up is not configured, `packs` is not called and no packs are returned. Otherwise, the
+/// packs are deduplicated and their number is reported. In dry-run mode, the pack ids are only
+/// printed and no packs are returned, so the warm-up command is not run.
+///
+/// # Arguments
+///
+/// * `packs` - Function computing the packs which are read, may contain duplicates
+fn packs_to_warm_up(packs: impl FnOnce() -> Result<Vec<Id>>) -> Result<Vec<Id>> {
+    let config = RUSTIC_APP.config();
+    let repo_opts = &config.repository.repo;
+    if !repo_opts.warm_up && !repo_opts.warm_up_command.is_set() {
+        return Ok(Vec::new());
+    }
+    let packs: Vec<_> = packs()?.into_iter().sorted_unstable().dedup().collect();
+    info!("{} packs queued for warm-up.", packs.len());
+    if config.global.dry_run {
+        for id in &packs {
+            println!("would warm up pack: {id}");
+        }
+        return Ok(Vec::new());
+    }
+    Ok(packs)
+}
+
+/// Warm up the packs which are read by a command and wait for the configured warm-up time
+///
+/// See [`packs_to_warm_up`] for the packs which are warmed up.
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `packs` - Function computing the packs which are read, may contain duplicates
+fn warm_up_packs<P: ProgressBars, S>(
+    repo: &Repository<P, S>,
+    packs: impl FnOnce() -> Result<Vec<Id>>,
+) -> Result<()> {
+    let packs = packs_to_warm_up(packs)?;
+    if !packs.is_empty() {
+        repo.warm_up_wait(packs.into_iter())?;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::commands::EntryPoint;
@@ -0,0 +1,256 @@
+//! `acl` subcommand
+
+use crate::{
+    commands::open_repository, config::hooks::is_saved_as, helpers::table_with_titles,
+    output::Output, status_err, Application, RUSTIC_APP,
+};
+
+use std::io::Write;
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use log::{info, warn};
+
+use rustic_core::{repofile::SnapshotFile, StringList};
+
+/// Prefix of the tags storing the principals which are allowed to restore a snapshot
+///
+/// Snapshots have no custom metadata fields, so the ACL is stored as tags like
+/// `acl:allow:alice`.
+const ACL_TAG_PREFIX: &str = "acl:allow:";
+
+/// `acl` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct AclCmd {
+    /// Subcommand to run
+    #[clap(subcommand)]
+    cmd: AclSubCmd,
+}
+
+#[derive(clap::Subcommand, Debug, Runnable)]
+enum AclSubCmd {
+    /// Set the users/groups which are allowed to restore snapshots
+    Set(SetCmd),
+    /// Show the users/groups which are allowed to restore snapshots
+    Get(GetCmd),
+    /// Remove the ACL of snapshots
+    Clear(ClearCmd),
+}
+
+impl Runnable for AclCmd {
+    fn run(&self) {
+        warn!("the ACL is advisory only: it is not enforced and everybody with a repository key can restore all snapshots.");
+        self.cmd.run();
+    }
+}
+
+/// `acl set` subcommand
+#[derive(clap::Parser, Debug)]
+pub(crate) struct SetCmd {
+    /// User or group (e.g. "@admins") which is allowed to restore the snapshots (can be specified
+    /// multiple times). Replaces the current ACL
+    #[clap(long, value_name = "USER", required = true)]
+    allow: Vec<String>,
+
+    /// Snapshots to change. If none is given, use filter options to filter from all snapshots
+    #[clap(value_name = "ID")]
+    ids: Vec<String>,
+}
+
+impl Runnable for SetCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl SetCmd {
+    fn inner_run(&self) -> Result<()> {
+        for principal in &self.allow {
+            if principal.is_empty() || principal.contains(',') {
+                bail!("invalid user or group {principal:?}: must not be empty or contain ','.");
+            }
+        }
+        let principals: Vec<_> = self.allow.iter().sorted().dedup().cloned().collect();
+        change_acl(&self.ids, &principals)
+    }
+}
+
+/// `acl get` subcommand
+#[derive(clap::Parser, Debug)]
+pub(crate) struct GetCmd {
+    /// Snapshots to show. If none is given, use filter options to filter from all snapshots
+    #[clap(value_name = "ID")]
+    ids: Vec<String>,
+}
+
+impl Runnable for GetCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run(&mut Output::stdout()) {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl GetCmd {
+    fn inner_run(&self, out: &mut Output<impl Write>) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let repo = open_repository(&config.repository)?;
+        let snapshots = if self.ids.is_empty() {
+            repo.get_matching_snapshots(|sn| config.snapshot_filter.matches(sn))?
+        } else {
+            repo.get_snapshots(&self.ids)?
+        };
+
+        let mut table = table_with_titles(["Snapshot", "Time", "Allowed"]);
+        for sn in &snapshots {
+            let principals = principals(sn);
+            _ = table.add_row([
+                sn.id.to_string(),
+                sn.time.format("%Y-%m-%d %H:%M:%S").to_string(),
+                if principals.is_empty() {
+                    "(no ACL)".to_string()
+                } else {
+                    principals.join("\n")
+                },
+            ]);
+        }
+        out.table(table)?;
+        writeln!(out, "{} snapshot(s)", snapshots.len())?;
+        Ok(())
+    }
+}
+
+/// `acl clear` subcommand
+#[derive(clap::Parser, Debug)]
+pub(crate) struct ClearCmd {
+    /// Snapshots to change. If none is given, use filter options to filter from all snapshots
+    #[clap(value_name = "ID")]
+    ids: Vec<String>,
+}
+
+impl Runnable for ClearCmd {
+    fn run(&self) {
+        if let Err(err) = change_acl(&self.ids, &[]) {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+/// Replace the ACL of snapshots, respecting `--dry-run`
+///
+/// # Arguments
+///
+/// * `ids` - the snapshots to change; if empty, the filter options are used
+/// * `allowed` - the new ACL; empty to remove the ACL
+fn change_acl(ids: &[String], allowed: &[String]) -> Result<()> {
+    let config = RUSTIC_APP.config();
+    let repo = open_repository(&config.repository)?;
+    let snapshots = if ids.is_empty() {
+        repo.get_matching_snapshots(|sn| config.snapshot_filter.matches(sn))?
+    } else {
+        repo.get_snapshots(ids)?
+    };
+
+    let mut changes = Vec::new();
+    for mut sn in snapshots {
+        if principals(&sn) == allowed {
+            continue;
+        }
+        let old = sn.clone();
+        sn.tags = tags_with_acl(&sn, allowed)?;
+        // the modified snapshot references the first snapshot it was derived from
+        _ = sn.original.get_or_insert(old.id);
+        changes.push((old, sn));
+    }
+
+    if changes.is_empty() {
+        info!("no snapshot changed.");
+    } else if config.global.dry_run {
+        println!("would have modified the following snapshots:");
+        for (old, _) in &changes {
+            println!(
+                "snapshot {}: [{}] -> [{}]",
+                old.id,
+                principals(old).join(","),
+                allowed.join(",")
+            );
+        }
+    } else {
+        let (old_snaps, snapshots): (Vec<_>, Vec<_>) = changes.into_iter().unzip();
+        let old_ids: Vec<_> = old_snaps.iter().map(|sn| sn.id).collect();
+        let count = snapshots.len();
+        let is_created = |saved: &SnapshotFile| snapshots.iter().any(|sn| is_saved_as(saved, sn));
+        config
+            .hooks
+            .track_snapshots(&repo, is_created, &old_snaps, || {
+                // old snapshots are only removed once the modified ones have been saved
+                repo.save_snapshots(snapshots.clone())?;
+                repo.delete_snapshots(&old_ids)?;
+                Ok(())
+            })?;
+        info!("changed the ACL of {count} snapshot(s).");
+    }
+    Ok(())
+}
+
+/// Get the users/groups which are allowed to restore the snapshot, sorted
+fn principals(sn: &SnapshotFile) -> Vec<String> {
+    sn.tags
+        .formatln()
+        .lines()
+        .filter_map(|tag| tag.strip_prefix(ACL_TAG_PREFIX))
+        .map(str::to_string)
+        .sorted()
+        .collect()
+}
+
+/// Get the tags of the snapshot with the ACL replaced by the given principals
+fn tags_with_acl(sn: &SnapshotFile, principals: &[String]) -> Result<StringList> {
+    let tags = sn
+        .tags
+        .formatln()
+        .lines()
+        .filter(|tag| !tag.is_empty() && !tag.starts_with(ACL_TAG_PREFIX))
+        .map(str::to_string)
+        .chain(principals.iter().map(|p| format!("{ACL_TAG_PREFIX}{p}")))
+        .join(",");
+    Ok(if tags.is_empty() {
+        StringList::default()
+    } else {
+        tags.parse()?
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_acl_tags_passes() -> Result<()> {
+        let mut sn = SnapshotFile {
+            tags: "daily,acl:allow:bob".parse()?,
+            ..Default::default()
+        };
+        assert_eq!(principals(&sn), vec!["bob".to_string()]);
+
+        sn.tags = tags_with_acl(&sn, &["@admins".to_string(), "alice".to_string()])?;
+        assert_eq!(
+            principals(&sn),
+            vec!["@admins".to_string(), "alice".to_string()]
+        );
+        assert!(sn.tags.formatln().lines().any(|tag| tag == "daily"));
+
+        sn.tags = tags_with_acl(&sn, &[])?;
+        assert!(principals(&sn).is_empty());
+        assert_eq!(sn.tags.formatln().trim(), "daily");
+        Ok(())
+    }
+}
@@ -1,10 +1,21 @@
 //! `backup` subcommand
 
-use std::path::PathBuf;
+mod compression;
+mod template;
+
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 
 use crate::{
-    commands::{get_repository, init::init, open_repository, snapshots::fill_table},
+    commands::{
+        get_backends, get_repository_with_backends, init::init, open_repository_from,
+        snapshots::fill_table,
+    },
+    events::{self, Event},
     helpers::{bold_cell, bytes_size_to_string, table},
+    retry::{retry_pack_uploads, DEFAULT_PACK_UPLOAD_RETRIES},
     status_err, Application, RUSTIC_APP,
 };
 
@@ -12,16 +23,19 @@ use abscissa_core::{Command, Runnable, Shutdown};
 use anyhow::{bail, Context, Result};
 use clap::ValueHint;
 use comfy_table::Cell;
-use log::{debug, info, warn};
+use itertools::Itertools;
+use log::{debug, error, info, warn};
 use merge::Merge;
 use serde::{Deserialize, Serialize};
-use serde_with::{serde_as, OneOrMany};
+use serde_with::{serde_as, DisplayFromStr, OneOrMany};
 
 use rustic_core::{
-    BackupOptions, ConfigOptions, KeyOptions, LocalSourceFilterOptions, LocalSourceSaveOptions,
+    BackupOptions, ConfigOptions, Id, KeyOptions, LocalSourceFilterOptions, LocalSourceSaveOptions,
     ParentOptions, PathList, SnapshotOptions,
 };
 
+use self::{compression::CompressionLevel, template::DescriptionTemplate};
+
 /// `backup` subcommand
 #[serde_as]
 #[derive(Clone, Command, Default, Debug, clap::Parser, Serialize, Deserialize, Merge)]
@@ -79,6 +93,13 @@ pub struct BackupCmd {
     #[merge(strategy = merge::bool::overwrite_false)]
     init: bool,
 
+    /// Compression level of newly written packs: none, fast, default, best or a zstd level from
+    /// 1 to 22. The level is saved in the repository config; existing packs are not recompressed.
+    /// [default: the level of the repository config]
+    #[clap(long, value_name = "LEVEL")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    compression: Option<CompressionLevel>,
+
     /// Parent processing options
     #[clap(flatten, next_help_heading = "Options for parent processing")]
     #[serde(flatten)]
@@ -94,6 +115,13 @@ pub struct BackupCmd {
     #[serde(flatten)]
     snap_opts: SnapshotOptions,
 
+    /// Template for the snapshot description, e.g. "{hostname} {source} at {time:%Y-%m-%d}".
+    /// Available placeholders: {hostname}, {label}, {tags}, {source}, {time}, {time:FORMAT}, {env:NAME}.
+    /// Ignored if --description or --description-from is given
+    #[clap(long, value_name = "TEMPLATE", help_heading = "Snapshot options")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    description_template: Option<DescriptionTemplate>,
+
     /// Key options (when using --init)
     #[clap(flatten, next_help_heading = "Key options (when using --init)")]
     #[serde(skip)]
@@ -143,7 +171,33 @@ impl Runnable for BackupCmd {
 impl BackupCmd {
     fn inner_run(&self) -> Result<()> {
         let config = RUSTIC_APP.config();
-        let repo = get_repository(&config.repository)?;
+        // pack uploads are retried here unless retries of all backend operations are configured
+        let pack_retries = if config.repository.backend_retries.is_some() {
+            0
+        } else {
+            DEFAULT_PACK_UPLOAD_RETRIES
+        };
+        let failed_uploads = Arc::new(Mutex::new(Vec::new()));
+        let backends = retry_pack_uploads(
+            get_backends(&config.repository)?,
+            pack_retries,
+            config.repository.backend_retry_backoff.map(Into::into),
+            config.repository.backend_retry_backoff_max.map(Into::into),
+            &failed_uploads,
+        );
+        let repo = get_repository_with_backends(&config.repository, &backends)?;
+
+        // the compression level applies to the repository, so it can't be set per source
+        if config
+            .backup
+            .sources
+            .iter()
+            .any(|opt| opt.compression.is_some())
+        {
+            bail!("key \"compression\" is not valid in a [[backup.sources]] section!");
+        }
+        let compression = self.compression.or(config.backup.compression);
+
         // Initialize repository if --init is set and it is not yet initialized
         let repo = if self.init && repo.config_id()?.is_none() {
             if config.global.dry_run {
@@ -152,9 +206,40 @@ impl BackupCmd {
                     repo.name
                 );
             }
-            init(repo, &self.key_opts, &self.config_opts)?
+            let mut config_opts = config.repository.config_options(&self.config_opts)?;
+            if config_opts.set_compression.is_none() {
+                config_opts.set_compression = compression.map(CompressionLevel::zstd_level);
+            }
+            init(repo, &self.key_opts, &config_opts)?
         } else {
-            open_repository(&config.repository)?
+            open_repository_from(repo)?
+        };
+
+        // the opened repository keeps using its old config, so it is reopened after the change
+        let repo = match compression.map(CompressionLevel::zstd_level) {
+            Some(level) if repo.config().compression != Some(level) => {
+                if repo.config().version == 1 && level != 0 {
+                    bail!(
+                        "compression is not supported by repository version 1, please use `rustic config --set-version 2` first"
+                    );
+                }
+                if config.global.dry_run {
+                    info!("would set the compression level of the repository to {level}");
+                    repo
+                } else {
+                    let config_opts = ConfigOptions {
+                        set_compression: Some(level),
+                        ..Default::default()
+                    };
+                    _ = repo.apply_config(&config_opts)?;
+                    info!("set the compression level of the repository to {level}");
+                    open_repository_from(get_repository_with_backends(
+                        &config.repository,
+                        &backends,
+                    )?)?
+                }
+            }
+            _ => repo,
         }
         .to_indexed_ids()?;
 
@@ -239,7 +324,30 @@ impl BackupCmd {
                 .ignore_filter_opts(opts.ignore_filter_opts)
                 .no_scan(opts.no_scan)
                 .dry_run(config.global.dry_run);
-            let snap = repo.backup(&backup_opts, &source, opts.snap_opts.to_snapshot()?)?;
+            let mut snap = opts.snap_opts.to_snapshot()?;
+            if snap.description.is_none() {
+                if let Some(template) = &opts.description_template {
+                    snap.description = Some(template.render(&snap, &source)?);
+                }
+            }
+            let snap = match repo.backup(&backup_opts, &source, snap) {
+                Ok(snap) => snap,
+                // continue with the remaining sources; the run fails at the end
+                Err(err) if !failed_packs(&failed_uploads).is_empty() => {
+                    error!("backup of {source} failed: {err}");
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+            if !config.global.dry_run {
+                config.hooks.snapshots_created([&snap]);
+            }
+            if events::is_enabled() {
+                events::emit(&Event::Summary {
+                    command: "backup",
+                    summary: serde_json::to_value(&snap)?,
+                });
+            }
 
             if opts.json {
                 let mut stdout = std::io::stdout();
@@ -282,6 +390,28 @@ impl BackupCmd {
             info!("backup of {source} done.");
         }
 
+        let failed = failed_packs(&failed_uploads);
+        if !failed.is_empty() {
+            bail!(
+                "{} pack(s) couldn't be uploaded: {}. Packs uploaded since the index was last saved \
+                may not be indexed; run `rustic repair index` to index them.",
+                failed.len(),
+                failed.iter().join(", ")
+            );
+        }
+
         Ok(())
     }
 }
+
+/// Get the packs whose upload failed after all retries
+///
+/// # Arguments
+///
+/// * `failed_uploads` - the packs recorded by the backend, see [`retry_pack_uploads`]
+fn failed_packs(failed_uploads: &Mutex<Vec<Id>>) -> Vec<Id> {
+    failed_uploads
+        .lock()
+        .map(|failed| failed.clone())
+        .unwrap_or_default()
+}
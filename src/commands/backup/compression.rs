@@ -0,0 +1,105 @@
+//! Compression levels for newly written packs
+
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{anyhow, Result};
+
+/// Range of the zstd levels which can be given as number
+const LEVELS: std::ops::RangeInclusive<i32> = 1..=22;
+
+/// Compression level of newly written packs: `none`, `fast`, `default`, `best` or a zstd level
+/// from 1 to 22
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum CompressionLevel {
+    /// No compression
+    None,
+    /// zstd level 1
+    Fast,
+    /// zstd level 3, the default of zstd and rustic
+    Default,
+    /// zstd level 19, the best level not needing much more memory for decompression
+    Best,
+    /// The given zstd level
+    Level(i32),
+}
+
+impl CompressionLevel {
+    /// The compression level as stored in the repository config; 0 means no compression
+    pub(crate) const fn zstd_level(self) -> i32 {
+        match self {
+            Self::None => 0,
+            Self::Fast => 1,
+            Self::Default => 3,
+            Self::Best => 19,
+            Self::Level(level) => level,
+        }
+    }
+}
+
+impl FromStr for CompressionLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let level = match s {
+            "none" => Self::None,
+            "fast" => Self::Fast,
+            "default" => Self::Default,
+            "best" => Self::Best,
+            _ => Self::Level(
+                s.parse()
+                    .ok()
+                    .filter(|level| LEVELS.contains(level))
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "invalid compression level \"{s}\": use none, fast, default, best or a number from {} to {}",
+                            LEVELS.start(),
+                            LEVELS.end()
+                        )
+                    })?,
+            ),
+        };
+        Ok(level)
+    }
+}
+
+impl Display for CompressionLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => f.write_str("none"),
+            Self::Fast => f.write_str("fast"),
+            Self::Default => f.write_str("default"),
+            Self::Best => f.write_str("best"),
+            Self::Level(level) => write!(f, "{level}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("none", 0)]
+    #[case("fast", 1)]
+    #[case("default", 3)]
+    #[case("best", 19)]
+    #[case("1", 1)]
+    #[case("22", 22)]
+    fn test_parse_compression_level_passes(#[case] s: &str, #[case] expected: i32) -> Result<()> {
+        let level: CompressionLevel = s.parse()?;
+        assert_eq!(level.zstd_level(), expected);
+        assert_eq!(level.to_string(), s);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("0")]
+    #[case("23")]
+    #[case("-1")]
+    #[case("max")]
+    fn test_parse_invalid_compression_level_fails(#[case] s: &str) {
+        assert!(s.parse::<CompressionLevel>().is_err());
+    }
+}
@@ -0,0 +1,187 @@
+//! Templates for snapshot descriptions
+
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::format::{Item, StrftimeItems};
+use rustic_core::{repofile::SnapshotFile, PathList};
+
+/// Time format used for `{time}` if no format is given
+const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// A part of a [`DescriptionTemplate`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    /// Literal text
+    Text(String),
+    /// `{hostname}`
+    Hostname,
+    /// `{label}`
+    Label,
+    /// `{tags}`
+    Tags,
+    /// `{source}`
+    Source,
+    /// `{time}` or `{time:FORMAT}` with a strftime format
+    Time(String),
+    /// `{env:NAME}`
+    Env(String),
+}
+
+/// Template for the snapshot description, e.g. `"{hostname} {source} scheduled {time:%Y-%m-%d}"`
+///
+/// Use `{{` and `}}` for literal braces.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct DescriptionTemplate {
+    /// The template as given by the user
+    template: String,
+    /// The parsed template
+    parts: Vec<Part>,
+}
+
+impl FromStr for DescriptionTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(template: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    _ = chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    _ = chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| anyhow!("unclosed placeholder in \"{template}\""))?;
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::parse(&rest[..end])?);
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => bail!("unmatched '}}' in \"{template}\", use '}}}}' for a literal brace"),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Self {
+            template: template.to_string(),
+            parts,
+        })
+    }
+}
+
+impl Display for DescriptionTemplate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.template)
+    }
+}
+
+impl Part {
+    /// Parse a placeholder, i.e. the content between `{` and `}`
+    fn parse(placeholder: &str) -> Result<Self> {
+        let part = match placeholder.split_once(':') {
+            None => match placeholder {
+                "hostname" => Self::Hostname,
+                "label" => Self::Label,
+                "tags" => Self::Tags,
+                "source" => Self::Source,
+                "time" => Self::Time(DEFAULT_TIME_FORMAT.to_string()),
+                _ => bail!("unknown placeholder {{{placeholder}}}"),
+            },
+            Some(("time", format)) => {
+                if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                    bail!("invalid time format \"{format}\" in placeholder {{{placeholder}}}");
+                }
+                Self::Time(format.to_string())
+            }
+            Some(("env", name)) if !name.is_empty() && !name.contains('=') => {
+                Self::Env(name.to_string())
+            }
+            _ => bail!("unknown placeholder {{{placeholder}}}"),
+        };
+        Ok(part)
+    }
+}
+
+impl DescriptionTemplate {
+    /// Render the template
+    ///
+    /// # Arguments
+    ///
+    /// * `snap` - the snapshot to be written
+    /// * `source` - the backup source
+    ///
+    /// # Errors
+    ///
+    /// * If an environment variable used in the template is not set
+    pub(crate) fn render(&self, snap: &SnapshotFile, source: &PathList) -> Result<String> {
+        let mut description = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => description.push_str(text),
+                Part::Hostname => description.push_str(&snap.hostname),
+                Part::Label => description.push_str(&snap.label),
+                Part::Tags => description.push_str(&snap.tags.to_string()),
+                Part::Source => description.push_str(&source.to_string()),
+                Part::Time(format) => description.push_str(&snap.time.format(format).to_string()),
+                Part::Env(name) => {
+                    description.push_str(&std::env::var(name).with_context(|| {
+                        format!("error reading environment variable {name} for description")
+                    })?)
+                }
+            }
+        }
+        Ok(description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[test]
+    fn test_parse_template_passes() -> Result<()> {
+        let template: DescriptionTemplate =
+            "{hostname} {{{source}}} at {time:%Y} {env:HOME}".parse()?;
+        assert_eq!(
+            template.parts,
+            [
+                Part::Hostname,
+                Part::Text(" {".to_string()),
+                Part::Source,
+                Part::Text("} at ".to_string()),
+                Part::Time("%Y".to_string()),
+                Part::Text(" ".to_string()),
+                Part::Env("HOME".to_string()),
+            ]
+        );
+        assert_eq!(
+            template.to_string(),
+            "{hostname} {{{source}}} at {time:%Y} {env:HOME}"
+        );
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("{host}")]
+    #[case("{hostname")]
+    #[case("hostname}")]
+    #[case("{time:%Q}")]
+    #[case("{env:}")]
+    fn test_parse_invalid_template_fails(#[case] template: &str) {
+        assert!(template.parse::<DescriptionTemplate>().is_err());
+    }
+}
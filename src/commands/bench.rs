@@ -0,0 +1,497 @@
+//! `bench` subcommand
+
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::Result;
+use bytes::Bytes;
+use bytesize::ByteSize;
+use log::info;
+use serde::Serialize;
+
+use rustic_backend::BackendOptions;
+use rustic_core::{
+    repofile::{FileType, SnapshotFile},
+    BackupOptions, ConfigOptions, Id, KeyOptions, LimitOption, LocalDestination, LsOptions,
+    NoProgressBars, OpenStatus, PathList, PruneOptions, ReadBackend, Repository,
+    RepositoryBackends, RepositoryOptions, RestoreOptions, SnapshotOptions, WriteBackend,
+};
+
+use crate::{
+    helpers::{bytes_size_to_string, table_right_from},
+    output::Output,
+    status_err, Application, RUSTIC_APP,
+};
+
+/// Password of the benchmark repository
+const PASSWORD: &str = "bench";
+
+/// `bench` subcommand
+///
+/// Runs an operation on generated data in a temporary repository and reports its throughput.
+/// The repository is initialized with the config options given, so the impact of e.g. the pack
+/// size or the compression level can be measured.
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct BenchCmd {
+    /// Operation to measure
+    #[clap(value_enum)]
+    operation: Operation,
+
+    /// Total size of the generated data
+    #[clap(long, value_name = "SIZE", default_value = "100MiB")]
+    size: ByteSize,
+
+    /// Number of generated files
+    #[clap(long, value_name = "N", default_value_t = 100)]
+    files: u64,
+
+    /// Seed of the generated data; the same seed generates the same data
+    #[clap(long, value_name = "SEED", default_value_t = 0)]
+    seed: u64,
+
+    /// Directory to create the data and the repository in [default: the temp directory]
+    #[clap(long, value_name = "DIR")]
+    dir: Option<PathBuf>,
+
+    /// Show the results in json format
+    #[clap(long)]
+    json: bool,
+
+    /// Config options of the benchmark repository
+    #[clap(
+        flatten,
+        next_help_heading = "Config options (of the benchmark repository)"
+    )]
+    config_opts: ConfigOptions,
+}
+
+/// Operation to measure
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Operation {
+    /// Back up the generated data
+    Backup,
+    /// Restore a snapshot of the generated data
+    Restore,
+    /// Prune after half of the generated data was changed and the first snapshot was removed
+    Prune,
+}
+
+/// Result of a benchmark
+#[derive(Debug, Serialize)]
+struct BenchResult {
+    /// The measured operation
+    operation: Operation,
+    /// Wall-clock time in seconds
+    seconds: f64,
+    /// Bytes read, from the source for backups, otherwise from the repository
+    bytes_read: u64,
+    /// Bytes written, to the repository or for restores to the destination
+    bytes_written: u64,
+    /// Read throughput in MB/s
+    read_mb_per_second: f64,
+    /// Write throughput in MB/s
+    written_mb_per_second: f64,
+    /// Number of data blobs (chunks) processed, if known
+    chunks: Option<u64>,
+    /// Data blobs (chunks) processed per second, if known
+    chunks_per_second: Option<f64>,
+}
+
+impl BenchResult {
+    /// Calculate the throughput of an operation
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - the measured operation
+    /// * `seconds` - the wall-clock time of the operation
+    /// * `bytes_read` - the bytes read by the operation
+    /// * `bytes_written` - the bytes written by the operation
+    /// * `chunks` - the number of data blobs processed, if known
+    #[allow(clippy::cast_precision_loss)]
+    fn new(
+        operation: Operation,
+        seconds: f64,
+        bytes_read: u64,
+        bytes_written: u64,
+        chunks: Option<u64>,
+    ) -> Self {
+        let per_second = |value: f64| if seconds > 0.0 { value / seconds } else { 0.0 };
+        Self {
+            operation,
+            seconds,
+            bytes_read,
+            bytes_written,
+            read_mb_per_second: per_second(bytes_read as f64 / 1_000_000.0),
+            written_mb_per_second: per_second(bytes_written as f64 / 1_000_000.0),
+            chunks,
+            chunks_per_second: chunks.map(|chunks| per_second(chunks as f64)),
+        }
+    }
+}
+
+impl Runnable for BenchCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run(&mut Output::stdout()) {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl BenchCmd {
+    fn inner_run(&self, out: &mut Output<impl Write>) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let config_opts = config.repository.config_options(&self.config_opts)?;
+
+        let temp_dir = match &self.dir {
+            Some(dir) => tempfile::tempdir_in(dir)?,
+            None => tempfile::tempdir()?,
+        };
+        let data = temp_dir.path().join("data");
+        info!(
+            "generating {} in {} files...",
+            bytes_size_to_string(self.size.as_u64()),
+            self.files
+        );
+        generate_data(&data, self.size.as_u64(), self.files, self.seed)?;
+
+        let bench = BenchRepository::init(&temp_dir.path().join("repo"), &config_opts)?;
+        let result = match self.operation {
+            Operation::Backup => {
+                bench.reset();
+                let start = Instant::now();
+                let snap = bench.backup(&data)?;
+                let seconds = start.elapsed().as_secs_f64();
+                let summary = snap.summary.unwrap_or_default();
+                BenchResult::new(
+                    self.operation,
+                    seconds,
+                    summary.total_bytes_processed,
+                    bench.written(),
+                    Some(summary.data_blobs),
+                )
+            }
+            Operation::Restore => {
+                let snap = bench.backup(&data)?;
+                let summary = snap.summary.clone().unwrap_or_default();
+                bench.reset();
+                let start = Instant::now();
+                bench.restore(&snap, &temp_dir.path().join("restore"))?;
+                let seconds = start.elapsed().as_secs_f64();
+                BenchResult::new(
+                    self.operation,
+                    seconds,
+                    bench.read(),
+                    summary.total_bytes_processed,
+                    Some(summary.data_blobs),
+                )
+            }
+            Operation::Prune => {
+                let first = bench.backup(&data)?;
+                // change the first half of the files, such that half of the data becomes unused
+                generate_data(&data, self.size.as_u64() / 2, self.files / 2, !self.seed)?;
+                _ = bench.backup(&data)?;
+                bench.open()?.delete_snapshots(&[first.id])?;
+                bench.reset();
+                let start = Instant::now();
+                bench.prune()?;
+                let seconds = start.elapsed().as_secs_f64();
+                BenchResult::new(self.operation, seconds, bench.read(), bench.written(), None)
+            }
+        };
+
+        if self.json {
+            return out.json(&result);
+        }
+        let mut table = table_right_from(
+            1,
+            [
+                "Operation",
+                "Time",
+                "Read",
+                "Written",
+                "Read MB/s",
+                "Written MB/s",
+                "Chunks/s",
+            ],
+        );
+        _ = table.add_row([
+            format!("{:?}", result.operation).to_lowercase(),
+            format!("{:.2}s", result.seconds),
+            bytes_size_to_string(result.bytes_read),
+            bytes_size_to_string(result.bytes_written),
+            format!("{:.1}", result.read_mb_per_second),
+            format!("{:.1}", result.written_mb_per_second),
+            result
+                .chunks_per_second
+                .map_or_else(|| "-".to_string(), |chunks| format!("{chunks:.0}")),
+        ]);
+        out.table(table)
+    }
+}
+
+/// Generate files with pseudo-random content
+///
+/// The content is not compressible and only depends on the seed. Existing files are overwritten.
+///
+/// # Arguments
+///
+/// * `dir` - the directory to create the files in
+/// * `size` - the total size of the files
+/// * `files` - the number of files
+/// * `seed` - the seed of the content
+fn generate_data(dir: &Path, size: u64, files: u64, seed: u64) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    let files = files.max(1);
+    let mut rng = XorShift::new(seed);
+    for i in 0..files {
+        let file_size = size / files + u64::from(i < size % files);
+        let mut file = BufWriter::new(File::create(dir.join(format!("file-{i:06}")))?);
+        let mut written = 0;
+        while written < file_size {
+            let bytes = rng.next_u64().to_le_bytes();
+            let len = bytes.len().min(usize::try_from(file_size - written)?);
+            file.write_all(&bytes[..len])?;
+            written += len as u64;
+        }
+        file.flush()?;
+    }
+    Ok(())
+}
+
+/// A xorshift pseudo-random number generator, see <https://www.jstatsoft.org/v08/i14/paper>
+struct XorShift(u64);
+
+impl XorShift {
+    /// Create the generator; the state must not be 0
+    const fn new(seed: u64) -> Self {
+        Self(seed ^ 0x9E37_79B9_7F4A_7C15)
+    }
+
+    /// Get the next number
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+/// Counter of the bytes read from and written to a backend
+#[derive(Debug, Default)]
+struct Counter {
+    read: AtomicU64,
+    written: AtomicU64,
+}
+
+/// A backend counting the bytes read and written
+struct CountingBackend {
+    /// the wrapped backend
+    be: Arc<dyn WriteBackend>,
+    /// the counter
+    counter: Arc<Counter>,
+}
+
+impl CountingBackend {
+    /// Count the bytes read
+    fn count_read(&self, data: Result<Bytes>) -> Result<Bytes> {
+        if let Ok(data) = &data {
+            _ = self
+                .counter
+                .read
+                .fetch_add(data.len() as u64, Ordering::Relaxed);
+        }
+        data
+    }
+}
+
+impl ReadBackend for CountingBackend {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> Result<Vec<(Id, u32)>> {
+        self.be.list_with_size(tpe)
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> Result<Bytes> {
+        self.count_read(self.be.read_full(tpe, id))
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> Result<Bytes> {
+        self.count_read(self.be.read_partial(tpe, id, cacheable, offset, length))
+    }
+}
+
+impl WriteBackend for CountingBackend {
+    fn create(&self) -> Result<()> {
+        self.be.create()
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> Result<()> {
+        let len = buf.len() as u64;
+        self.be.write_bytes(tpe, id, cacheable, buf)?;
+        _ = self.counter.written.fetch_add(len, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> Result<()> {
+        self.be.remove(tpe, id, cacheable)
+    }
+}
+
+/// The temporary repository of a benchmark
+struct BenchRepository {
+    /// the backends, counting the bytes read and written
+    backends: RepositoryBackends,
+    /// the counter of the backends
+    counter: Arc<Counter>,
+}
+
+impl BenchRepository {
+    /// Initialize the repository
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the path of the repository
+    /// * `config_opts` - the config options of the repository
+    fn init(path: &Path, config_opts: &ConfigOptions) -> Result<Self> {
+        let be = BackendOptions {
+            repository: Some(path.to_string_lossy().to_string()),
+            ..Default::default()
+        }
+        .to_backends()?;
+        let counter = Arc::new(Counter::default());
+        let backends = RepositoryBackends::new(
+            Arc::new(CountingBackend {
+                be: be.repository(),
+                counter: counter.clone(),
+            }),
+            None,
+        );
+        let bench = Self { backends, counter };
+        _ = bench.repository()?.init_with_password(
+            PASSWORD,
+            &KeyOptions::default(),
+            config_opts,
+        )?;
+        Ok(bench)
+    }
+
+    /// Get the repository; the cache is not used to measure the backend
+    fn repository(&self) -> Result<Repository<NoProgressBars, ()>> {
+        let mut opts = RepositoryOptions::default();
+        opts.password = Some(PASSWORD.to_string());
+        opts.no_cache = true;
+        Ok(Repository::new_with_progress(
+            &opts,
+            &self.backends,
+            NoProgressBars,
+        )?)
+    }
+
+    /// Open the repository
+    fn open(&self) -> Result<Repository<NoProgressBars, OpenStatus>> {
+        Ok(self.repository()?.open()?)
+    }
+
+    /// Back up a directory
+    fn backup(&self, dir: &Path) -> Result<SnapshotFile> {
+        let source = PathList::from_iter([dir]).sanitize()?;
+        let snap = SnapshotOptions::default().to_snapshot()?;
+        Ok(self
+            .open()?
+            .to_indexed_ids()?
+            .backup(&BackupOptions::default(), &source, snap)?)
+    }
+
+    /// Restore a snapshot to a directory
+    fn restore(&self, snap: &SnapshotFile, dest: &Path) -> Result<()> {
+        let repo = self.open()?.to_indexed()?;
+        let node = repo.node_from_snapshot_and_path(snap, "")?;
+        let ls = repo.ls(&node, &LsOptions::default())?;
+        let dest = LocalDestination::new(&dest.to_string_lossy(), true, !node.is_dir())?;
+        let opts = RestoreOptions::default();
+        let restore_infos = repo.prepare_restore(&opts, ls.clone(), &dest, false)?;
+        repo.restore(restore_infos, &opts, ls, &dest)?;
+        Ok(())
+    }
+
+    /// Prune the repository, removing all unused data immediately
+    fn prune(&self) -> Result<()> {
+        let repo = self.open()?;
+        let mut opts = PruneOptions::default();
+        opts.max_unused = LimitOption::Percentage(0);
+        opts.instant_delete = true;
+        let pruner = repo.prune_plan(&opts)?;
+        pruner.do_prune(&repo, &opts)?;
+        Ok(())
+    }
+
+    /// Reset the counter
+    fn reset(&self) {
+        self.counter.read.store(0, Ordering::Relaxed);
+        self.counter.written.store(0, Ordering::Relaxed);
+    }
+
+    /// Get the bytes read since the last reset
+    fn read(&self) -> u64 {
+        self.counter.read.load(Ordering::Relaxed)
+    }
+
+    /// Get the bytes written since the last reset
+    fn written(&self) -> u64 {
+        self.counter.written.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_generate_data_passes() -> Result<()> {
+        let dir = tempdir()?;
+        generate_data(dir.path(), 1000, 3, 42)?;
+        let sizes: Vec<_> = (0..3)
+            .map(|i| fs::metadata(dir.path().join(format!("file-{i:06}"))).map(|m| m.len()))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(sizes, vec![334, 333, 333]);
+
+        // the same seed generates the same data
+        let content = fs::read(dir.path().join("file-000000"))?;
+        generate_data(dir.path(), 1000, 3, 42)?;
+        assert_eq!(fs::read(dir.path().join("file-000000"))?, content);
+        generate_data(dir.path(), 1000, 3, 43)?;
+        assert_ne!(fs::read(dir.path().join("file-000000"))?, content);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bench_result_passes() {
+        let result = BenchResult::new(Operation::Backup, 2.0, 10_000_000, 5_000_000, Some(100));
+        assert_eq!(result.read_mb_per_second, 5.0);
+        assert_eq!(result.written_mb_per_second, 2.5);
+        assert_eq!(result.chunks_per_second, Some(50.0));
+    }
+}
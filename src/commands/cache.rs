@@ -0,0 +1,382 @@
+//! `cache` subcommand
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, Metadata},
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{anyhow, bail, Result};
+use directories::ProjectDirs;
+use log::{info, warn};
+
+use crate::{
+    helpers::{bytes_size_to_string, table_with_titles},
+    output::Output,
+    status_err, Application, RUSTIC_APP,
+};
+
+/// Directories of the cache of a repository containing cached files
+const CACHE_SUBDIRS: [&str; 4] = ["data", "index", "keys", "snapshots"];
+
+/// File marking the cache directory, see <https://bford.info/cachedir/>
+const CACHEDIR_TAG: &str = "CACHEDIR.TAG";
+
+/// `cache` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct CacheCmd {
+    /// Subcommand to run
+    #[clap(subcommand)]
+    cmd: CacheSubCmd,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum CacheSubCmd {
+    /// Print the cache directory
+    Location,
+    /// Show the number and size of cached files per repository
+    Stats,
+    /// Remove cached files
+    Clear(ClearCmd),
+}
+
+/// `cache clear` subcommand
+#[derive(clap::Parser, Debug)]
+struct ClearCmd {
+    /// Only remove files which have not been used for this duration, e.g. "30d"
+    #[clap(long, value_name = "DURATION")]
+    max_age: Option<humantime::Duration>,
+
+    /// Only remove files of the repository with this id (or id prefix)
+    #[clap(long, value_name = "ID")]
+    repo_id: Option<String>,
+}
+
+impl Runnable for CacheCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run(&mut Output::stdout()) {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl CacheCmd {
+    fn inner_run(&self, out: &mut Output<impl Write>) -> Result<()> {
+        let dir = cache_dir()?;
+        match &self.cmd {
+            CacheSubCmd::Location => {
+                writeln!(out, "{}", dir.display())?;
+                if RUSTIC_APP.config().repository.repo.no_cache {
+                    info!("caching is disabled by --no-cache.");
+                }
+            }
+            CacheSubCmd::Stats => {
+                let files = cache_files(&dir)?;
+                let mut stats: BTreeMap<&str, (usize, u64)> = BTreeMap::new();
+                for file in &files {
+                    let (count, size) = stats.entry(&file.repo).or_default();
+                    *count += 1;
+                    *size += file.size;
+                }
+                let mut table = table_with_titles(["Repository ID", "Files", "Size"]);
+                for (repo, (count, size)) in &stats {
+                    _ = table.add_row([
+                        (*repo).to_string(),
+                        count.to_string(),
+                        bytes_size_to_string(*size),
+                    ]);
+                }
+                writeln!(out, "{table}")?;
+                writeln!(
+                    out,
+                    "total: {} files, {}",
+                    files.len(),
+                    bytes_size_to_string(files.iter().map(|file| file.size).sum())
+                )?;
+            }
+            CacheSubCmd::Clear(opts) => opts.clear(&dir, out)?,
+        }
+        Ok(())
+    }
+}
+
+impl ClearCmd {
+    /// Remove the selected cached files
+    fn clear(&self, dir: &Path, out: &mut Output<impl Write>) -> Result<()> {
+        let dry_run = RUSTIC_APP.config().global.dry_run;
+        let now = SystemTime::now();
+        let files: Vec<_> = cache_files(dir)?
+            .into_iter()
+            .filter(|file| match &self.repo_id {
+                Some(id) => file.repo.starts_with(id.as_str()),
+                None => true,
+            })
+            .filter(|file| match self.max_age {
+                Some(max_age) => now
+                    .duration_since(file.last_used)
+                    .is_ok_and(|age| age > *max_age),
+                None => true,
+            })
+            .collect();
+        let size = files.iter().map(|file| file.size).sum();
+        if dry_run {
+            for file in &files {
+                writeln!(out, "would remove {}", file.path.display())?;
+            }
+            info!(
+                "would remove {} files ({}).",
+                files.len(),
+                bytes_size_to_string(size)
+            );
+            return Ok(());
+        }
+        for file in &files {
+            fs::remove_file(&file.path)?;
+        }
+        info!(
+            "removed {} files ({}).",
+            files.len(),
+            bytes_size_to_string(size)
+        );
+        Ok(())
+    }
+}
+
+/// A file in the cache directory
+#[derive(Debug)]
+struct CacheFile {
+    /// Id of the repository the file belongs to
+    repo: String,
+    /// Path to the file
+    path: PathBuf,
+    /// Size of the file
+    size: u64,
+    /// Time the file was last accessed or, if not available, modified
+    last_used: SystemTime,
+}
+
+/// Get the cache directory used for all repositories
+///
+/// # Errors
+///
+/// * If no cache directory is given and the default one can't be determined
+fn cache_dir() -> Result<PathBuf> {
+    match &RUSTIC_APP.config().repository.repo.cache_dir {
+        Some(dir) => Ok(dir.clone()),
+        None => ProjectDirs::from("", "", "rustic")
+            .map(|dirs| dirs.cache_dir().to_path_buf())
+            .ok_or_else(|| anyhow!("cannot determine the cache directory, use --cache-dir")),
+    }
+}
+
+/// Check if `name` consists of `len` lower case hex digits, as used for ids
+fn is_hex(name: &str, len: usize) -> bool {
+    name.len() == len
+        && name
+            .bytes()
+            .all(|c| c.is_ascii_digit() || (b'a'..=b'f').contains(&c))
+}
+
+/// Get the entries of a directory with the given file names
+///
+/// # Arguments
+///
+/// * `dir` - the directory; a missing directory has no entries
+/// * `is_dir` - whether to get directories or files
+/// * `name_len` - the length of the hex names of the entries; other entries are ignored
+fn hex_entries(dir: &Path, is_dir: bool, name_len: usize) -> Result<Vec<(String, PathBuf)>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        if is_hex(&name, name_len) && entry.file_type()?.is_dir() == is_dir {
+            entries.push((name, entry.path()));
+        }
+    }
+    Ok(entries)
+}
+
+/// Get the time a file was last used
+fn last_used(meta: &Metadata) -> SystemTime {
+    meta.accessed()
+        .or_else(|_| meta.modified())
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Get all cached files
+///
+/// Only files matching the layout of the rustic cache are returned, i.e.
+/// `<REPO_ID>/{index,keys,snapshots}/<ID>` and `<REPO_ID>/data/<XX>/<ID>`. So files which are not
+/// created by rustic are never removed, even if a wrong cache directory is given.
+///
+/// # Arguments
+///
+/// * `dir` - the cache directory
+///
+/// # Errors
+///
+/// * If the directory exists but isn't marked as cache directory by a `CACHEDIR.TAG` file
+/// * If the directory can't be read
+fn cache_files(dir: &Path) -> Result<Vec<CacheFile>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    if !dir.join(CACHEDIR_TAG).is_file() {
+        bail!(
+            "{} is not a rustic cache directory: {CACHEDIR_TAG} is missing.",
+            dir.display()
+        );
+    }
+    let mut files = Vec::new();
+    for (repo, repo_dir) in hex_entries(dir, true, 64)? {
+        for subdir in CACHE_SUBDIRS {
+            let subdir = repo_dir.join(subdir);
+            let dirs = if subdir.ends_with("data") {
+                hex_entries(&subdir, true, 2)?
+                    .into_iter()
+                    .map(|(_, path)| path)
+                    .collect()
+            } else {
+                vec![subdir]
+            };
+            for dir in dirs {
+                for (_, path) in hex_entries(&dir, false, 64)? {
+                    let meta = fs::metadata(&path)?;
+                    files.push(CacheFile {
+                        repo: repo.clone(),
+                        path,
+                        size: meta.len(),
+                        last_used: last_used(&meta),
+                    });
+                }
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Select the least recently used files to remove such that the remaining files meet the limit
+///
+/// # Arguments
+///
+/// * `files` - the cached files
+/// * `limit` - the maximum total size of the remaining files
+fn files_to_evict(mut files: Vec<CacheFile>, limit: u64) -> Vec<CacheFile> {
+    let mut size: u64 = files.iter().map(|file| file.size).sum();
+    files.sort_unstable_by_key(|file| file.last_used);
+    files
+        .into_iter()
+        .take_while(|file| {
+            let evict = size > limit;
+            size -= file.size;
+            evict
+        })
+        .collect()
+}
+
+/// Remove the least recently used cached files until the cache is within the size limit
+///
+/// Errors are only logged as warnings; this is done after the command finished.
+///
+/// # Arguments
+///
+/// * `limit` - the maximum size of the cache directory
+pub(crate) fn limit_cache_size(limit: u64) {
+    let evict = || -> Result<()> {
+        let files = files_to_evict(cache_files(&cache_dir()?)?, limit);
+        let size = files.iter().map(|file| file.size).sum();
+        for file in &files {
+            fs::remove_file(&file.path)?;
+        }
+        if !files.is_empty() {
+            info!(
+                "removed {} cached files ({}) to meet the cache size limit.",
+                files.len(),
+                bytes_size_to_string(size)
+            );
+        }
+        Ok(())
+    };
+    if let Err(err) = evict() {
+        warn!("error limiting the cache size: {err:#}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    const REPO: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+    const ID: &str = "fedcba9876543210fedcba9876543210fedcba9876543210fedcba9876543210";
+
+    #[test]
+    fn test_cache_files_passes() -> Result<()> {
+        let dir = tempdir()?;
+        fs::write(
+            dir.path().join(CACHEDIR_TAG),
+            "Signature: 8a477f597d28d172789f06886806bc55",
+        )?;
+        let repo = dir.path().join(REPO);
+        fs::create_dir_all(repo.join("snapshots"))?;
+        fs::create_dir_all(repo.join("data").join("fe"))?;
+        fs::write(repo.join("snapshots").join(ID), "snapshot")?;
+        fs::write(repo.join("data").join("fe").join(ID), "pack")?;
+        // files not matching the layout are ignored
+        fs::write(repo.join("snapshots").join("notes.txt"), "keep")?;
+        fs::write(repo.join("data").join(ID), "keep")?;
+        fs::create_dir_all(dir.path().join("other").join("snapshots"))?;
+        fs::write(dir.path().join("other").join("snapshots").join(ID), "keep")?;
+
+        let mut files: Vec<_> = cache_files(dir.path())?
+            .into_iter()
+            .map(|file| file.path)
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                repo.join("data").join("fe").join(ID),
+                repo.join("snapshots").join(ID)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_files_without_tag_fails() -> Result<()> {
+        let dir = tempdir()?;
+        fs::create_dir_all(dir.path().join(REPO).join("snapshots"))?;
+        assert!(cache_files(dir.path()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_files_to_evict_passes() {
+        let file = |name: &str, size, secs| CacheFile {
+            repo: REPO.to_string(),
+            path: PathBuf::from(name),
+            size,
+            last_used: SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+        };
+        let files = vec![file("new", 10, 3), file("old", 10, 1), file("mid", 10, 2)];
+        let evicted: Vec<_> = files_to_evict(files, 15)
+            .into_iter()
+            .map(|file| file.path)
+            .collect();
+        assert_eq!(evicted, vec![PathBuf::from("old"), PathBuf::from("mid")]);
+    }
+}
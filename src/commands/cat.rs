@@ -1,19 +1,27 @@
 //! `cat` subcommand
 
+use std::io::Write;
+
 use crate::{
     commands::{open_repository, open_repository_indexed},
+    output::Output,
     status_err, Application, RUSTIC_APP,
 };
 
 use abscissa_core::{Command, Runnable, Shutdown};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use itertools::Itertools;
 
-use rustic_core::repofile::{BlobType, FileType};
+use rustic_core::{
+    repofile::{BlobType, FileType},
+    Id,
+};
 
 /// `cat` subcommand
 ///
-/// Output the contents of a file or blob
+/// Output the contents of a file or blob. Files and tree blobs are printed as pretty-printed json,
+/// data blobs as raw bytes.
 #[derive(clap::Parser, Command, Debug)]
 pub(crate) struct CatCmd {
     #[clap(subcommand)]
@@ -27,6 +35,8 @@ enum CatSubCmd {
     TreeBlob(IdOpt),
     /// Display a data blob
     DataBlob(IdOpt),
+    /// Display a data or tree blob
+    Blob(IdOpt),
     /// Display the config file
     Config,
     /// Display an index file
@@ -39,7 +49,7 @@ enum CatSubCmd {
 
 #[derive(Default, clap::Parser, Debug)]
 struct IdOpt {
-    /// Id to display
+    /// Id to display; files can also be given by a unique prefix of their id
     id: String,
 }
 
@@ -52,7 +62,7 @@ struct TreeOpts {
 
 impl Runnable for CatCmd {
     fn run(&self) {
-        if let Err(err) = self.inner_run() {
+        if let Err(err) = self.inner_run(&mut Output::stdout()) {
             status_err!("{}", err);
             RUSTIC_APP.shutdown(Shutdown::Crash);
         };
@@ -60,28 +70,115 @@ impl Runnable for CatCmd {
 }
 
 impl CatCmd {
-    fn inner_run(&self) -> Result<()> {
+    fn inner_run(&self, out: &mut Output<impl Write>) -> Result<()> {
         let config = RUSTIC_APP.config();
-        let data =
-            match &self.cmd {
-                CatSubCmd::Config => {
-                    open_repository(&config.repository)?.cat_file(FileType::Config, "")?
-                }
-                CatSubCmd::Index(opt) => {
-                    open_repository(&config.repository)?.cat_file(FileType::Index, &opt.id)?
-                }
-                CatSubCmd::Snapshot(opt) => {
-                    open_repository(&config.repository)?.cat_file(FileType::Snapshot, &opt.id)?
+        let cat_file = |tpe: FileType, id: &str| -> Result<_> {
+            let repo = open_repository(&config.repository)?;
+            let id = find_id(repo.list(tpe)?, tpe, id)?;
+            Ok(repo.cat_file(tpe, &id.to_hex())?)
+        };
+        let json = match &self.cmd {
+            CatSubCmd::Config => {
+                open_repository(&config.repository)?.cat_file(FileType::Config, "")?
+            }
+            CatSubCmd::Index(opt) => cat_file(FileType::Index, &opt.id)?,
+            CatSubCmd::Snapshot(opt) => cat_file(FileType::Snapshot, &opt.id)?,
+            CatSubCmd::TreeBlob(opt) => {
+                open_repository_indexed(&config.repository)?.cat_blob(BlobType::Tree, &opt.id)?
+            }
+            CatSubCmd::Tree(opt) => open_repository_indexed(&config.repository)?
+                .cat_tree(&opt.snap, |sn| config.snapshot_filter.matches(sn))?,
+            CatSubCmd::DataBlob(opt) => {
+                let data = open_repository_indexed(&config.repository)?
+                    .cat_blob(BlobType::Data, &opt.id)?;
+                // raw contents, without trailing newline
+                out.write_all(&data)?;
+                return Ok(());
+            }
+            CatSubCmd::Blob(opt) => {
+                let repo = open_repository_indexed(&config.repository)?;
+                let id = Id::from_hex(&opt.id)?;
+                if repo.get_index_entry(BlobType::Data, &id).is_ok() {
+                    out.write_all(&repo.cat_blob(BlobType::Data, &opt.id)?)?;
+                    return Ok(());
                 }
-                CatSubCmd::TreeBlob(opt) => open_repository_indexed(&config.repository)?
-                    .cat_blob(BlobType::Tree, &opt.id)?,
-                CatSubCmd::DataBlob(opt) => open_repository_indexed(&config.repository)?
-                    .cat_blob(BlobType::Data, &opt.id)?,
-                CatSubCmd::Tree(opt) => open_repository_indexed(&config.repository)?
-                    .cat_tree(&opt.snap, |sn| config.snapshot_filter.matches(sn))?,
-            };
-        println!("{}", String::from_utf8(data.to_vec())?);
+                repo.cat_blob(BlobType::Tree, &opt.id)?
+            }
+        };
+        write_json(out, &json)
+    }
+}
+
+/// Find the id of a file given by a prefix of its id
+///
+/// # Arguments
+///
+/// * `ids` - the ids of all files of the type
+/// * `tpe` - the file type, used for error messages
+/// * `prefix` - the id or a prefix of it
+///
+/// # Errors
+///
+/// * If no id or more than one id starts with the prefix; the error lists all candidates
+pub(crate) fn find_id(
+    ids: impl IntoIterator<Item = Id>,
+    tpe: FileType,
+    prefix: &str,
+) -> Result<Id> {
+    let candidates: Vec<_> = ids
+        .into_iter()
+        .filter(|id| id.to_hex().starts_with(prefix))
+        .collect();
+    match candidates.as_slice() {
+        [id] => Ok(*id),
+        [] => bail!("no {tpe:?} file with id {prefix} found."),
+        _ => bail!(
+            "id {prefix} is ambiguous, candidates:\n{}",
+            candidates.iter().map(|id| id.to_hex()).join("\n")
+        ),
+    }
+}
+
+/// Write json data pretty-printed, followed by a newline
+fn write_json(out: &mut Output<impl Write>, json: &[u8]) -> Result<()> {
+    let value: serde_json::Value = serde_json::from_slice(json)?;
+    out.json(&value)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_find_id_passes() -> Result<()> {
+        let ids: Vec<Id> = ["12aa", "12bb", "34cc"]
+            .iter()
+            .map(|prefix| Id::from_hex(&format!("{prefix:0<64}")))
+            .collect::<Result<_, _>>()?;
+
+        assert_eq!(find_id(ids.clone(), FileType::Snapshot, "34")?, ids[2]);
+        assert_eq!(find_id(ids.clone(), FileType::Snapshot, "12a")?, ids[0]);
+        let err = find_id(ids.clone(), FileType::Snapshot, "12")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains(&ids[0].to_hex().to_string()));
+        assert!(err.contains(&ids[1].to_hex().to_string()));
+        assert!(find_id(ids, FileType::Snapshot, "56").is_err());
+        Ok(())
+    }
 
+    #[test]
+    fn test_write_json_passes() -> Result<()> {
+        let mut buf = Vec::new();
+        write_json(&mut Output::new(&mut buf), br#"{"a":[1,2]}"#)?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "{\n  \"a\": [\n    1,\n    2\n  ]\n}\n"
+        );
         Ok(())
     }
 }
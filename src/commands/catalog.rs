@@ -0,0 +1,453 @@
+//! `catalog` subcommand: a searchable catalog of the files of all snapshots
+
+use std::{
+    collections::HashSet,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{bail, Result};
+use clap::ValueHint;
+use log::info;
+use rusqlite::{params, Connection, Transaction};
+use sha2::{Digest, Sha256};
+
+use rustic_core::{
+    repofile::{Node, SnapshotFile},
+    IndexedFull, LsOptions, Progress, ProgressBars, Repository, RusticResult,
+};
+
+use crate::{
+    commands::open_repository_indexed,
+    helpers::{bytes_size_to_string, table_with_titles},
+    output::Output,
+    status_err, Application, RUSTIC_APP,
+};
+
+/// Schema of the catalog
+///
+/// Identical entries (same path, mtime, size and content) of different snapshots are stored only
+/// once. `content` is the SHA-256 hash of the ids of the content blobs, i.e. files with identical
+/// contents have the same `content`. Paths are searched using a trigram index.
+pub(crate) const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS snapshots (
+    id TEXT PRIMARY KEY NOT NULL,
+    time TEXT NOT NULL,
+    hostname TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS entries (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL,
+    mtime TEXT,
+    size INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    UNIQUE (path, mtime, size, content)
+);
+CREATE TABLE IF NOT EXISTS snapshot_entries (
+    snapshot TEXT NOT NULL REFERENCES snapshots (id) ON DELETE CASCADE,
+    entry INTEGER NOT NULL REFERENCES entries (id),
+    PRIMARY KEY (snapshot, entry)
+) WITHOUT ROWID;
+CREATE INDEX IF NOT EXISTS snapshot_entries_entry ON snapshot_entries (entry);
+CREATE VIRTUAL TABLE IF NOT EXISTS entries_fts USING fts5 (
+    path, content = 'entries', content_rowid = 'id', tokenize = 'trigram'
+);
+CREATE TRIGGER IF NOT EXISTS entries_insert AFTER INSERT ON entries BEGIN
+    INSERT INTO entries_fts (rowid, path) VALUES (new.id, new.path);
+END;
+CREATE TRIGGER IF NOT EXISTS entries_delete AFTER DELETE ON entries BEGIN
+    INSERT INTO entries_fts (entries_fts, rowid, path) VALUES ('delete', old.id, old.path);
+END;
+";
+
+/// Minimum length of a search pattern, as paths are indexed by trigrams
+const MIN_PATTERN_LEN: usize = 3;
+
+/// `catalog` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct CatalogCmd {
+    /// Subcommand to run
+    #[clap(subcommand)]
+    cmd: CatalogSubCmd,
+}
+
+#[derive(clap::Subcommand, Debug, Runnable)]
+enum CatalogSubCmd {
+    /// Create a new catalog of the files of all snapshots
+    Build(BuildCmd),
+    /// Add new snapshots to the catalog and remove the ones which no longer exist
+    Update(UpdateCmd),
+    /// Search the catalog for files whose path contains a pattern
+    Search(SearchCmd),
+}
+
+impl Runnable for CatalogCmd {
+    fn run(&self) {
+        self.cmd.run();
+    }
+}
+
+/// `catalog build` subcommand
+#[derive(clap::Parser, Debug)]
+pub(crate) struct BuildCmd {
+    /// SQLite database to create. Snapshots are selected by the filter options
+    #[clap(value_name = "OUTPUT", value_hint = ValueHint::FilePath)]
+    output: PathBuf,
+}
+
+impl Runnable for BuildCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl BuildCmd {
+    fn inner_run(&self) -> Result<()> {
+        if self.output.exists() {
+            bail!(
+                "{} already exists. Use `catalog update` to refresh it.",
+                self.output.display()
+            );
+        }
+        update_catalog(&self.output)
+    }
+}
+
+/// `catalog update` subcommand
+#[derive(clap::Parser, Debug)]
+pub(crate) struct UpdateCmd {
+    /// Catalog to update
+    #[clap(long, short, value_name = "PATH", value_hint = ValueHint::FilePath, env = "RUSTIC_CATALOG")]
+    catalog: PathBuf,
+}
+
+impl Runnable for UpdateCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl UpdateCmd {
+    fn inner_run(&self) -> Result<()> {
+        if !self.catalog.exists() {
+            bail!(
+                "{} doesn't exist. Use `catalog build` to create it.",
+                self.catalog.display()
+            );
+        }
+        update_catalog(&self.catalog)
+    }
+}
+
+/// `catalog search` subcommand
+#[derive(clap::Parser, Debug)]
+pub(crate) struct SearchCmd {
+    /// Catalog to search
+    #[clap(long, short, value_name = "PATH", value_hint = ValueHint::FilePath, env = "RUSTIC_CATALOG")]
+    catalog: PathBuf,
+
+    /// Part of the path to search for, e.g. "report.pdf" (at least 3 characters)
+    #[clap(value_name = "PATTERN")]
+    pattern: String,
+}
+
+impl Runnable for SearchCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run(&mut Output::stdout()) {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl SearchCmd {
+    fn inner_run(&self, out: &mut Output<impl Write>) -> Result<()> {
+        let conn = Connection::open(&self.catalog)?;
+        let matches = search(&conn, &self.pattern)?;
+
+        let mut table = table_with_titles(["Path", "Snapshot", "Time", "Size", "Modified"]);
+        for m in &matches {
+            _ = table.add_row([
+                m.path.clone(),
+                m.snapshot.chars().take(8).collect(),
+                m.time.clone(),
+                bytes_size_to_string(m.size),
+                m.mtime.clone().unwrap_or_default(),
+            ]);
+        }
+        out.table(table)?;
+        writeln!(out, "{} match(es)", matches.len())?;
+        Ok(())
+    }
+}
+
+/// A file found in the catalog
+#[derive(Debug, PartialEq, Eq)]
+struct CatalogMatch {
+    /// Path of the file
+    path: String,
+    /// Id of the snapshot containing the file
+    snapshot: String,
+    /// Time of the snapshot
+    time: String,
+    /// Size of the file
+    size: u64,
+    /// Modification time of the file
+    mtime: Option<String>,
+}
+
+/// Synchronize the catalog with the snapshots selected by the filter options
+///
+/// Only snapshots which are not yet contained in the catalog are read.
+///
+/// # Arguments
+///
+/// * `path` - the catalog; it is created if it doesn't exist
+fn update_catalog(path: &Path) -> Result<()> {
+    let config = RUSTIC_APP.config();
+    let repo = open_repository_indexed(&config.repository)?;
+    let snapshots = repo.get_matching_snapshots(|sn| config.snapshot_filter.matches(sn))?;
+
+    let mut conn = Connection::open(path)?;
+    conn.execute_batch(SCHEMA)?;
+    conn.pragma_update(None, "foreign_keys", true)?;
+
+    let cataloged = cataloged_snapshots(&conn)?;
+    let selected: HashSet<_> = snapshots
+        .iter()
+        .map(|sn| sn.id.to_hex().to_string())
+        .collect();
+    let removed: Vec<_> = cataloged.difference(&selected).cloned().collect();
+    let new: Vec<_> = snapshots
+        .iter()
+        .filter(|sn| !cataloged.contains(sn.id.to_hex().as_str()))
+        .collect();
+
+    let p = config
+        .global
+        .progress_options
+        .progress_counter("cataloging snapshots...");
+    p.set_length(new.len() as u64);
+    let tx = conn.transaction()?;
+    remove_snapshots(&tx, &removed)?;
+    let mut files = 0;
+    for sn in &new {
+        files += add_snapshot(&tx, sn, snapshot_files(&repo, sn)?)?;
+        p.inc(1);
+    }
+    tx.commit()?;
+    p.finish();
+
+    info!(
+        "added {} snapshot(s) with {files} file(s), removed {} snapshot(s).",
+        new.len(),
+        removed.len()
+    );
+    Ok(())
+}
+
+/// Get all entries of a snapshot
+fn snapshot_files<'a, P, S: IndexedFull>(
+    repo: &'a Repository<P, S>,
+    sn: &SnapshotFile,
+) -> Result<impl Iterator<Item = RusticResult<(PathBuf, Node)>> + 'a> {
+    let node = repo.node_from_snapshot_and_path(sn, "")?;
+    let mut opts = LsOptions::default();
+    opts.recursive = true;
+    Ok(repo.ls(&node, &opts)?)
+}
+
+/// Get the ids of the snapshots in the catalog
+fn cataloged_snapshots(conn: &Connection) -> Result<HashSet<String>> {
+    let mut stmt = conn.prepare("SELECT id FROM snapshots")?;
+    let ids = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(ids)
+}
+
+/// Add the files of a snapshot to the catalog
+///
+/// # Arguments
+///
+/// * `tx` - the transaction to use
+/// * `sn` - the snapshot
+/// * `entries` - the entries of the snapshot; only files are added
+///
+/// # Returns
+///
+/// The number of added files
+fn add_snapshot(
+    tx: &Transaction<'_>,
+    sn: &SnapshotFile,
+    entries: impl Iterator<Item = RusticResult<(PathBuf, Node)>>,
+) -> Result<usize> {
+    let id = sn.id.to_hex().to_string();
+    _ = tx.execute(
+        "INSERT INTO snapshots (id, time, hostname) VALUES (?1, ?2, ?3)",
+        params![
+            id,
+            sn.time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            sn.hostname
+        ],
+    )?;
+
+    let mut insert_entry = tx.prepare_cached(
+        "INSERT OR IGNORE INTO entries (path, mtime, size, content) VALUES (?1, ?2, ?3, ?4)",
+    )?;
+    let mut select_entry = tx.prepare_cached(
+        "SELECT id FROM entries WHERE path = ?1 AND mtime IS ?2 AND size = ?3 AND content = ?4",
+    )?;
+    let mut insert_ref = tx.prepare_cached(
+        "INSERT OR IGNORE INTO snapshot_entries (snapshot, entry) VALUES (?1, ?2)",
+    )?;
+
+    let mut count = 0;
+    for entry in entries {
+        let (path, node) = entry?;
+        if !node.is_file() {
+            continue;
+        }
+        let path = path.to_string_lossy().to_string();
+        let mtime = node
+            .meta
+            .mtime
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string());
+        let content = content_hash(&node);
+        let entry = params![path, mtime, node.meta.size, content];
+        _ = insert_entry.execute(entry)?;
+        let entry_id: i64 = select_entry.query_row(entry, |row| row.get(0))?;
+        _ = insert_ref.execute(params![id, entry_id])?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Remove snapshots and the entries which are no longer referenced from the catalog
+fn remove_snapshots(tx: &Transaction<'_>, ids: &[String]) -> Result<()> {
+    for id in ids {
+        _ = tx.execute("DELETE FROM snapshots WHERE id = ?1", [id])?;
+    }
+    if !ids.is_empty() {
+        _ = tx.execute(
+            "DELETE FROM entries WHERE id NOT IN (SELECT entry FROM snapshot_entries)",
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+/// Hash of the content of a file, i.e. of the ids of its content blobs
+fn content_hash(node: &Node) -> String {
+    let mut hasher = Sha256::new();
+    for id in node.content.iter().flatten() {
+        hasher.update(id.to_hex().as_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Search the catalog for files whose path contains the pattern
+///
+/// # Errors
+///
+/// * If the pattern is shorter than [`MIN_PATTERN_LEN`] characters
+fn search(conn: &Connection, pattern: &str) -> Result<Vec<CatalogMatch>> {
+    if pattern.chars().count() < MIN_PATTERN_LEN {
+        bail!("the search pattern must have at least {MIN_PATTERN_LEN} characters.");
+    }
+    // search for the pattern as a phrase, so it may contain any characters
+    let phrase = format!("\"{}\"", pattern.replace('"', "\"\""));
+    let mut stmt = conn.prepare(
+        "SELECT e.path, s.id, s.time, e.size, e.mtime
+         FROM entries_fts f
+         JOIN entries e ON e.id = f.rowid
+         JOIN snapshot_entries se ON se.entry = e.id
+         JOIN snapshots s ON s.id = se.snapshot
+         WHERE entries_fts MATCH ?1
+         ORDER BY e.path, s.time",
+    )?;
+    let matches = stmt
+        .query_map([phrase], |row| {
+            Ok(CatalogMatch {
+                path: row.get(0)?,
+                snapshot: row.get(1)?,
+                time: row.get(2)?,
+                size: row.get(3)?,
+                mtime: row.get(4)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::helpers::test_snapshot;
+
+    use pretty_assertions::assert_eq;
+    use rusqlite::OptionalExtension;
+    use rustic_core::{
+        repofile::{Metadata, NodeType},
+        Id,
+    };
+
+    /// A file entry with the given path and size
+    fn file(path: &str, size: u64) -> RusticResult<(PathBuf, Node)> {
+        let meta = Metadata {
+            size,
+            ..Default::default()
+        };
+        let name = Path::new(path).file_name().unwrap().to_string_lossy();
+        let mut node = Node::new(name.to_string(), NodeType::File, meta);
+        node.content = Some(vec![Id::from_hex(&"a".repeat(64)).unwrap()]);
+        Ok((PathBuf::from(path), node))
+    }
+
+    /// Check if a snapshot is contained in the catalog
+    fn is_cataloged(conn: &Connection, id: &str) -> Result<bool> {
+        Ok(conn
+            .query_row("SELECT 1 FROM snapshots WHERE id = ?1", [id], |_| Ok(()))
+            .optional()?
+            .is_some())
+    }
+
+    #[test]
+    fn test_catalog_passes() -> Result<()> {
+        let mut conn = Connection::open_in_memory()?;
+        conn.execute_batch(SCHEMA)?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        let snaps = [test_snapshot('1', 5), test_snapshot('2', 5)];
+
+        let tx = conn.transaction()?;
+        let files = [file("home/report.pdf", 10), file("home/notes.txt", 5)];
+        assert_eq!(add_snapshot(&tx, &snaps[0], files.into_iter())?, 2);
+        assert_eq!(
+            add_snapshot(&tx, &snaps[1], [file("home/report.pdf", 10)].into_iter())?,
+            1
+        );
+        tx.commit()?;
+
+        // identical entries are stored once
+        let entries: u64 = conn.query_row("SELECT COUNT(*) FROM entries", [], |row| row.get(0))?;
+        assert_eq!(entries, 2);
+        assert_eq!(search(&conn, "report.pdf")?.len(), 2);
+        assert_eq!(search(&conn, "notes")?.len(), 1);
+        assert!(search(&conn, "pd").is_err());
+
+        let tx = conn.transaction()?;
+        remove_snapshots(&tx, &[snaps[0].id.to_hex().to_string()])?;
+        tx.commit()?;
+        assert!(!is_cataloged(&conn, snaps[0].id.to_hex().as_str())?);
+        assert!(search(&conn, "notes")?.is_empty());
+        assert_eq!(search(&conn, "report")?.len(), 1);
+        Ok(())
+    }
+}
@@ -1,10 +1,36 @@
 //! `check` subcommand
 
-use crate::{commands::open_repository, status_err, Application, RUSTIC_APP};
+use std::{
+    io::Write,
+    str::FromStr,
+    thread::available_parallelism,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    commands::{get_backends, open_repository, warm_up_packs},
+    events::{self, Event},
+    helpers::bytes_size_to_string,
+    output::Output,
+    status_err, Application, RUSTIC_APP,
+};
 
 use abscissa_core::{Command, Runnable, Shutdown};
-use anyhow::Result;
-use rustic_core::CheckOptions;
+use anyhow::{anyhow, bail, Result};
+use bytesize::ByteSize;
+use chrono::{DateTime, Local};
+use humantime::format_duration;
+use log::{error, info, warn};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use rustic_core::{
+    repofile::{BlobType, FileType},
+    CheckOptions, Id, Progress, ProgressBars, ReadBackend, WriteBackend,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Maximum size of the chunks in which packs are read by `--read-data-subset`
+const READ_CHUNK_SIZE: u64 = 4 * 1024 * 1024;
 
 /// `check` subcommand
 #[derive(clap::Parser, Command, Debug)]
@@ -12,22 +38,591 @@ pub(crate) struct CheckCmd {
     /// Check options
     #[clap(flatten)]
     opts: CheckOptions,
+
+    /// Limit the memory used for reading pack data, e.g. "512MiB": --read-data reduces the number
+    /// of packs processed concurrently, --read-data-subset reads the packs in smaller chunks.
+    /// [default: no limit]
+    #[clap(long, value_name = "SIZE")]
+    max_memory: Option<ByteSize>,
+
+    /// Read and verify a subset of all pack files: either a percentage (e.g. "5%"), a total size
+    /// (e.g. "200GiB") or the n-th of m buckets (e.g. "1/7"). For percentages and sizes, each day
+    /// a different subset is selected such that all packs are eventually checked. [default: 0%]
+    #[clap(long, value_name = "PERCENT|SIZE|n/m", conflicts_with = "read_data")]
+    read_data_subset: Option<ReadDataSubset>,
+
+    /// Number selecting which subset of pack files is read for a percentage or size given by
+    /// --read-data-subset; subsequent numbers select subsequent subsets. [default: the current day]
+    #[clap(long, value_name = "SEED", requires = "read_data_subset")]
+    read_data_subset_seed: Option<u64>,
+
+    /// Show the final report in json format
+    #[clap(long)]
+    json: bool,
+}
+
+/// Kind of the errors logged by the check of `rustic_core`
+///
+/// `rustic_core` reports the errors it finds only as log messages, so neither their kind nor the
+/// affected id, snapshot or path are known.
+const CHECK_ERROR_KIND: &str = "check-error";
+
+/// Final report of the `check` command
+#[derive(Debug, Serialize)]
+struct CheckReport {
+    /// Whether no errors have been found
+    ok: bool,
+    /// Start time of the check
+    time: DateTime<Local>,
+    /// Duration of the check in seconds
+    duration: f64,
+    /// Numbers of the checked items
+    checked: CheckedCounts,
+    /// The errors which have been found
+    errors: Vec<CheckError>,
+}
+
+/// Numbers of the checked items
+#[derive(Debug, Default, Serialize)]
+struct CheckedCounts {
+    /// Number of packs in the index
+    packs: Option<u64>,
+    /// Number of data blobs in the index
+    data_blobs: Option<u64>,
+    /// Number of tree blobs in the index
+    tree_blobs: Option<u64>,
+    /// Whether all pack data has been read
+    read_data: bool,
+    /// Number of read packs, if a subset has been read
+    read_packs: Option<usize>,
+    /// Size of the read packs, if a subset has been read
+    read_bytes: Option<u64>,
+}
+
+/// An error found by the `check` command
+///
+/// Errors found by the checks of `rustic_core` only have a message, see [`CHECK_ERROR_KIND`]. The
+/// referencing snapshots and paths are not known by any check, so they are not reported.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct CheckError {
+    /// Kind of the error, e.g. `pack-hash-mismatch`, or [`CHECK_ERROR_KIND`]
+    kind: &'static str,
+    /// Id of the affected pack, if known
+    id: Option<String>,
+    /// The error message
+    message: String,
+}
+
+impl CheckError {
+    /// Create an error record for an error logged by the check of `rustic_core`
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - the logged message
+    fn logged(message: String) -> Self {
+        Self {
+            kind: CHECK_ERROR_KIND,
+            id: None,
+            message,
+        }
+    }
+}
+
+/// Subset of the pack files to read and verify
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ReadDataSubset {
+    /// Percentage of all pack files
+    Percentage(f64),
+    /// Pack files of about the given total size
+    Size(ByteSize),
+    /// The n-th (starting at 1) of m buckets of pack files
+    Bucket(u64, u64),
+}
+
+impl FromStr for ReadDataSubset {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some((n, m)) = s.split_once('/') {
+            let (n, m): (u64, u64) = (n.trim().parse()?, m.trim().parse()?);
+            if n == 0 || n > m {
+                bail!("bucket must be given as n/m with 1 <= n <= m, got {s}");
+            }
+            return Ok(Self::Bucket(n, m));
+        }
+        // plain numbers are percentages
+        let percentage = match s.strip_suffix('%') {
+            Some(percentage) => percentage.trim().parse::<f64>()?,
+            None => match s.parse::<f64>() {
+                Ok(percentage) => percentage,
+                Err(_) => return Ok(Self::Size(s.parse().map_err(|err| anyhow!("{err}"))?)),
+            },
+        };
+        if !(0.0..=100.0).contains(&percentage) {
+            bail!("percentage must be between 0 and 100, got {s}");
+        }
+        Ok(Self::Percentage(percentage))
+    }
+}
+
+impl ReadDataSubset {
+    /// Select the packs to read
+    ///
+    /// The packs are ordered by a stable hash seeded with the repository id, see [`pack_hash`]. For percentages and sizes,
+    /// the selection is deterministic for a given `run` and subsequent runs select subsequent
+    /// slices of this order. Buckets don't depend on `run`.
+    ///
+    /// # Arguments
+    ///
+    /// * `packs` - all packs with their sizes
+    /// * `seed` - the seed for ordering the packs
+    /// * `run` - the number of the run, e.g. the current day
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn select(self, mut packs: Vec<(Id, u32)>, seed: &Id, run: u64) -> Vec<(Id, u32)> {
+        let hash = |id: &Id| pack_hash(seed, id);
+        if packs.is_empty() {
+            return packs;
+        }
+        packs.sort_by_cached_key(|(id, _)| hash(id));
+
+        match self {
+            Self::Percentage(percentage) => {
+                let count =
+                    ((packs.len() as f64 * percentage / 100.0).ceil() as usize).min(packs.len());
+                if count == 0 {
+                    return Vec::new();
+                }
+                let start = usize::try_from(run % packs.len() as u64).unwrap_or_default() * count;
+                packs.rotate_left(start % packs.len());
+                packs.truncate(count);
+                packs
+            }
+            Self::Size(size) => {
+                let size = size.as_u64();
+                let total: u64 = packs.iter().map(|(_, size)| u64::from(*size)).sum();
+                if size >= total {
+                    return packs;
+                }
+                // select the packs starting within the window of this run; the windows of
+                // subsequent runs are adjacent
+                let offset = (u128::from(run) * u128::from(size) % u128::from(total)) as u64;
+                let mut pos = 0;
+                packs
+                    .into_iter()
+                    .filter(|(_, pack_size)| {
+                        let start = pos;
+                        pos += u64::from(*pack_size);
+                        (start + total - offset) % total < size
+                    })
+                    .collect()
+            }
+            Self::Bucket(n, m) => packs
+                .into_iter()
+                .filter(|(id, _)| hash(id) % m == n - 1)
+                .collect(),
+        }
+    }
+}
+
+/// Hash of a pack id seeded with the repository id
+///
+/// This must not change between rustic versions or platforms, as subsequent runs of
+/// `--read-data-subset` rely on the same order of the packs.
+fn pack_hash(seed: &Id, id: &Id) -> u64 {
+    let digest = Sha256::new()
+        .chain_update(seed.to_hex().to_string())
+        .chain_update(id.to_hex().to_string())
+        .finalize();
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_le_bytes(bytes)
 }
 
 impl Runnable for CheckCmd {
     fn run(&self) {
-        if let Err(err) = self.inner_run() {
-            status_err!("{}", err);
-            RUSTIC_APP.shutdown(Shutdown::Crash);
-        };
+        match self.inner_run(&mut Output::stdout()) {
+            Ok(true) => {}
+            // errors have been found
+            Ok(false) => RUSTIC_APP.shutdown_with_exitcode(Shutdown::Crash, 1),
+            // the check itself failed
+            Err(err) => {
+                status_err!("{}", err);
+                RUSTIC_APP.shutdown_with_exitcode(Shutdown::Crash, 2);
+            }
+        }
     }
 }
 
 impl CheckCmd {
-    fn inner_run(&self) -> Result<()> {
+    /// Run the check and write the final report
+    ///
+    /// # Returns
+    ///
+    /// Whether no errors have been found
+    fn inner_run(&self, out: &mut Output<impl Write>) -> Result<bool> {
         let config = RUSTIC_APP.config();
+        let time = Local::now();
+        let start = Instant::now();
+        let pool = match (self.max_memory, self.opts.read_data) {
+            (Some(max_memory), true) => {
+                let backends = get_backends(&config.repository)?;
+                Some(limited_thread_pool(
+                    backends.repository().as_ref(),
+                    max_memory,
+                )?)
+            }
+            _ => None,
+        };
         let repo = open_repository(&config.repository)?;
-        repo.check(self.opts)?;
+        if self.opts.read_data {
+            warm_up_packs(&repo, || {
+                Ok(get_backends(&config.repository)?
+                    .repository()
+                    .list(FileType::Pack)?)
+            })?;
+        }
+        let (result, logged) = events::collect_errors(|| match &pool {
+            // rustic_core reads the packs in parallel using the thread pool it is called in
+            Some(pool) => pool.install(|| repo.check(self.opts)),
+            None => repo.check(self.opts),
+        });
+        result?;
+        let mut errors: Vec<_> = logged
+            .into_iter()
+            .map(|error| CheckError::logged(error.message))
+            .collect();
+
+        let mut checked = CheckedCounts {
+            read_data: self.opts.read_data,
+            ..Default::default()
+        };
+        if let Some(subset) = self.read_data_subset {
+            let backends = get_backends(&config.repository)?;
+            let run = match self.read_data_subset_seed {
+                Some(seed) => seed,
+                None => u64::try_from(Local::now().timestamp() / 86400)?,
+            };
+            let (packs, size, pack_errors) = check_pack_subset(
+                backends.repository().as_ref(),
+                subset,
+                &repo.config().id,
+                run,
+                self.max_memory,
+                |packs| warm_up_packs(&repo, || Ok(packs)),
+            )?;
+            checked.read_packs = Some(packs);
+            checked.read_bytes = Some(size);
+            errors.extend(pack_errors);
+        }
+
+        if self.json {
+            let index = repo.infos_index()?;
+            checked.packs = Some(index.packs.iter().map(|info| info.count).sum());
+            for info in &index.blobs {
+                match info.blob_type {
+                    BlobType::Data => checked.data_blobs = Some(info.count),
+                    BlobType::Tree => checked.tree_blobs = Some(info.count),
+                }
+            }
+        }
+
+        let duration = start.elapsed();
+        let report = CheckReport {
+            ok: errors.is_empty(),
+            time,
+            duration: duration.as_secs_f64(),
+            checked,
+            errors,
+        };
+        if events::is_enabled() {
+            events::emit(&Event::Summary {
+                command: "check",
+                summary: serde_json::to_value(&report)?,
+            });
+        }
+
+        if self.json {
+            out.json(&report)?;
+        } else {
+            let duration = format_duration(Duration::from_secs(duration.as_secs()));
+            match report.errors.len() {
+                0 => writeln!(out, "check finished in {duration}: no errors found.")?,
+                n => writeln!(out, "check finished in {duration}: {n} errors found.")?,
+            }
+        }
+        Ok(report.ok)
+    }
+}
+
+/// Read a subset of the pack files and verify that their contents match their ids
+///
+/// The packs are read one after another in chunks of at most [`READ_CHUNK_SIZE`], or less if
+/// `max_memory` is smaller, so the whole pack is never held in memory.
+///
+/// # Arguments
+///
+/// * `be` - the backend of the repository
+/// * `subset` - the subset of packs to check
+/// * `seed` - the seed for selecting the packs
+/// * `run` - the number of the run
+/// * `max_memory` - the memory budget for reading
+/// * `warm_up` - warms up the selected packs before they are read
+///
+/// # Returns
+///
+/// The number and total size of the read packs and the errors for damaged packs
+///
+/// # Errors
+///
+/// * If the pack files can't be listed
+fn check_pack_subset(
+    be: &dyn WriteBackend,
+    subset: ReadDataSubset,
+    seed: &Id,
+    run: u64,
+    max_memory: Option<ByteSize>,
+    warm_up: impl FnOnce(Vec<Id>) -> Result<()>,
+) -> Result<(usize, u64, Vec<CheckError>)> {
+    let packs = be.list_with_size(FileType::Pack)?;
+    let total = packs.len();
+    let packs = subset.select(packs, seed, run);
+    warm_up(packs.iter().map(|(id, _)| *id).collect())?;
+    let size: u64 = packs.iter().map(|(_, size)| u64::from(*size)).sum();
+
+    let p = RUSTIC_APP
+        .config()
+        .global
+        .progress_options
+        .progress_bytes("reading pack data...");
+    p.set_length(size);
+    let chunk_size = max_memory.map_or(READ_CHUNK_SIZE, |max_memory| {
+        max_memory.as_u64().clamp(1, READ_CHUNK_SIZE)
+    });
+    let mut errors = Vec::new();
+    for (id, pack_size) in &packs {
+        let error = match pack_matches_id(be, id, *pack_size, chunk_size) {
+            Ok(true) => None,
+            Ok(false) => Some((
+                "pack-hash-mismatch",
+                format!("pack {id}: hash mismatch, the pack file is damaged."),
+            )),
+            Err(err) => Some((
+                "pack-read-error",
+                format!("pack {id}: error reading pack file: {err}"),
+            )),
+        };
+        if let Some((kind, message)) = error {
+            error!("{message}");
+            errors.push(CheckError {
+                kind,
+                id: Some(id.to_hex().to_string()),
+                message,
+            });
+        }
+        p.inc(u64::from(*pack_size));
+    }
+    p.finish();
+
+    info!(
+        "read {} of {total} packs ({}): {} damaged.",
+        packs.len(),
+        bytes_size_to_string(size),
+        errors.len()
+    );
+    Ok((packs.len(), size, errors))
+}
+
+/// Read a pack file in chunks and check whether the hash of its contents matches its id
+///
+/// # Arguments
+///
+/// * `be` - the backend of the repository
+/// * `id` - the id of the pack
+/// * `size` - the size of the pack
+/// * `chunk_size` - the maximum size of the chunks to read
+///
+/// # Errors
+///
+/// * If the pack file can't be read
+fn pack_matches_id(be: &dyn WriteBackend, id: &Id, size: u32, chunk_size: u64) -> Result<bool> {
+    let chunk_size = u32::try_from(chunk_size).unwrap_or(u32::MAX);
+    let mut hasher = Sha256::new();
+    let mut offset = 0;
+    while offset < size {
+        let length = chunk_size.min(size - offset);
+        let data = be.read_partial(FileType::Pack, id, false, offset, length)?;
+        if data.is_empty() {
+            // the pack file is shorter than listed
+            return Ok(false);
+        }
+        hasher.update(&data);
+        offset += u32::try_from(data.len())?;
+    }
+    Ok(format!("{:x}", hasher.finalize()) == id.to_hex().to_string())
+}
+
+/// Create a thread pool limiting the number of packs which are read concurrently such that the
+/// memory budget is met
+///
+/// Reading a pack needs memory for the pack data and for the decrypted blobs, so we assume that
+/// each concurrently processed pack needs twice the size of the largest pack in the repository.
+///
+/// # Arguments
+///
+/// * `be` - the backend of the repository
+/// * `max_memory` - the memory budget
+///
+/// # Errors
+///
+/// * If the pack files can't be listed
+/// * If the thread pool can't be created
+fn limited_thread_pool(be: &dyn WriteBackend, max_memory: ByteSize) -> Result<ThreadPool> {
+    let max_pack_size = be
+        .list_with_size(FileType::Pack)?
+        .into_iter()
+        .map(|(_, size)| u64::from(size))
+        .max()
+        .unwrap_or_default();
+    let per_pack = 2 * max_pack_size;
+    let max_threads = available_parallelism().map_or(1, usize::from);
+    let threads = usize::try_from(max_memory.as_u64() / per_pack.max(1))
+        .unwrap_or(usize::MAX)
+        .clamp(1, max_threads);
+
+    if per_pack > max_memory.as_u64() {
+        warn!(
+            "reading the largest pack may need {}, which exceeds the memory budget of {}.",
+            bytes_size_to_string(per_pack),
+            bytes_size_to_string(max_memory.as_u64())
+        );
+    }
+    info!(
+        "memory budget {}: reading up to {threads} packs concurrently (largest pack: {}).",
+        bytes_size_to_string(max_memory.as_u64()),
+        bytes_size_to_string(max_pack_size)
+    );
+    Ok(ThreadPoolBuilder::new().num_threads(threads).build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeSet;
+
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("0", 0.0)]
+    #[case("5", 5.0)]
+    #[case("12.5%", 12.5)]
+    #[case("100%", 100.0)]
+    fn test_parse_read_data_subset_passes(#[case] s: &str, #[case] expected: f64) -> Result<()> {
+        assert_eq!(
+            s.parse::<ReadDataSubset>()?,
+            ReadDataSubset::Percentage(expected)
+        );
         Ok(())
     }
+
+    #[rstest]
+    #[case("1/7", ReadDataSubset::Bucket(1, 7))]
+    #[case("3/3", ReadDataSubset::Bucket(3, 3))]
+    #[case("200GiB", ReadDataSubset::Size(ByteSize::gib(200)))]
+    #[case("10MB", ReadDataSubset::Size(ByteSize::mb(10)))]
+    fn test_parse_read_data_subset_variants_passes(
+        #[case] s: &str,
+        #[case] expected: ReadDataSubset,
+    ) -> Result<()> {
+        assert_eq!(s.parse::<ReadDataSubset>()?, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("-1")]
+    #[case("101%")]
+    #[case("all")]
+    #[case("0/3")]
+    #[case("4/3")]
+    #[case("1/x")]
+    fn test_parse_read_data_subset_fails(#[case] s: &str) {
+        assert!(s.parse::<ReadDataSubset>().is_err());
+    }
+
+    #[test]
+    fn test_pack_hash_is_stable() -> Result<()> {
+        let seed = Id::from_hex(&"0".repeat(64))?;
+        let id = Id::from_hex(&"0123456789abcdef".repeat(4))?;
+        assert_eq!(pack_hash(&seed, &id), 11_274_709_963_093_685_683);
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_covers_all_packs() {
+        let packs: Vec<_> = (0..10).map(|_| (Id::random(), 1)).collect();
+        let seed = Id::random();
+        let subset = ReadDataSubset::Percentage(30.0);
+
+        // the selection is deterministic
+        assert_eq!(
+            subset.select(packs.clone(), &seed, 7),
+            subset.select(packs.clone(), &seed, 7)
+        );
+
+        let mut checked = BTreeSet::new();
+        for run in 0..4 {
+            let selected = subset.select(packs.clone(), &seed, run);
+            assert_eq!(selected.len(), 3);
+            checked.extend(selected.into_iter().map(|(id, _)| id));
+        }
+        assert_eq!(checked.len(), packs.len());
+
+        assert!(ReadDataSubset::Percentage(0.0)
+            .select(packs.clone(), &seed, 0)
+            .is_empty());
+        assert_eq!(
+            ReadDataSubset::Percentage(100.0)
+                .select(packs.clone(), &seed, 3)
+                .len(),
+            packs.len()
+        );
+    }
+
+    #[test]
+    fn test_select_size_covers_all_packs() {
+        let packs: Vec<_> = (0..10).map(|_| (Id::random(), 100)).collect();
+        let seed = Id::random();
+        let subset = ReadDataSubset::Size(ByteSize::b(250));
+
+        let mut checked = BTreeSet::new();
+        for run in 0..4 {
+            let selected = subset.select(packs.clone(), &seed, run);
+            // the size may be exceeded by less than one pack
+            assert!((2..=3).contains(&selected.len()));
+            checked.extend(selected.into_iter().map(|(id, _)| id));
+        }
+        assert_eq!(checked.len(), packs.len());
+    }
+
+    #[test]
+    fn test_select_buckets_partition_packs() {
+        let packs: Vec<_> = (0..100).map(|_| (Id::random(), 1)).collect();
+        let seed = Id::random();
+
+        let mut checked = BTreeSet::new();
+        for n in 1..=7 {
+            let bucket = ReadDataSubset::Bucket(n, 7);
+            let selected = bucket.select(packs.clone(), &seed, 0);
+            // buckets don't depend on the run
+            assert_eq!(selected, bucket.select(packs.clone(), &seed, 42));
+            for (id, _) in selected {
+                assert!(checked.insert(id));
+            }
+        }
+        assert_eq!(checked.len(), packs.len());
+    }
 }
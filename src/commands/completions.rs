@@ -1,14 +1,26 @@
 //! `completions` subcommand
 
-use abscissa_core::{Command, Runnable};
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{anyhow, Result};
 
-use std::io::Write;
+use std::{io::Write, sync::mpsc, thread::spawn, time::Duration};
 
 use clap::CommandFactory;
 
 use clap_complete::{generate, shells, Generator};
+use humantime::format_duration;
+use itertools::Itertools;
+use rustic_core::{repofile::SnapshotFile, NoProgressBars};
+
+use crate::{
+    commands::get_repository_with_progress, config::get_profile_names, output::Output, status_err,
+    Application, RUSTIC_APP,
+};
 
 /// `completions` subcommand
+///
+/// For bash, zsh and fish, the generated scripts additionally complete snapshot ids and tags of
+/// the configured repository as well as profile names by calling `rustic __complete`.
 #[derive(clap::Parser, Command, Debug)]
 pub(crate) struct CompletionsCmd {
     /// Shell to generate completions for
@@ -22,38 +34,364 @@ pub(super) enum Variant {
     Fish,
     Zsh,
     Powershell,
+    Elvish,
 }
 
 impl Runnable for CompletionsCmd {
     fn run(&self) {
+        let mut stdout = std::io::stdout();
         match self.sh {
-            Variant::Bash => generate_completion(shells::Bash, &mut std::io::stdout()),
-            Variant::Fish => generate_completion(shells::Fish, &mut std::io::stdout()),
-            Variant::Zsh => generate_completion(shells::Zsh, &mut std::io::stdout()),
-            Variant::Powershell => generate_completion(shells::PowerShell, &mut std::io::stdout()),
+            Variant::Bash => generate_completion(shells::Bash, &mut stdout),
+            Variant::Fish => generate_completion(shells::Fish, &mut stdout),
+            Variant::Zsh => generate_completion(shells::Zsh, &mut stdout),
+            Variant::Powershell => generate_completion(shells::PowerShell, &mut stdout),
+            Variant::Elvish => generate_completion(shells::Elvish, &mut stdout),
+        }
+        if let Some(script) = dynamic_completion(&self.sh) {
+            _ = stdout.write_all(script.as_bytes());
         }
     }
 }
 
+/// Subcommands which take snapshot ids as arguments
+const SNAPSHOT_COMMANDS: [&str; 15] = [
+    "acl",
+    "cat",
+    "copy",
+    "diff",
+    "dump",
+    "find",
+    "forget",
+    "ls",
+    "merge",
+    "mount",
+    "repair",
+    "restore",
+    "snapshots",
+    "tag",
+    "webdav",
+];
+
+/// Options which take a list of tags as argument
+const TAG_OPTIONS: [&str; 7] = [
+    "filter-tags",
+    "tag",
+    "add",
+    "remove",
+    "set",
+    "set-tag",
+    "add-tag",
+];
+
+/// Dynamic completion for bash, added after the clap generated completion
+const BASH_DYNAMIC: &str = r#"
+_rustic_dynamic() {
+    _rustic "$@"
+    local cur="${COMP_WORDS[COMP_CWORD]}" prev="${COMP_WORDS[COMP_CWORD-1]}" word
+    case "$prev" in
+        -P|--use-profile)
+            COMPREPLY=($(compgen -W "$(__BIN__ __complete profiles "$cur" 2>/dev/null)" -- "$cur"))
+            return
+            ;;
+        __TAG_OPTIONS__)
+            COMPREPLY=($(compgen -W "$(__BIN__ __complete tags "$cur" 2>/dev/null)" -- "$cur"))
+            return
+            ;;
+    esac
+    [[ "$cur" == -* ]] && return
+    for word in "${COMP_WORDS[@]:1:COMP_CWORD-1}"; do
+        case " __COMMANDS__ " in
+            *" $word "*)
+                COMPREPLY+=($(compgen -W "$(__BIN__ __complete snapshot-ids "$cur" 2>/dev/null)" -- "$cur"))
+                return
+                ;;
+        esac
+    done
+}
+complete -F _rustic_dynamic -o nosort -o bashdefault -o default __BIN__
+"#;
+
+/// Dynamic completion for zsh, added after the clap generated completion
+const ZSH_DYNAMIC: &str = r#"
+_rustic_dynamic() {
+    local cmd index
+    local -a values
+    case "$words[CURRENT-1]" in
+        -P|--use-profile)
+            compadd -- ${(f)"$(__BIN__ __complete profiles "$PREFIX" 2>/dev/null)"}
+            return
+            ;;
+        __TAG_OPTIONS__)
+            compadd -- ${(f)"$(__BIN__ __complete tags "$PREFIX" 2>/dev/null)"}
+            return
+            ;;
+    esac
+    if [[ $PREFIX != -* ]]; then
+        for cmd in __COMMANDS__; do
+            index=${words[(Ie)$cmd]}
+            if (( index > 1 && index < CURRENT )); then
+                values=(${(f)"$(__BIN__ __complete snapshot-ids --describe "$PREFIX" 2>/dev/null)"})
+                values=("${(@)values//$'\t'/:}")
+                _describe -t snapshot-ids 'snapshot id' values
+                break
+            fi
+        done
+    fi
+    _rustic "$@"
+}
+compdef _rustic_dynamic __BIN__
+"#;
+
+/// Dynamic completion for fish, added after the clap generated completion
+const FISH_DYNAMIC: &str = r#"
+complete -c __BIN__ -n "__fish_seen_subcommand_from __COMMANDS__" -a "(__BIN__ __complete snapshot-ids --describe (commandline -ct) 2>/dev/null)"
+complete -c __BIN__ -s P -l use-profile -x -a "(__BIN__ __complete profiles (commandline -ct) 2>/dev/null)"
+complete -c __BIN__ __TAG_OPTIONS__ -x -a "(__BIN__ __complete tags (commandline -ct) 2>/dev/null)"
+"#;
+
+/// Get the script completing snapshot ids, profiles and tags for the given shell, if supported
+fn dynamic_completion(sh: &Variant) -> Option<String> {
+    let (template, tag_options) = match sh {
+        Variant::Bash => (
+            BASH_DYNAMIC,
+            TAG_OPTIONS.map(|opt| format!("--{opt}")).join("|"),
+        ),
+        Variant::Zsh => (
+            ZSH_DYNAMIC,
+            TAG_OPTIONS.map(|opt| format!("--{opt}")).join("|"),
+        ),
+        Variant::Fish => (
+            FISH_DYNAMIC,
+            TAG_OPTIONS.map(|opt| format!("-l {opt}")).join(" "),
+        ),
+        Variant::Powershell | Variant::Elvish => return None,
+    };
+    let command = crate::commands::EntryPoint::command();
+    // some subcommands are only available with certain features
+    let commands: Vec<_> = SNAPSHOT_COMMANDS
+        .into_iter()
+        .filter(|name| command.find_subcommand(name).is_some())
+        .collect();
+    Some(
+        template
+            .replace("__BIN__", bin_name())
+            .replace("__COMMANDS__", &commands.join(" "))
+            .replace("__TAG_OPTIONS__", &tag_options),
+    )
+}
+
+/// Name of the binary to generate completions for
+fn bin_name() -> &'static str {
+    option_env!("CARGO_BIN_NAME").unwrap_or("rustic")
+}
+
 pub fn generate_completion<G: Generator>(shell: G, buf: &mut dyn Write) {
     let mut command = crate::commands::EntryPoint::command();
-    generate(
-        shell,
-        &mut command,
-        option_env!("CARGO_BIN_NAME").unwrap_or("rustic"),
-        buf,
-    );
+    generate(shell, &mut command, bin_name(), buf);
+}
+
+/// `__complete` subcommand: complete values which depend on the repository or the config
+///
+/// This is called by the completion scripts and not meant to be used directly. Values are read
+/// using the cache of the repository and backend operations are not retried; if reading takes
+/// longer than `--max-time`, nothing is completed.
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct CompleteCmd {
+    #[clap(subcommand)]
+    cmd: CompleteSubCmd,
+
+    /// Maximum time to read values from the repository
+    #[clap(long, global = true, value_name = "DURATION", default_value = "1s")]
+    max_time: humantime::Duration,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum CompleteSubCmd {
+    /// Print the snapshot ids starting with the given prefix, one per line
+    SnapshotIds {
+        /// Add the time and host of the snapshots, separated by a tab
+        #[clap(long)]
+        describe: bool,
+
+        /// Prefix of the snapshot ids
+        #[clap(default_value = "")]
+        prefix: String,
+    },
+    /// Print the names of the profiles starting with the given prefix, one per line
+    Profiles {
+        /// Prefix of the profile names
+        #[clap(default_value = "")]
+        prefix: String,
+    },
+    /// Print the tags of all snapshots starting with the given prefix, one per line
+    ///
+    /// The prefix may be a comma separated list; then the last tag of the list is completed.
+    Tags {
+        /// Prefix of the tags
+        #[clap(default_value = "")]
+        prefix: String,
+    },
+}
+
+impl Runnable for CompleteCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run(&mut Output::stdout()) {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl CompleteCmd {
+    fn inner_run(&self, out: &mut Output<impl Write>) -> Result<()> {
+        match &self.cmd {
+            CompleteSubCmd::SnapshotIds { describe, prefix } => {
+                let snapshots = snapshots(*self.max_time)?;
+                let ids = std::iter::once(("latest".to_string(), "latest snapshot".to_string()))
+                    .chain(snapshots.iter().map(|sn| {
+                        let time = sn.time.format("%Y-%m-%d %H:%M:%S");
+                        (sn.id.to_string(), format!("{time} {}", sn.hostname))
+                    }));
+                for (id, description) in ids.filter(|(id, _)| id.starts_with(prefix.as_str())) {
+                    if *describe {
+                        writeln!(out, "{id}\t{description}")?;
+                    } else {
+                        writeln!(out, "{id}")?;
+                    }
+                }
+            }
+            CompleteSubCmd::Profiles { prefix } => {
+                let config_dir = RUSTIC_APP.config().global.config_dir.clone();
+                for name in get_profile_names(config_dir.as_deref())
+                    .into_iter()
+                    .filter(|name| name.starts_with(prefix.as_str()))
+                {
+                    writeln!(out, "{name}")?;
+                }
+            }
+            CompleteSubCmd::Tags { prefix } => {
+                let tags = snapshots(*self.max_time)?
+                    .iter()
+                    .flat_map(|sn| {
+                        sn.tags
+                            .formatln()
+                            .lines()
+                            .map(ToString::to_string)
+                            .collect::<Vec<_>>()
+                    })
+                    .sorted()
+                    .dedup();
+                for tag in complete_list(prefix, tags) {
+                    writeln!(out, "{tag}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Complete the last element of a comma separated list
+///
+/// # Arguments
+///
+/// * `prefix` - the list typed so far
+/// * `values` - the possible values of an element
+///
+/// # Returns
+///
+/// The lists completing `prefix`
+fn complete_list(prefix: &str, values: impl IntoIterator<Item = String>) -> Vec<String> {
+    let values = values.into_iter();
+    match prefix.rsplit_once(',') {
+        Some((head, last)) => values
+            .filter(|value| value.starts_with(last))
+            .map(|value| format!("{head},{value}"))
+            .collect(),
+        None => values.filter(|value| value.starts_with(prefix)).collect(),
+    }
+}
+
+/// Get all snapshots of the configured repository, sorted by time
+///
+/// Completion must never block on a password prompt; if no password is configured, no snapshots
+/// are returned. The snapshots are read in a separate thread, so an error is returned once
+/// `max_time` is exceeded even if the backend hangs.
+///
+/// # Errors
+///
+/// * If the snapshots can't be read or reading them takes longer than `max_time`
+fn snapshots(max_time: Duration) -> Result<Vec<SnapshotFile>> {
+    let (tx, rx) = mpsc::channel();
+    _ = spawn(move || {
+        _ = tx.send(read_snapshots());
+    });
+    match rx.recv_timeout(max_time) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(anyhow!(
+            "reading the snapshots timed out after {}",
+            format_duration(max_time)
+        )),
+        Err(mpsc::RecvTimeoutError::Disconnected) => Err(anyhow!("reading the snapshots panicked")),
+    }
+}
+
+/// Read all snapshots of the configured repository, see [`snapshots`]
+fn read_snapshots() -> Result<Vec<SnapshotFile>> {
+    let mut repo_opts = RUSTIC_APP.config().repository.clone();
+    // fail fast instead of waiting for retries
+    repo_opts.backend_retries = Some(0);
+    let repo = get_repository_with_progress(&repo_opts, NoProgressBars)?;
+    let Some(pass) = repo.password()? else {
+        return Ok(Vec::new());
+    };
+    let repo = repo.open_with_password(&pass)?;
+    let mut snapshots = repo.get_all_snapshots()?;
+    snapshots.sort_unstable();
+    Ok(snapshots)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
     #[test]
     fn test_completions() {
         generate_completion(shells::Bash, &mut std::io::sink());
         generate_completion(shells::Fish, &mut std::io::sink());
         generate_completion(shells::PowerShell, &mut std::io::sink());
         generate_completion(shells::Zsh, &mut std::io::sink());
+        generate_completion(shells::Elvish, &mut std::io::sink());
+    }
+
+    #[test]
+    fn test_dynamic_completion_passes() {
+        for sh in [Variant::Bash, Variant::Zsh, Variant::Fish] {
+            let script = dynamic_completion(&sh).unwrap();
+            assert!(script.contains("rustic __complete snapshot-ids"));
+            assert!(script.contains(" restore "));
+            assert!(script.contains("rustic __complete profiles"));
+            assert!(script.contains("rustic __complete tags"));
+            assert!(
+                !script.contains("__BIN__")
+                    && !script.contains("__COMMANDS__")
+                    && !script.contains("__TAG_OPTIONS__")
+            );
+        }
+        assert!(dynamic_completion(&Variant::Powershell).is_none());
+        assert!(dynamic_completion(&Variant::Elvish).is_none());
+    }
+
+    #[rstest]
+    #[case("", vec!["daily", "weekly"])]
+    #[case("d", vec!["daily"])]
+    #[case("weekly,d", vec!["weekly,daily"])]
+    #[case("a,b,", vec!["a,b,daily", "a,b,weekly"])]
+    #[case("x", vec![])]
+    fn test_complete_list_passes(#[case] prefix: &str, #[case] expected: Vec<&str>) {
+        let values = ["daily", "weekly"].map(ToString::to_string);
+        assert_eq!(complete_list(prefix, values), expected);
     }
 }
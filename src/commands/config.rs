@@ -1,16 +1,33 @@
 //! `config` subcommand
 
-use crate::{commands::open_repository, status_err, Application, RUSTIC_APP};
+use crate::{
+    commands::open_repository, helpers::table_with_titles, output::Output, status_err, Application,
+    RUSTIC_APP,
+};
 
-use abscissa_core::{Command, Runnable, Shutdown};
+use std::io::Write;
 
-use anyhow::Result;
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{bail, Result};
+use bytesize::ByteSize;
+use log::info;
+use serde_json::Value;
 
-use rustic_core::ConfigOptions;
+use rustic_core::{repofile::ConfigFile, ConfigOptions};
 
 /// `config` subcommand
+///
+/// Without any option, the current repository config is printed.
 #[derive(clap::Parser, Command, Debug)]
 pub(crate) struct ConfigCmd {
+    /// Set the target size of data packs (e.g. 64MiB); alias for --set-datapack-size
+    #[clap(long, value_name = "SIZE", conflicts_with = "set_datapack_size")]
+    set_pack_size_data: Option<ByteSize>,
+
+    /// Set the target size of tree packs (e.g. 8MiB); alias for --set-treepack-size
+    #[clap(long, value_name = "SIZE", conflicts_with = "set_treepack_size")]
+    set_pack_size_tree: Option<ByteSize>,
+
     /// Config options
     #[clap(flatten)]
     config_opts: ConfigOptions,
@@ -18,7 +35,7 @@ pub(crate) struct ConfigCmd {
 
 impl Runnable for ConfigCmd {
     fn run(&self) {
-        if let Err(err) = self.inner_run() {
+        if let Err(err) = self.inner_run(&mut Output::stdout()) {
             status_err!("{}", err);
             RUSTIC_APP.shutdown(Shutdown::Crash);
         };
@@ -26,18 +43,149 @@ impl Runnable for ConfigCmd {
 }
 
 impl ConfigCmd {
-    fn inner_run(&self) -> Result<()> {
+    fn inner_run(&self, out: &mut Output<impl Write>) -> Result<()> {
         let config = RUSTIC_APP.config();
         let repo = open_repository(&config.repository)?;
 
-        let changed = repo.apply_config(&self.config_opts)?;
+        let mut opts = self.config_opts.clone();
+        if self.set_pack_size_data.is_some() {
+            opts.set_datapack_size = self.set_pack_size_data;
+        }
+        if self.set_pack_size_tree.is_some() {
+            opts.set_treepack_size = self.set_pack_size_tree;
+        }
 
-        if changed {
-            println!("saved new config");
+        // compute and check the new config before anything is written
+        let old = repo.config().clone();
+        let mut new = old.clone();
+        opts.apply(&mut new)?;
+        check_config(&new)?;
+
+        let old = serde_json::to_value(&old)?;
+        let new = serde_json::to_value(&new)?;
+        let changes = config_changes(&old, &new);
+        if changes.is_empty() {
+            out.table(config_table(&old))?;
+            info!("config is unchanged");
+            return Ok(());
+        }
+
+        let mut table = table_with_titles(["Setting", "Old", "New"]);
+        _ = table.add_rows(changes);
+        out.table(table)?;
+
+        if config.global.dry_run {
+            writeln!(out, "would save the following config:")?;
+            out.table(config_table(&new))?;
         } else {
-            println!("config is unchanged");
+            _ = repo.apply_config(&opts)?;
+            info!("saved new config");
         }
 
         Ok(())
     }
 }
+
+/// Reject config settings which can't be used together
+///
+/// # Arguments
+///
+/// * `config` - the config to check
+///
+/// # Errors
+///
+/// * If compression is set for a repository of version 1
+fn check_config(config: &ConfigFile) -> Result<()> {
+    if config.version == 1 && config.compression.is_some_and(|level| level != 0) {
+        bail!(
+            "compression is not supported by repository version 1, please also use --set-version 2"
+        );
+    }
+    Ok(())
+}
+
+/// Display a value of the config; unset values use the default
+fn display_value(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => "(default)".to_string(),
+        Some(Value::String(s)) => s.clone(),
+        Some(value) => value.to_string(),
+    }
+}
+
+/// Get the settings which differ between two configs
+///
+/// # Arguments
+///
+/// * `old` - the current config, serialized as json
+/// * `new` - the new config, serialized as json
+///
+/// # Returns
+///
+/// The changed settings with their old and new value, sorted by name
+fn config_changes(old: &Value, new: &Value) -> Vec<[String; 3]> {
+    let (Value::Object(old), Value::Object(new)) = (old, new) else {
+        return Vec::new();
+    };
+    let mut keys: Vec<_> = old.keys().chain(new.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    keys.into_iter()
+        .filter_map(|key| {
+            let (old, new) = (old.get(key), new.get(key));
+            (display_value(old) != display_value(new))
+                .then(|| [key.clone(), display_value(old), display_value(new)])
+        })
+        .collect()
+}
+
+/// Table with all settings of a config, serialized as json
+fn config_table(config: &Value) -> comfy_table::Table {
+    let mut table = table_with_titles(["Setting", "Value"]);
+    if let Value::Object(config) = config {
+        for (key, value) in config {
+            _ = table.add_row([key.clone(), display_value(Some(value))]);
+        }
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn test_config_changes_passes() {
+        let old = json!({"version": 2, "compression": 3, "datapack_size": null});
+        let new = json!({"version": 2, "compression": 10, "datapack_size": 67_108_864});
+        assert_eq!(
+            config_changes(&old, &new),
+            vec![
+                ["compression".to_string(), "3".to_string(), "10".to_string()],
+                [
+                    "datapack_size".to_string(),
+                    "(default)".to_string(),
+                    "67108864".to_string()
+                ],
+            ]
+        );
+        assert!(config_changes(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn test_check_config_passes() {
+        let mut config = ConfigFile {
+            version: 2,
+            compression: Some(5),
+            ..Default::default()
+        };
+        assert!(check_config(&config).is_ok());
+        config.version = 1;
+        assert!(check_config(&config).is_err());
+        config.compression = Some(0);
+        assert!(check_config(&config).is_ok());
+    }
+}
@@ -1,19 +1,48 @@
 //! `copy` subcommand
 
 use crate::{
-    commands::{get_repository, init::init_password, open_repository, open_repository_indexed},
-    helpers::table_with_titles,
+    commands::{
+        get_backends, get_repository_with_backends,
+        init::init_password,
+        open_repository_from, open_repository_indexed,
+        snapshots::{sizes::snapshot_packs, snap_to_table},
+        warm_up_packs,
+    },
+    config::{
+        hooks::{is_saved_as, Hooks},
+        AllRepositoryOptions,
+    },
+    helpers::{bytes_size_to_string, table_with_titles},
     status_err, Application, RusticConfig, RUSTIC_APP,
 };
 use abscissa_core::{config::Override, Command, FrameworkError, Runnable, Shutdown};
 use anyhow::{bail, Result};
+use itertools::Itertools;
 use log::{error, info, log, Level};
 use merge::Merge;
+use rayon::ThreadPoolBuilder;
 use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr, PickFirst};
 
-use rustic_core::{CopySnapshot, Id, KeyOptions};
+use std::{
+    collections::BTreeSet,
+    convert::Infallible,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use bytes::Bytes;
+
+use rustic_core::{
+    repofile::{FileType, SnapshotFile},
+    CopySnapshot, Id, KeyOptions, ReadBackend, RepositoryBackends, StringList, WriteBackend,
+};
 
 /// `copy` subcommand
+#[serde_as]
 #[derive(clap::Parser, Command, Default, Clone, Debug, Serialize, Deserialize, Merge)]
 pub struct CopyCmd {
     /// Snapshots to copy. If none is given, use filter options to filter from all snapshots.
@@ -22,16 +51,39 @@ pub struct CopyCmd {
     #[merge(skip)]
     ids: Vec<String>,
 
+    /// Snapshot to copy (can be specified multiple times). Bypasses the snapshot filter options.
+    #[clap(long = "snapshot", short = 's', value_name = "ID")]
+    #[serde(skip)]
+    #[merge(skip)]
+    snapshots: Vec<String>,
+
     /// Initialize non-existing target repositories
     #[clap(long)]
     #[serde(skip)]
     #[merge(skip)]
     init: bool,
 
+    /// Show the copied and skipped snapshots of all targets in json format
+    #[clap(long)]
+    #[serde(skip)]
+    #[merge(skip)]
+    json: bool,
+
     /// Target repository (can be specified multiple times)
     #[clap(long = "target", value_name = "TARGET")]
+    #[serde_as(as = "Vec<PickFirst<(_, DisplayFromStr)>>")]
     #[merge(strategy = merge::vec::overwrite_empty)]
-    targets: Vec<String>,
+    targets: Vec<CopyTarget>,
+
+    /// Number of threads used to copy blobs [default: number of CPUs]
+    #[clap(long, value_name = "N")]
+    copy_threads: Option<usize>,
+
+    /// Options to modify the copied snapshots in all targets
+    #[clap(flatten, next_help_heading = "Modify copied snapshots")]
+    #[serde(skip)]
+    #[merge(skip)]
+    modify: ModifyOptions,
 
     /// Key options (when using --init)
     #[clap(flatten, next_help_heading = "Key options (when using --init)")]
@@ -40,6 +92,134 @@ pub struct CopyCmd {
     key_opts: KeyOptions,
 }
 
+/// Target of the copy command
+///
+/// In the config file, a target is either given by its config profile or as table which also
+/// contains options to modify the snapshots copied to this target.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CopyTarget {
+    /// Config profile of the target repository
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    profile: String,
+
+    /// Named repository (see `[repositories]`) to use as target instead of a profile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    repository: Option<String>,
+
+    /// Options to modify the snapshots copied to this target
+    #[serde(flatten)]
+    modify: ModifyOptions,
+}
+
+impl FromStr for CopyTarget {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            profile: s.to_string(),
+            repository: None,
+            modify: ModifyOptions::default(),
+        })
+    }
+}
+
+impl std::fmt::Display for CopyTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.repository {
+            Some(name) => write!(f, "repository {name}"),
+            None => f.write_str(&self.profile),
+        }
+    }
+}
+
+impl CopyTarget {
+    /// Get the repository options and the hooks of the target, either from its profile or from
+    /// the named repository of the current config. Named repositories use the hooks of the
+    /// current config.
+    ///
+    /// # Errors
+    ///
+    /// * If both or none of profile and repository are given
+    /// * If the named repository is not defined
+    fn repository_options(&self, config: &RusticConfig) -> Result<(AllRepositoryOptions, Hooks)> {
+        let (mut repo_opts, hooks) = match (&self.repository, self.profile.is_empty()) {
+            (Some(name), true) => (config.named_repository(name)?, config.hooks.clone()),
+            (None, false) => {
+                let mut merge_logs = Vec::new();
+                let mut target_config = RusticConfig::default();
+                target_config.merge_profile(&self.profile, &mut merge_logs, Level::Error)?;
+                // display logs from merging
+                for (level, merge_log) in merge_logs {
+                    log!(level, "{}", merge_log);
+                }
+                (target_config.repository, target_config.hooks)
+            }
+            (Some(name), false) => {
+                bail!("copy target {name}: only one of profile and repository may be given.")
+            }
+            (None, true) => bail!("copy target without profile or repository."),
+        };
+        repo_opts.apply_backend_options();
+        Ok((repo_opts, hooks))
+    }
+}
+
+/// Options to modify the copied snapshots. Only the snapshots in the target are modified, the
+/// `original` field still references the source snapshot.
+#[serde_as]
+#[derive(Clone, Debug, Default, clap::Parser, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ModifyOptions {
+    /// Tag list to set in the copied snapshots (can be specified multiple times)
+    #[clap(long = "set-tag", value_name = "TAG[,TAG,..]")]
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    set_tags: Vec<StringList>,
+
+    /// Tags to add to the copied snapshots (can be specified multiple times)
+    #[clap(long = "add-tag", value_name = "TAG[,TAG,..]")]
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    add_tags: Vec<StringList>,
+
+    /// Hostname to set in the copied snapshots
+    #[clap(long, value_name = "HOSTNAME")]
+    set_hostname: Option<String>,
+}
+
+impl ModifyOptions {
+    /// Combine the options given on the command line with the options of a target.
+    /// Tags to set and the hostname given on the command line take precedence.
+    fn with_target(&self, target: &Self) -> Self {
+        let set_tags = if self.set_tags.is_empty() {
+            target.set_tags.clone()
+        } else {
+            self.set_tags.clone()
+        };
+        Self {
+            set_tags,
+            add_tags: target
+                .add_tags
+                .iter()
+                .chain(&self.add_tags)
+                .cloned()
+                .collect(),
+            set_hostname: self
+                .set_hostname
+                .clone()
+                .or_else(|| target.set_hostname.clone()),
+        }
+    }
+
+    /// Apply the options to a snapshot
+    fn modify(&self, mut sn: SnapshotFile) -> SnapshotFile {
+        _ = sn.modify_sn(self.set_tags.clone(), self.add_tags.clone(), &[], &None);
+        if let Some(hostname) = &self.set_hostname {
+            sn.hostname.clone_from(hostname);
+        }
+        sn
+    }
+}
+
 impl Override<RusticConfig> for CopyCmd {
     // Process the given command line options, overriding settings from
     // a configuration file using explicit flags taken from command-line
@@ -63,6 +243,16 @@ impl Runnable for CopyCmd {
 }
 
 impl CopyCmd {
+    /// Get all explicitly given snapshot ids, either as argument or using `--snapshot`
+    fn snapshot_ids(&self) -> Vec<&str> {
+        self.ids
+            .iter()
+            .chain(&self.snapshots)
+            .map(String::as_str)
+            .unique()
+            .collect()
+    }
+
     fn inner_run(&self) -> Result<()> {
         let config = RUSTIC_APP.config();
 
@@ -70,27 +260,43 @@ impl CopyCmd {
             bail!("No target given. Please specify at least 1 target either in the profile or using --target!");
         }
 
+        match config.copy.copy_threads {
+            Some(0) => bail!("copy-threads must be at least 1"),
+            Some(threads) => {
+                info!("copying blobs using {threads} threads.");
+                // rustic_core copies the blobs in parallel using the thread pool it is called in
+                let pool = ThreadPoolBuilder::new().num_threads(threads).build()?;
+                pool.install(|| self.copy())
+            }
+            None => self.copy(),
+        }
+    }
+
+    /// Copy the snapshots to all targets
+    fn copy(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
         let repo = open_repository_indexed(&config.repository)?;
-        let mut snapshots = if self.ids.is_empty() {
+        let ids = self.snapshot_ids();
+        let mut snapshots = if ids.is_empty() {
             repo.get_matching_snapshots(|sn| config.snapshot_filter.matches(sn))?
         } else {
-            repo.get_snapshots(&self.ids)?
+            // fails if any of the given ids is not found, so nothing is copied in that case
+            repo.get_snapshots(&ids)?
         };
         // sort for nicer output
         snapshots.sort_unstable();
 
         let poly = repo.config().poly()?;
+        // snapshots which would be copied in dry-run mode, together with the target name
+        let mut would_copy = Vec::new();
+        let mut reports = Vec::new();
         for target in &config.copy.targets {
-            let mut merge_logs = Vec::new();
-            let mut target_config = RusticConfig::default();
-            target_config.merge_profile(target, &mut merge_logs, Level::Error)?;
-            // display logs from merging
-            for (level, merge_log) in merge_logs {
-                log!(level, "{}", merge_log);
-            }
-            let target_opt = &target_config.repository;
+            let (target_opt, target_hooks) = &target.repository_options(&config)?;
 
-            let repo_dest = get_repository(target_opt)?;
+            // the size of the written packs is counted by the target's backend
+            let written = Arc::new(AtomicU64::new(0));
+            let backends = count_written_packs(get_backends(target_opt)?, &written);
+            let repo_dest = get_repository_with_backends(target_opt, &backends)?;
 
             info!("copying to target {}...", repo_dest.name);
             let repo_dest = if self.init && repo_dest.config_id()?.is_none() {
@@ -106,52 +312,218 @@ impl CopyCmd {
                 let pass = init_password(&repo_dest)?;
                 repo_dest.init_with_config(&pass, &self.key_opts, config_dest)?
             } else {
-                open_repository(target_opt)?
+                open_repository_from(repo_dest)?
             };
 
             if poly != repo_dest.config().poly()? {
                 bail!("cannot copy to repository with different chunker parameter (re-chunking not implemented)!");
             }
 
-            let snaps = repo_dest.relevant_copy_snapshots(
-                |sn| !self.ids.is_empty() || config.snapshot_filter.matches(sn),
-                &snapshots,
-            )?;
-
-            let mut table =
-                table_with_titles(["ID", "Time", "Host", "Label", "Tags", "Paths", "Status"]);
-            for CopySnapshot { relevant, sn } in snaps.iter() {
-                let tags = sn.tags.formatln();
-                let paths = sn.paths.formatln();
-                let time = sn.time.format("%Y-%m-%d %H:%M:%S").to_string();
-                _ = table.add_row([
-                    &sn.id.to_string(),
-                    &time,
-                    &sn.hostname,
-                    &sn.label,
-                    &tags,
-                    &paths,
-                    &(if *relevant { "to copy" } else { "existing" }).to_string(),
-                ]);
+            // existing copies are identified by their original, as they may be modified such
+            // that they don't match the snapshot filter
+            let originals: BTreeSet<_> = repo_dest
+                .get_all_snapshots()?
+                .into_iter()
+                .map(|sn| sn.original.unwrap_or(sn.id))
+                .collect();
+
+            // the source snapshots are only modified in memory; ids are kept such that the
+            // copied snapshots reference the source snapshots as original
+            let modify = self.modify.with_target(&target.modify);
+            let snaps: Vec<_> = snapshots
+                .iter()
+                .map(|sn| CopySnapshot {
+                    relevant: !originals.contains(&sn.original.unwrap_or(sn.id)),
+                    sn: modify.modify(sn.clone()),
+                })
+                .collect();
+
+            if !self.json {
+                let mut table =
+                    table_with_titles(["ID", "Time", "Host", "Label", "Tags", "Paths", "Status"]);
+                for CopySnapshot { relevant, sn } in snaps.iter() {
+                    let tags = sn.tags.formatln();
+                    let paths = sn.paths.formatln();
+                    let time = sn.time.format("%Y-%m-%d %H:%M:%S").to_string();
+                    _ = table.add_row([
+                        &sn.id.to_string(),
+                        &time,
+                        &sn.hostname,
+                        &sn.label,
+                        &tags,
+                        &paths,
+                        &(if *relevant { "to copy" } else { "existing" }).to_string(),
+                    ]);
+                }
+                println!("{table}");
             }
-            println!("{table}");
 
-            let count = snaps.iter().filter(|sn| sn.relevant).count();
+            let (to_copy, existing): (Vec<_>, Vec<_>) =
+                snaps.into_iter().partition(|sn| sn.relevant);
+            let to_copy: Vec<_> = to_copy
+                .into_iter()
+                .map(|CopySnapshot { sn, .. }| sn)
+                .collect();
+            let mut report = CopyReport {
+                target: repo_dest.name.clone(),
+                dry_run: config.global.dry_run,
+                copied: Vec::new(),
+                existing: existing
+                    .into_iter()
+                    .map(|CopySnapshot { sn, .. }| sn)
+                    .collect(),
+                bytes_transferred: 0,
+            };
+            let count = to_copy.len();
             if count > 0 {
+                warm_up_packs(&repo, || snapshot_packs(&repo, &to_copy))?;
                 if config.global.dry_run {
                     info!("would have copied {count} snapshots.");
-                } else {
-                    repo.copy(
-                        &repo_dest.to_indexed_ids()?,
-                        snaps
+                    would_copy.extend(
+                        to_copy
                             .iter()
-                            .filter_map(|CopySnapshot { relevant, sn }| relevant.then_some(sn)),
-                    )?;
+                            .map(|sn| (repo_dest.name.clone(), sn.clone())),
+                    );
+                } else {
+                    let written_before = written.load(Ordering::Relaxed);
+                    // hooks of the target repository are called for the copied snapshots
+                    let is_created =
+                        |saved: &SnapshotFile| to_copy.iter().any(|sn| is_saved_as(saved, sn));
+                    target_hooks.track_snapshots(&repo_dest, is_created, &[], || {
+                        Ok(repo.copy(&repo_dest.clone().to_indexed_ids()?, to_copy.iter())?)
+                    })?;
+                    report.bytes_transferred = written.load(Ordering::Relaxed) - written_before;
+                    info!(
+                        "copied {count} snapshots to {}, transferred {}.",
+                        repo_dest.name,
+                        bytes_size_to_string(report.bytes_transferred)
+                    );
                 }
             } else {
                 info!("nothing to copy.");
             }
+            report.copied = to_copy;
+            reports.push(report);
+        }
+
+        if self.json {
+            let mut stdout = std::io::stdout();
+            serde_json::to_writer_pretty(&mut stdout, &reports)?;
+        } else if !would_copy.is_empty() {
+            print_would_copy(&would_copy);
+        }
+
+        Ok(())
+    }
+}
+
+/// Report of the snapshots copied to a target
+#[derive(Debug, Serialize)]
+struct CopyReport {
+    /// Name of the target repository
+    target: String,
+    /// Whether the snapshots have only been selected for copying
+    dry_run: bool,
+    /// Snapshots which have been copied, or would be copied in dry-run mode
+    copied: Vec<SnapshotFile>,
+    /// Snapshots which already exist in the target and have been skipped
+    existing: Vec<SnapshotFile>,
+    /// Size of the pack files written to the target
+    bytes_transferred: u64,
+}
+
+/// A backend counting the size of the written pack files
+struct PackCountingBackend {
+    /// the wrapped backend
+    be: Arc<dyn WriteBackend>,
+    /// the total size of the written pack files
+    written: Arc<AtomicU64>,
+}
+
+impl ReadBackend for PackCountingBackend {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> Result<Vec<(Id, u32)>> {
+        self.be.list_with_size(tpe)
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> Result<Bytes> {
+        self.be.read_full(tpe, id)
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> Result<Bytes> {
+        self.be.read_partial(tpe, id, cacheable, offset, length)
+    }
+
+    fn needs_warm_up(&self) -> bool {
+        self.be.needs_warm_up()
+    }
+
+    fn warm_up(&self, tpe: FileType, id: &Id) -> Result<()> {
+        self.be.warm_up(tpe, id)
+    }
+}
+
+impl WriteBackend for PackCountingBackend {
+    fn create(&self) -> Result<()> {
+        self.be.create()
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> Result<()> {
+        let len = buf.len() as u64;
+        self.be.write_bytes(tpe, id, cacheable, buf)?;
+        if tpe == FileType::Pack {
+            _ = self.written.fetch_add(len, Ordering::Relaxed);
         }
         Ok(())
     }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> Result<()> {
+        self.be.remove(tpe, id, cacheable)
+    }
+}
+
+/// Count the size of the pack files written to the repository; the copies of tree packs in the
+/// hot repository are not counted
+///
+/// # Arguments
+///
+/// * `backends` - the backends to wrap
+/// * `written` - the counter for the size of the written pack files
+fn count_written_packs(
+    backends: RepositoryBackends,
+    written: &Arc<AtomicU64>,
+) -> RepositoryBackends {
+    RepositoryBackends::new(
+        Arc::new(PackCountingBackend {
+            be: backends.repository(),
+            written: written.clone(),
+        }),
+        backends.repo_hot(),
+    )
+}
+
+/// Print a summary of the snapshots which would be copied in dry-run mode
+///
+/// # Arguments
+///
+/// * `would_copy` - target names and the snapshots which would be copied to them
+fn print_would_copy(would_copy: &[(String, SnapshotFile)]) {
+    let mut table = table_with_titles(["ID", "Time", "Host", "Paths", "Target"]);
+    for (target, sn) in would_copy {
+        let [id, time, host, _label, _tags, paths, ..] = snap_to_table(sn, 0);
+        _ = table.add_row([id, time, host, paths, target.clone()]);
+    }
+    println!();
+    println!("would copy:");
+    println!("{table}");
 }
@@ -1,16 +1,21 @@
 //! `diff` subcommand
 
-use crate::{commands::open_repository_indexed, status_err, Application, RUSTIC_APP};
+use crate::{
+    commands::open_repository_indexed, output::Output, status_err, Application, RUSTIC_APP,
+};
 
 use abscissa_core::{Command, Runnable, Shutdown};
 use clap::ValueHint;
 
 use std::{
+    borrow::Cow,
     fmt::Display,
+    io::Write,
     path::{Path, PathBuf},
 };
 
 use anyhow::{bail, Context, Result};
+use serde::Serialize;
 
 use rustic_core::{
     repofile::{BlobType, Node, NodeType},
@@ -33,10 +38,15 @@ pub(crate) struct DiffCmd {
     #[clap(long)]
     metadata: bool,
 
-    /// don't check for different file contents
+    /// don't check for different file contents, but detect modified files by their size, mtime
+    /// and mode
     #[clap(long)]
     no_content: bool,
 
+    /// show one json object per change, followed by a summary object
+    #[clap(long)]
+    json: bool,
+
     /// Ignore options
     #[clap(flatten)]
     ignore_opts: LocalSourceFilterOptions,
@@ -44,7 +54,7 @@ pub(crate) struct DiffCmd {
 
 impl Runnable for DiffCmd {
     fn run(&self) {
-        if let Err(err) = self.inner_run() {
+        if let Err(err) = self.inner_run(&mut Output::stdout()) {
             status_err!("{}", err);
             RUSTIC_APP.shutdown(Shutdown::Crash);
         };
@@ -52,7 +62,7 @@ impl Runnable for DiffCmd {
 }
 
 impl DiffCmd {
-    fn inner_run(&self) -> Result<()> {
+    fn inner_run(&self, out: &mut Output<impl Write>) -> Result<()> {
         let config = RUSTIC_APP.config();
         let repo = open_repository_indexed(&config.repository)?;
 
@@ -71,11 +81,13 @@ impl DiffCmd {
                 let node2 = repo.node_from_snapshot_and_path(snap2, path2)?;
 
                 diff(
+                    out,
                     repo.ls(&node1, &LsOptions::default())?,
                     repo.ls(&node2, &LsOptions::default())?,
                     self.no_content,
                     |_path, node1, node2| Ok(node1.content == node2.content),
                     self.metadata,
+                    self.json,
                 )?;
             }
             (Some(id1), None) => {
@@ -109,11 +121,13 @@ impl DiffCmd {
                 });
 
                 diff(
+                    out,
                     repo.ls(&node1, &LsOptions::default())?,
                     src,
                     self.no_content,
                     |path, node1, _node2| identical_content_local(&local, &repo, path, node1),
                     self.metadata,
+                    self.json,
                 )?;
             }
             (None, _) => {
@@ -168,7 +182,7 @@ fn arg_to_snap_path<'a>(arg: &'a str, default_path: &'a str) -> (Option<&'a str>
 /// `false` otherwise
 ///
 /// [`RepositoryErrorKind::IdNotFound`]: rustic_core::error::RepositoryErrorKind::IdNotFound
-fn identical_content_local<P, S: IndexedFull>(
+pub(crate) fn identical_content_local<P, S: IndexedFull>(
     local: &LocalDestination,
     repo: &Repository<P, S>,
     path: &Path,
@@ -188,8 +202,50 @@ fn identical_content_local<P, S: IndexedFull>(
     Ok(true)
 }
 
+/// Kind of a change listed with the [`DiffCmd`] command
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Change {
+    /// The entry has been added
+    Added,
+    /// The entry has been removed
+    Removed,
+    /// The content of the file or the target of the symlink has been modified
+    Modified,
+    /// The type of the entry has been changed
+    TypeChanged,
+    /// Only the metadata of the file has been changed
+    MetadataChanged,
+}
+
+impl Change {
+    /// Prefix of the change in the text output
+    const fn prefix(self) -> char {
+        match self {
+            Self::Added => '+',
+            Self::Removed => '-',
+            Self::Modified => 'M',
+            Self::TypeChanged => 'T',
+            Self::MetadataChanged => 'U',
+        }
+    }
+}
+
+/// A change as shown in the json output
+#[derive(Serialize)]
+struct ChangeRecord<'a> {
+    /// Kind of the change
+    change: Change,
+    /// Path of the changed entry
+    path: Cow<'a, str>,
+    /// Size of the entry in the first snapshot, if it exists there
+    size1: Option<u64>,
+    /// Size of the entry in the second snapshot or local path, if it exists there
+    size2: Option<u64>,
+}
+
 /// Statistics about the differences listed with the [`DiffCmd`] command
-#[derive(Default)]
+#[derive(Default, Serialize)]
 struct DiffStatistics {
     files_added: usize,
     files_removed: usize,
@@ -203,6 +259,8 @@ struct DiffStatistics {
     symlink_added: usize,
     symlink_removed: usize,
     symlink_changed: usize,
+    bytes_added: u64,
+    bytes_removed: u64,
 }
 
 impl DiffStatistics {
@@ -239,6 +297,15 @@ impl DiffStatistics {
     fn changed_symlink(&mut self) {
         self.symlink_changed += 1;
     }
+
+    fn changed_size(&mut self, size1: Option<u64>, size2: Option<u64>) {
+        let (size1, size2) = (size1.unwrap_or_default(), size2.unwrap_or_default());
+        if size2 > size1 {
+            self.bytes_added += size2 - size1;
+        } else {
+            self.bytes_removed += size1 - size2;
+        }
+    }
 }
 
 impl Display for DiffStatistics {
@@ -284,52 +351,132 @@ impl Display for DiffStatistics {
     }
 }
 
+/// Printer for the changes listed with the [`DiffCmd`] command, which also collects the statistics
+struct DiffPrinter<'a, W> {
+    /// the output to print to
+    out: &'a mut W,
+    /// print json objects, one per line
+    json: bool,
+    /// statistics about the printed changes
+    stats: DiffStatistics,
+}
+
+impl<W: Write> DiffPrinter<'_, W> {
+    /// Print a change
+    ///
+    /// # Arguments
+    ///
+    /// * `change` - the kind of the change
+    /// * `path` - the path of the changed entry
+    /// * `node1` - the entry in the first tree, if it exists there
+    /// * `node2` - the entry in the second tree, if it exists there
+    fn print(
+        &mut self,
+        change: Change,
+        path: &Path,
+        node1: Option<&Node>,
+        node2: Option<&Node>,
+    ) -> Result<()> {
+        let (size1, size2) = (node1.map(|n| n.meta.size), node2.map(|n| n.meta.size));
+        self.stats.changed_size(size1, size2);
+        if self.json {
+            let record = ChangeRecord {
+                change,
+                path: path.to_string_lossy(),
+                size1,
+                size2,
+            };
+            serde_json::to_writer(&mut *self.out, &record)?;
+            writeln!(self.out)?;
+        } else {
+            writeln!(self.out, "{}    {path:?}", change.prefix())?;
+        }
+        Ok(())
+    }
+
+    /// Print the statistics
+    fn finish(mut self) -> Result<()> {
+        if self.json {
+            /// The statistics as shown in the json output
+            #[derive(Serialize)]
+            struct Summary<'a> {
+                summary: &'a DiffStatistics,
+            }
+            serde_json::to_writer(
+                &mut *self.out,
+                &Summary {
+                    summary: &self.stats,
+                },
+            )?;
+            writeln!(self.out)?;
+        } else {
+            writeln!(self.out, "{}", self.stats)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check if the metadata relevant for detecting modified files differs
+fn metadata_differs(node1: &Node, node2: &Node) -> bool {
+    node1.meta.size != node2.meta.size
+        || node1.meta.mtime != node2.meta.mtime
+        || node1.meta.mode != node2.meta.mode
+}
+
 /// Compare two streams of nodes and print the differences
 ///
 /// # Arguments
 ///
+/// * `out` - the output to print to
 /// * `tree_streamer1` - first stream of nodes
 /// * `tree_streamer2` - second stream of nodes
-/// * `no_content` - don't check for different file contents
+/// * `no_content` - don't check for different file contents, but compare size, mtime and mode
 /// * `file_identical` - function to check if the content of two files is identical
 /// * `metadata` - show differences in metadata
+/// * `json` - print the changes and the statistics as json objects, one per line
 ///
 /// # Errors
 ///
 // TODO!: add errors!
 fn diff(
+    out: &mut impl Write,
     mut tree_streamer1: impl Iterator<Item = RusticResult<(PathBuf, Node)>>,
     mut tree_streamer2: impl Iterator<Item = RusticResult<(PathBuf, Node)>>,
     no_content: bool,
     file_identical: impl Fn(&Path, &Node, &Node) -> Result<bool>,
     metadata: bool,
+    json: bool,
 ) -> Result<()> {
     let mut item1 = tree_streamer1.next().transpose()?;
     let mut item2 = tree_streamer2.next().transpose()?;
 
-    let mut diff_statistics = DiffStatistics::default();
+    let mut printer = DiffPrinter {
+        out,
+        json,
+        stats: DiffStatistics::default(),
+    };
 
     loop {
         match (&item1, &item2) {
             (None, None) => break,
             (Some(i1), None) => {
-                println!("-    {:?}", i1.0);
-                diff_statistics.removed_node(&i1.1.node_type);
+                printer.print(Change::Removed, &i1.0, Some(&i1.1), None)?;
+                printer.stats.removed_node(&i1.1.node_type);
                 item1 = tree_streamer1.next().transpose()?;
             }
             (None, Some(i2)) => {
-                println!("+    {:?}", i2.0);
-                diff_statistics.added_node(&i2.1.node_type);
+                printer.print(Change::Added, &i2.0, None, Some(&i2.1))?;
+                printer.stats.added_node(&i2.1.node_type);
                 item2 = tree_streamer2.next().transpose()?;
             }
             (Some(i1), Some(i2)) if i1.0 < i2.0 => {
-                println!("-    {:?}", i1.0);
-                diff_statistics.removed_node(&i1.1.node_type);
+                printer.print(Change::Removed, &i1.0, Some(&i1.1), None)?;
+                printer.stats.removed_node(&i1.1.node_type);
                 item1 = tree_streamer1.next().transpose()?;
             }
             (Some(i1), Some(i2)) if i1.0 > i2.0 => {
-                println!("+    {:?}", i2.0);
-                diff_statistics.added_node(&i2.1.node_type);
+                printer.print(Change::Added, &i2.0, None, Some(&i2.1))?;
+                printer.stats.added_node(&i2.1.node_type);
                 item2 = tree_streamer2.next().transpose()?;
             }
             (Some(i1), Some(i2)) => {
@@ -345,21 +492,30 @@ fn diff(
                     // that their type is different AND that they are not both symlinks
                     tpe if tpe != &node2.node_type && !are_both_symlink => {
                         // type was changed
-                        println!("T    {path:?}");
-                        diff_statistics.changed_node_type();
+                        printer.print(Change::TypeChanged, path, Some(node1), Some(node2))?;
+                        printer.stats.changed_node_type();
+                    }
+                    NodeType::File if no_content && metadata_differs(node1, node2) => {
+                        printer.print(Change::Modified, path, Some(node1), Some(node2))?;
+                        printer.stats.changed_file();
                     }
                     NodeType::File if !no_content && !file_identical(path, node1, node2)? => {
-                        println!("M    {path:?}");
-                        diff_statistics.changed_file();
+                        printer.print(Change::Modified, path, Some(node1), Some(node2))?;
+                        printer.stats.changed_file();
                     }
                     NodeType::File if metadata && node1.meta != node2.meta => {
-                        println!("U    {path:?}");
-                        diff_statistics.changed_metadata();
+                        printer.print(Change::MetadataChanged, path, Some(node1), Some(node2))?;
+                        printer.stats.changed_metadata();
                     }
                     NodeType::Symlink { .. } => {
                         if node1.node_type.to_link() != node2.node_type.to_link() {
-                            println!("U    {path:?}");
-                            diff_statistics.changed_symlink();
+                            printer.print(
+                                Change::MetadataChanged,
+                                path,
+                                Some(node1),
+                                Some(node2),
+                            )?;
+                            printer.stats.changed_symlink();
                         }
                     }
                     _ => {} // no difference to show
@@ -369,6 +525,92 @@ fn diff(
             }
         }
     }
-    println!("{diff_statistics}");
-    Ok(())
+    printer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use rustic_core::repofile::Metadata;
+
+    fn entry(name: &str, node_type: NodeType, size: u64) -> RusticResult<(PathBuf, Node)> {
+        let meta = Metadata {
+            size,
+            ..Default::default()
+        };
+        Ok((name.into(), Node::new(name.to_string(), node_type, meta)))
+    }
+
+    #[test]
+    fn test_diff_output_passes() -> Result<()> {
+        let nodes1 = vec![
+            entry("a", NodeType::File, 0),
+            entry("b", NodeType::Dir, 0),
+            entry("c", NodeType::File, 1),
+            entry("e", NodeType::File, 0),
+        ];
+        let nodes2 = vec![
+            entry("a", NodeType::Dir, 0),
+            entry("b", NodeType::Dir, 0),
+            entry("c", NodeType::File, 2),
+            entry("d", NodeType::File, 0),
+        ];
+
+        let mut out = Vec::new();
+        diff(
+            &mut out,
+            nodes1.into_iter(),
+            nodes2.into_iter(),
+            false,
+            |_path, node1, node2| Ok(node1.meta.size == node2.meta.size),
+            false,
+            false,
+        )?;
+
+        assert_eq!(
+            String::from_utf8(out)?,
+            "T    \"a\"\n\
+            M    \"c\"\n\
+            +    \"d\"\n\
+            -    \"e\"\n\
+            Files   :\t1 new,\t1 removed,\t1 changed\n\
+            Dirs    :\t0 new,\t0 removed\n\
+            NodeType:\t1 changed\n\
+            \n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_json_output_passes() -> Result<()> {
+        let nodes1 = vec![
+            entry("a", NodeType::File, 10),
+            entry("c", NodeType::File, 5),
+        ];
+        let nodes2 = vec![entry("b", NodeType::File, 3), entry("c", NodeType::File, 7)];
+
+        let mut out = Vec::new();
+        // without content check, modified files are detected by their metadata
+        diff(
+            &mut out,
+            nodes1.into_iter(),
+            nodes2.into_iter(),
+            true,
+            |_path, _node1, _node2| bail!("content must not be checked"),
+            false,
+            true,
+        )?;
+
+        assert_eq!(
+            String::from_utf8(out)?,
+            r#"{"change":"removed","path":"a","size1":10,"size2":null}
+{"change":"added","path":"b","size1":null,"size2":3}
+{"change":"modified","path":"c","size1":5,"size2":7}
+{"summary":{"files_added":1,"files_removed":1,"files_changed":1,"directories_added":0,"directories_removed":0,"others_added":0,"others_removed":0,"node_type_changed":0,"metadata_changed":0,"symlink_added":0,"symlink_removed":0,"symlink_changed":0,"bytes_added":5,"bytes_removed":10}}
+"#
+        );
+        Ok(())
+    }
 }
@@ -1,16 +1,49 @@
 //! `dump` subcommand
 
-use crate::{commands::open_repository_indexed, status_err, Application, RUSTIC_APP};
+mod archive;
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    commands::open_repository_indexed,
+    helpers::{prepare_output_path, OutputKind},
+    status_err, Application, RUSTIC_APP,
+};
 
 use abscissa_core::{Command, Runnable, Shutdown};
-use anyhow::Result;
+use anyhow::{bail, Result};
+use clap::ValueHint;
+use log::warn;
+
+use self::archive::ArchiveFormat;
 
 /// `dump` subcommand
 #[derive(clap::Parser, Command, Debug)]
 pub(crate) struct DumpCmd {
-    /// file from snapshot to dump
+    /// file or directory from snapshot to dump
     #[clap(value_name = "SNAPSHOT[:PATH]")]
     snap: String,
+
+    /// Dump as archive of the given format; needed to dump directories. Devices, fifos and sockets
+    /// are skipped
+    #[clap(long, value_name = "FORMAT")]
+    archive: Option<ArchiveFormat>,
+
+    /// Write to the given file instead of stdout
+    #[clap(long, alias = "output", value_name = "FILE", value_hint = ValueHint::FilePath)]
+    file: Option<PathBuf>,
+
+    /// Overwrite the file given by --file if it already exists
+    #[clap(long, requires = "file")]
+    force: bool,
+
+    /// Create missing parent directories of the file given by --file
+    #[clap(long, requires = "file")]
+    create_parents: bool,
 }
 
 impl Runnable for DumpCmd {
@@ -25,13 +58,36 @@ impl Runnable for DumpCmd {
 impl DumpCmd {
     fn inner_run(&self) -> Result<()> {
         let config = RUSTIC_APP.config();
+        let file = self
+            .file
+            .as_ref()
+            .map(|file| {
+                prepare_output_path(file, OutputKind::File, self.force, self.create_parents)
+            })
+            .transpose()?;
+
         let repo = open_repository_indexed(&config.repository)?;
 
         let node =
             repo.node_from_snapshot_path(&self.snap, |sn| config.snapshot_filter.matches(sn))?;
 
-        let mut stdout = std::io::stdout();
-        repo.dump(&node, &mut stdout)?;
+        if node.is_dir() && self.archive.is_none() {
+            bail!("{} is a directory, use --archive to dump it.", self.snap);
+        }
+
+        let mut writer: Box<dyn Write> = match file {
+            Some(file) => Box::new(BufWriter::new(File::create(file)?)),
+            None => Box::new(std::io::stdout().lock()),
+        };
+        if let Some(format) = self.archive {
+            let skipped = archive::write_archive(&repo, &node, format, &mut writer)?;
+            if skipped > 0 {
+                warn!("skipped {skipped} devices, fifos or sockets which can't be archived.");
+            }
+        } else {
+            repo.dump(&node, &mut writer)?;
+        }
+        writer.flush()?;
 
         Ok(())
     }
@@ -0,0 +1,317 @@
+//! Dumping directories as tar or zip archives
+
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use chrono::{Datelike, Timelike};
+use flate2::{write::GzEncoder, Compression};
+use rustic_core::{
+    repofile::{Node, NodeType},
+    vfs::OpenFile,
+    IndexedFull, LsOptions, Repository,
+};
+use tar::{EntryType, Header};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+/// Size of the file contents read at once
+const READ_SIZE: usize = 1024 * 1024;
+
+/// Archive format for dumping directories
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum ArchiveFormat {
+    /// Uncompressed tar archive
+    Tar,
+    /// Gzip-compressed tar archive
+    #[value(name = "tar.gz")]
+    TarGz,
+    /// Zip archive
+    Zip,
+}
+
+/// Reader for the contents of a file in a snapshot
+///
+/// Only one chunk of the file is held in memory at a time.
+struct FileReader<'a, P, S> {
+    /// the repository
+    repo: &'a Repository<P, S>,
+    /// the file to read
+    file: OpenFile,
+    /// current position within the file
+    offset: usize,
+}
+
+impl<P, S: IndexedFull> Read for FileReader<'_, P, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self
+            .repo
+            .read_file_at(&self.file, self.offset, buf.len().min(READ_SIZE))
+            .map_err(io::Error::other)?;
+        buf[..data.len()].copy_from_slice(&data);
+        self.offset += data.len();
+        Ok(data.len())
+    }
+}
+
+/// Write the subtree of `node` as archive
+///
+/// The entries are streamed one by one, so the memory usage doesn't depend on the size of the
+/// subtree.
+///
+/// # Arguments
+///
+/// * `repo` - the repository
+/// * `node` - the node to dump; a directory is dumped with all its contents
+/// * `format` - the archive format
+/// * `out` - where to write the archive to
+///
+/// # Returns
+///
+/// The number of skipped entries, i.e. devices, fifos and sockets
+pub(crate) fn write_archive<P, S: IndexedFull>(
+    repo: &Repository<P, S>,
+    node: &Node,
+    format: ArchiveFormat,
+    out: impl Write,
+) -> Result<usize> {
+    let mut opts = LsOptions::default();
+    opts.recursive = true;
+    let entries = repo.ls(node, &opts)?.map(|item| Ok(item?));
+    let open = |node: &Node| {
+        anyhow::Ok(FileReader {
+            repo,
+            file: repo.open_file(node)?,
+            offset: 0,
+        })
+    };
+    match format {
+        ArchiveFormat::Tar => write_tar(entries, open, out),
+        ArchiveFormat::TarGz => {
+            let mut encoder = GzEncoder::new(out, Compression::default());
+            let skipped = write_tar(entries, open, &mut encoder)?;
+            _ = encoder.finish()?;
+            Ok(skipped)
+        }
+        ArchiveFormat::Zip => write_zip(entries, open, out),
+    }
+}
+
+/// Write entries as tar archive
+///
+/// # Arguments
+///
+/// * `entries` - the entries to write with their paths within the archive
+/// * `open` - open the contents of a file entry
+/// * `out` - where to write the archive to
+///
+/// # Returns
+///
+/// The number of skipped entries
+fn write_tar<R: Read>(
+    entries: impl Iterator<Item = Result<(PathBuf, Node)>>,
+    open: impl Fn(&Node) -> Result<R>,
+    out: impl Write,
+) -> Result<usize> {
+    let mut builder = tar::Builder::new(out);
+    // paths of already written files which have multiple hard links, by device and inode
+    let mut hardlinks = HashMap::new();
+    let mut skipped = 0;
+    for entry in entries {
+        let (path, node) = entry?;
+        let mut header = Header::new_gnu();
+        let meta = &node.meta;
+        header.set_mtime(
+            meta.mtime
+                .map_or(0, |t| t.timestamp().try_into().unwrap_or(0)),
+        );
+        header.set_uid(meta.uid.unwrap_or(0).into());
+        header.set_gid(meta.gid.unwrap_or(0).into());
+        if let Some(user) = &meta.user {
+            header.set_username(user)?;
+        }
+        if let Some(group) = &meta.group {
+            header.set_groupname(group)?;
+        }
+        header.set_size(0);
+        match &node.node_type {
+            NodeType::Dir => {
+                header.set_mode(meta.mode.map_or(0o755, |mode| mode & 0o7777));
+                header.set_entry_type(EntryType::Directory);
+                builder.append_data(&mut header, &path, io::empty())?;
+            }
+            NodeType::File => {
+                header.set_mode(meta.mode.map_or(0o644, |mode| mode & 0o7777));
+                if meta.links > 1 {
+                    if let Some(target) = hardlinks.get(&(meta.device_id, meta.inode)) {
+                        header.set_entry_type(EntryType::Link);
+                        builder.append_link(&mut header, &path, target)?;
+                        continue;
+                    }
+                    _ = hardlinks.insert((meta.device_id, meta.inode), path.clone());
+                }
+                header.set_entry_type(EntryType::Regular);
+                header.set_size(meta.size);
+                builder.append_data(&mut header, &path, open(&node)?.take(meta.size))?;
+            }
+            NodeType::Symlink { .. } => {
+                header.set_mode(0o777);
+                header.set_entry_type(EntryType::Symlink);
+                builder.append_link(&mut header, &path, node.node_type.to_link())?;
+            }
+            _ => skipped += 1,
+        }
+    }
+    builder.into_inner()?.flush()?;
+    Ok(skipped)
+}
+
+/// Write entries as zip archive
+///
+/// Zip doesn't support hard links, so the contents of hard-linked files are written for each link.
+///
+/// # Arguments
+///
+/// * `entries` - the entries to write with their paths within the archive
+/// * `open` - open the contents of a file entry
+/// * `out` - where to write the archive to
+///
+/// # Returns
+///
+/// The number of skipped entries
+fn write_zip<R: Read>(
+    entries: impl Iterator<Item = Result<(PathBuf, Node)>>,
+    open: impl Fn(&Node) -> Result<R>,
+    out: impl Write,
+) -> Result<usize> {
+    let mut zip = ZipWriter::new_stream(out);
+    let mut skipped = 0;
+    for entry in entries {
+        let (path, node) = entry?;
+        let meta = &node.meta;
+        let mut options = SimpleFileOptions::default();
+        if let Some(mtime) = meta.mtime.and_then(|t| {
+            let t = t.naive_local();
+            zip::DateTime::from_date_and_time(
+                t.year().try_into().ok()?,
+                t.month().try_into().ok()?,
+                t.day().try_into().ok()?,
+                t.hour().try_into().ok()?,
+                t.minute().try_into().ok()?,
+                t.second().try_into().ok()?,
+            )
+            .ok()
+        }) {
+            options = options.last_modified_time(mtime);
+        }
+        let name = zip_name(&path);
+        match &node.node_type {
+            NodeType::Dir => {
+                options = options.unix_permissions(meta.mode.map_or(0o755, |mode| mode & 0o7777));
+                zip.add_directory(name, options)?;
+            }
+            NodeType::File => {
+                options = options
+                    .unix_permissions(meta.mode.map_or(0o644, |mode| mode & 0o7777))
+                    .large_file(meta.size >= u64::from(u32::MAX));
+                zip.start_file(name, options)?;
+                _ = io::copy(&mut open(&node)?.take(meta.size), &mut zip)?;
+            }
+            NodeType::Symlink { .. } => {
+                let target = node.node_type.to_link().to_string_lossy().to_string();
+                zip.add_symlink(name, target, options)?;
+            }
+            _ => skipped += 1,
+        }
+    }
+    zip.finish()?.flush()?;
+    Ok(skipped)
+}
+
+/// Name of an entry in a zip archive, which always uses `/` as separator
+fn zip_name(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use pretty_assertions::assert_eq;
+    use rustic_core::repofile::Metadata;
+
+    fn entries() -> Vec<(PathBuf, Node)> {
+        let node = |name: &str, node_type: NodeType, size: u64| {
+            let meta = Metadata {
+                size,
+                mode: Some(0o640),
+                ..Default::default()
+            };
+            Node::new(name.to_string(), node_type, meta)
+        };
+        vec![
+            ("dir".into(), node("dir", NodeType::Dir, 0)),
+            ("dir/a".into(), node("a", NodeType::File, 5)),
+            ("dir/fifo".into(), node("fifo", NodeType::Fifo, 0)),
+        ]
+    }
+
+    fn open(node: &Node) -> Result<Cursor<Vec<u8>>> {
+        Ok(Cursor::new(node.name().as_encoded_bytes().repeat(5)))
+    }
+
+    #[test]
+    fn test_write_tar_passes() -> Result<()> {
+        let mut out = Vec::new();
+        let skipped = write_tar(entries().into_iter().map(Ok), open, &mut out)?;
+        assert_eq!(skipped, 1);
+
+        let mut archive = tar::Archive::new(out.as_slice());
+        let mut found = Vec::new();
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let mut content = String::new();
+            _ = entry.read_to_string(&mut content)?;
+            found.push((
+                entry
+                    .path()?
+                    .to_string_lossy()
+                    .trim_end_matches('/')
+                    .to_string(),
+                entry.header().mode()?,
+                content,
+            ));
+        }
+        assert_eq!(
+            found,
+            vec![
+                ("dir".to_string(), 0o640, String::new()),
+                ("dir/a".to_string(), 0o640, "aaaaa".to_string()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_zip_passes() -> Result<()> {
+        let mut out = Vec::new();
+        let skipped = write_zip(entries().into_iter().map(Ok), open, &mut out)?;
+        assert_eq!(skipped, 1);
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(out))?;
+        assert_eq!(archive.len(), 2);
+        let mut file = archive.by_name("dir/a")?;
+        let mut content = String::new();
+        _ = file.read_to_string(&mut content)?;
+        assert_eq!(content, "aaaaa");
+        assert_eq!(file.unix_mode().map(|mode| mode & 0o7777), Some(0o640));
+        Ok(())
+    }
+}
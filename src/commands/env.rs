@@ -0,0 +1,117 @@
+//! `env` subcommand
+
+use std::io::Write;
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{bail, Result};
+
+use crate::{output::Output, status_err, Application, RUSTIC_APP};
+
+/// `env` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(super) struct EnvCmd {
+    /// Subcommand to run
+    #[clap(subcommand)]
+    cmd: EnvSubCmd,
+}
+
+#[derive(clap::Subcommand, Debug, Runnable)]
+enum EnvSubCmd {
+    /// Print shell commands exporting the environment variables of `[global.env]`
+    ///
+    /// The output can be evaluated by the shell, e.g. `eval "$(rustic env export)"`.
+    Export(ExportCmd),
+}
+
+/// Shell to print commands for
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// `env export` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct ExportCmd {
+    /// Shell to print the export commands for
+    #[clap(long, value_enum, default_value = "bash")]
+    shell: Shell,
+}
+
+impl Runnable for EnvCmd {
+    fn run(&self) {
+        self.cmd.run();
+    }
+}
+
+impl Runnable for ExportCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run(&mut Output::stdout()) {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl ExportCmd {
+    fn inner_run(&self, out: &mut Output<impl Write>) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let mut env: Vec<_> = config.global.env.iter().collect();
+        env.sort_unstable();
+        for (key, value) in env {
+            writeln!(out, "{}", export_command(self.shell, key, value)?)?;
+        }
+        Ok(())
+    }
+}
+
+/// Create the shell command exporting an environment variable
+///
+/// # Errors
+///
+/// * If the key is no valid name of a shell variable
+fn export_command(shell: Shell, key: &str, value: &str) -> Result<String> {
+    let valid = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        bail!("environment variable {key:?} can't be exported by a shell");
+    }
+    Ok(match shell {
+        Shell::Bash | Shell::Zsh => format!("export {key}='{}'", value.replace('\'', r"'\''")),
+        Shell::Fish => format!(
+            "set -gx {key} '{}'",
+            value.replace('\\', r"\\").replace('\'', r"\'")
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(Shell::Bash, "plain", "export KEY='plain'")]
+    #[case(Shell::Zsh, "$HOME `id` \"x\"", "export KEY='$HOME `id` \"x\"'")]
+    #[case(Shell::Bash, "it's", r"export KEY='it'\''s'")]
+    #[case(Shell::Fish, "plain", "set -gx KEY 'plain'")]
+    #[case(Shell::Fish, r"it's a\b", r"set -gx KEY 'it\'s a\\b'")]
+    fn test_export_command_passes(
+        #[case] shell: Shell,
+        #[case] value: &str,
+        #[case] expected: &str,
+    ) -> Result<()> {
+        assert_eq!(export_command(shell, "KEY", value)?, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("1KEY")]
+    #[case("KEY; rm -rf /")]
+    #[case("")]
+    fn test_export_command_invalid_key_fails(#[case] key: &str) {
+        assert!(export_command(Shell::Bash, key, "value").is_err());
+    }
+}
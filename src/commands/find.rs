@@ -1,21 +1,29 @@
 //! `find` subcommand
 
-use std::path::{Path, PathBuf};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
 
-use crate::{commands::open_repository_indexed, status_err, Application, RUSTIC_APP};
+use crate::{
+    commands::open_repository_indexed, helpers::parse_datetime, output::Output, status_err,
+    Application, RUSTIC_APP,
+};
 
 use abscissa_core::{Command, Runnable, Shutdown};
 use anyhow::Result;
+use chrono::{DateTime, Local};
 use clap::ValueHint;
 use globset::{Glob, GlobBuilder, GlobSetBuilder};
 use itertools::Itertools;
+use serde::Serialize;
 
 use rustic_core::{
     repofile::{Node, SnapshotFile},
-    FindMatches, FindNode, SnapshotGroupCriterion,
+    FindMatches, FindNode, Id, SnapshotGroup, SnapshotGroupCriterion,
 };
 
-use super::ls::print_node;
+use super::ls::node_line;
 
 /// `find` subcommand
 #[derive(clap::Parser, Command, Debug)]
@@ -56,43 +64,101 @@ pub(crate) struct FindCmd {
     /// Show uid/gid instead of user/group
     #[clap(long, long("numeric-uid-gid"))]
     numeric_id: bool,
+
+    /// Show the ids of the blobs of found entries, i.e. their content or subtree
+    #[clap(long)]
+    show_blobs: bool,
+
+    /// Only search in snapshots taken at or after the given time, e.g. "2024-01-31"
+    #[clap(long, value_name = "DATETIME", value_parser = parse_datetime)]
+    oldest: Option<DateTime<Local>>,
+
+    /// Only search in snapshots taken at or before the given time, e.g. "2024-01-31 12:00:00"
+    #[clap(long, value_name = "DATETIME", value_parser = parse_datetime)]
+    newest: Option<DateTime<Local>>,
+
+    /// Show search results in json format
+    #[clap(long)]
+    json: bool,
 }
 
 impl Runnable for FindCmd {
     fn run(&self) {
-        if let Err(err) = self.inner_run() {
+        if let Err(err) = self.inner_run(&mut Output::stdout()) {
             status_err!("{}", err);
             RUSTIC_APP.shutdown(Shutdown::Crash);
         };
     }
 }
 
+/// Search results of a snapshot group
+#[derive(Debug, Serialize)]
+struct GroupResults {
+    /// the snapshot group
+    group: SnapshotGroup,
+    /// the search results, summarized for consecutive snapshots with identical results
+    results: Vec<FindResult>,
+}
+
+/// Search result shared by consecutive snapshots
+#[derive(Debug, Serialize)]
+struct FindResult {
+    /// the snapshots with this result
+    snapshots: Vec<SnapshotRef>,
+    /// the found entries; empty if the snapshots don't contain a match
+    matches: Vec<FindMatch>,
+}
+
+/// A snapshot in the search results
+#[derive(Debug, Serialize)]
+struct SnapshotRef {
+    /// snapshot id
+    id: Id,
+    /// snapshot time
+    time: DateTime<Local>,
+}
+
+/// A found entry
+#[derive(Debug, Serialize)]
+struct FindMatch {
+    /// path of the entry
+    path: PathBuf,
+    /// the entry
+    #[serde(skip)]
+    node: Node,
+    /// size of the entry
+    size: u64,
+    /// modification time of the entry
+    mtime: Option<DateTime<Local>>,
+    /// ids of the content or subtree blobs, if `--show-blobs` is given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blobs: Option<Vec<Id>>,
+}
+
 impl FindCmd {
-    fn inner_run(&self) -> Result<()> {
+    fn inner_run(&self, out: &mut Output<impl Write>) -> Result<()> {
         let config = RUSTIC_APP.config();
         let repo = open_repository_indexed(&config.repository)?;
 
         let groups = repo.get_snapshot_group(&self.ids, self.group_by, |sn| {
             config.snapshot_filter.matches(sn)
         })?;
+        let mut all_results = Vec::new();
         for (group, mut snapshots) in groups {
-            snapshots.sort_unstable();
-            if !group.is_empty() {
-                println!("\nsearching in snapshots group {group}...");
+            snapshots.retain(|sn| self.in_time_range(sn));
+            if snapshots.is_empty() {
+                continue;
             }
+            snapshots.sort_unstable();
+            // identical trees are only searched once
             let ids = snapshots.iter().map(|sn| sn.tree);
-            if let Some(path) = &self.path {
+            let results = if let Some(path) = &self.path {
                 let FindNode { nodes, matches } = repo.find_nodes_from_path(ids, path)?;
-                for (idx, g) in &matches
-                    .iter()
-                    .zip(snapshots.iter())
-                    .chunk_by(|(idx, _)| *idx)
-                {
-                    self.print_identical_snapshots(idx.iter(), g.into_iter().map(|(_, sn)| sn));
-                    if let Some(idx) = idx {
-                        print_node(&nodes[*idx], path, self.numeric_id);
-                    }
-                }
+                self.summarize(&snapshots, &matches, |idx| {
+                    idx.iter()
+                        .map(|idx| self.found(path.clone(), &nodes[*idx]))
+                        .collect()
+                })
             } else {
                 let mut builder = GlobSetBuilder::new();
                 for glob in &self.glob {
@@ -110,43 +176,123 @@ impl FindCmd {
                     nodes,
                     matches,
                 } = repo.find_matching_nodes(ids, &matches)?;
-                for (idx, g) in &matches
-                    .iter()
-                    .zip(snapshots.iter())
-                    .chunk_by(|(idx, _)| *idx)
-                {
-                    self.print_identical_snapshots(idx.iter(), g.into_iter().map(|(_, sn)| sn));
-                    for (path_idx, node_idx) in idx {
-                        print_node(&nodes[*node_idx], &paths[*path_idx], self.numeric_id);
-                    }
+                self.summarize(&snapshots, &matches, |idx| {
+                    idx.iter()
+                        .map(|(path_idx, node_idx)| {
+                            self.found(paths[*path_idx].clone(), &nodes[*node_idx])
+                        })
+                        .collect()
+                })
+            };
+
+            if self.json {
+                all_results.push(GroupResults { group, results });
+            } else {
+                if !group.is_empty() {
+                    writeln!(out, "\nsearching in snapshots group {group}...")?;
                 }
+                self.print_results(out, &results)?;
             }
         }
+        if self.json {
+            out.json(&all_results)?;
+        }
         Ok(())
     }
 
-    fn print_identical_snapshots<'a>(
+    /// Check if the snapshot is within the time bounds given by `--oldest` and `--newest`
+    fn in_time_range(&self, sn: &SnapshotFile) -> bool {
+        self.oldest.map_or(true, |oldest| sn.time >= oldest)
+            && self.newest.map_or(true, |newest| sn.time <= newest)
+    }
+
+    /// Create the search result for a found entry
+    fn found(&self, path: PathBuf, node: &Node) -> FindMatch {
+        let blobs = self.show_blobs.then(|| {
+            node.content
+                .iter()
+                .flatten()
+                .copied()
+                .chain(node.subtree)
+                .collect()
+        });
+        FindMatch {
+            path,
+            node: node.clone(),
+            size: node.meta.size,
+            mtime: node.meta.mtime,
+            blobs,
+        }
+    }
+
+    /// Summarize the matches of consecutive snapshots with identical results
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshots` - the searched snapshots
+    /// * `matches` - the matches for each snapshot
+    /// * `found` - create the found entries from the matches of a snapshot
+    fn summarize<T: PartialEq>(
         &self,
-        mut idx: impl Iterator,
-        mut g: impl Iterator<Item = &'a SnapshotFile>,
-    ) {
-        let empty_result = idx.next().is_none();
-        let not = if empty_result { "not " } else { "" };
-        if self.show_misses || !empty_result {
+        snapshots: &[SnapshotFile],
+        matches: &[T],
+        found: impl Fn(&T) -> Vec<FindMatch>,
+    ) -> Vec<FindResult> {
+        let mut results = Vec::new();
+        for (idx, g) in &matches
+            .iter()
+            .zip(snapshots.iter())
+            .chunk_by(|(idx, _)| *idx)
+        {
+            let matches = found(idx);
+            if matches.is_empty() && !self.show_misses {
+                continue;
+            }
+            let snapshots = g
+                .map(|(_, sn)| SnapshotRef {
+                    id: sn.id,
+                    time: sn.time,
+                })
+                .collect();
+            results.push(FindResult { snapshots, matches });
+        }
+        results
+    }
+
+    /// Print the search results
+    fn print_results(&self, out: &mut Output<impl Write>, results: &[FindResult]) -> Result<()> {
+        for result in results {
+            let not = if result.matches.is_empty() {
+                "not "
+            } else {
+                ""
+            };
             if self.all {
-                for sn in g {
+                for sn in &result.snapshots {
                     let time = sn.time.format("%Y-%m-%d %H:%M:%S");
-                    println!("{not}found in {} from {time}", sn.id);
+                    writeln!(out, "{not}found in {} from {time}", sn.id)?;
                 }
             } else {
-                let sn = g.next().unwrap();
-                let count = g.count();
+                let sn = &result.snapshots[0];
                 let time = sn.time.format("%Y-%m-%d %H:%M:%S");
-                match count {
-                    0 => println!("{not}found in {} from {time}", sn.id),
-                    count => println!("{not}found in {} from {time} (+{count})", sn.id),
+                match result.snapshots.len() - 1 {
+                    0 => writeln!(out, "{not}found in {} from {time}", sn.id)?,
+                    count => writeln!(out, "{not}found in {} from {time} (+{count})", sn.id)?,
                 };
             }
+            for found in &result.matches {
+                writeln!(
+                    out,
+                    "{}",
+                    node_line(&found.node, &found.path, self.numeric_id)
+                )?;
+                if let Some(blobs) = &found.blobs {
+                    for blob in blobs {
+                        writeln!(out, "    blob {}", blob.to_hex())?;
+                    }
+                }
+            }
         }
+        Ok(())
     }
 }
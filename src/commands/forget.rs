@@ -1,10 +1,15 @@
 //! `forget` subcommand
 
 use crate::{
-    commands::open_repository, helpers::table_with_titles, status_err, Application, RusticConfig,
-    RUSTIC_APP,
+    commands::open_repository,
+    events::{self, Event},
+    helpers::table_with_titles,
+    output::Output,
+    status_err, Application, RusticConfig, RUSTIC_APP,
 };
 
+use std::io::Write;
+
 use abscissa_core::{config::Override, Shutdown};
 use abscissa_core::{Command, FrameworkError, Runnable};
 use anyhow::Result;
@@ -12,12 +17,17 @@ use anyhow::Result;
 use chrono::Local;
 use merge::Merge;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use serde_with::{serde_as, DisplayFromStr};
 
-use crate::{commands::prune::PruneCmd, filtering::SnapshotFilter};
+use crate::{
+    commands::prune::{PruneCmd, PruneThresholds},
+    filtering::SnapshotFilter,
+};
 
 use rustic_core::{
-    ForgetGroup, ForgetGroups, ForgetSnapshot, KeepOptions, SnapshotGroup, SnapshotGroupCriterion,
+    ForgetGroup, ForgetGroups, ForgetSnapshot, KeepOptions, PruneOptions, SnapshotGroup,
+    SnapshotGroupCriterion,
 };
 
 /// `forget` subcommand
@@ -44,7 +54,7 @@ pub(super) struct ForgetCmd {
         flatten,
         next_help_heading = "PRUNE OPTIONS (only when used with --prune)"
     )]
-    prune_opts: PruneCmd,
+    prune_opts: PruneOptions,
 }
 
 impl Override<RusticConfig> for ForgetCmd {
@@ -77,6 +87,11 @@ pub struct ForgetOptions {
     #[merge(strategy = merge::bool::overwrite_false)]
     prune: bool,
 
+    /// Thresholds of unused data below which pruning is skipped (only when used with --prune)
+    #[clap(flatten)]
+    #[serde(flatten)]
+    prune_thresholds: PruneThresholds,
+
     /// Snapshot filter options
     #[clap(flatten, next_help_heading = "Snapshot filter options")]
     #[serde(flatten)]
@@ -90,7 +105,7 @@ pub struct ForgetOptions {
 
 impl Runnable for ForgetCmd {
     fn run(&self) {
-        if let Err(err) = self.inner_run() {
+        if let Err(err) = self.inner_run(&mut Output::stdout()) {
             status_err!("{}", err);
             RUSTIC_APP.shutdown(Shutdown::Crash);
         };
@@ -101,7 +116,7 @@ impl ForgetCmd {
     /// be careful about self vs `RUSTIC_APP.config()` usage
     /// only the `RUSTIC_APP.config()` involves the TOML and ENV merged configurations
     /// see <https://github.com/rustic-rs/rustic/issues/1242>
-    fn inner_run(&self) -> Result<()> {
+    fn inner_run(&self, out: &mut Output<impl Write>) -> Result<()> {
         let config = RUSTIC_APP.config();
         let repo = open_repository(&config.repository)?;
 
@@ -139,44 +154,66 @@ impl ForgetCmd {
         };
 
         if self.json {
-            let mut stdout = std::io::stdout();
-            serde_json::to_writer_pretty(&mut stdout, &groups)?;
+            out.json(&groups)?;
         } else if !self.quiet {
-            print_groups(&groups);
+            print_groups(out, &groups)?;
         }
 
+        let removed_snaps: Vec<_> = groups
+            .0
+            .iter()
+            .flat_map(|group| &group.snapshots)
+            .filter(|snap| !snap.keep)
+            .map(|snap| snap.snapshot.clone())
+            .collect();
         let forget_snaps = groups.into_forget_ids();
+        if events::is_enabled() {
+            events::emit(&Event::Summary {
+                command: "forget",
+                summary: json!({
+                    "removed": forget_snaps.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                    "dry_run": config.global.dry_run,
+                }),
+            });
+        }
 
         match (forget_snaps.is_empty(), config.global.dry_run, self.json) {
-            (true, _, false) => println!("nothing to remove"),
+            (true, _, false) => writeln!(out, "nothing to remove")?,
             (false, true, false) => {
-                println!("would have removed the following snapshots:\n {forget_snaps:?}");
+                writeln!(
+                    out,
+                    "would have removed the following snapshots:\n {forget_snaps:?}"
+                )?;
             }
             (false, false, _) => {
                 repo.delete_snapshots(&forget_snaps)?;
+                config.hooks.snapshots_removed(&removed_snaps);
             }
             (_, _, true) => {}
         }
 
         if config.forget.prune {
             let mut prune_opts = self.prune_opts.clone();
-            prune_opts.opts.ignore_snaps = forget_snaps;
-            prune_opts.run();
+            prune_opts.ignore_snaps = forget_snaps;
+            let mut prune = PruneCmd::from(prune_opts);
+            prune.thresholds = config.forget.prune_thresholds.clone();
+            prune.run();
         }
 
         Ok(())
     }
 }
 
-/// Print groups to stdout
+/// Print groups
 ///
 /// # Arguments
 ///
+/// * `out` - the output to print to
 /// * `groups` - forget groups to print
-fn print_groups(groups: &ForgetGroups) {
+fn print_groups(out: &mut Output<impl Write>, groups: &ForgetGroups) -> Result<()> {
     for ForgetGroup { group, snapshots } in &groups.0 {
         if !group.is_empty() {
-            println!("snapshots for {group}");
+            writeln!(out, "snapshots for {group}")?;
         }
         let mut table = table_with_titles([
             "ID", "Time", "Host", "Label", "Tags", "Paths", "Action", "Reason",
@@ -205,8 +242,56 @@ fn print_groups(groups: &ForgetGroups) {
             ]);
         }
 
-        println!();
-        println!("{table}");
-        println!();
+        writeln!(out)?;
+        out.table(table)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::helpers::test_snapshot;
+
+    use pretty_assertions::assert_eq;
+    use rustic_core::repofile::SnapshotFile;
+
+    fn forget_snapshot(id: char, day: u32, keep: bool, reason: &str) -> ForgetSnapshot {
+        ForgetSnapshot {
+            snapshot: SnapshotFile {
+                hostname: "host1".to_string(),
+                paths: "/home".parse().unwrap(),
+                ..test_snapshot(id, day)
+            },
+            keep,
+            reasons: vec![reason.to_string()],
+        }
+    }
+
+    #[test]
+    fn test_print_groups_passes() -> Result<()> {
+        let groups = ForgetGroups(vec![ForgetGroup {
+            group: SnapshotGroup::default(),
+            snapshots: vec![
+                forget_snapshot('1', 5, true, "last"),
+                forget_snapshot('2', 4, false, "id argument"),
+            ],
+        }]);
+
+        let mut buf = Vec::new();
+        print_groups(&mut Output::new(&mut buf), &groups)?;
+
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "\n\
+            | ID       | Time                | Host  | Label | Tags | Paths | Action | Reason      |\n\
+            |----------|---------------------|-------|-------|------|-------|--------|-------------|\n\
+            | 11111111 | 2024-01-05 12:00:00 | host1 |       |      | /home | keep   | last        |\n\
+            | 22222222 | 2024-01-04 12:00:00 | host1 |       |      | /home | remove | id argument |\n\
+            \n"
+        );
+        Ok(())
     }
 }
@@ -1,12 +1,19 @@
 //! `init` subcommand
 
 use abscissa_core::{status_err, Command, Runnable, Shutdown};
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use dialoguer::Password;
+use log::{log, Level};
 
-use crate::{commands::get_repository, Application, RUSTIC_APP};
+use crate::{
+    commands::{get_backends, get_repository, open_repository},
+    Application, RusticConfig, RUSTIC_APP,
+};
 
-use rustic_core::{ConfigOptions, KeyOptions, OpenStatus, Repository};
+use rustic_core::{
+    repofile::FileType, ConfigOptions, Id, KeyOptions, OpenStatus, ReadBackend, Repository,
+    WriteBackend,
+};
 
 /// `init` subcommand
 #[derive(clap::Parser, Command, Debug)]
@@ -18,6 +25,19 @@ pub(crate) struct InitCmd {
     /// Config options
     #[clap(flatten, next_help_heading = "Config options")]
     config_opts: ConfigOptions,
+
+    /// Copy the chunker parameters and the other repository settings from the repository of the
+    /// given config profile, such that snapshots can be copied between the repositories with full
+    /// deduplication. Config options given on the command line are applied on top. Note that the
+    /// chunker parameters are stored in the repository config, not in snapshots, so they can't be
+    /// copied from a snapshot
+    #[clap(long, value_name = "PROFILE")]
+    copy_chunker_params: Option<String>,
+
+    /// Like --copy-chunker-params, but copy the settings from the repository defined as
+    /// `[repositories.<NAME>]` in the config file
+    #[clap(long, value_name = "NAME", conflicts_with = "copy_chunker_params")]
+    from_repo: Option<String>,
 }
 
 impl Runnable for InitCmd {
@@ -36,8 +56,15 @@ impl InitCmd {
 
         // Note: This is again checked in repo.init_with_password(), however we want to inform
         // users before they are prompted to enter a password
-        if repo.config_id()?.is_some() {
-            bail!("Config file already exists. Aborting.");
+        if repo
+            .config_id()
+            .with_context(|| format!("error accessing repository {}", repo.name))?
+            .is_some()
+        {
+            bail!(
+                "repository {} already exists (config file found). Aborting.",
+                repo.name
+            );
         }
 
         // Handle dry-run mode
@@ -48,7 +75,64 @@ impl InitCmd {
             );
         }
 
-        let _ = init(repo, &self.key_opts, &self.config_opts)?;
+        // a missing config file might have been removed by accident, so don't overwrite any data.
+        // The locations are created first, as the initialization would do anyway, so errors
+        // listing them are real errors.
+        let backends = get_backends(&config.repository)?;
+        let hot = backends.repo_hot().map(|be| ("hot repository", be));
+        for (name, be) in std::iter::once(("repository", backends.repository())).chain(hot) {
+            be.create()
+                .with_context(|| format!("error creating {name} {}", be.location()))?;
+            for tpe in [
+                FileType::Key,
+                FileType::Snapshot,
+                FileType::Index,
+                FileType::Pack,
+            ] {
+                let count = be
+                    .list(tpe)
+                    .with_context(|| format!("error listing {name} {}", be.location()))?
+                    .len();
+                if count > 0 {
+                    bail!(
+                        "{name} location {} is not empty ({count} {tpe:?} files found). Aborting.",
+                        be.location()
+                    );
+                }
+            }
+        }
+
+        let config_opts = config.repository.config_options(&self.config_opts)?;
+
+        let source_opts = match (&self.copy_chunker_params, &self.from_repo) {
+            (Some(profile), _) => {
+                let mut merge_logs = Vec::new();
+                let mut source_config = RusticConfig::default();
+                source_config.merge_profile(profile, &mut merge_logs, Level::Error)?;
+                // display logs from merging
+                for (level, merge_log) in merge_logs {
+                    log!(level, "{}", merge_log);
+                }
+                Some(source_config.repository)
+            }
+            (None, Some(name)) => Some(config.named_repository(name)?),
+            (None, None) => None,
+        };
+
+        let repo = if let Some(mut source_opts) = source_opts {
+            source_opts.apply_backend_options();
+            let source = open_repository(&source_opts)?;
+
+            // the chunker parameters are part of the config file, so copy it and set a new id
+            let mut config_file = source.config().clone();
+            config_file.id = Id::random();
+            config_opts.apply(&mut config_file)?;
+            let pass = init_password(&repo)?;
+            repo.init_with_config(&pass, &self.key_opts, config_file)?
+        } else {
+            init(repo, &self.key_opts, &config_opts)?
+        };
+        println!("repository id: {}", repo.config().id);
         Ok(())
     }
 }
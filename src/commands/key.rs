@@ -1,15 +1,25 @@
 //! `key` subcommand
 
-use crate::{commands::open_repository, status_err, Application, RUSTIC_APP};
+use crate::{
+    commands::{cat::find_id, get_backends, get_repository, open_repository},
+    config::password_keyring,
+    helpers::table_with_titles,
+    status_err, Application, RUSTIC_APP,
+};
 
 use std::path::PathBuf;
 
 use abscissa_core::{Command, Runnable, Shutdown};
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use clap::ValueHint;
 use dialoguer::Password;
 use log::info;
+use serde::Deserialize;
 
-use rustic_core::{KeyOptions, RepositoryOptions};
+use rustic_core::{
+    repofile::FileType, Id, KeyOptions, ReadBackend, Repository, RepositoryOptions, WriteBackend,
+};
 
 /// `key` subcommand
 #[derive(clap::Parser, Command, Debug)]
@@ -23,24 +33,34 @@ pub(super) struct KeyCmd {
 enum KeySubCmd {
     /// Add a new key to the repository
     Add(AddCmd),
+    /// List the keys of the repository
+    List(ListCmd),
+    /// Remove a key from the repository
+    Remove(RemoveCmd),
+    /// Change the password of the key used to open the repository, i.e. add a new key and remove
+    /// the current one
+    Passwd(PasswdCmd),
+    /// Ask for the repository password and store it in the keyring entry given by
+    /// --password-keyring
+    StorePassword(StorePasswordCmd),
 }
 
 #[derive(clap::Parser, Debug)]
 pub(crate) struct AddCmd {
-    /// New password
-    #[clap(long)]
+    /// New password; if no new password is given, it is asked for
+    #[clap(long, value_name = "PASSWORD")]
     pub(crate) new_password: Option<String>,
 
     /// File from which to read the new password
-    #[clap(long)]
+    #[clap(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
     pub(crate) new_password_file: Option<PathBuf>,
 
     /// Command to get the new password from
-    #[clap(long)]
+    #[clap(long, value_name = "COMMAND")]
     pub(crate) new_password_command: Vec<String>,
 
-    /// Key options
-    #[clap(flatten)]
+    /// Key options, e.g. the hostname and username stored in the new key
+    #[clap(flatten, next_help_heading = "Key options")]
     pub(crate) key_opts: KeyOptions,
 }
 
@@ -64,6 +84,22 @@ impl AddCmd {
         let config = RUSTIC_APP.config();
         let repo = open_repository(&config.repository)?;
 
+        if config.global.dry_run {
+            info!("would have added a new key.");
+            return Ok(());
+        }
+        let id = self.add_key(&repo)?;
+        info!("key {id} successfully added.");
+
+        Ok(())
+    }
+
+    /// Add a new key, asking for the new password if it is not given
+    ///
+    /// # Returns
+    ///
+    /// The id of the new key
+    fn add_key<P, S>(&self, repo: &Repository<P, S>) -> Result<Id> {
         // create new Repository options which just contain password information
         let pass_opts = RepositoryOptions {
             password: self.new_password.clone(),
@@ -84,9 +120,237 @@ impl AddCmd {
                     .interact()?)
             })?;
 
-        let id = repo.add_key(&pass, &self.key_opts)?;
-        info!("key {id} successfully added.");
+        Ok(repo.add_key(&pass, &self.key_opts)?)
+    }
+}
+
+/// `key list` subcommand
+#[derive(clap::Parser, Debug)]
+pub(crate) struct ListCmd {}
+
+/// Metadata of a key as stored in its (unencrypted) key file
+#[derive(Debug, Deserialize)]
+struct KeyInfo {
+    /// Host on which the key was created
+    hostname: Option<String>,
+    /// User who created the key
+    username: Option<String>,
+    /// Creation time
+    created: Option<DateTime<Local>>,
+}
+
+impl Runnable for ListCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl ListCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let repo = open_repository(&config.repository)?;
+        let be = get_backends(&config.repository)?.repository();
+
+        let mut table = table_with_titles(["ID", "Created", "Host", "User", "In use"]);
+        let mut count = 0;
+        for id in repo.list(FileType::Key)? {
+            let info: KeyInfo = serde_json::from_slice(&be.read_full(FileType::Key, &id)?)?;
+            let unknown = || "?".to_string();
+            _ = table.add_row([
+                id.to_hex().to_string(),
+                info.created
+                    .map_or_else(unknown, |time| time.format("%Y-%m-%d %H:%M:%S").to_string()),
+                info.hostname.unwrap_or_else(unknown),
+                info.username.unwrap_or_else(unknown),
+                if id == *repo.key_id() { "*" } else { "" }.to_string(),
+            ]);
+            count += 1;
+        }
+        println!("{table}");
+        println!("{count} key(s)");
+
+        Ok(())
+    }
+}
+
+/// `key remove` subcommand
+#[derive(clap::Parser, Debug)]
+pub(crate) struct RemoveCmd {
+    /// Key to remove, given by its id or a unique prefix of it
+    #[clap(value_name = "ID")]
+    id: String,
+}
+
+impl Runnable for RemoveCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl RemoveCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let repo = open_repository(&config.repository)?;
+        let keys: Vec<_> = repo.list(FileType::Key)?.collect();
+        let id = find_id(keys.iter().copied(), FileType::Key, &self.id)?;
+        check_removable(keys.len(), &id, repo.key_id())?;
+
+        if config.global.dry_run {
+            info!("would have removed key {id}.");
+            return Ok(());
+        }
+        remove_key(&id)?;
+        info!("key {id} successfully removed.");
+
+        Ok(())
+    }
+}
+
+/// `key passwd` subcommand
+#[derive(clap::Parser, Debug)]
+pub(crate) struct PasswdCmd {
+    /// Options for the new key
+    #[clap(flatten)]
+    add: AddCmd,
+}
+
+impl Runnable for PasswdCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl PasswdCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let repo = open_repository(&config.repository)?;
+        let old_id = *repo.key_id();
+
+        if config.global.dry_run {
+            info!("would have replaced key {old_id} by a new key.");
+            return Ok(());
+        }
+        // the old key is only removed once the new key has been saved
+        let id = self.add.add_key(&repo)?;
+        remove_key(&old_id).with_context(|| {
+            format!("new key {id} has been added, but removing the old key {old_id} failed")
+        })?;
+        info!("key {old_id} successfully replaced by key {id}.");
+
+        Ok(())
+    }
+}
+
+#[derive(clap::Parser, Debug)]
+pub(crate) struct StorePasswordCmd {}
+
+impl Runnable for StorePasswordCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl StorePasswordCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let Some(entry) = &config.repository.password_keyring else {
+            bail!("no keyring entry given, please use --password-keyring SERVICE/ACCOUNT.");
+        };
+        let pass = Password::new()
+            .with_prompt("enter repository password")
+            .allow_empty_password(true)
+            .interact()?;
+
+        // only store a password which opens the repository; the keyring entry may not exist yet
+        let mut repo_opts = config.repository.clone();
+        repo_opts.password_keyring = None;
+        _ = get_repository(&repo_opts)?
+            .open_with_password(&pass)
+            .context("the password doesn't open the repository")?;
+
+        if config.global.dry_run {
+            info!("would have stored the password in keyring {entry}.");
+            return Ok(());
+        }
+        password_keyring::store_password(entry, &pass)?;
+        info!("password stored in keyring {entry}.");
+        Ok(())
+    }
+}
+
+/// Check if a key may be removed
+///
+/// # Arguments
+///
+/// * `keys` - the number of keys of the repository
+/// * `id` - the key to remove
+/// * `current` - the key used to open the repository
+///
+/// # Errors
+///
+/// * If the key is the last key of the repository
+/// * If the key is used to open the repository
+fn check_removable(keys: usize, id: &Id, current: &Id) -> Result<()> {
+    if keys <= 1 {
+        bail!("key {id} is the only key of the repository, refusing to remove it.");
+    }
+    if id == current {
+        bail!("key {id} is used to open the repository, refusing to remove it. Use `key passwd` to replace it or open the repository with another key.");
+    }
+    Ok(())
+}
+
+/// Remove a key file from the repository and, if given, from the hot repository
+///
+/// Key files are written to both repositories, so the key must be removed from both such that its
+/// password can't be used anymore.
+///
+/// # Arguments
+///
+/// * `id` - the id of the key to remove
+fn remove_key(id: &Id) -> Result<()> {
+    let backends = get_backends(&RUSTIC_APP.config().repository)?;
+    backends.repository().remove(FileType::Key, id, false)?;
+    if let Some(hot) = backends.repo_hot() {
+        // the key may be missing in the hot repository, e.g. if it was added before
+        if hot.list(FileType::Key)?.contains(id) {
+            hot.remove(FileType::Key, id, false)
+                .with_context(|| format!("error removing key {id} from the hot repository"))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_removable_passes() -> Result<()> {
+        let id1 = Id::from_hex(&"1".repeat(64))?;
+        let id2 = Id::from_hex(&"2".repeat(64))?;
 
+        check_removable(2, &id2, &id1)?;
+        assert!(check_removable(1, &id2, &id1)
+            .unwrap_err()
+            .to_string()
+            .contains("only key"));
+        assert!(check_removable(2, &id1, &id1)
+            .unwrap_err()
+            .to_string()
+            .contains("used to open the repository"));
         Ok(())
     }
 }
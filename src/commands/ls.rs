@@ -1,11 +1,14 @@
 //! `ls` subcommand
 
-use std::path::Path;
+use std::{io::Write, path::Path};
 
-use crate::{commands::open_repository_indexed, status_err, Application, RUSTIC_APP};
+use crate::{
+    commands::open_repository_indexed, output::Output, status_err, Application, RUSTIC_APP,
+};
 
 use abscissa_core::{Command, Runnable, Shutdown};
 use anyhow::Result;
+use serde::Serialize;
 
 use rustic_core::{
     repofile::{Node, NodeType},
@@ -47,6 +50,18 @@ pub(crate) struct LsCmd {
     #[clap(long, long("numeric-uid-gid"))]
     numeric_id: bool,
 
+    /// only list entries up to the given depth below the listed path (implies --recursive)
+    #[clap(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// separate entries by NUL characters instead of newlines, e.g. for `xargs -0`
+    #[clap(long, conflicts_with_all = ["long", "json"])]
+    print0: bool,
+
+    /// show entries in json format, one object per line
+    #[clap(long, conflicts_with = "long")]
+    json: bool,
+
     /// Listing options
     #[clap(flatten)]
     ls_opts: LsOptions,
@@ -54,7 +69,7 @@ pub(crate) struct LsCmd {
 
 impl Runnable for LsCmd {
     fn run(&self) {
-        if let Err(err) = self.inner_run() {
+        if let Err(err) = self.inner_run(&mut Output::stdout()) {
             status_err!("{}", err);
             RUSTIC_APP.shutdown(Shutdown::Crash);
         };
@@ -64,7 +79,7 @@ impl Runnable for LsCmd {
 /// Sumary of a ls command
 ///
 /// This struct is used to print a summary of the ls command.
-#[derive(Default)]
+#[derive(Default, Serialize)]
 pub struct Summary {
     pub files: usize,
     pub size: u64,
@@ -122,7 +137,7 @@ impl NodeLs for Node {
 }
 
 impl LsCmd {
-    fn inner_run(&self) -> Result<()> {
+    fn inner_run(&self, out: &mut Output<impl Write>) -> Result<()> {
         let config = RUSTIC_APP.config();
         let repo = open_repository_indexed(&config.repository)?;
 
@@ -131,39 +146,144 @@ impl LsCmd {
 
         // recursive if standard if we specify a snapshot without dirs. In other cases, use the parameter `recursive`
         let mut ls_opts = self.ls_opts.clone();
-        ls_opts.recursive = !self.snap.contains(':') || ls_opts.recursive;
+        ls_opts.recursive =
+            !self.snap.contains(':') || ls_opts.recursive || self.max_depth.is_some();
 
-        let mut summary = Summary::default();
+        let mut printer = LsPrinter {
+            out,
+            format: if self.json {
+                LsFormat::Json
+            } else if self.print0 {
+                LsFormat::Print0
+            } else if self.long {
+                LsFormat::Long {
+                    numeric_id: self.numeric_id,
+                }
+            } else {
+                LsFormat::Plain
+            },
+            summary: Summary::default(),
+        };
 
         for item in repo.ls(&node, &ls_opts)? {
             let (path, node) = item?;
-            summary.update(&node);
-            if self.long {
-                print_node(&node, &path, self.numeric_id);
-            } else {
-                println!("{path:?} ");
+            // paths are relative to the listed node, so the depth is the number of components
+            if self
+                .max_depth
+                .is_some_and(|depth| path.components().count() > depth)
+            {
+                continue;
             }
+            printer.print(&path, &node)?;
         }
 
         if self.summary {
-            println!(
+            printer.finish()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Output format of the ls command
+#[derive(Debug, Clone, Copy)]
+enum LsFormat {
+    /// only the paths, one per line
+    Plain,
+    /// only the paths, separated by NUL characters
+    Print0,
+    /// long listing, see [`node_line`]
+    Long {
+        /// show uid/gid instead of user/group
+        numeric_id: bool,
+    },
+    /// json objects, one per line
+    Json,
+}
+
+/// An entry as shown in the json output
+#[derive(Serialize)]
+struct LsRecord<'a> {
+    /// path of the entry
+    path: &'a Path,
+    /// the entry
+    #[serde(flatten)]
+    node: &'a Node,
+}
+
+/// Printer for the entries listed by the ls command
+struct LsPrinter<'a, W> {
+    /// the output to print to
+    out: &'a mut W,
+    /// the output format
+    format: LsFormat,
+    /// summary of the printed entries
+    summary: Summary,
+}
+
+impl<W: Write> LsPrinter<'_, W> {
+    /// Print an entry
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the path of the entry
+    /// * `node` - the entry
+    fn print(&mut self, path: &Path, node: &Node) -> Result<()> {
+        self.summary.update(node);
+        match self.format {
+            LsFormat::Plain => writeln!(self.out, "{path:?} ")?,
+            LsFormat::Print0 => {
+                self.out.write_all(path.as_os_str().as_encoded_bytes())?;
+                self.out.write_all(b"\0")?;
+            }
+            LsFormat::Long { numeric_id } => {
+                writeln!(self.out, "{}", node_line(node, path, numeric_id))?;
+            }
+            LsFormat::Json => {
+                serde_json::to_writer(&mut *self.out, &LsRecord { path, node })?;
+                writeln!(self.out)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Print the summary
+    fn finish(mut self) -> Result<()> {
+        let summary = &self.summary;
+        match self.format {
+            LsFormat::Json => {
+                /// The summary as shown in the json output
+                #[derive(Serialize)]
+                struct JsonSummary<'a> {
+                    summary: &'a Summary,
+                }
+                serde_json::to_writer(&mut *self.out, &JsonSummary { summary })?;
+                writeln!(self.out)?;
+            }
+            // don't mix the summary into the NUL-separated list
+            LsFormat::Print0 => eprintln!(
+                "total: {} dirs, {} files, {} bytes",
+                summary.dirs, summary.files, summary.size
+            ),
+            _ => writeln!(
+                self.out,
                 "total: {} dirs, {} files, {} bytes",
                 summary.dirs, summary.files, summary.size
-            );
+            )?,
         }
-
         Ok(())
     }
 }
 
-/// Print node in format similar to unix `ls`
+/// Format node in format similar to unix `ls`
 ///
 /// # Arguments
 ///
-/// * `node` - the node to print
+/// * `node` - the node to format
 /// * `path` - the path of the node
-pub fn print_node(node: &Node, path: &Path, numeric_uid_gid: bool) {
-    println!(
+/// * `numeric_uid_gid` - show uid/gid instead of user/group
+pub(crate) fn node_line(node: &Node, path: &Path, numeric_uid_gid: bool) -> String {
+    format!(
         "{:>10} {:>8} {:>8} {:>9} {:>12} {path:?} {}",
         node.mode_str(),
         if numeric_uid_gid {
@@ -184,7 +304,7 @@ pub fn print_node(node: &Node, path: &Path, numeric_uid_gid: bool) {
             .map(|t| t.format("%_d %b %H:%M").to_string())
             .unwrap_or_else(|| "?".to_string()),
         node.link_str(),
-    );
+    )
 }
 
 /// Convert permissions into readable format
@@ -220,3 +340,62 @@ fn triplet(mode: u32, read: u32, write: u32, execute: u32) -> String {
     }
     .to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use rustic_core::repofile::Metadata;
+
+    fn print_all(format: LsFormat) -> Result<String> {
+        let entries = [
+            ("dir", NodeType::Dir, 0),
+            ("dir/a b", NodeType::File, 3),
+            ("dir/c", NodeType::File, 5),
+        ];
+        let mut out = Vec::new();
+        let mut printer = LsPrinter {
+            out: &mut out,
+            format,
+            summary: Summary::default(),
+        };
+        for (path, node_type, size) in entries {
+            let meta = Metadata {
+                size,
+                ..Default::default()
+            };
+            let name = Path::new(path).file_name().unwrap().to_string_lossy();
+            printer.print(
+                Path::new(path),
+                &Node::new(name.to_string(), node_type, meta),
+            )?;
+        }
+        printer.finish()?;
+        Ok(String::from_utf8(out)?)
+    }
+
+    #[test]
+    fn test_ls_print0_passes() -> Result<()> {
+        assert_eq!(print_all(LsFormat::Print0)?, "dir\0dir/a b\0dir/c\0");
+        Ok(())
+    }
+
+    #[test]
+    fn test_ls_json_passes() -> Result<()> {
+        let output = print_all(LsFormat::Json)?;
+        let lines: Vec<serde_json::Value> = output
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<serde_json::Result<_>>()?;
+        let paths: Vec<_> = lines[..3].iter().map(|line| &line["path"]).collect();
+        assert_eq!(paths, ["dir", "dir/a b", "dir/c"]);
+        assert_eq!(lines[1]["name"], "a b");
+        assert_eq!(lines[1]["size"], 3);
+        assert_eq!(
+            lines[3],
+            serde_json::json!({"summary": {"files": 2, "size": 8, "dirs": 1}})
+        );
+        Ok(())
+    }
+}
@@ -1,13 +1,24 @@
 //! `merge` subcommand
 
-use crate::{commands::open_repository, status_err, Application, RUSTIC_APP};
+use crate::{commands::open_repository, output::Output, status_err, Application, RUSTIC_APP};
 use abscissa_core::{Command, Runnable, Shutdown};
-use anyhow::Result;
-use log::info;
+use anyhow::{bail, Result};
+use log::{info, warn};
+
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    ffi::OsString,
+    io::Write,
+    path::PathBuf,
+};
 
 use chrono::Local;
+use itertools::Itertools;
 
-use rustic_core::{last_modified_node, repofile::SnapshotFile, SnapshotOptions};
+use rustic_core::{
+    last_modified_node, repofile::Node, repofile::SnapshotFile, IndexedTree, LsOptions,
+    ProgressBars, Repository, SnapshotOptions,
+};
 
 /// `merge` subcommand
 #[derive(clap::Parser, Default, Command, Debug)]
@@ -21,17 +32,34 @@ pub(super) struct MergeCmd {
     json: bool,
 
     /// Remove input snapshots after merging
-    #[clap(long)]
+    #[clap(long, alias = "delete-inputs")]
     delete: bool,
 
+    /// How to resolve paths which are contained in several snapshots with different content
+    #[clap(long, value_enum, default_value_t)]
+    conflict: Conflict,
+
     /// Snapshot options
     #[clap(flatten, next_help_heading = "Snapshot options")]
     snap_opts: SnapshotOptions,
 }
 
+/// Resolution of paths contained in several of the merged snapshots with different content
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+enum Conflict {
+    /// Use the entry modified last
+    #[default]
+    Newest,
+    /// Use the entry of the first snapshot containing the path, in the order the snapshots are
+    /// given
+    First,
+    /// Don't merge the snapshots
+    Error,
+}
+
 impl Runnable for MergeCmd {
     fn run(&self) {
-        if let Err(err) = self.inner_run() {
+        if let Err(err) = self.inner_run(&mut Output::stdout()) {
             status_err!("{}", err);
             RUSTIC_APP.shutdown(Shutdown::Crash);
         };
@@ -39,7 +67,7 @@ impl Runnable for MergeCmd {
 }
 
 impl MergeCmd {
-    fn inner_run(&self) -> Result<()> {
+    fn inner_run(&self, out: &mut Output<impl Write>) -> Result<()> {
         let config = RUSTIC_APP.config();
         let repo = open_repository(&config.repository)?.to_indexed_ids()?;
 
@@ -49,13 +77,72 @@ impl MergeCmd {
             repo.get_snapshots(&self.ids)?
         };
 
-        let snap = SnapshotFile::from_options(&self.snap_opts)?;
+        // the trees are only read if conflicts must be known
+        let layout = (config.global.dry_run || self.conflict != Conflict::Newest)
+            .then(|| merge_layout(&repo, &snapshots))
+            .transpose()?
+            .unwrap_or_default();
+
+        if config.global.dry_run {
+            writeln!(out, "merged snapshot would contain:")?;
+            for (name, indices) in &layout.top_level {
+                let ids = indices.iter().map(|&i| snapshots[i].id).join(", ");
+                writeln!(out, "  {} (from {ids})", name.to_string_lossy())?;
+            }
+            for (path, indices) in &layout.conflicts {
+                let ids = indices.iter().map(|&i| snapshots[i].id).join(", ");
+                writeln!(out, "conflict: {} (in {ids})", path.display())?;
+            }
+        }
+
+        if let Some(path) = layout.conflicts.keys().next() {
+            if self.conflict == Conflict::Error {
+                bail!(
+                    "{} paths differ between the snapshots, e.g. {}. Aborting.",
+                    layout.conflicts.len(),
+                    path.display()
+                );
+            }
+            warn!(
+                "{} paths differ between the snapshots, using the entries of the {} snapshot.",
+                layout.conflicts.len(),
+                if self.conflict == Conflict::First {
+                    "first"
+                } else {
+                    "newest"
+                }
+            );
+        }
+
+        if config.global.dry_run {
+            return Ok(());
+        }
 
-        let snap = repo.merge_snapshots(&snapshots, &last_modified_node, snap)?;
+        let mut snap = SnapshotFile::from_options(&self.snap_opts)?;
+        let sources = format!(
+            "merged from snapshots {}",
+            snapshots.iter().map(|sn| sn.id).join(", ")
+        );
+        snap.description = Some(match snap.description.take() {
+            Some(description) => format!("{description}\n\n{sources}"),
+            None => sources,
+        });
+
+        let snap = if self.conflict == Conflict::First {
+            let first = |node1: &Node, node2: &Node| match (layout.rank(node1), layout.rank(node2))
+            {
+                // a lower snapshot index is preferred
+                (Some(rank1), Some(rank2)) => rank2.cmp(&rank1),
+                _ => last_modified_node(node1, node2),
+            };
+            repo.merge_snapshots(&snapshots, &first, snap)?
+        } else {
+            repo.merge_snapshots(&snapshots, &last_modified_node, snap)?
+        };
+        config.hooks.snapshots_created([&snap]);
 
         if self.json {
-            let mut stdout = std::io::stdout();
-            serde_json::to_writer_pretty(&mut stdout, &snap)?;
+            serde_json::to_writer_pretty(&mut *out, &snap)?;
         }
         info!("saved new snapshot as {}.", snap.id);
 
@@ -68,8 +155,142 @@ impl MergeCmd {
                 .map(|sn| sn.id)
                 .collect();
             repo.delete_snapshots(&snap_ids)?;
+            let removed: Vec<_> = snapshots
+                .into_iter()
+                .filter(|sn| snap_ids.contains(&sn.id))
+                .collect();
+            config.hooks.snapshots_removed(&removed);
+        }
+
+        Ok(())
+    }
+}
+
+/// Entries of the snapshots to merge
+#[derive(Debug, Default)]
+struct MergeLayout {
+    /// Indices of the snapshots containing each top-level entry
+    top_level: BTreeMap<OsString, Vec<usize>>,
+    /// First entry of each path together with the index of its snapshot
+    entries: HashMap<PathBuf, (usize, Node)>,
+    /// Paths with different content in several snapshots with the indices of these snapshots
+    conflicts: BTreeMap<PathBuf, Vec<usize>>,
+    /// Index of the first snapshot containing each conflicting entry, keyed by the serialized entry
+    ranks: HashMap<String, usize>,
+}
+
+impl MergeLayout {
+    /// Add an entry of a snapshot
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - the index of the snapshot; entries must be added in the order of the snapshots
+    /// * `path` - the path of the entry
+    /// * `node` - the entry
+    fn add(&mut self, index: usize, path: PathBuf, node: Node) -> Result<()> {
+        if path.components().count() == 1 {
+            let indices = self
+                .top_level
+                .entry(path.as_os_str().to_os_string())
+                .or_default();
+            if indices.last() != Some(&index) {
+                indices.push(index);
+            }
+        }
+        match self.entries.entry(path) {
+            Entry::Vacant(entry) => {
+                _ = entry.insert((index, node));
+            }
+            Entry::Occupied(entry) => {
+                let (first, first_node) = entry.get();
+                if differs(first_node, &node) {
+                    self.conflicts
+                        .entry(entry.key().clone())
+                        .or_insert_with(|| vec![*first])
+                        .push(index);
+                    _ = self
+                        .ranks
+                        .entry(serde_json::to_string(first_node)?)
+                        .or_insert(*first);
+                    _ = self
+                        .ranks
+                        .entry(serde_json::to_string(&node)?)
+                        .or_insert(index);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the index of the first snapshot containing a conflicting entry
+    fn rank(&self, node: &Node) -> Option<usize> {
+        let key = serde_json::to_string(node).ok()?;
+        self.ranks.get(&key).copied()
+    }
+}
+
+/// Check if two entries of the same path conflict, i.e. aren't both directories and differ in type
+/// or content
+fn differs(node1: &Node, node2: &Node) -> bool {
+    !(node1.is_dir() && node2.is_dir())
+        && (node1.node_type != node2.node_type || node1.content != node2.content)
+}
+
+/// Read the entries of all snapshots to merge
+///
+/// # Arguments
+///
+/// * `repo` - the repository
+/// * `snapshots` - the snapshots to merge
+fn merge_layout<P: ProgressBars, S: IndexedTree>(
+    repo: &Repository<P, S>,
+    snapshots: &[SnapshotFile],
+) -> Result<MergeLayout> {
+    let mut layout = MergeLayout::default();
+    for (index, sn) in snapshots.iter().enumerate() {
+        let node = repo.node_from_snapshot_and_path(sn, "")?;
+        for item in repo.ls(&node, &LsOptions::default())? {
+            let (path, node) = item?;
+            layout.add(index, path, node)?;
         }
+    }
+    Ok(layout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use rustic_core::repofile::{Metadata, NodeType};
+
+    fn node(name: &str, node_type: NodeType) -> Node {
+        Node::new(name.to_string(), node_type, Metadata::default())
+    }
+
+    #[test]
+    fn test_merge_layout_passes() -> Result<()> {
+        let mut layout = MergeLayout::default();
+        layout.add(0, PathBuf::from("home"), node("home", NodeType::Dir))?;
+        layout.add(0, PathBuf::from("home/a"), node("a", NodeType::File))?;
+        layout.add(1, PathBuf::from("home"), node("home", NodeType::Dir))?;
+        layout.add(1, PathBuf::from("home/a"), node("a", NodeType::Dir))?;
+        layout.add(1, PathBuf::from("etc"), node("etc", NodeType::Dir))?;
+        // same type and content is no conflict
+        layout.add(2, PathBuf::from("home/a"), node("a", NodeType::File))?;
 
+        assert_eq!(
+            layout.top_level.into_iter().collect::<Vec<_>>(),
+            vec![
+                (OsString::from("etc"), vec![1]),
+                (OsString::from("home"), vec![0, 1])
+            ]
+        );
+        assert_eq!(
+            layout.conflicts.into_iter().collect::<Vec<_>>(),
+            vec![(PathBuf::from("home/a"), vec![0, 1])]
+        );
+        assert_eq!(layout.ranks.len(), 2);
         Ok(())
     }
 }
@@ -0,0 +1,170 @@
+//! `mount` subcommand
+
+// ignore markdown clippy lints as we use doc-comments to generate clap help texts
+#![allow(clippy::doc_markdown)]
+
+#[cfg(not(unix))]
+compile_error!("the mount feature is only supported on Linux and macOS");
+
+mod fusefs;
+
+use std::{ffi::OsStr, path::PathBuf, str::FromStr, sync::mpsc, time::Duration};
+
+use crate::{commands::open_repository_indexed, status_err, Application, RusticConfig, RUSTIC_APP};
+use abscissa_core::{config::Override, Command, FrameworkError, Runnable, Shutdown};
+use anyhow::{anyhow, Result};
+use bytesize::ByteSize;
+use fuse_mt::FuseMT;
+use log::info;
+use merge::Merge;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+
+use rustic_core::vfs::{FilePolicy, IdenticalSnapshot, Latest, Vfs};
+
+use self::fusefs::FuseFS;
+
+/// Default maximum size of cached file contents
+const DEFAULT_BLOB_CACHE_SIZE: ByteSize = ByteSize::mib(64);
+
+#[serde_as]
+#[derive(Clone, Command, Default, Debug, clap::Parser, Serialize, Deserialize, Merge)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct MountCmd {
+    /// The path template to use for snapshots. {id}, {id_long}, {time}, {username}, {hostname}, {label}, {tags}, {backup_start}, {backup_end} are replaced. [default: "[{hostname}]/[{label}]/{time}"]
+    #[clap(long)]
+    path_template: Option<String>,
+
+    /// The time template to use to display times in the path template. See https://docs.rs/chrono/latest/chrono/format/strftime/index.html for format options. [default: "%Y-%m-%d_%H-%M-%S"]
+    #[clap(long)]
+    time_template: Option<String>,
+
+    /// Don't allow other users to access the mount point
+    #[clap(long)]
+    #[merge(strategy = merge::bool::overwrite_false)]
+    exclusive: bool,
+
+    /// How to handle access to files. [default: "forbidden" for hot/cold repositories, else "read"]
+    #[clap(long)]
+    file_access: Option<String>,
+
+    /// Maximum size of file contents cached in memory (e.g. 256MiB); 0 disables the cache.
+    /// [default: 64MiB]
+    #[clap(long, value_name = "SIZE")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    blob_cache_size: Option<ByteSize>,
+
+    /// The mount point to use
+    #[clap(value_name = "PATH")]
+    mount_point: Option<PathBuf>,
+
+    /// Specify directly which snapshot/path to mount
+    #[clap(value_name = "SNAPSHOT[:PATH]")]
+    snapshot_path: Option<String>,
+}
+
+impl Override<RusticConfig> for MountCmd {
+    // Process the given command line options, overriding settings from
+    // a configuration file using explicit flags taken from command-line
+    // arguments.
+    fn override_config(&self, mut config: RusticConfig) -> Result<RusticConfig, FrameworkError> {
+        let mut self_config = self.clone();
+        // merge "mount" section from config file, if given
+        self_config.merge(config.mount);
+        config.mount = self_config;
+        Ok(config)
+    }
+}
+
+impl Runnable for MountCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl MountCmd {
+    /// be careful about self VS RUSTIC_APP.config() usage
+    /// only the RUSTIC_APP.config() involves the TOML and ENV merged configurations
+    /// see https://github.com/rustic-rs/rustic/issues/1242
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let mount_point = config
+            .mount
+            .mount_point
+            .clone()
+            .ok_or_else(|| anyhow!("please specify a mount point"))?;
+
+        let repo = open_repository_indexed(&config.repository)?;
+
+        let path_template = config
+            .mount
+            .path_template
+            .clone()
+            .unwrap_or_else(|| "[{hostname}]/[{label}]/{time}".to_string());
+        let time_template = config
+            .mount
+            .time_template
+            .clone()
+            .unwrap_or_else(|| "%Y-%m-%d_%H-%M-%S".to_string());
+
+        let sn_filter = |sn: &_| config.snapshot_filter.matches(sn);
+
+        let vfs = if let Some(snap) = &config.mount.snapshot_path {
+            let node = repo.node_from_snapshot_path(snap, sn_filter)?;
+            Vfs::from_dir_node(&node)
+        } else {
+            let snapshots = repo.get_matching_snapshots(sn_filter)?;
+            Vfs::from_snapshots(
+                snapshots,
+                &path_template,
+                &time_template,
+                Latest::AsLink,
+                IdenticalSnapshot::AsLink,
+            )?
+        };
+
+        let file_access = config.mount.file_access.as_ref().map_or_else(
+            || {
+                if repo.config().is_hot == Some(true) {
+                    Ok(FilePolicy::Forbidden)
+                } else {
+                    Ok(FilePolicy::Read)
+                }
+            },
+            |s| FilePolicy::from_str(s),
+        )?;
+
+        let mut mount_options = "ro,default_permissions,fsname=rusticfs".to_string();
+        if !config.mount.exclusive {
+            mount_options.push_str(",allow_other");
+        }
+        let options = [OsStr::new("-o"), OsStr::new(&mount_options)];
+
+        let blob_cache_size = config
+            .mount
+            .blob_cache_size
+            .unwrap_or(DEFAULT_BLOB_CACHE_SIZE);
+        let fs = FuseMT::new(
+            FuseFS::new(repo, vfs, file_access, blob_cache_size.as_u64()),
+            1,
+        );
+        let session = fuse_mt::spawn_mount(fs, &mount_point, &options)?;
+        info!("mounted repository at {}", mount_point.display());
+
+        // wait until we get SIGINT/SIGTERM or the filesystem is unmounted externally
+        let (tx, rx) = mpsc::channel();
+        ctrlc::set_handler(move || {
+            _ = tx.send(());
+        })?;
+        while rx.recv_timeout(Duration::from_secs(1)).is_err() && !session.guard.is_finished() {}
+
+        // dropping the session unmounts the filesystem
+        drop(session);
+        info!("unmounted {}", mount_point.display());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,405 @@
+//! Read-only FUSE filesystem for the snapshots within a repository
+
+use std::{
+    collections::BTreeMap,
+    ffi::{CString, OsStr},
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use bytes::Bytes;
+use cached::{Cached, SizedCache};
+use fuse_mt::{
+    CallbackResult, DirectoryEntry, FileAttr, FileType, FilesystemMT, RequestInfo, ResultData,
+    ResultEmpty, ResultEntry, ResultOpen, ResultReaddir, ResultSlice, ResultXattr, Xattr,
+};
+use itertools::Itertools;
+use rustic_core::{
+    repofile::{Node, NodeType},
+    vfs::{FilePolicy, OpenFile, Vfs},
+    IndexedFull, Repository,
+};
+
+/// Time to cache entries and attributes; snapshots don't change, but new snapshots may appear
+const TTL: Duration = Duration::from_secs(1);
+
+/// Size of the blocks in which file contents are read and cached
+const BLOCK_SIZE: usize = 1024 * 1024;
+
+/// Number of nodes whose lookup by path is cached
+const NODE_CACHE_SIZE: usize = 16 * 1024;
+
+/// Number of directories whose entries are cached
+const DIR_CACHE_SIZE: usize = 1024;
+
+/// Cache of file content blocks, by path and block number
+type BlockCache = Mutex<SizedCache<(PathBuf, usize), Bytes>>;
+
+/// Read-only FUSE filesystem using a [`Vfs`]
+pub(super) struct FuseFS<P, S> {
+    /// The repository
+    repo: Repository<P, S>,
+    /// The virtual filesystem
+    vfs: Vfs,
+    /// Currently open files, indexed by file handle
+    open_files: RwLock<BTreeMap<u64, OpenFile>>,
+    /// Time used for virtual directories
+    now: SystemTime,
+    /// Whether opening files is allowed
+    file_policy: FilePolicy,
+    /// Cached nodes by path, such that trees are not loaded again for each lookup
+    nodes: Mutex<SizedCache<PathBuf, Node>>,
+    /// Cached directory entries by path
+    dirs: Mutex<SizedCache<PathBuf, Vec<Node>>>,
+    /// Cached blocks of file contents; `None` if disabled
+    blocks: Option<BlockCache>,
+}
+
+impl<P, S: IndexedFull> FuseFS<P, S> {
+    /// Create a new [`FuseFS`]
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - the repository
+    /// * `vfs` - the virtual filesystem to serve
+    /// * `file_policy` - whether opening files is allowed
+    /// * `blob_cache_size` - the maximum size of cached file contents in bytes; 0 disables caching
+    pub(super) fn new(
+        repo: Repository<P, S>,
+        vfs: Vfs,
+        file_policy: FilePolicy,
+        blob_cache_size: u64,
+    ) -> Self {
+        let blocks = usize::try_from(blob_cache_size / BLOCK_SIZE as u64).unwrap_or(usize::MAX);
+        Self {
+            repo,
+            vfs,
+            open_files: RwLock::new(BTreeMap::new()),
+            now: SystemTime::now(),
+            file_policy,
+            nodes: Mutex::new(SizedCache::with_size(NODE_CACHE_SIZE)),
+            dirs: Mutex::new(SizedCache::with_size(DIR_CACHE_SIZE)),
+            blocks: (blocks > 0).then(|| Mutex::new(SizedCache::with_size(blocks))),
+        }
+    }
+
+    /// Get the node for the given path
+    fn node_from_path(&self, path: &Path) -> Result<Node, i32> {
+        let mut nodes = self.nodes.lock().map_err(|_| libc::EIO)?;
+        if let Some(node) = nodes.cache_get(path) {
+            return Ok(node.clone());
+        }
+        let node = self
+            .vfs
+            .node_from_path(&self.repo, path)
+            .map_err(|_| libc::ENOENT)?;
+        _ = nodes.cache_set(path.to_path_buf(), node.clone());
+        Ok(node)
+    }
+
+    /// Get the entries of the directory with the given path
+    fn dir_entries_from_path(&self, path: &Path) -> Result<Vec<Node>, i32> {
+        let mut dirs = self.dirs.lock().map_err(|_| libc::EIO)?;
+        if let Some(nodes) = dirs.cache_get(path) {
+            return Ok(nodes.clone());
+        }
+        let nodes = self
+            .vfs
+            .dir_entries_from_path(&self.repo, path)
+            .map_err(|_| libc::ENOENT)?;
+        _ = dirs.cache_set(path.to_path_buf(), nodes.clone());
+        Ok(nodes)
+    }
+
+    /// Read from the open file with the given file handle, using the block cache if enabled
+    fn read_file(&self, path: &Path, fh: u64, offset: u64, size: u32) -> Result<Vec<u8>, i32> {
+        let open_files = self.open_files.read().map_err(|_| libc::EIO)?;
+        let open_file = open_files.get(&fh).ok_or(libc::EBADF)?;
+        let offset = usize::try_from(offset).map_err(|_| libc::EINVAL)?;
+        let size = usize::try_from(size).map_err(|_| libc::EINVAL)?;
+        let read_at = |offset, size| {
+            self.repo
+                .read_file_at(open_file, offset, size)
+                .map_err(|_| libc::EIO)
+        };
+        self.blocks.as_ref().map_or_else(
+            || read_at(offset, size).map(|data| data.to_vec()),
+            |cache| {
+                read_blocks(cache, path, offset, size, |offset| {
+                    read_at(offset, BLOCK_SIZE)
+                })
+            },
+        )
+    }
+}
+
+/// Read file contents block-wise, using and filling the block cache
+///
+/// # Arguments
+///
+/// * `cache` - the block cache
+/// * `path` - the path of the file, used as key of the cached blocks
+/// * `offset` - where to start reading
+/// * `size` - the number of bytes to read; less bytes are returned at the end of the file
+/// * `read_block` - read the block starting at the given offset, which is shorter than
+///   [`BLOCK_SIZE`] only at the end of the file
+fn read_blocks(
+    cache: &BlockCache,
+    path: &Path,
+    offset: usize,
+    size: usize,
+    read_block: impl Fn(usize) -> Result<Bytes, i32>,
+) -> Result<Vec<u8>, i32> {
+    let mut data = Vec::with_capacity(size);
+    let end = offset.saturating_add(size);
+    let mut pos = offset;
+    while pos < end {
+        let block = pos / BLOCK_SIZE;
+        let key = (path.to_path_buf(), block);
+        let cached = cache
+            .lock()
+            .map_err(|_| libc::EIO)?
+            .cache_get(&key)
+            .cloned();
+        let bytes = match cached {
+            Some(bytes) => bytes,
+            None => {
+                let bytes = read_block(block * BLOCK_SIZE)?;
+                _ = cache
+                    .lock()
+                    .map_err(|_| libc::EIO)?
+                    .cache_set(key, bytes.clone());
+                bytes
+            }
+        };
+        let start = pos - block * BLOCK_SIZE;
+        if start >= bytes.len() {
+            // end of file
+            break;
+        }
+        let len = (bytes.len() - start).min(end - pos);
+        data.extend_from_slice(&bytes[start..start + len]);
+        pos += len;
+    }
+    Ok(data)
+}
+
+/// Convert a [`NodeType`] to a FUSE [`FileType`]
+const fn node_type_to_file_type(node_type: &NodeType) -> FileType {
+    match node_type {
+        NodeType::File => FileType::RegularFile,
+        NodeType::Dir => FileType::Directory,
+        NodeType::Symlink { .. } => FileType::Symlink,
+        NodeType::Chardev { .. } => FileType::CharDevice,
+        NodeType::Dev { .. } => FileType::BlockDevice,
+        NodeType::Fifo => FileType::NamedPipe,
+        NodeType::Socket => FileType::Socket,
+    }
+}
+
+/// Get the device number of a [`Node`]
+fn node_to_rdev(node: &Node) -> u32 {
+    match node.node_type {
+        NodeType::Dev { device } | NodeType::Chardev { device } => {
+            u32::try_from(device).unwrap_or_default()
+        }
+        _ => 0,
+    }
+}
+
+/// Get the FUSE [`FileAttr`] of a [`Node`]
+///
+/// # Arguments
+///
+/// * `node` - the node
+/// * `now` - time to use if the node has no times set, e.g. for virtual directories
+fn node_to_file_attr(node: &Node, now: SystemTime) -> FileAttr {
+    let meta = &node.meta;
+    let to_time = |time: Option<_>| time.map_or(now, SystemTime::from);
+    let default_perm = if node.is_dir() { 0o555 } else { 0o444 };
+    FileAttr {
+        size: meta.size,
+        blocks: meta.size.div_ceil(512),
+        atime: to_time(meta.atime),
+        mtime: to_time(meta.mtime),
+        ctime: to_time(meta.ctime),
+        crtime: to_time(meta.mtime),
+        kind: node_type_to_file_type(&node.node_type),
+        perm: meta.mode.map_or(default_perm, |mode| {
+            u16::try_from(mode & 0o7777).unwrap_or(default_perm)
+        }),
+        nlink: u32::try_from(meta.links.max(1)).unwrap_or(1),
+        uid: meta.uid.unwrap_or_default(),
+        gid: meta.gid.unwrap_or_default(),
+        rdev: node_to_rdev(node),
+        flags: 0,
+    }
+}
+
+impl<P: Send + Sync, S: IndexedFull + Send + Sync> FilesystemMT for FuseFS<P, S> {
+    fn getattr(&self, _req: RequestInfo, path: &Path, _fh: Option<u64>) -> ResultEntry {
+        let node = self.node_from_path(path)?;
+        Ok((TTL, node_to_file_attr(&node, self.now)))
+    }
+
+    fn readlink(&self, _req: RequestInfo, path: &Path) -> ResultData {
+        let node = self.node_from_path(path)?;
+        if !node.is_symlink() {
+            return Err(libc::EINVAL);
+        }
+        Ok(node
+            .node_type
+            .to_link()
+            .as_os_str()
+            .as_encoded_bytes()
+            .to_vec())
+    }
+
+    fn open(&self, _req: RequestInfo, path: &Path, _flags: u32) -> ResultOpen {
+        if matches!(self.file_policy, FilePolicy::Forbidden) {
+            return Err(libc::EACCES);
+        }
+        let node = self.node_from_path(path)?;
+        if !node.is_file() {
+            return Err(libc::EISDIR);
+        }
+        let open_file = self.repo.open_file(&node).map_err(|_| libc::EIO)?;
+
+        let mut open_files = self.open_files.write().map_err(|_| libc::EIO)?;
+        let fh = open_files
+            .last_key_value()
+            .map_or(0, |(fh, _)| fh.wrapping_add(1));
+        _ = open_files.insert(fh, open_file);
+        Ok((fh, 0))
+    }
+
+    fn release(
+        &self,
+        _req: RequestInfo,
+        _path: &Path,
+        fh: u64,
+        _flags: u32,
+        _lock_owner: u64,
+        _flush: bool,
+    ) -> ResultEmpty {
+        _ = self.open_files.write().map_err(|_| libc::EIO)?.remove(&fh);
+        Ok(())
+    }
+
+    fn read(
+        &self,
+        _req: RequestInfo,
+        path: &Path,
+        fh: u64,
+        offset: u64,
+        size: u32,
+        callback: impl FnOnce(ResultSlice<'_>) -> CallbackResult,
+    ) -> CallbackResult {
+        match self.read_file(path, fh, offset, size) {
+            Ok(data) => callback(Ok(&data)),
+            Err(err) => callback(Err(err)),
+        }
+    }
+
+    fn opendir(&self, _req: RequestInfo, _path: &Path, _flags: u32) -> ResultOpen {
+        Ok((0, 0))
+    }
+
+    fn readdir(&self, _req: RequestInfo, path: &Path, _fh: u64) -> ResultReaddir {
+        let nodes = self.dir_entries_from_path(path)?;
+
+        let entries = [".", ".."]
+            .into_iter()
+            .map(|name| DirectoryEntry {
+                name: name.into(),
+                kind: FileType::Directory,
+            })
+            .chain(nodes.into_iter().map(|node| DirectoryEntry {
+                kind: node_type_to_file_type(&node.node_type),
+                name: node.name(),
+            }))
+            .collect();
+        Ok(entries)
+    }
+
+    fn releasedir(&self, _req: RequestInfo, _path: &Path, _fh: u64, _flags: u32) -> ResultEmpty {
+        Ok(())
+    }
+
+    fn listxattr(&self, _req: RequestInfo, path: &Path, size: u32) -> ResultXattr {
+        let node = self.node_from_path(path)?;
+        let xattrs: Vec<u8> = node
+            .meta
+            .extended_attributes
+            .iter()
+            .filter_map(|attr| CString::new(attr.name.as_bytes()).ok())
+            .map(CString::into_bytes_with_nul)
+            .concat();
+        xattr_result(xattrs, size)
+    }
+
+    fn getxattr(&self, _req: RequestInfo, path: &Path, name: &OsStr, size: u32) -> ResultXattr {
+        let node = self.node_from_path(path)?;
+        let value = node
+            .meta
+            .extended_attributes
+            .into_iter()
+            .find(|attr| OsStr::new(&attr.name) == name)
+            .ok_or(libc::ENODATA)?
+            .value;
+        xattr_result(value.unwrap_or_default(), size)
+    }
+}
+
+/// Answer an extended attribute request, see `getxattr(2)`
+///
+/// # Arguments
+///
+/// * `data` - the requested data
+/// * `size` - the buffer size given by the caller; 0 means the size of the data is requested
+fn xattr_result(data: Vec<u8>, size: u32) -> ResultXattr {
+    if size == 0 {
+        Ok(Xattr::Size(
+            u32::try_from(data.len()).map_err(|_| libc::E2BIG)?,
+        ))
+    } else if data.len() > size as usize {
+        Err(libc::ERANGE)
+    } else {
+        Ok(Xattr::Data(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_read_blocks_passes() -> Result<(), i32> {
+        let file: Vec<u8> = (0..BLOCK_SIZE + 100).map(|i| (i % 251) as u8).collect();
+        let reads = AtomicUsize::new(0);
+        let read_block = |offset: usize| {
+            _ = reads.fetch_add(1, Ordering::Relaxed);
+            let end = (offset + BLOCK_SIZE).min(file.len());
+            Ok(Bytes::copy_from_slice(&file[offset.min(end)..end]))
+        };
+        let cache = Mutex::new(SizedCache::with_size(4));
+        let path = Path::new("file");
+
+        // read across the block boundary and beyond the end of the file
+        let offset = BLOCK_SIZE - 10;
+        let data = read_blocks(&cache, path, offset, 1000, read_block)?;
+        assert_eq!(data, file[offset..]);
+        assert_eq!(reads.load(Ordering::Relaxed), 2);
+
+        // the blocks are now cached
+        let data = read_blocks(&cache, path, 5, 10, read_block)?;
+        assert_eq!(data, file[5..15]);
+        assert_eq!(reads.load(Ordering::Relaxed), 2);
+        Ok(())
+    }
+}
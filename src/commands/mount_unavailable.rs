@@ -0,0 +1,33 @@
+//! `mount` subcommand if rustic is compiled without the `mount` feature
+
+use crate::{status_err, Application, RUSTIC_APP};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{bail, Result};
+
+/// `mount` subcommand, only reporting that mounting is not available
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct MountUnavailableCmd {
+    /// All arguments are ignored
+    #[clap(trailing_var_arg = true, allow_hyphen_values = true, hide = true)]
+    args: Vec<String>,
+}
+
+impl Runnable for MountUnavailableCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl MountUnavailableCmd {
+    #[allow(clippy::unused_self)]
+    fn inner_run(&self) -> Result<()> {
+        if cfg!(unix) {
+            bail!("mount is not available: this rustic binary was compiled without the `mount` feature. Please use a build with FUSE support or `rustic webdav`.");
+        }
+        bail!("mount is not available: FUSE is only supported on Linux and macOS. Please use `rustic webdav` instead.");
+    }
+}
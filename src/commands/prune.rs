@@ -1,14 +1,35 @@
 //! `prune` subcommand
 
 use crate::{
-    commands::open_repository, helpers::bytes_size_to_string, status_err, Application, RUSTIC_APP,
+    commands::{get_backends, open_repository, packs_to_warm_up},
+    helpers::bytes_size_to_string,
+    status_err, Application, RUSTIC_APP,
 };
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
 use abscissa_core::{Command, Runnable, Shutdown};
-use log::debug;
+use bytesize::ByteSize;
+use chrono::{Duration, Local};
+use log::{debug, info, warn};
+use merge::Merge;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+
+use rustic_core::{
+    repofile::{BlobType, FileType, IndexFile},
+    Id, LimitOption, PruneOptions, PruneStats, ReadBackend,
+};
 
-use rustic_core::{PruneOptions, PruneStats};
+/// Maximum number of packs listed in the detailed dry-run report
+const MAX_REPORT_PACKS: usize = 20;
 
 /// `prune` subcommand
 #[allow(clippy::struct_excessive_bools)]
@@ -17,6 +38,118 @@ pub(crate) struct PruneCmd {
     /// Prune options
     #[clap(flatten)]
     pub(crate) opts: PruneOptions,
+
+    /// Show the space forecast in json format
+    #[clap(long)]
+    json: bool,
+
+    /// With --dry-run, also list the packs which would be rewritten and why
+    #[clap(long)]
+    verbose: bool,
+
+    /// Stop selecting packs for repacking once their total size exceeds this size (e.g. 10GiB).
+    /// The remaining packs are repacked by later runs, so the repository may be left in a valid
+    /// but not fully pruned state
+    #[clap(long, value_name = "SIZE", conflicts_with = "max_repack")]
+    max_repack_size: Option<ByteSize>,
+
+    /// Don't repack or remove packs newer than this duration, as they may be used by concurrent
+    /// backups of other clients. Also warns about recent activity in the repository [default: no
+    /// grace period]
+    #[clap(long, value_name = "DURATION")]
+    grace_period: Option<humantime::Duration>,
+
+    /// Prune even if lock files of other clients (e.g. restic) are found in the repository. Lock
+    /// files can only be detected in local repositories; for other backends, a warning is shown.
+    #[clap(long)]
+    ignore_foreign_locks: bool,
+
+    /// Thresholds of unused data below which pruning is skipped
+    #[clap(flatten)]
+    pub(crate) thresholds: PruneThresholds,
+}
+
+impl From<PruneOptions> for PruneCmd {
+    fn from(opts: PruneOptions) -> Self {
+        Self {
+            opts,
+            json: false,
+            verbose: false,
+            max_repack_size: None,
+            grace_period: None,
+            ignore_foreign_locks: false,
+            thresholds: PruneThresholds::default(),
+        }
+    }
+}
+
+/// Thresholds of unused data in the repository. If any threshold is given, the repository is
+/// only pruned if the unused data exceeds at least one of them.
+#[serde_as]
+#[derive(Clone, Default, Debug, clap::Parser, Serialize, Deserialize, Merge)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PruneThresholds {
+    /// Only prune if the unused data exceeds this size (e.g. 1GiB). Note that --max-unused sets
+    /// the unused data tolerated after pruning.
+    #[clap(long, value_name = "SIZE")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    max_unused_size: Option<ByteSize>,
+
+    /// Only prune if the unused data exceeds this percentage (0-100) of the repository size
+    #[clap(long, value_name = "PERCENT")]
+    max_unused_percent: Option<f64>,
+}
+
+impl PruneThresholds {
+    /// Check if pruning is needed
+    ///
+    /// # Arguments
+    ///
+    /// * `forecast` - the space forecast of the prune plan
+    ///
+    /// # Errors
+    ///
+    /// * If the percentage is not within 0-100
+    #[allow(clippy::cast_precision_loss)]
+    fn exceeded(&self, forecast: &SpaceForecast) -> Result<bool> {
+        if let Some(percent) = self.max_unused_percent {
+            if !(0.0..=100.0).contains(&percent) {
+                bail!("max-unused-percent must be within 0-100, got {percent}");
+            }
+        }
+        if self.max_unused_size.is_none() && self.max_unused_percent.is_none() {
+            return Ok(true);
+        }
+        let unused = forecast.unused_size_before;
+        let unused_percent = if forecast.total_size == 0 {
+            0.0
+        } else {
+            unused as f64 / forecast.total_size as f64 * 100.0
+        };
+        Ok(self.max_unused_size.is_some_and(|size| unused > size.0)
+            || self
+                .max_unused_percent
+                .is_some_and(|percent| unused_percent > percent))
+    }
+}
+
+/// Prune options which can be set in the `[prune]` section of the config profile
+///
+/// Values given on the command line take precedence.
+#[serde_as]
+#[derive(Clone, Default, Debug, Serialize, Deserialize, Merge)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PruneProfileOptions {
+    /// Only repack packs which are cacheable [default: true for a hot/cold repository, else false]
+    repack_cacheable_only: Option<bool>,
+
+    /// Repack packs containing uncompressed blobs
+    #[merge(strategy = merge::bool::overwrite_false)]
+    repack_uncompressed: bool,
+
+    /// Don't repack or remove packs newer than this duration [default: no grace period]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    grace_period: Option<humantime::Duration>,
 }
 
 impl Runnable for PruneCmd {
@@ -31,22 +164,239 @@ impl Runnable for PruneCmd {
 impl PruneCmd {
     fn inner_run(&self) -> Result<()> {
         let config = RUSTIC_APP.config();
+        self.check_foreign_locks()?;
         let repo = open_repository(&config.repository)?;
 
-        let pruner = repo.prune_plan(&self.opts)?;
+        let mut opts = self.opts.clone();
+        let profile_opts = &config.prune;
+        opts.repack_cacheable_only = opts
+            .repack_cacheable_only
+            .or(profile_opts.repack_cacheable_only);
+        opts.repack_uncompressed |= profile_opts.repack_uncompressed;
+        // the repack limit is checked by the pack selection of the prune plan
+        if let Some(size) = self.max_repack_size {
+            opts.max_repack = LimitOption::Size(size);
+        }
+
+        // packs within the grace period are kept like with --keep-pack
+        if let Some(grace_period) = self.grace_period.or(profile_opts.grace_period) {
+            if *opts.keep_pack < *grace_period {
+                if !opts.keep_pack.is_zero() {
+                    warn!(
+                        "keep-pack {} is shorter than the grace period, keeping packs newer than {grace_period}. Use --grace-period to shorten it.",
+                        opts.keep_pack
+                    );
+                }
+                opts.keep_pack = grace_period;
+            }
 
-        print_stats(&pruner.stats);
+            // warn about other clients which recently saved snapshots
+            let hostname = gethostname::gethostname().to_string_lossy().to_string();
+            let since = Local::now() - Duration::from_std(*grace_period)?;
+            let mut recent = BTreeMap::new();
+            for sn in
+                repo.get_matching_snapshots(|sn| sn.time > since && sn.hostname != hostname)?
+            {
+                let latest = recent.entry(sn.hostname).or_insert(sn.time);
+                *latest = (*latest).max(sn.time);
+            }
+            for (host, time) in recent {
+                warn!(
+                    "host {host} saved a snapshot at {}, which is within the grace period of {grace_period}. Packs newer than that are kept.",
+                    time.format("%Y-%m-%d %H:%M:%S")
+                );
+            }
+            warn_recently_written(grace_period)?;
+        }
+
+        let pruner = repo.prune_plan(&opts)?;
+
+        let forecast = SpaceForecast::new(&pruner.stats);
+        if !self.thresholds.exceeded(&forecast)? {
+            println!(
+                "no pruning needed: {} unused data is within the thresholds.",
+                bytes_size_to_string(forecast.unused_size_before)
+            );
+            return Ok(());
+        }
 
-        if config.global.dry_run {
-            repo.warm_up(pruner.repack_packs().into_iter())?;
+        if self.json {
+            let mut stdout = std::io::stdout();
+            serde_json::to_writer_pretty(&mut stdout, &forecast)?;
+        } else if config.global.dry_run {
+            let packs = self
+                .verbose
+                .then(|| -> Result<_> {
+                    // the pack sizes are only known by the backend
+                    let backends = get_backends(&config.repository)?;
+                    let sizes: HashMap<_, _> = backends
+                        .repository()
+                        .list_with_size(FileType::Pack)?
+                        .into_iter()
+                        .collect();
+                    // the reasons are derived from the blobs of the packs
+                    let ids = pruner.repack_packs();
+                    let repack: HashSet<_> = ids.iter().copied().collect();
+                    let mut blobs = HashMap::new();
+                    for item in repo.stream_files::<IndexFile>()? {
+                        let (_, index) = item?;
+                        for pack in index.packs {
+                            if repack.contains(&pack.id) {
+                                let uncompressed = pack
+                                    .blobs
+                                    .iter()
+                                    .any(|blob| blob.uncompressed_length.is_none());
+                                let tpe = pack.blobs.first().map(|blob| blob.tpe);
+                                _ = blobs.insert(pack.id, (tpe, uncompressed));
+                            }
+                        }
+                    }
+                    Ok(ids
+                        .into_iter()
+                        .map(|id| {
+                            let (tpe, uncompressed) = blobs.get(&id).copied().unwrap_or_default();
+                            RepackPack {
+                                id,
+                                size: sizes.get(&id).map(|size| u64::from(*size)),
+                                tpe,
+                                reason: RepackReason::new(&opts, uncompressed),
+                            }
+                        })
+                        .collect::<Vec<_>>())
+                })
+                .transpose()?;
+            write_dry_run_report(
+                &mut std::io::stdout(),
+                &forecast,
+                packs.as_deref(),
+                &repack_rules(&opts),
+            )?;
         } else {
-            pruner.do_prune(&repo, &self.opts)?;
+            print_stats(&pruner.stats);
         }
 
+        // when pruning, the packs to repack are warmed up by rustic_core
+        _ = packs_to_warm_up(|| Ok(pruner.repack_packs()))?;
+        if !config.global.dry_run {
+            pruner.do_prune(&repo, &opts)?;
+        }
+
+        Ok(())
+    }
+
+    /// Check for lock files of other clients like restic, which are only possible to detect in
+    /// local repositories. rustic itself doesn't use lock files.
+    ///
+    /// # Errors
+    ///
+    /// * If foreign lock files are found and `--ignore-foreign-locks` is not given
+    fn check_foreign_locks(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let Some(location) = &config.repository.be.repository else {
+            return Ok(());
+        };
+        let Some(path) = local_repository_path(location) else {
+            warn!("cannot check for lock files of other clients in {location} as it is not a local repository, please make sure no other client is using the repository.");
+            return Ok(());
+        };
+        let locks = match std::fs::read_dir(path.join("locks")) {
+            Ok(dir) => dir.count(),
+            Err(err) if err.kind() == ErrorKind::NotFound => 0,
+            Err(err) => return Err(err.into()),
+        };
+        match (locks, self.ignore_foreign_locks) {
+            (0, _) => {}
+            (_, true) => warn!("ignoring {locks} lock files of other clients."),
+            (_, false) => bail!(
+                "found {locks} lock files of other clients (e.g. restic) in {location}, refusing to prune. Use --ignore-foreign-locks to prune anyway."
+            ),
+        }
         Ok(())
     }
 }
 
+/// Warn about pack and index files written within the grace period, e.g. by concurrent backups
+///
+/// The backends don't report the modification times of files, so this is only possible for
+/// local repositories.
+///
+/// # Arguments
+///
+/// * `grace_period` - the grace period
+///
+/// # Errors
+///
+/// * If the files of the repository can't be read
+fn warn_recently_written(grace_period: humantime::Duration) -> Result<()> {
+    let config = RUSTIC_APP.config();
+    let Some(path) = config
+        .repository
+        .be
+        .repository
+        .as_deref()
+        .and_then(local_repository_path)
+    else {
+        info!("cannot check the modification times of files in a non-local repository.");
+        return Ok(());
+    };
+    let Some(since) = SystemTime::now().checked_sub(*grace_period) else {
+        return Ok(());
+    };
+    let mut count = 0;
+    for dir in ["data", "index"] {
+        count += count_modified_since(&path.join(dir), since)?;
+    }
+    if count > 0 {
+        warn!(
+            "{count} pack and index files have been written within the grace period of {grace_period}, maybe by a concurrent backup. They are kept."
+        );
+    }
+    Ok(())
+}
+
+/// Count the files in a directory and its subdirectories modified since the given time
+///
+/// # Arguments
+///
+/// * `dir` - the directory; a missing directory contains no files
+/// * `since` - the time
+fn count_modified_since(dir: &Path, since: SystemTime) -> Result<usize> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err.into()),
+    };
+    let mut count = 0;
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            count += count_modified_since(&entry.path(), since)?;
+        } else if metadata.modified()? > since {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Get the path of a local repository
+///
+/// # Arguments
+///
+/// * `location` - the repository as given in the options
+///
+/// # Returns
+///
+/// `None` if the repository is not a local repository
+fn local_repository_path(location: &str) -> Option<PathBuf> {
+    match location.split_once(':') {
+        Some(("local", path)) => Some(path.into()),
+        Some(("rclone" | "rest" | "opendal", _)) => None,
+        // also includes windows paths like C:\repo
+        _ => Some(location.into()),
+    }
+}
+
 /// Print statistics about the prune operation
 ///
 /// # Arguments
@@ -147,3 +497,309 @@ fn print_stats(stats: &PruneStats) {
         stats.index_files_rebuild, stats.index_files
     );
 }
+
+/// Forecast of the repository space usage before and after pruning
+///
+/// This struct is used to serialize the forecast in `json` format and is shown as summary with
+/// `--dry-run`.
+#[derive(Debug, Serialize)]
+struct SpaceForecast {
+    total_size: u64,
+    unused_size_before: u64,
+    unused_size_after: u64,
+    repack_size: u64,
+    delete_size: u64,
+    reclaimed_size: u64,
+    remaining_size: u64,
+    unused_percent_after: f64,
+    /// number of packs to rewrite
+    repack_packs: u64,
+    /// size of the data which is still used in the packs to rewrite, i.e. the data to write
+    repack_write_size: u64,
+    /// number of packs to delete completely
+    delete_packs: u64,
+}
+
+impl SpaceForecast {
+    /// Compute the forecast from the statistics of a prune plan
+    ///
+    /// # Arguments
+    ///
+    /// * `stats` - Statistics about the prune operation
+    #[allow(clippy::cast_precision_loss)]
+    fn new(stats: &PruneStats) -> Self {
+        let size_stat = stats.size_sum();
+        let remaining_size = size_stat.total_after_prune();
+        let unused_size_after = size_stat.unused_after_prune();
+        let unused_percent_after = if remaining_size == 0 {
+            0.0
+        } else {
+            unused_size_after as f64 / remaining_size as f64 * 100.0
+        };
+
+        Self {
+            total_size: size_stat.total() + stats.size_unref,
+            unused_size_before: size_stat.unused + stats.size_unref,
+            unused_size_after,
+            repack_size: size_stat.repack,
+            delete_size: size_stat.remove + stats.size_unref,
+            reclaimed_size: size_stat.repackrm + size_stat.remove + stats.size_unref,
+            remaining_size,
+            unused_percent_after,
+            repack_packs: stats.packs.repack,
+            repack_write_size: size_stat.repack - size_stat.repackrm,
+            delete_packs: stats.packs.unused + stats.packs_unref,
+        }
+    }
+}
+
+/// Describe the rules by which packs are selected for rewriting
+///
+/// The prune plan doesn't tell which rule selected an individual pack, so all rules in effect are
+/// described.
+///
+/// # Arguments
+///
+/// * `opts` - The prune options used for planning
+fn repack_rules(opts: &PruneOptions) -> String {
+    if opts.repack_all {
+        return "all packs are rewritten (--repack-all)".to_string();
+    }
+    let max_unused = match opts.max_unused {
+        LimitOption::Size(size) => bytes_size_to_string(size.as_u64()),
+        LimitOption::Percentage(percent) => format!("{percent}%"),
+        LimitOption::Unlimited => "unlimited".to_string(),
+    };
+    let mut rules = vec![format!(
+        "packs containing unused data, keeping at most {max_unused} unused (--max-unused)"
+    )];
+    if opts.repack_uncompressed {
+        rules.push("packs containing uncompressed blobs (--repack-uncompressed)".to_string());
+    }
+    if opts.repack_cacheable_only == Some(true) {
+        rules.push("only cacheable packs (--repack-cacheable-only)".to_string());
+    }
+    if let LimitOption::Size(size) = opts.max_repack {
+        rules.push(format!(
+            "until {} are selected (--max-repack)",
+            bytes_size_to_string(size.as_u64())
+        ));
+    }
+    rules.join("; ")
+}
+
+/// The reason why a pack is rewritten
+///
+/// The prune plan doesn't tell which rule selected a pack, so the reason is derived from the prune
+/// options and the blobs of the pack. Packs with unused data and packs of unsuitable size can't be
+/// told apart without the used blobs, which are only known within the prune plan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RepackReason {
+    /// all packs are rewritten (`--repack-all`)
+    All,
+    /// the pack contains uncompressed blobs (`--repack-uncompressed`)
+    Uncompressed,
+    /// the pack contains unused data or is too small or too large
+    UnusedOrSize,
+}
+
+impl RepackReason {
+    /// Get the reason why a pack is rewritten
+    ///
+    /// # Arguments
+    ///
+    /// * `opts` - The prune options used for planning
+    /// * `uncompressed` - whether the pack contains uncompressed blobs
+    fn new(opts: &PruneOptions, uncompressed: bool) -> Self {
+        if opts.repack_all {
+            Self::All
+        } else if opts.repack_uncompressed && uncompressed {
+            Self::Uncompressed
+        } else {
+            Self::UnusedOrSize
+        }
+    }
+}
+
+impl Display for RepackReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::All => "all packs are rewritten (--repack-all)",
+            Self::Uncompressed => "contains uncompressed blobs (--repack-uncompressed)",
+            Self::UnusedOrSize => "contains unused data or has an unsuitable size",
+        })
+    }
+}
+
+/// A pack to rewrite as listed in the dry-run report
+#[derive(Debug, Clone, Copy)]
+struct RepackPack {
+    /// id of the pack
+    id: Id,
+    /// size of the pack, if known by the backend
+    size: Option<u64>,
+    /// type of the blobs in the pack, if known by the index
+    tpe: Option<BlobType>,
+    /// why the pack is rewritten
+    reason: RepackReason,
+}
+
+/// Write the dry-run report
+///
+/// # Arguments
+///
+/// * `out` - where to write the report to
+/// * `forecast` - the space forecast of the prune
+/// * `packs` - the packs to rewrite; if given, they are listed (at most
+///   [`MAX_REPORT_PACKS`])
+/// * `rules` - the rules by which packs are selected for rewriting, see [`repack_rules`]
+fn write_dry_run_report(
+    out: &mut impl Write,
+    forecast: &SpaceForecast,
+    packs: Option<&[RepackPack]>,
+    rules: &str,
+) -> Result<()> {
+    writeln!(out)?;
+    writeln!(out, "dry-run summary:")?;
+    writeln!(
+        out,
+        "total pack size:  {:>10}",
+        bytes_size_to_string(forecast.total_size)
+    )?;
+    writeln!(
+        out,
+        "packs to delete:  {:>10} ({})",
+        forecast.delete_packs,
+        bytes_size_to_string(forecast.delete_size)
+    )?;
+    writeln!(
+        out,
+        "packs to rewrite: {:>10} (read {}, write {})",
+        forecast.repack_packs,
+        bytes_size_to_string(forecast.repack_size),
+        bytes_size_to_string(forecast.repack_write_size)
+    )?;
+    writeln!(out, "rewrite selection: {rules}")?;
+    writeln!(
+        out,
+        "space freed:      {:>10}",
+        bytes_size_to_string(forecast.reclaimed_size)
+    )?;
+    writeln!(
+        out,
+        "remaining size:   {:>10}",
+        bytes_size_to_string(forecast.remaining_size)
+    )?;
+    writeln!(
+        out,
+        "unused:           {:>10} before, {} after ({:.2}% of remaining size)",
+        bytes_size_to_string(forecast.unused_size_before),
+        bytes_size_to_string(forecast.unused_size_after),
+        forecast.unused_percent_after
+    )?;
+
+    if let Some(packs) = packs {
+        writeln!(out)?;
+        writeln!(out, "packs to rewrite:")?;
+        for pack in packs.iter().take(MAX_REPORT_PACKS) {
+            let size = pack
+                .size
+                .map_or_else(|| "?".to_string(), bytes_size_to_string);
+            let tpe = match pack.tpe {
+                Some(BlobType::Tree) => "tree",
+                Some(BlobType::Data) => "data",
+                None => "?",
+            };
+            writeln!(
+                out,
+                "  {} {size:>10} {tpe}: {}",
+                pack.id.to_hex(),
+                pack.reason
+            )?;
+        }
+        if packs.len() > MAX_REPORT_PACKS {
+            writeln!(out, "  and {} more", packs.len() - MAX_REPORT_PACKS)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::Parser;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_dry_run_report_passes() -> Result<()> {
+        let forecast = SpaceForecast {
+            total_size: 8192,
+            unused_size_before: 5120,
+            unused_size_after: 1024,
+            repack_size: 2048,
+            delete_size: 3072,
+            reclaimed_size: 4096,
+            remaining_size: 4096,
+            unused_percent_after: 25.0,
+            repack_packs: 22,
+            repack_write_size: 1024,
+            delete_packs: 2,
+        };
+        let packs: Vec<_> = (0..22)
+            .map(|i| RepackPack {
+                id: Id::from_hex(&format!("{i:064x}")).unwrap(),
+                size: Some(100),
+                tpe: Some(BlobType::Data),
+                reason: RepackReason::UnusedOrSize,
+            })
+            .collect();
+
+        let mut out = Vec::new();
+        write_dry_run_report(&mut out, &forecast, None, "rules")?;
+        let summary = "\n\
+            dry-run summary:\n\
+            total pack size:     8.0 KiB\n\
+            packs to delete:           2 (3.0 KiB)\n\
+            packs to rewrite:         22 (read 2.0 KiB, write 1.0 KiB)\n\
+            rewrite selection: rules\n\
+            space freed:         4.0 KiB\n\
+            remaining size:      4.0 KiB\n\
+            unused:              5.0 KiB before, 1.0 KiB after (25.00% of remaining size)\n";
+        assert_eq!(String::from_utf8(out)?, summary);
+
+        let mut out = Vec::new();
+        write_dry_run_report(&mut out, &forecast, Some(&packs), "rules")?;
+        let out = String::from_utf8(out)?;
+        let listed: Vec<_> = out.lines().skip(11).collect();
+        assert_eq!(listed.len(), MAX_REPORT_PACKS + 1);
+        assert_eq!(
+            listed[0],
+            format!(
+                "  {:064x}      100 B data: contains unused data or has an unsuitable size",
+                0
+            )
+        );
+        assert_eq!(listed[MAX_REPORT_PACKS], "  and 2 more");
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_repack_size_passes() -> Result<()> {
+        let cmd = PruneCmd::try_parse_from(["prune", "--max-repack-size", "1MiB"])?;
+        assert_eq!(cmd.max_repack_size, Some(ByteSize::mib(1)));
+        assert!(PruneCmd::try_parse_from([
+            "prune",
+            "--max-repack-size",
+            "1MiB",
+            "--max-repack",
+            "5%"
+        ])
+        .is_err());
+
+        let mut opts = PruneOptions::default();
+        opts.max_repack = LimitOption::Size(ByteSize::mib(1));
+        assert!(repack_rules(&opts).ends_with("until 1.0 MiB are selected (--max-repack)"));
+        Ok(())
+    }
+}
@@ -1,11 +1,19 @@
 //! `repair` subcommand
 
+use std::{collections::HashSet, path::PathBuf};
+
 use crate::{commands::open_repository, status_err, Application, RUSTIC_APP};
 use abscissa_core::{Command, Runnable, Shutdown};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use log::{info, warn};
 
-use rustic_core::{RepairIndexOptions, RepairSnapshotsOptions};
+use rustic_core::{
+    repofile::{BlobType, FileType, IndexFile, SnapshotFile},
+    IndexedFull, LsOptions, Progress, ProgressBars, ReadBackend, RepairIndexOptions,
+    RepairSnapshotsOptions, Repository,
+};
 
 /// `repair` subcommand
 #[derive(clap::Parser, Command, Debug)]
@@ -21,6 +29,8 @@ enum RepairSubCmd {
     Index(IndexSubCmd),
     /// Repair snapshots
     Snapshots(SnapSubCmd),
+    /// Copy files missing in the hot repository from the repository
+    Hotcopy(HotcopySubCmd),
 }
 
 #[derive(Default, Debug, clap::Parser, Command)]
@@ -28,6 +38,10 @@ struct IndexSubCmd {
     /// Index repair options
     #[clap(flatten)]
     opts: RepairIndexOptions,
+
+    /// Confirm rewriting the index, which removes references to unreadable packs
+    #[clap(long)]
+    yes_really: bool,
 }
 
 /// `repair snapshots` subcommand
@@ -40,8 +54,16 @@ struct SnapSubCmd {
     /// Snapshots to repair. If none is given, use filter to filter from all snapshots.
     #[clap(value_name = "ID")]
     ids: Vec<String>,
+
+    /// Confirm removing the damaged snapshots (with --delete)
+    #[clap(long, alias = "yes")]
+    yes_really: bool,
 }
 
+/// `repair hotcopy` subcommand
+#[derive(Default, Debug, clap::Parser, Command)]
+struct HotcopySubCmd {}
+
 impl Runnable for RepairCmd {
     fn run(&self) {
         self.cmd.run();
@@ -60,10 +82,60 @@ impl Runnable for IndexSubCmd {
 impl IndexSubCmd {
     fn inner_run(&self) -> Result<()> {
         let config = RUSTIC_APP.config();
+        if !config.global.dry_run && !self.yes_really {
+            bail!("repairing the index removes references to unreadable packs. Use --dry-run to show the changes and --yes-really to confirm.");
+        }
         let repo = open_repository(&config.repository)?;
         repo.repair_index(&self.opts, config.global.dry_run)?;
-        Ok(())
+        if config.global.dry_run {
+            return Ok(());
+        }
+        // read the new index and check that it covers all snapshots
+        let repo = repo.to_indexed()?;
+        validate_index(&repo)
+    }
+}
+
+/// Validate the index against the snapshots, warning about all snapshots which are incomplete
+///
+/// # Arguments
+///
+/// * `repo` - the repository with the new index
+fn validate_index<P, S: IndexedFull>(repo: &Repository<P, S>) -> Result<()> {
+    let snaps = repo.get_all_snapshots()?;
+    let p = RUSTIC_APP
+        .config()
+        .global
+        .progress_options
+        .progress_counter("validating index against snapshots...");
+    p.set_length(snaps.len() as u64);
+    let mut damaged_snaps = 0;
+    for snap in &snaps {
+        let damage = damaged_files(repo, snap)?;
+        if !damage.files.is_empty() || damage.unreadable > 0 {
+            damaged_snaps += 1;
+            warn!(
+                "snapshot {}: {} files miss data, {} entries can't be read.",
+                snap.id,
+                damage.files.len(),
+                damage.unreadable
+            );
+        }
+        p.inc(1);
+    }
+    p.finish();
+    if damaged_snaps > 0 {
+        warn!(
+            "{damaged_snaps} of {} snapshots are incomplete. Use `repair snapshots` to repair them.",
+            snaps.len()
+        );
+    } else {
+        info!(
+            "index validated: all {} snapshots are complete.",
+            snaps.len()
+        );
     }
+    Ok(())
 }
 
 impl Runnable for SnapSubCmd {
@@ -78,13 +150,168 @@ impl Runnable for SnapSubCmd {
 impl SnapSubCmd {
     fn inner_run(&self) -> Result<()> {
         let config = RUSTIC_APP.config();
+        let dry_run = config.global.dry_run;
+        if self.opts.delete && !dry_run && !self.yes_really {
+            bail!("--delete removes the damaged snapshots. Use --dry-run to show the changes and --yes-really to confirm.");
+        }
         let repo = open_repository(&config.repository)?.to_indexed()?;
         let snaps = if self.ids.is_empty() {
             repo.get_all_snapshots()?
         } else {
             repo.get_snapshots(&self.ids)?
         };
-        repo.repair_snapshots(&self.opts, snaps, config.global.dry_run)?;
+
+        let mut damaged_snaps = Vec::new();
+        for snap in &snaps {
+            let damage = damaged_files(&repo, snap)?;
+            if damage.files.is_empty() && damage.unreadable == 0 {
+                continue;
+            }
+            damaged_snaps.push(snap.clone());
+            let verb = if dry_run { "would lose" } else { "lose" };
+            println!(
+                "snapshot {}: {} files {verb} data, {} entries can't be read.",
+                snap.id,
+                damage.files.len(),
+                damage.unreadable
+            );
+            for path in &damage.files {
+                println!("  {}", path.display());
+            }
+        }
+        println!(
+            "{} of {} snapshots are damaged.",
+            damaged_snaps.len(),
+            snaps.len()
+        );
+
+        // repaired snapshots reference the snapshot they were derived from as original
+        let origins: HashSet<_> = damaged_snaps
+            .iter()
+            .map(|sn| sn.original.unwrap_or(sn.id))
+            .collect();
+        let is_created = |sn: &SnapshotFile| {
+            sn.original
+                .is_some_and(|original| origins.contains(&original))
+        };
+        config
+            .hooks
+            .track_snapshots(&repo, is_created, &damaged_snaps, || {
+                Ok(repo.repair_snapshots(&self.opts, snaps, config.global.dry_run)?)
+            })?;
+        Ok(())
+    }
+}
+
+impl Runnable for HotcopySubCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl HotcopySubCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let backends = config.repository.be.to_backends()?;
+        let Some(hot) = backends.repo_hot() else {
+            bail!("no hot repository is given. Please use --repo-hot!");
+        };
+        let cold = backends.repository();
+        let repo = open_repository(&config.repository)?;
+
+        // the hot repository contains all metadata files and the tree packs
+        let mut files = Vec::new();
+        for tpe in [
+            FileType::Config,
+            FileType::Key,
+            FileType::Snapshot,
+            FileType::Index,
+        ] {
+            files.extend(cold.list(tpe)?.into_iter().map(|id| (tpe, id)));
+        }
+        for item in repo.stream_files::<IndexFile>()? {
+            let (_, index) = item?;
+            files.extend(
+                index
+                    .packs
+                    .iter()
+                    .filter(|pack| pack.blobs.iter().any(|blob| blob.tpe == BlobType::Tree))
+                    .map(|pack| (FileType::Pack, pack.id)),
+            );
+        }
+
+        let mut missing = Vec::new();
+        for (tpe, files) in &files.into_iter().chunk_by(|(tpe, _)| *tpe) {
+            let in_hot: HashSet<_> = hot.list(tpe)?.into_iter().collect();
+            missing.extend(files.filter(|(_, id)| !in_hot.contains(id)));
+        }
+
+        if config.global.dry_run {
+            for (tpe, id) in &missing {
+                println!("would copy {tpe:?} file {id} to the hot repository");
+            }
+            return Ok(());
+        }
+
+        let p = config
+            .global
+            .progress_options
+            .progress_counter("copying files to the hot repository...");
+        p.set_length(missing.len() as u64);
+        for (tpe, id) in &missing {
+            let data = cold.read_full(*tpe, id)?;
+            hot.write_bytes(*tpe, id, true, data)?;
+            p.inc(1);
+        }
+        p.finish();
+        info!("copied {} files to the hot repository.", missing.len());
         Ok(())
     }
 }
+
+/// Files of a snapshot which lose data when the snapshot is repaired
+#[derive(Debug, Default)]
+struct Damage {
+    /// Files with missing data blobs
+    files: Vec<PathBuf>,
+    /// Number of entries which can't be read, e.g. due to missing trees
+    unreadable: usize,
+}
+
+/// Find the files of a snapshot which reference missing data blobs
+///
+/// # Arguments
+///
+/// * `repo` - the repository
+/// * `snap` - the snapshot to examine
+fn damaged_files<P, S: IndexedFull>(
+    repo: &Repository<P, S>,
+    snap: &SnapshotFile,
+) -> Result<Damage> {
+    let mut damage = Damage::default();
+    let Ok(node) = repo.node_from_snapshot_and_path(snap, "") else {
+        damage.unreadable += 1;
+        return Ok(damage);
+    };
+    let mut opts = LsOptions::default();
+    opts.recursive = true;
+    for item in repo.ls(&node, &opts)? {
+        match item {
+            Ok((path, node)) => {
+                let missing = node
+                    .content
+                    .iter()
+                    .flatten()
+                    .any(|id| repo.get_index_entry(BlobType::Data, id).is_err());
+                if missing {
+                    damage.files.push(path);
+                }
+            }
+            Err(_) => damage.unreadable += 1,
+        }
+    }
+    Ok(damage)
+}
@@ -1,16 +1,26 @@
 //! `repoinfo` subcommand
 
 use crate::{
-    commands::{get_repository, open_repository},
+    commands::{
+        get_repository, open_repository, open_repository_indexed,
+        snapshots::sizes::{calculate_sizes, SnapshotSizes},
+    },
+    config::suggested_pack_size,
     helpers::{bytes_size_to_string, table_right_from},
+    output::Output,
     status_err, Application, RUSTIC_APP,
 };
 
+use std::io::Write;
+
 use abscissa_core::{Command, Runnable, Shutdown};
+use chrono::{DateTime, Local};
 use serde::Serialize;
 
 use anyhow::Result;
-use rustic_core::{IndexInfos, RepoFileInfo, RepoFileInfos};
+use rustic_core::{
+    Id, IndexInfos, IndexedFull, ProgressBars, RepoFileInfo, RepoFileInfos, Repository,
+};
 
 /// `repoinfo` subcommand
 #[derive(clap::Parser, Command, Debug)]
@@ -26,11 +36,15 @@ pub(crate) struct RepoInfoCmd {
     /// Show infos in json format
     #[clap(long)]
     json: bool,
+
+    /// Show how many blobs and bytes are referenced exclusively by each snapshot (reads all trees)
+    #[clap(long, conflicts_with = "only_files")]
+    blobs_per_snapshot: bool,
 }
 
 impl Runnable for RepoInfoCmd {
     fn run(&self) {
-        if let Err(err) = self.inner_run() {
+        if let Err(err) = self.inner_run(&mut Output::stdout()) {
             status_err!("{}", err);
             RUSTIC_APP.shutdown(Shutdown::Crash);
         };
@@ -42,15 +56,38 @@ impl Runnable for RepoInfoCmd {
 /// This struct is used to serialize infos in `json` format.
 #[serde_with::apply(Option => #[serde(default, skip_serializing_if = "Option::is_none")])]
 #[derive(Serialize)]
-struct Infos {
-    files: Option<RepoFileInfos>,
-    index: Option<IndexInfos>,
+pub(crate) struct Infos {
+    pub(crate) files: Option<RepoFileInfos>,
+    pub(crate) index: Option<IndexInfos>,
+    /// Ratio of the uncompressed to the stored size of all blobs
+    pub(crate) compression_ratio: Option<f64>,
+    /// Suggested data pack size for the size of the stored data, in bytes
+    pub(crate) suggested_pack_size: Option<u64>,
+    pub(crate) snapshots: Option<Vec<SnapshotBlobInfo>>,
+}
+
+/// Blobs referenced by a snapshot
+#[derive(Serialize)]
+pub(crate) struct SnapshotBlobInfo {
+    /// Id of the snapshot
+    id: Id,
+    /// Time of the snapshot
+    time: DateTime<Local>,
+    /// Referenced and exclusive sizes
+    #[serde(flatten)]
+    sizes: SnapshotSizes,
 }
 
 impl RepoInfoCmd {
-    fn inner_run(&self) -> Result<()> {
+    fn inner_run(&self, out: &mut Output<impl Write>) -> Result<()> {
         let config = RUSTIC_APP.config();
 
+        let index: Option<IndexInfos> = (!self.only_files)
+            .then(|| -> Result<_> {
+                let repo = open_repository(&config.repository)?;
+                Ok(repo.infos_index()?)
+            })
+            .transpose()?;
         let infos = Infos {
             files: (!self.only_index)
                 .then(|| -> Result<_> {
@@ -58,41 +95,138 @@ impl RepoInfoCmd {
                     Ok(repo.infos_files()?)
                 })
                 .transpose()?,
-            index: (!self.only_files)
+            compression_ratio: index.as_ref().and_then(|index| {
+                let (data_size, size) = index
+                    .blobs
+                    .iter()
+                    .fold((0, 0), |(d, s), b| (d + b.data_size, s + b.size));
+                compression_ratio(data_size, size)
+            }),
+            suggested_pack_size: index.as_ref().map(|index| {
+                let size = index.blobs.iter().map(|b| b.size).sum();
+                suggested_pack_size(size).as_u64()
+            }),
+            index,
+            snapshots: self
+                .blobs_per_snapshot
                 .then(|| -> Result<_> {
-                    let repo = open_repository(&config.repository)?;
-                    Ok(repo.infos_index()?)
+                    let repo = open_repository_indexed(&config.repository)?;
+                    snapshot_blob_infos(&repo)
                 })
                 .transpose()?,
         };
 
         if self.json {
-            let mut stdout = std::io::stdout();
-            serde_json::to_writer_pretty(&mut stdout, &infos)?;
-            return Ok(());
+            return out.json(&infos);
         }
 
         if let Some(file_info) = infos.files {
-            print_file_info("repository files", file_info.repo);
+            print_file_info(out, "repository files", file_info.repo)?;
             if let Some(info) = file_info.repo_hot {
-                print_file_info("hot repository files", info);
+                print_file_info(out, "hot repository files", info)?;
             }
         }
 
         if let Some(index_info) = infos.index {
-            print_index_info(index_info);
+            print_index_info(out, index_info)?;
+        }
+
+        if let Some(size) = infos.suggested_pack_size {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "suggested pack size for this amount of data: {} (see --pack-size)",
+                bytes_size_to_string(size)
+            )?;
+        }
+
+        if let Some(snapshots) = infos.snapshots {
+            print_snapshot_blob_infos(out, &snapshots)?;
         }
         Ok(())
     }
 }
 
+/// Estimate the compression ratio, i.e. the ratio of uncompressed to stored size
+///
+/// # Returns
+///
+/// `None` if nothing is stored
+#[allow(clippy::cast_precision_loss)]
+fn compression_ratio(data_size: u64, size: u64) -> Option<f64> {
+    (size > 0).then(|| data_size as f64 / size as f64)
+}
+
+/// Calculate the blobs referenced by each snapshot of the repository
+///
+/// # Arguments
+///
+/// * `repo` - the indexed repository
+fn snapshot_blob_infos<P: ProgressBars, S: IndexedFull>(
+    repo: &Repository<P, S>,
+) -> Result<Vec<SnapshotBlobInfo>> {
+    let snapshots = repo.get_all_snapshots()?;
+    let sizes = calculate_sizes(repo, &snapshots)?;
+    Ok(snapshots
+        .into_iter()
+        .zip(sizes)
+        .map(|(sn, sizes)| SnapshotBlobInfo {
+            id: sn.id,
+            time: sn.time,
+            sizes,
+        })
+        .collect())
+}
+
+/// Print the blobs referenced exclusively by the snapshots
+///
+/// # Arguments
+///
+/// * `out` - the output to print to
+/// * `infos` - the [`SnapshotBlobInfo`]s to print
+fn print_snapshot_blob_infos(
+    out: &mut Output<impl Write>,
+    infos: &[SnapshotBlobInfo],
+) -> Result<()> {
+    let mut table = table_right_from(
+        2,
+        [
+            "Snapshot",
+            "Time",
+            "Blobs",
+            "Size",
+            "Exclusive Blobs",
+            "Exclusive Size",
+        ],
+    );
+    for info in infos {
+        _ = table.add_row([
+            info.id.to_string(),
+            info.time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            info.sizes.blobs.to_string(),
+            bytes_size_to_string(info.sizes.size),
+            info.sizes.exclusive_blobs.to_string(),
+            bytes_size_to_string(info.sizes.exclusive_size),
+        ]);
+    }
+    writeln!(out)?;
+    writeln!(out, "blobs referenced by snapshots (exclusive blobs are removed by forgetting the snapshot and pruning)")?;
+    writeln!(out)?;
+    out.table(table)
+}
+
 /// Print infos about repository files
 ///
 /// # Arguments
 ///
+/// * `out` - the output to print to
 /// * `text` - the text to print before the table
 /// * `info` - the [`RepoFileInfo`]s to print
-pub fn print_file_info(text: &str, info: Vec<RepoFileInfo>) {
+pub fn print_file_info(
+    out: &mut Output<impl Write>,
+    text: &str,
+    info: Vec<RepoFileInfo>,
+) -> Result<()> {
     let mut table = table_right_from(1, ["File type", "Count", "Total Size"]);
     let mut total_count = 0;
     let mut total_size = 0;
@@ -105,27 +239,35 @@ pub fn print_file_info(text: &str, info: Vec<RepoFileInfo>) {
         total_count += row.count;
         total_size += row.size;
     }
-    println!("{text}");
+    writeln!(out, "{text}")?;
     _ = table.add_row([
         "Total".to_string(),
         total_count.to_string(),
         bytes_size_to_string(total_size),
     ]);
 
-    println!();
-    println!("{table}");
-    println!();
+    writeln!(out)?;
+    out.table(table)?;
+    writeln!(out)?;
+    Ok(())
 }
 
 /// Print infos about index
 ///
 /// # Arguments
 ///
+/// * `out` - the output to print to
 /// * `index_info` - the [`IndexInfos`] to print
-pub fn print_index_info(index_info: IndexInfos) {
+pub fn print_index_info(out: &mut Output<impl Write>, index_info: IndexInfos) -> Result<()> {
     let mut table = table_right_from(
         1,
-        ["Blob type", "Count", "Total Size", "Total Size in Packs"],
+        [
+            "Blob type",
+            "Count",
+            "Total Size",
+            "Total Size in Packs",
+            "Compression Ratio",
+        ],
     );
 
     let mut total_count = 0;
@@ -138,6 +280,7 @@ pub fn print_index_info(index_info: IndexInfos) {
             blobs.count.to_string(),
             bytes_size_to_string(blobs.data_size),
             bytes_size_to_string(blobs.size),
+            display_ratio(blobs.data_size, blobs.size),
         ]);
         total_count += blobs.count;
         total_data_size += blobs.data_size;
@@ -150,6 +293,7 @@ pub fn print_index_info(index_info: IndexInfos) {
                 blobs.count.to_string(),
                 bytes_size_to_string(blobs.data_size),
                 bytes_size_to_string(blobs.size),
+                display_ratio(blobs.data_size, blobs.size),
             ]);
             total_count += blobs.count;
             total_data_size += blobs.data_size;
@@ -162,10 +306,11 @@ pub fn print_index_info(index_info: IndexInfos) {
         total_count.to_string(),
         bytes_size_to_string(total_data_size),
         bytes_size_to_string(total_size),
+        display_ratio(total_data_size, total_size),
     ]);
 
-    println!();
-    println!("{table}");
+    writeln!(out)?;
+    out.table(table)?;
 
     let mut table = table_right_from(
         1,
@@ -190,6 +335,48 @@ pub fn print_index_info(index_info: IndexInfos) {
             ]);
         }
     }
-    println!();
-    println!("{table}");
+    writeln!(out)?;
+    out.table(table)
+}
+
+/// Display the compression ratio of the given sizes
+fn display_ratio(data_size: u64, size: u64) -> String {
+    compression_ratio(data_size, size).map_or_else(|| "-".to_string(), |r| format!("{r:.2}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use rustic_core::repofile::FileType;
+
+    #[test]
+    fn test_print_file_info_passes() -> Result<()> {
+        let info = vec![RepoFileInfo {
+            tpe: FileType::Snapshot,
+            count: 2,
+            size: 512,
+        }];
+
+        let mut buf = Vec::new();
+        print_file_info(&mut Output::new(&mut buf), "repository files", info)?;
+
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "repository files\n\
+            \n\
+            | File type | Count | Total Size |\n\
+            |-----------|-------|------------|\n\
+            | Snapshot  |     2 |      512 B |\n\
+            | Total     |     2 |      512 B |\n\
+            \n"
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_display_ratio_passes() {
+        assert_eq!(display_ratio(300, 100), "3.00");
+        assert_eq!(display_ratio(0, 0), "-");
+    }
 }
@@ -1,18 +1,44 @@
 //! `restore` subcommand
 
+mod manifest;
+mod overwrite;
+mod owner;
+mod sparse;
+
+use std::{
+    collections::HashSet,
+    io::{BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
 use crate::{
-    commands::open_repository_indexed, helpers::bytes_size_to_string, status_err, Application,
-    RUSTIC_APP,
+    commands::{diff::identical_content_local, open_repository_indexed, packs_to_warm_up},
+    config::progress_options::{ProgressOptions, RusticProgress},
+    events::{self, Event},
+    helpers::{bytes_size_to_string, prepare_output_path, OutputKind},
+    status_err, Application, RUSTIC_APP,
 };
 
 use abscissa_core::{Command, Runnable, Shutdown};
-use anyhow::Result;
-use log::info;
+use anyhow::{bail, Result};
+use clap::ValueHint;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use log::{info, warn};
+use serde_json::json;
 
-use rustic_core::{LocalDestination, LsOptions, RestoreOptions};
+use rustic_core::{
+    repofile::Node, IndexedFull, LocalDestination, LsOptions, Progress, ProgressBars, Repository,
+    RestoreOptions, RusticResult,
+};
 
 use crate::filtering::SnapshotFilter;
 
+use self::{
+    manifest::ManifestWriter,
+    overwrite::{OverwriteOptions, OverwritePolicy},
+    owner::{unprivileged_uid, OwnerOptions},
+};
+
 /// `restore` subcommand
 #[allow(clippy::struct_excessive_bools)]
 #[derive(clap::Parser, Command, Debug)]
@@ -21,14 +47,68 @@ pub(crate) struct RestoreCmd {
     #[clap(value_name = "SNAPSHOT[:PATH]")]
     snap: String,
 
-    /// Restore destination
+    /// Restore destination, use - to write the content of a single file to stdout
     #[clap(value_name = "DESTINATION")]
     dest: String,
 
+    /// Only restore entries matching this glob pattern, including their contents (can be specified
+    /// multiple times). Patterns are matched against the path within the snapshot or the file name
+    #[clap(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Don't restore entries matching this glob pattern, including their contents (can be specified
+    /// multiple times)
+    #[clap(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Strip the given number of leading path components from all restored entries. Directories
+    /// restored to the same path are merged
+    #[clap(long, value_name = "N", default_value_t = 0)]
+    strip_components: usize,
+
+    /// Verify the contents of all restored files against the repository after restoring
+    #[clap(long)]
+    verify: bool,
+
+    /// Don't reuse existing files in the destination, but completely rewrite them. By default,
+    /// existing files matching the snapshot are skipped and only differing contents are restored
+    #[clap(long)]
+    no_in_place: bool,
+
+    /// Write sparse files, i.e. don't allocate space for blocks consisting of zeros
+    #[clap(long)]
+    sparse: bool,
+
+    /// Only verify the contents of the files in an existing destination against the repository
+    /// without restoring
+    #[clap(long, conflicts_with_all = ["delete", "manifest"])]
+    verify_only: bool,
+
+    /// Overwrite existing destination and manifest files and allow --delete to remove entries even if
+    /// the destination is the filesystem root
+    #[clap(long)]
+    force: bool,
+
+    /// Create missing parent directories of the destination and the manifest file
+    #[clap(long)]
+    create_parents: bool,
+
+    /// Write a manifest of all restored and deleted entries to the given file (NDJSON format)
+    #[clap(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    manifest: Option<PathBuf>,
+
     /// Restore options
     #[clap(flatten)]
     opts: RestoreOptions,
 
+    /// Overwrite options
+    #[clap(flatten)]
+    overwrite_opts: OverwriteOptions,
+
+    /// Ownership options
+    #[clap(flatten)]
+    owner_opts: OwnerOptions,
+
     /// List options
     #[clap(flatten)]
     ls_opts: LsOptions,
@@ -55,17 +135,133 @@ impl RestoreCmd {
         let dry_run = config.global.dry_run;
         let repo = open_repository_indexed(&config.repository)?;
 
-        let node =
-            repo.node_from_snapshot_path(&self.snap, |sn| config.snapshot_filter.matches(sn))?;
+        let (id, path) = self.snap.split_once(':').unwrap_or((&self.snap, ""));
+        let snap = repo.get_snapshot_from_str(id, |sn| config.snapshot_filter.matches(sn))?;
+        let node = repo.node_from_snapshot_and_path(&snap, path)?;
+
+        if self.dest != "-" && !self.verify_only {
+            let (kind, force) = if node.is_dir() {
+                (OutputKind::Dir, self.force)
+            } else {
+                // an existing file may also be overwritten depending on the overwrite policy
+                let policy = self.overwrite_opts.overwrite;
+                (
+                    OutputKind::File,
+                    self.force || policy != OverwritePolicy::Always,
+                )
+            };
+            _ = prepare_output_path(Path::new(&self.dest), kind, force, self.create_parents)?;
+        }
+        let manifest_path = self
+            .manifest
+            .as_ref()
+            .map(|manifest| {
+                prepare_output_path(manifest, OutputKind::File, self.force, self.create_parents)
+            })
+            .transpose()?;
 
         // for restore, always recurse into tree
         let mut ls_opts = self.ls_opts.clone();
         ls_opts.recursive = true;
-        let ls = repo.ls(&node, &ls_opts)?;
+        // the tree is walked once, all following steps use the collected entries
+        let mut entries: Vec<_> = repo.ls(&node, &ls_opts)?.collect::<RusticResult<_>>()?;
+
+        let filter = EntryFilter::new(&self.include, &self.exclude, path)?;
+        if filter.is_active() {
+            let selected = filter.select(&entries);
+            entries.retain(|(path, _)| selected.contains(path));
+        }
+
+        if self.dest == "-" {
+            return self.restore_to_stdout(&repo, &node, &entries);
+        }
+
+        if node.is_dir() && entries.is_empty() {
+            let mut all_opts = LsOptions::default();
+            all_opts.recursive = true;
+            if repo.ls(&node, &all_opts)?.next().is_some() {
+                bail!("no entries match the given patterns, nothing to restore.");
+            }
+        }
+
+        if self.strip_components > 0 {
+            entries = strip_components(entries, self.strip_components)?;
+        }
+
+        if self.verify_only {
+            return verify_destination(&repo, &self.dest, &node, &entries);
+        }
+
+        let delete = self.opts.delete && node.is_dir();
+        if delete {
+            self.check_delete_allowed()?;
+        }
 
         let dest = LocalDestination::new(&self.dest, true, !node.is_dir())?;
 
-        let restore_infos = repo.prepare_restore(&self.opts, ls.clone(), &dest, dry_run)?;
+        // entries to delete must be determined before restoring
+        let deletions = if delete {
+            let keep: HashSet<_> = entries.iter().map(|(path, _)| path.clone()).collect();
+            collect_deletions(Path::new(&self.dest), &keep, |path| filter.matches(path))?
+        } else {
+            Vec::new()
+        };
+
+        // existing files which should not be overwritten are removed from the restore
+        let skipped = self.skipped_entries(&repo, &dest, &node, &entries)?;
+        if !skipped.is_empty() {
+            info!(
+                "skipping {} existing files due to overwrite policy.",
+                skipped.len()
+            );
+        }
+        entries.retain(|(path, _)| !skipped.contains(path));
+
+        let owner_opts = &self.owner_opts;
+        for (_, node) in &mut entries {
+            owner_opts.map_node(node);
+        }
+        let ls = || entries.iter().cloned().map(RusticResult::Ok);
+
+        // deletion is done after restoring, respecting the include/exclude filters
+        let mut opts = self.opts.clone();
+        opts.delete = false;
+        opts.numeric_id |= owner_opts.numeric_uid_gid;
+
+        // without privileges, the ownership cannot be changed to other users; only summarize these
+        let unprivileged_uid = (!opts.no_ownership && !dry_run)
+            .then(unprivileged_uid)
+            .flatten();
+        if unprivileged_uid.is_some() {
+            opts.no_ownership = true;
+        }
+
+        if self.no_in_place {
+            if dry_run {
+                info!("existing files would be completely rewritten.");
+            } else {
+                let removed =
+                    remove_existing_files(Path::new(&self.dest), node.is_dir(), &entries)?;
+                info!("removed {removed} existing files to rewrite them.");
+            }
+        }
+
+        if self.sparse && !dry_run {
+            if cfg!(windows) {
+                warn!("sparse files are not supported on windows, writing dense files.");
+            } else {
+                // file contents are written before, such that the restore only sets the metadata
+                sparse::write_sparse_files(
+                    &repo,
+                    &dest,
+                    Path::new(&self.dest),
+                    node.is_dir(),
+                    &entries,
+                )?;
+            }
+        }
+
+        let restore_infos = repo.prepare_restore(&opts, ls(), &dest, dry_run)?;
 
         let fs = restore_infos.stats.files;
         println!(
@@ -77,6 +273,10 @@ impl RestoreCmd {
             "Dirs:   {} to restore, {} to modify, {} additional",
             ds.restore, ds.modify, ds.additional
         );
+        let up_to_date = fs.unchanged + fs.verified;
+        if up_to_date > 0 {
+            info!("skipping {up_to_date} files which already match the snapshot.");
+        }
 
         info!(
             "total restore size: {}",
@@ -91,14 +291,645 @@ impl RestoreCmd {
         if restore_infos.restore_size == 0 {
             info!("all file contents are fine.");
         }
+        let summary = json!({
+            "files": {
+                "restore": fs.restore,
+                "unchanged": fs.unchanged,
+                "verified": fs.verified,
+                "modify": fs.modify,
+                "additional": fs.additional,
+            },
+            "dirs": {
+                "restore": ds.restore,
+                "modify": ds.modify,
+                "additional": ds.additional,
+            },
+            "restore_size": restore_infos.restore_size,
+            "matched_size": restore_infos.matched_size,
+            "deletions": deletions.len(),
+            "dry_run": dry_run,
+        });
 
+        // when restoring, the packs are warmed up by rustic_core
+        _ = packs_to_warm_up(|| Ok(restore_infos.to_packs()))?;
         if dry_run {
-            repo.warm_up(restore_infos.to_packs().into_iter())?;
+            for path in &deletions {
+                println!("would delete: {}", path.display());
+            }
         } else {
-            repo.restore(restore_infos, &self.opts, ls, &dest)?;
+            repo.restore(restore_infos, &opts, ls(), &dest)?;
             println!("restore done.");
+            delete_entries(Path::new(&self.dest), &deletions)?;
+        }
+        if delete {
+            info!("{} entries to delete.", deletions.len());
+        }
+
+        let mut mismatches = 0;
+        let mut foreign_owner = 0;
+        let mut manifest = manifest_path
+            .as_ref()
+            .map(|manifest| ManifestWriter::create(manifest))
+            .transpose()?;
+        let verify = self.verify && !dry_run;
+        let p = if verify {
+            verify_progress(&entries)
+        } else {
+            ProgressOptions::no_progress()
+        };
+        if verify || manifest.is_some() || unprivileged_uid.is_some() {
+            let action = if dry_run { "would-restore" } else { "restored" };
+            for (path, node) in &entries {
+                if let (Some(uid), Some(node_uid)) = (unprivileged_uid, node.meta.uid) {
+                    if uid != node_uid {
+                        foreign_owner += 1;
+                    }
+                }
+                let verified = (verify && node.is_file())
+                    .then(|| verify_file(&repo, &dest, path, node, &p))
+                    .transpose()?;
+                if verified == Some(false) {
+                    mismatches += 1;
+                }
+                if let Some(writer) = &mut manifest {
+                    writer.entry(path, node, action, verified)?;
+                }
+            }
+        }
+
+        p.finish();
+
+        if let Some(mut writer) = manifest {
+            for path in &deletions {
+                writer.deleted(path)?;
+            }
+            writer.finish(&snap.id.to_string(), dry_run)?;
+        }
+
+        if foreign_owner > 0 {
+            warn!("ownership of {foreign_owner} entries owned by other users has not been restored as rustic is not running as root.");
+        }
+
+        if mismatches > 0 {
+            bail!("verification failed: {mismatches} restored files don't match the snapshot.");
+        } else if verify {
+            println!("verification successful.");
+        }
+
+        events::emit(&Event::Summary {
+            command: "restore",
+            summary,
+        });
+        Ok(())
+    }
+
+    /// Write the content of a single file to stdout
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - the repository
+    /// * `node` - the restored node
+    /// * `entries` - the entries to restore
+    ///
+    /// # Errors
+    ///
+    /// * If the entries to restore don't consist of exactly one regular file
+    fn restore_to_stdout<P, S: IndexedFull>(
+        &self,
+        repo: &Repository<P, S>,
+        node: &Node,
+        entries: &[(PathBuf, Node)],
+    ) -> Result<()> {
+        if self.opts.delete
+            || self.manifest.is_some()
+            || self.verify
+            || self.verify_only
+            || self.sparse
+        {
+            bail!("--delete, --manifest, --verify, --verify-only and --sparse cannot be used when restoring to stdout.");
+        }
+        let file = if node.is_file() {
+            node.clone()
+        } else {
+            let files: Vec<_> = entries.iter().filter(|(_, node)| node.is_file()).collect();
+            match files.len() {
+                1 => files[0].1.clone(),
+                0 if node.is_dir() && self.include.is_empty() => bail!(
+                    "cannot restore a directory to stdout, please use `rustic dump` with an archive format."
+                ),
+                n => bail!(
+                    "{n} files match, only a single file can be restored to stdout. Please use `rustic dump` with an archive format."
+                ),
+            }
+        };
+
+        let mut stdout = BrokenPipeWriter::new(BufWriter::new(std::io::stdout().lock()));
+        match repo
+            .dump(&file, &mut stdout)
+            .map_err(anyhow::Error::from)
+            .and_then(|()| Ok(stdout.flush()?))
+        {
+            // the reader of the pipe is gone, e.g. `| head`; this is no error
+            Err(_) if stdout.is_broken() => Ok(()),
+            res => res,
+        }
+    }
+
+    /// Determine the entries which are not restored due to the overwrite policy
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - the repository
+    /// * `dest` - the restore destination
+    /// * `node` - the restored node
+    /// * `entries` - the entries to restore
+    ///
+    /// # Returns
+    ///
+    /// The paths (relative to the restored node) of all skipped entries
+    fn skipped_entries<P, S: IndexedFull>(
+        &self,
+        repo: &Repository<P, S>,
+        dest: &LocalDestination,
+        node: &Node,
+        entries: &[(PathBuf, Node)],
+    ) -> Result<HashSet<PathBuf>> {
+        let mut skipped = HashSet::new();
+        if self.overwrite_opts.overwrite == OverwritePolicy::Always {
+            return Ok(skipped);
+        }
+        for (path, entry) in entries {
+            let local = if node.is_dir() {
+                Path::new(&self.dest).join(path)
+            } else {
+                PathBuf::from(&self.dest)
+            };
+            let differs = || Ok(!identical_content_local(dest, repo, path, entry)?);
+            if !self.overwrite_opts.should_restore(&local, entry, differs)? {
+                _ = skipped.insert(path.clone());
+            }
+        }
+        Ok(skipped)
+    }
+
+    /// Check if deleting entries in the destination is allowed
+    ///
+    /// # Errors
+    ///
+    /// * If the destination is the filesystem root and `--force` is not given
+    /// * If `--glob` options are given, as these cannot be respected when deleting
+    fn check_delete_allowed(&self) -> Result<()> {
+        let dest = Path::new(&self.dest);
+        let dest = dest.canonicalize().unwrap_or_else(|_| dest.to_path_buf());
+        if dest.parent().is_none() && !self.force {
+            bail!(
+                "refusing to delete entries in {}, use --force to override.",
+                dest.display()
+            );
+        }
+        if self.strip_components > 0 && (!self.include.is_empty() || !self.exclude.is_empty()) {
+            bail!("--delete cannot be used with --strip-components and --include/--exclude.");
+        }
+        let ls_opts = &self.ls_opts;
+        if !(ls_opts.glob.is_empty()
+            && ls_opts.iglob.is_empty()
+            && ls_opts.glob_file.is_empty()
+            && ls_opts.iglob_file.is_empty())
+        {
+            bail!("--delete cannot be used with glob options, please use --include/--exclude.");
+        }
+        Ok(())
+    }
+}
+
+/// Include/exclude filter for restored entries
+#[derive(Debug)]
+struct EntryFilter {
+    /// Entries to include, `None` means everything
+    include: Option<GlobSet>,
+    /// Entries to exclude
+    exclude: Option<GlobSet>,
+    /// Path of the restored node within the snapshot
+    prefix: PathBuf,
+}
+
+impl EntryFilter {
+    /// Create a new filter
+    ///
+    /// # Arguments
+    ///
+    /// * `include` - glob patterns of entries to include
+    /// * `exclude` - glob patterns of entries to exclude
+    /// * `path` - path of the restored node within the snapshot
+    fn new(include: &[String], exclude: &[String], path: &str) -> Result<Self> {
+        let path = Path::new(path);
+        Ok(Self {
+            include: glob_set(include)?,
+            exclude: glob_set(exclude)?,
+            prefix: path.strip_prefix("/").unwrap_or(path).to_path_buf(),
+        })
+    }
+
+    /// Check if any filter is given
+    fn is_active(&self) -> bool {
+        self.include.is_some() || self.exclude.is_some()
+    }
+
+    /// Check if an entry is selected by this filter
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - path of the entry relative to the restored node
+    fn matches(&self, path: &Path) -> bool {
+        let path = self.prefix.join(path);
+        let is_match = |globset: &GlobSet, path: &Path| {
+            globset.is_match(path) || path.file_name().is_some_and(|f| globset.is_match(f))
+        };
+        // a pattern matching a directory also applies to its contents
+        let any_match = |globset: &GlobSet| {
+            path.ancestors()
+                .any(|p| !p.as_os_str().is_empty() && is_match(globset, p))
+        };
+        self.include.as_ref().map_or(true, any_match)
+            && !self.exclude.as_ref().is_some_and(any_match)
+    }
+
+    /// Select all entries matching the filter, including the directories containing them
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - the entries to select from
+    ///
+    /// # Returns
+    ///
+    /// The paths (relative to the restored node) of all selected entries
+    fn select(&self, entries: &[(PathBuf, Node)]) -> HashSet<PathBuf> {
+        entries
+            .iter()
+            .filter(|(path, _)| self.matches(path))
+            .flat_map(|(path, _)| path.ancestors().map(Path::to_path_buf))
+            .collect()
+    }
+}
+
+/// Verify the files in an existing destination against the snapshot without restoring
+///
+/// # Arguments
+///
+/// * `repo` - the repository
+/// * `dest` - the destination to verify
+/// * `node` - the restored node
+/// * `entries` - the entries to verify
+///
+/// # Errors
+///
+/// * If the destination doesn't exist or any file doesn't match the snapshot
+fn verify_destination<P, S: IndexedFull>(
+    repo: &Repository<P, S>,
+    dest: &str,
+    node: &Node,
+    entries: &[(PathBuf, Node)],
+) -> Result<()> {
+    if !Path::new(dest).exists() {
+        bail!("destination {dest} doesn't exist, nothing to verify.");
+    }
+    let dest = LocalDestination::new(dest, false, !node.is_dir())?;
+
+    let p = verify_progress(entries);
+    let mut mismatches = 0;
+    for (path, node) in entries {
+        if node.is_file() && !verify_file(repo, &dest, path, node, &p)? {
+            mismatches += 1;
+        }
+    }
+    p.finish();
+
+    if mismatches > 0 {
+        bail!("verification failed: {mismatches} files don't match the snapshot.");
+    }
+    println!("verification successful.");
+    Ok(())
+}
+
+/// Create a progress bar for verifying the given entries over the total size of all files
+fn verify_progress(entries: &[(PathBuf, Node)]) -> RusticProgress {
+    let size = entries
+        .iter()
+        .filter(|(_, node)| node.is_file())
+        .map(|(_, node)| node.meta.size)
+        .sum();
+    let p = RUSTIC_APP
+        .config()
+        .global
+        .progress_options
+        .progress_bytes("verifying files...");
+    p.set_length(size);
+    p
+}
+
+/// Verify the content of a file in the destination against the snapshot, reporting a mismatch
+///
+/// # Returns
+///
+/// `true` if the file matches the snapshot
+fn verify_file<P, S: IndexedFull>(
+    repo: &Repository<P, S>,
+    dest: &LocalDestination,
+    path: &Path,
+    node: &Node,
+    p: &RusticProgress,
+) -> Result<bool> {
+    let identical = identical_content_local(dest, repo, path, node)?;
+    if !identical {
+        warn!("file {path:?} doesn't match the snapshot!");
+    }
+    p.inc(node.meta.size);
+    Ok(identical)
+}
+
+/// Build a [`GlobSet`] from the given patterns
+///
+/// # Returns
+///
+/// `None` if no pattern is given
+fn glob_set(globs: &[String]) -> Result<Option<GlobSet>> {
+    if globs.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        _ = builder.add(Glob::new(glob)?);
+    }
+    Ok(Some(builder.build()?))
+}
+
+/// Collect all entries within the destination which are not contained in the restored tree
+///
+/// Directories which are not contained are returned as a whole, their contents are not listed.
+/// Entries outside of `in_scope` are never returned, but directories are searched for entries in scope.
+///
+/// # Arguments
+///
+/// * `dest` - the restore destination
+/// * `keep` - paths (relative to `dest`) of all entries which are restored
+/// * `in_scope` - whether an entry (given by its path relative to `dest`) may be deleted
+///
+/// # Returns
+///
+/// The paths relative to `dest` which will be deleted
+fn collect_deletions(
+    dest: &Path,
+    keep: &HashSet<PathBuf>,
+    in_scope: impl Fn(&Path) -> bool,
+) -> Result<Vec<PathBuf>> {
+    let mut deletions = Vec::new();
+    if !dest.is_dir() {
+        return Ok(deletions);
+    }
+
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dest.join(&dir))? {
+            let entry = entry?;
+            let path = dir.join(entry.file_name());
+            if !keep.contains(&path) && in_scope(&path) {
+                deletions.push(path);
+            } else if entry.file_type()?.is_dir() {
+                dirs.push(path);
+            }
         }
+    }
+    deletions.sort_unstable();
+    Ok(deletions)
+}
+
+/// Writer which remembers if writing failed due to a broken pipe
+#[derive(Debug)]
+struct BrokenPipeWriter<W> {
+    /// The inner writer
+    inner: W,
+    /// Whether a broken pipe error occurred
+    broken: bool,
+}
 
+impl<W: Write> BrokenPipeWriter<W> {
+    /// Create a new [`BrokenPipeWriter`]
+    const fn new(inner: W) -> Self {
+        Self {
+            inner,
+            broken: false,
+        }
+    }
+
+    /// Check if a broken pipe error occurred
+    const fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    /// Remember broken pipe errors
+    fn check<T>(&mut self, res: std::io::Result<T>) -> std::io::Result<T> {
+        if matches!(&res, Err(err) if err.kind() == ErrorKind::BrokenPipe) {
+            self.broken = true;
+        }
+        res
+    }
+}
+
+impl<W: Write> Write for BrokenPipeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let res = self.inner.write(buf);
+        self.check(res)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        let res = self.inner.flush();
+        self.check(res)
+    }
+}
+
+/// Strip leading path components from the entries to restore
+///
+/// Directories which are completely stripped away are removed. Directories which are restored to
+/// the same path are merged; the metadata of the first one is restored.
+///
+/// # Arguments
+///
+/// * `entries` - the entries to restore
+/// * `n` - the number of components to strip
+///
+/// # Errors
+///
+/// * If stripping would produce an empty path for an entry which is not a directory
+/// * If two entries would be restored to the same path and not both of them are directories
+///
+/// # Returns
+///
+/// The entries with stripped paths, sorted by path
+fn strip_components(entries: Vec<(PathBuf, Node)>, n: usize) -> Result<Vec<(PathBuf, Node)>> {
+    let mut stripped = Vec::new();
+    for (path, node) in entries {
+        let new_path: PathBuf = path.components().skip(n).collect();
+        if new_path.as_os_str().is_empty() {
+            if !node.is_dir() {
+                bail!("stripping {n} components from {path:?} results in an empty path.");
+            }
+            continue;
+        }
+        stripped.push((new_path, path, node));
+    }
+    // the sort is stable, so the first of the merged directories is kept
+    stripped.sort_by(|(path1, ..), (path2, ..)| path1.cmp(path2));
+    let mut merged: Vec<(PathBuf, PathBuf, Node)> = Vec::with_capacity(stripped.len());
+    for (path, orig, node) in stripped {
+        match merged.last() {
+            Some((last, last_orig, last_node)) if *last == path => {
+                if !(node.is_dir() && last_node.is_dir()) {
+                    bail!("{last_orig:?} and {orig:?} would both be restored to {path:?}.");
+                }
+            }
+            _ => merged.push((path, orig, node)),
+        }
+    }
+    Ok(merged
+        .into_iter()
+        .map(|(path, _, node)| (path, node))
+        .collect())
+}
+
+/// Delete the given entries within the restore destination
+///
+/// # Arguments
+///
+/// * `dest` - the restore destination
+/// * `deletions` - paths (relative to `dest`) of the entries to delete
+fn delete_entries(dest: &Path, deletions: &[PathBuf]) -> Result<()> {
+    for path in deletions {
+        let path = dest.join(path);
+        if path.symlink_metadata()?.is_dir() {
+            std::fs::remove_dir_all(&path)?;
+        } else {
+            std::fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove existing files in the restore destination which would be restored, such that they are
+/// completely rewritten
+///
+/// # Arguments
+///
+/// * `dest` - the restore destination
+/// * `is_dir` - whether a directory is restored
+/// * `entries` - the entries to restore
+///
+/// # Returns
+///
+/// The number of removed files
+fn remove_existing_files(dest: &Path, is_dir: bool, entries: &[(PathBuf, Node)]) -> Result<usize> {
+    let mut removed = 0;
+    for (path, node) in entries {
+        if !node.is_file() {
+            continue;
+        }
+        let local = if is_dir {
+            dest.join(path)
+        } else {
+            dest.to_path_buf()
+        };
+        match local.symlink_metadata() {
+            Ok(meta) if meta.is_file() => {
+                std::fs::remove_file(&local)?;
+                removed += 1;
+            }
+            Ok(_) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rustic_core::repofile::{Metadata, NodeType};
+
+    fn entry(path: &str, node_type: NodeType) -> (PathBuf, Node) {
+        let name = path.rsplit('/').next().unwrap().to_string();
+        (
+            PathBuf::from(path),
+            Node::new(name, node_type, Metadata::default()),
+        )
+    }
+
+    #[test]
+    fn test_strip_components_passes() -> Result<()> {
+        let ls = vec![
+            entry("home", NodeType::Dir),
+            entry("home/user", NodeType::Dir),
+            entry("home/user/docs", NodeType::Dir),
+            entry("home/user/docs/file.txt", NodeType::File),
+        ];
+        let paths: Vec<_> = strip_components(ls, 2)?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(
+            paths,
+            [PathBuf::from("docs"), PathBuf::from("docs/file.txt")]
+        );
+
+        // directories restored to the same path are merged
+        let ls = vec![
+            entry("a", NodeType::Dir),
+            entry("a/docs", NodeType::Dir),
+            entry("a/docs/x", NodeType::File),
+            entry("b", NodeType::Dir),
+            entry("b/docs", NodeType::Dir),
+            entry("b/docs/y", NodeType::File),
+        ];
+        let paths: Vec<_> = strip_components(ls, 1)?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                PathBuf::from("docs"),
+                PathBuf::from("docs/x"),
+                PathBuf::from("docs/y")
+            ]
+        );
         Ok(())
     }
+
+    #[test]
+    fn test_strip_components_fails() {
+        // empty path
+        let ls = vec![
+            entry("home", NodeType::Dir),
+            entry("home/file", NodeType::File),
+        ];
+        assert!(strip_components(ls, 2).is_err());
+
+        // collision
+        let ls = vec![
+            entry("a", NodeType::Dir),
+            entry("a/file", NodeType::File),
+            entry("b", NodeType::Dir),
+            entry("b/file", NodeType::File),
+        ];
+        assert!(strip_components(ls, 1).is_err());
+
+        // collision of a directory and a file
+        let ls = vec![
+            entry("a", NodeType::Dir),
+            entry("a/docs", NodeType::Dir),
+            entry("b", NodeType::Dir),
+            entry("b/docs", NodeType::File),
+        ];
+        assert!(strip_components(ls, 1).is_err());
+    }
 }
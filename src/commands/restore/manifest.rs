@@ -0,0 +1,128 @@
+//! Manifest of restored entries
+
+use std::{
+    fs::File,
+    io::{LineWriter, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use rustic_core::repofile::Node;
+
+/// A single record of the restore manifest
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum Record<'a> {
+    /// An entry of the snapshot which has been restored
+    Entry {
+        path: &'a Path,
+        action: &'a str,
+        size: u64,
+        blobs: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        verified: Option<bool>,
+    },
+    /// An entry of the destination which has been deleted
+    Deleted { path: &'a Path },
+    /// Totals, written as last record
+    Summary {
+        snapshot: &'a str,
+        entries: u64,
+        deleted: u64,
+        size: u64,
+        dry_run: bool,
+    },
+}
+
+/// Writer for a restore manifest in NDJSON format
+///
+/// Every record is written as a single line and flushed immediately, so that an
+/// interrupted restore still leaves a usable partial manifest.
+#[derive(Debug)]
+pub(super) struct ManifestWriter {
+    writer: LineWriter<File>,
+    entries: u64,
+    deleted: u64,
+    size: u64,
+}
+
+impl ManifestWriter {
+    /// Create a new manifest file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - path of the manifest file
+    pub(super) fn create(path: &Path) -> Result<Self> {
+        let file =
+            File::create(path).with_context(|| format!("error creating manifest file {path:?}"))?;
+        Ok(Self {
+            writer: LineWriter::new(file),
+            entries: 0,
+            deleted: 0,
+            size: 0,
+        })
+    }
+
+    /// Add a record for a restored entry
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - path of the entry relative to the destination
+    /// * `node` - node of the entry within the snapshot
+    /// * `action` - action which was taken for this entry
+    /// * `verified` - result of the verification, if it ran
+    pub(super) fn entry(
+        &mut self,
+        path: &Path,
+        node: &Node,
+        action: &str,
+        verified: Option<bool>,
+    ) -> Result<()> {
+        self.entries += 1;
+        self.size += node.meta.size;
+        self.write(&Record::Entry {
+            path,
+            action,
+            size: node.meta.size,
+            blobs: node.content.as_ref().map_or(0, Vec::len),
+            verified,
+        })
+    }
+
+    /// Add a record for a deleted entry
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - path of the deleted entry relative to the destination
+    pub(super) fn deleted(&mut self, path: &Path) -> Result<()> {
+        self.deleted += 1;
+        self.write(&Record::Deleted { path })
+    }
+
+    /// Write the summary record
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshot` - id of the restored snapshot
+    /// * `dry_run` - whether the restore ran in dry-run mode
+    pub(super) fn finish(mut self, snapshot: &str, dry_run: bool) -> Result<()> {
+        let summary = Record::Summary {
+            snapshot,
+            entries: self.entries,
+            deleted: self.deleted,
+            size: self.size,
+            dry_run,
+        };
+        self.write(&summary)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn write(&mut self, record: &Record<'_>) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, record)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+//! Overwrite policy for existing files in the restore destination
+
+use std::{io::ErrorKind, path::Path};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use rustic_core::repofile::Node;
+
+/// Policy which existing files in the restore destination are overwritten
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum OverwritePolicy {
+    /// Always overwrite existing files
+    #[default]
+    Always,
+    /// Never overwrite existing files
+    Never,
+    /// Only overwrite existing files if the version in the snapshot is newer
+    IfNewer,
+    /// Only overwrite existing files if their content differs
+    IfDifferent,
+}
+
+/// Options for handling existing files in the restore destination
+#[derive(Clone, Copy, Debug, Default, clap::Parser)]
+pub(crate) struct OverwriteOptions {
+    /// Which existing files in the destination to overwrite
+    #[clap(long, value_name = "POLICY", value_enum, default_value = "always")]
+    pub(crate) overwrite: OverwritePolicy,
+}
+
+impl OverwriteOptions {
+    /// Check if an entry should be restored
+    ///
+    /// Directories are always restored, as they may contain entries which need to be restored.
+    ///
+    /// # Arguments
+    ///
+    /// * `local` - the path of the entry in the destination
+    /// * `node` - the node of the entry in the snapshot
+    /// * `content_differs` - check if the content of the local file differs from the snapshot
+    pub(crate) fn should_restore(
+        &self,
+        local: &Path,
+        node: &Node,
+        content_differs: impl FnOnce() -> Result<bool>,
+    ) -> Result<bool> {
+        if self.overwrite == OverwritePolicy::Always || node.is_dir() {
+            return Ok(true);
+        }
+        let meta = match local.symlink_metadata() {
+            Ok(meta) => meta,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(true),
+            Err(err) => return Err(err.into()),
+        };
+        match self.overwrite {
+            OverwritePolicy::Always => Ok(true),
+            OverwritePolicy::Never => Ok(false),
+            OverwritePolicy::IfNewer => {
+                let local_mtime = DateTime::<Local>::from(meta.modified()?);
+                Ok(node.meta.mtime.is_some_and(|mtime| mtime > local_mtime))
+            }
+            OverwritePolicy::IfDifferent => {
+                Ok(!meta.is_file() || meta.len() != node.meta.size || content_differs()?)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::Duration;
+    use rstest::{fixture, rstest};
+    use rustic_core::repofile::{Metadata, NodeType};
+    use tempfile::{tempdir, TempDir};
+
+    /// Destination containing the file `existing` with content `content`
+    #[fixture]
+    fn dest() -> TempDir {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("existing"), "content").unwrap();
+        dir
+    }
+
+    fn file_node(mtime: DateTime<Local>, size: u64) -> Node {
+        let meta = Metadata {
+            mtime: Some(mtime),
+            size,
+            ..Default::default()
+        };
+        Node::new("existing".to_string(), NodeType::File, meta)
+    }
+
+    fn should_restore(
+        dest: &TempDir,
+        overwrite: OverwritePolicy,
+        name: &str,
+        node: &Node,
+        differs: bool,
+    ) -> bool {
+        OverwriteOptions { overwrite }
+            .should_restore(&dest.path().join(name), node, || Ok(differs))
+            .unwrap()
+    }
+
+    #[rstest]
+    #[case(OverwritePolicy::Always, true)]
+    #[case(OverwritePolicy::Never, false)]
+    #[case(OverwritePolicy::IfNewer, false)]
+    #[case(OverwritePolicy::IfDifferent, false)]
+    fn test_existing_identical_file(
+        dest: TempDir,
+        #[case] policy: OverwritePolicy,
+        #[case] expected: bool,
+    ) {
+        let node = file_node(Local::now() - Duration::days(1), 7);
+        assert_eq!(
+            should_restore(&dest, policy, "existing", &node, false),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case(OverwritePolicy::Always, true)]
+    #[case(OverwritePolicy::Never, false)]
+    #[case(OverwritePolicy::IfNewer, true)]
+    #[case(OverwritePolicy::IfDifferent, true)]
+    fn test_existing_changed_file(
+        dest: TempDir,
+        #[case] policy: OverwritePolicy,
+        #[case] expected: bool,
+    ) {
+        let node = file_node(Local::now() + Duration::days(1), 7);
+        assert_eq!(
+            should_restore(&dest, policy, "existing", &node, true),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case(OverwritePolicy::Always)]
+    #[case(OverwritePolicy::Never)]
+    #[case(OverwritePolicy::IfNewer)]
+    #[case(OverwritePolicy::IfDifferent)]
+    fn test_missing_file_is_restored(dest: TempDir, #[case] policy: OverwritePolicy) {
+        let node = file_node(Local::now() - Duration::days(1), 7);
+        assert!(should_restore(&dest, policy, "missing", &node, false));
+    }
+
+    #[rstest]
+    fn test_if_different_checks_size_first(dest: TempDir) {
+        let node = file_node(Local::now(), 42);
+        let restore = OverwriteOptions {
+            overwrite: OverwritePolicy::IfDifferent,
+        }
+        .should_restore(&dest.path().join("existing"), &node, || {
+            panic!("content should not be read if the size differs")
+        })
+        .unwrap();
+        assert!(restore);
+    }
+}
@@ -0,0 +1,182 @@
+//! Mapping of owners (users and groups) when restoring
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use rustic_core::repofile::Node;
+
+/// A user or group, given by name or numeric id
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Owner {
+    /// Numeric uid/gid
+    Id(u32),
+    /// User or group name
+    Name(String),
+}
+
+impl FromStr for Owner {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.is_empty() {
+            return Err(anyhow!("empty user or group"));
+        }
+        Ok(s.parse()
+            .map_or_else(|_| Self::Name(s.to_string()), Self::Id))
+    }
+}
+
+/// Mapping of an owner in the snapshot to the owner in the restore destination
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct OwnerMapping {
+    /// Owner in the snapshot
+    from: Owner,
+    /// Owner to restore
+    to: Owner,
+}
+
+impl FromStr for OwnerMapping {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (from, to) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("invalid mapping \"{s}\", please use OLD=NEW"))?;
+        Ok(Self {
+            from: from.parse()?,
+            to: to.parse()?,
+        })
+    }
+}
+
+impl OwnerMapping {
+    /// Apply the mapping to the given name and id, if it matches
+    ///
+    /// # Returns
+    ///
+    /// Whether the mapping has been applied
+    fn apply(&self, name: &mut Option<String>, id: &mut Option<u32>) -> bool {
+        let matches = match &self.from {
+            Owner::Id(from) => *id == Some(*from),
+            Owner::Name(from) => name.as_ref() == Some(from),
+        };
+        if matches {
+            match &self.to {
+                // remove the name such that the numeric id is used
+                Owner::Id(to) => {
+                    *name = None;
+                    *id = Some(*to);
+                }
+                Owner::Name(to) => *name = Some(to.clone()),
+            }
+        }
+        matches
+    }
+}
+
+/// Options for restoring ownership
+#[derive(Clone, Debug, Default, clap::Parser)]
+pub(crate) struct OwnerOptions {
+    /// Restore ownership using the stored numeric uid/gid instead of resolving user and group names
+    /// (same as --numeric-id)
+    #[clap(long)]
+    pub(crate) numeric_uid_gid: bool,
+
+    /// Map a user (name or uid) in the snapshot to another user when restoring (can be specified
+    /// multiple times)
+    #[clap(long, value_name = "OLD=NEW")]
+    map_user: Vec<OwnerMapping>,
+
+    /// Map a group (name or gid) in the snapshot to another group when restoring (can be specified
+    /// multiple times)
+    #[clap(long, value_name = "OLD=NEW")]
+    map_group: Vec<OwnerMapping>,
+}
+
+impl OwnerOptions {
+    /// Apply the user and group mappings to the metadata of a node. The first matching mapping is used.
+    pub(crate) fn map_node(&self, node: &mut Node) {
+        let meta = &mut node.meta;
+        _ = self
+            .map_user
+            .iter()
+            .any(|mapping| mapping.apply(&mut meta.user, &mut meta.uid));
+        _ = self
+            .map_group
+            .iter()
+            .any(|mapping| mapping.apply(&mut meta.group, &mut meta.gid));
+    }
+}
+
+/// Get the uid of the current user, if it is not privileged to change the ownership of files
+#[cfg(not(windows))]
+pub(crate) fn unprivileged_uid() -> Option<u32> {
+    let uid = nix::unistd::geteuid();
+    (!uid.is_root()).then(|| uid.as_raw())
+}
+
+/// Get the uid of the current user, if it is not privileged to change the ownership of files
+///
+/// Ownership is not restored on windows, so this always returns `None`.
+#[cfg(windows)]
+pub(crate) fn unprivileged_uid() -> Option<u32> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rustic_core::repofile::{Metadata, NodeType};
+
+    fn node(user: &str, uid: u32, group: &str, gid: u32) -> Node {
+        let meta = Metadata {
+            user: Some(user.to_string()),
+            uid: Some(uid),
+            group: Some(group.to_string()),
+            gid: Some(gid),
+            ..Default::default()
+        };
+        Node::new("file".to_string(), NodeType::File, meta)
+    }
+
+    #[test]
+    fn test_parse_mapping_passes() -> Result<()> {
+        assert_eq!(
+            "alice=1000".parse::<OwnerMapping>()?,
+            OwnerMapping {
+                from: Owner::Name("alice".to_string()),
+                to: Owner::Id(1000),
+            }
+        );
+        assert!("alice".parse::<OwnerMapping>().is_err());
+        assert!("=bob".parse::<OwnerMapping>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_node_passes() -> Result<()> {
+        let opts = OwnerOptions {
+            numeric_uid_gid: false,
+            map_user: vec!["alice=bob".parse()?, "1001=1000".parse()?],
+            map_group: vec!["users=1000".parse()?],
+        };
+
+        let mut mapped = node("alice", 1001, "users", 100);
+        opts.map_node(&mut mapped);
+        // only the first matching mapping is applied
+        assert_eq!(mapped.meta.user.as_deref(), Some("bob"));
+        assert_eq!(mapped.meta.uid, Some(1001));
+        // mapping to an id removes the name
+        assert_eq!(mapped.meta.group, None);
+        assert_eq!(mapped.meta.gid, Some(1000));
+
+        let mut mapped = node("carol", 1001, "staff", 50);
+        opts.map_node(&mut mapped);
+        assert_eq!(mapped.meta.user, None);
+        assert_eq!(mapped.meta.uid, Some(1000));
+        assert_eq!(mapped.meta.group.as_deref(), Some("staff"));
+        assert_eq!(mapped.meta.gid, Some(50));
+        Ok(())
+    }
+}
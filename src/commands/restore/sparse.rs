@@ -0,0 +1,133 @@
+//! Restoring files as sparse files
+
+use std::{
+    fs::File,
+    io::{Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use rustic_core::{
+    repofile::Node, IndexedFull, LocalDestination, Progress, ProgressBars, Repository,
+};
+
+use crate::{commands::diff::identical_content_local, Application, RUSTIC_APP};
+
+/// Size of blocks which are checked for zeros; only blocks which are completely zero are skipped
+const SPARSE_BLOCK_SIZE: usize = 4096;
+
+/// Size of the file contents read at once, a multiple of [`SPARSE_BLOCK_SIZE`]
+const READ_SIZE: usize = 256 * SPARSE_BLOCK_SIZE;
+
+/// Write the contents of the given files as sparse files, i.e. seek over blocks of zeros instead of
+/// writing them
+///
+/// Files whose contents already match are left untouched. The metadata is set by the following
+/// restore which then finds matching contents.
+///
+/// # Arguments
+///
+/// * `repo` - the repository
+/// * `dest` - the restore destination
+/// * `dest_path` - the path of the restore destination
+/// * `is_dir` - whether a directory is restored
+/// * `entries` - the entries to restore
+pub(crate) fn write_sparse_files<P, S: IndexedFull>(
+    repo: &Repository<P, S>,
+    dest: &LocalDestination,
+    dest_path: &Path,
+    is_dir: bool,
+    entries: &[(PathBuf, Node)],
+) -> Result<()> {
+    let size = entries
+        .iter()
+        .filter(|(_, node)| node.is_file())
+        .map(|(_, node)| node.meta.size)
+        .sum();
+    let p = RUSTIC_APP
+        .config()
+        .global
+        .progress_options
+        .progress_bytes("writing sparse files...");
+    p.set_length(size);
+
+    for (path, node) in entries {
+        if !node.is_file() {
+            continue;
+        }
+        if identical_content_local(dest, repo, path, node)? {
+            p.inc(node.meta.size);
+            continue;
+        }
+        let target = if is_dir {
+            dest_path.join(path)
+        } else {
+            dest_path.to_path_buf()
+        };
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = File::create(&target)
+            .with_context(|| format!("error creating {}", target.display()))?;
+
+        let open_file = repo.open_file(node)?;
+        let mut offset = 0;
+        while offset < node.meta.size {
+            let data = repo.read_file_at(&open_file, offset.try_into()?, READ_SIZE)?;
+            if data.is_empty() {
+                break;
+            }
+            write_sparse(&mut file, &data)
+                .with_context(|| format!("error writing {}", target.display()))?;
+            offset += data.len() as u64;
+            p.inc(data.len() as u64);
+        }
+        // trailing zeros have only been skipped, so the length must be set explicitly
+        file.set_len(node.meta.size)?;
+    }
+    p.finish();
+    Ok(())
+}
+
+/// Write data at the current position, but seek over all blocks consisting of zeros
+///
+/// Note that the file length is not extended if the data ends with zeros.
+fn write_sparse(file: &mut (impl Write + Seek), data: &[u8]) -> std::io::Result<()> {
+    // start of the data which is not written yet
+    let mut start = 0;
+    let mut offset = 0;
+    for block in data.chunks(SPARSE_BLOCK_SIZE) {
+        if block.iter().all(|byte| *byte == 0) {
+            file.write_all(&data[start..offset])?;
+            _ = file.seek(SeekFrom::Current(block.len() as i64))?;
+            start = offset + block.len();
+        }
+        offset += block.len();
+    }
+    file.write_all(&data[start..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Read;
+
+    #[test]
+    fn test_write_sparse_passes() -> Result<()> {
+        let mut data = vec![0; 5 * SPARSE_BLOCK_SIZE];
+        data[10] = 1;
+        data[2 * SPARSE_BLOCK_SIZE + 5] = 2;
+
+        let mut file = tempfile::tempfile()?;
+        write_sparse(&mut file, &data)?;
+        write_sparse(&mut file, &data)?;
+        file.set_len(2 * data.len() as u64)?;
+
+        let mut content = Vec::new();
+        _ = file.seek(SeekFrom::Start(0))?;
+        _ = file.read_to_end(&mut content)?;
+        assert_eq!(content, [data.as_slice(), data.as_slice()].concat());
+        Ok(())
+    }
+}
@@ -6,12 +6,55 @@ use abscissa_core::{status_err, Command, Runnable, Shutdown};
 
 use anyhow::Result;
 
+/// Exit code of `self-update --check-only` if an update is available
+#[cfg(feature = "self-update")]
+const UPDATE_AVAILABLE_EXIT_CODE: i32 = 10;
+
+/// Owner of the GitHub repository containing the releases
+#[cfg(feature = "self-update")]
+const REPO_OWNER: &str = "rustic-rs";
+
+/// Name of the GitHub repository containing the releases
+#[cfg(feature = "self-update")]
+const REPO_NAME: &str = "rustic";
+
 /// `self-update` subcommand
 #[derive(clap::Parser, Command, Debug)]
 pub(crate) struct SelfUpdateCmd {
     /// Do not ask before processing the self-update
     #[clap(long, conflicts_with = "dry_run")]
     force: bool,
+
+    /// Only check if an update is available; exits with code 10 if it is
+    #[clap(long, conflicts_with = "force")]
+    check_only: bool,
+
+    /// Update to this version instead of the latest version of the channel; allows downgrades
+    #[clap(long, value_name = "VERSION")]
+    to_version: Option<String>,
+
+    /// Release channel to get the latest version from
+    #[clap(long, value_enum, default_value_t, conflicts_with = "to_version")]
+    channel: Channel,
+}
+
+/// Release channel
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+enum Channel {
+    /// Only stable releases
+    #[default]
+    Stable,
+    /// Stable releases and pre-releases
+    Prerelease,
+}
+
+impl std::fmt::Display for Channel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Stable => write!(f, "stable"),
+            Self::Prerelease => write!(f, "prerelease"),
+        }
+    }
 }
 
 impl Runnable for SelfUpdateCmd {
@@ -26,41 +69,118 @@ impl Runnable for SelfUpdateCmd {
 impl SelfUpdateCmd {
     #[cfg(feature = "self-update")]
     fn inner_run(&self) -> Result<()> {
-        let current_version = semver::Version::parse(self_update::cargo_crate_version!())?;
-
-        let release = self_update::backends::github::Update::configure()
-            .repo_owner("rustic-rs")
-            .repo_name("rustic")
-            .bin_name("rustic")
-            .show_download_progress(true)
-            .current_version(current_version.to_string().as_str())
-            .no_confirm(self.force)
-            .build()?;
+        use anyhow::Context;
 
-        let latest_release = release.get_latest_release()?;
+        let current_version = semver::Version::parse(self_update::cargo_crate_version!())?;
 
-        let upstream_version = semver::Version::parse(&latest_release.version)?;
+        let target_version = match &self.to_version {
+            Some(version) => semver::Version::parse(version.trim_start_matches('v'))
+                .with_context(|| format!("invalid version {version}"))?,
+            None => latest_version(self.channel)?,
+        };
 
-        match current_version.cmp(&upstream_version) {
-            std::cmp::Ordering::Greater => {
+        match current_version.cmp(&target_version) {
+            std::cmp::Ordering::Greater if self.to_version.is_none() => {
+                println!(
+                    "Your rustic version {current_version} is newer than the {} version {target_version} on upstream!",
+                    self.channel
+                );
+            }
+            std::cmp::Ordering::Greater if self.check_only => {
                 println!(
-                    "Your rustic version {current_version} is newer than the stable version {upstream_version} on upstream!"
+                    "rustic version {current_version} is newer than the requested version {target_version}."
                 );
             }
             std::cmp::Ordering::Equal => {
                 println!("rustic version {current_version} is up-to-date!");
             }
-            std::cmp::Ordering::Less => {
-                let status = release.update()?;
-
-                if let self_update::Status::Updated(str) = status {
-                    println!("rustic version has been updated to: {str}");
-                }
+            std::cmp::Ordering::Less if self.check_only => {
+                println!(
+                    "rustic version {target_version} is available, installed is {current_version}."
+                );
+                RUSTIC_APP.shutdown_with_exitcode(Shutdown::Graceful, UPDATE_AVAILABLE_EXIT_CODE);
             }
+            _ => self.update(&current_version, &target_version)?,
         }
 
         Ok(())
     }
+
+    /// Download the release of the given version for the current target, verify its checksum and
+    /// replace the running executable
+    ///
+    /// # Errors
+    ///
+    /// * If the release or its checksum can't be downloaded
+    /// * If the checksum doesn't match
+    /// * If the executable can't be replaced
+    #[cfg(feature = "self-update")]
+    fn update(
+        &self,
+        current_version: &semver::Version,
+        target_version: &semver::Version,
+    ) -> Result<()> {
+        use anyhow::{bail, Context};
+        use dialoguer::Confirm;
+        use self_update::{update::ReleaseUpdate, ArchiveKind, Compression, Download, Extract};
+
+        let tag = format!("v{target_version}");
+        let release = self_update::backends::github::Update::configure()
+            .repo_owner(REPO_OWNER)
+            .repo_name(REPO_NAME)
+            .bin_name("rustic")
+            .current_version(current_version.to_string().as_str())
+            .build()?
+            .get_release_version(&tag)?;
+        let target = self_update::get_target();
+        let Some(asset) = release.asset_for(target, None) else {
+            bail!("release {tag} contains no archive for {target}");
+        };
+        if !asset.name.ends_with(".tar.gz") {
+            bail!("unsupported archive {}, please update manually", asset.name);
+        }
+
+        if !self.force
+            && !Confirm::new()
+                .with_prompt(format!(
+                    "Update rustic from version {current_version} to {target_version}?"
+                ))
+                .default(false)
+                .interact()?
+        {
+            println!("update aborted.");
+            return Ok(());
+        }
+
+        // the public download urls of the assets are used, which don't need the GitHub api
+        let url = |name: &str| {
+            format!("https://github.com/{REPO_OWNER}/{REPO_NAME}/releases/download/{tag}/{name}")
+        };
+        let mut archive = Vec::new();
+        Download::from_url(&url(&asset.name))
+            .show_progress(true)
+            .download_to(&mut archive)?;
+        let mut checksum = Vec::new();
+        Download::from_url(&url(&format!("{}.sha256", asset.name)))
+            .download_to(&mut checksum)
+            .with_context(|| format!("error downloading the checksum of {}", asset.name))?;
+        verify_sha256(&archive, &String::from_utf8(checksum)?)
+            .with_context(|| format!("error verifying {}", asset.name))?;
+
+        let tmp_dir = self_update::TempDir::new()?;
+        let archive_path = tmp_dir.path().join(&asset.name);
+        std::fs::write(&archive_path, archive)?;
+        let bin_name = format!("rustic{}", std::env::consts::EXE_SUFFIX);
+        Extract::from_source(&archive_path)
+            .archive(ArchiveKind::Tar(Some(Compression::Gz)))
+            .extract_file(tmp_dir.path(), &bin_name)?;
+        // this also handles replacing the running executable on Windows
+        self_update::self_replace::self_replace(tmp_dir.path().join(&bin_name))?;
+
+        println!("rustic version has been updated to: {target_version}");
+        Ok(())
+    }
+
     #[cfg(not(feature = "self-update"))]
     fn inner_run(&self) -> Result<()> {
         anyhow::bail!(
@@ -68,3 +188,68 @@ impl SelfUpdateCmd {
         );
     }
 }
+
+/// Get the latest released version of the given channel
+///
+/// # Errors
+///
+/// * If the releases can't be fetched or there is no release in the channel
+#[cfg(feature = "self-update")]
+fn latest_version(channel: Channel) -> Result<semver::Version> {
+    let releases = self_update::backends::github::ReleaseList::configure()
+        .repo_owner(REPO_OWNER)
+        .repo_name(REPO_NAME)
+        .build()?
+        .fetch()?;
+    releases
+        .iter()
+        .filter_map(|release| semver::Version::parse(&release.version).ok())
+        .filter(|version| channel == Channel::Prerelease || version.pre.is_empty())
+        .max()
+        .ok_or_else(|| anyhow::anyhow!("no {channel} release found"))
+}
+
+/// Verify data against the contents of a `.sha256` file
+///
+/// # Arguments
+///
+/// * `data` - the downloaded data
+/// * `checksum` - the checksum file, containing the hex-encoded sha256 hash optionally followed by
+///   the file name
+///
+/// # Errors
+///
+/// * If the checksum file contains no hash or the hash doesn't match
+#[cfg(feature = "self-update")]
+fn verify_sha256(data: &[u8], checksum: &str) -> Result<()> {
+    use sha2::{Digest, Sha256};
+
+    let Some(expected) = checksum.split_whitespace().next() else {
+        anyhow::bail!("the checksum file is empty");
+    };
+    let actual = format!("{:x}", Sha256::digest(data));
+    if !actual.eq_ignore_ascii_case(expected) {
+        anyhow::bail!("checksum mismatch: expected sha256 {expected}, got {actual}");
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "self-update"))]
+mod tests {
+    use super::*;
+
+    // sha256 of "rustic"
+    const CHECKSUM: &str = "528638bed53175b1490906fcc7a600409483947d21e8923aa0673b2ea3ed853b";
+
+    #[test]
+    fn test_verify_sha256_passes() -> Result<()> {
+        let checksum = format!("{}  rustic.tar.gz\n", CHECKSUM.to_uppercase());
+        verify_sha256(b"rustic", &checksum)
+    }
+
+    #[test]
+    fn test_verify_sha256_mismatch_fails() {
+        assert!(verify_sha256(b"tampered", CHECKSUM).is_err());
+        assert!(verify_sha256(b"rustic", "").is_err());
+    }
+}
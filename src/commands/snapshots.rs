@@ -1,20 +1,38 @@
 //! `smapshot` subcommand
 
+#[cfg(feature = "sqlite")]
+mod export;
+mod html;
+pub(crate) mod sizes;
+mod template;
+mod verify;
+
 use crate::{
-    commands::open_repository,
+    commands::{open_repository, open_repository_indexed, repoinfo::Infos},
     helpers::{bold_cell, bytes_size_to_string, table, table_right_from},
+    output::Output,
     status_err, Application, RUSTIC_APP,
 };
 
+use std::{collections::HashMap, io::Write};
+
 use abscissa_core::{Command, Runnable, Shutdown};
 use anyhow::Result;
+use chrono::Local;
 use comfy_table::Cell;
 use humantime::format_duration;
 use itertools::Itertools;
+use serde::Serialize;
 
 use rustic_core::{
     repofile::{DeleteOption, SnapshotFile},
-    SnapshotGroupCriterion,
+    Id, SnapshotGroup, SnapshotGroupCriterion,
+};
+
+use self::{
+    html::ReportOptions,
+    sizes::{calculate_sizes, SnapshotSizes},
+    template::SnapshotFormat,
 };
 
 #[cfg(feature = "tui")]
@@ -22,6 +40,7 @@ use super::tui;
 
 /// `snapshot` subcommand
 #[derive(clap::Parser, Command, Debug)]
+#[clap(args_conflicts_with_subcommands = true)]
 pub(crate) struct SnapshotCmd {
     /// Snapshots to show. If none is given, use filter options to filter from all snapshots
     #[clap(value_name = "ID")]
@@ -40,23 +59,73 @@ pub(crate) struct SnapshotCmd {
     #[arg(long)]
     long: bool,
 
+    /// Add a column with the parent snapshot to the table
+    #[clap(long)]
+    show_parent: bool,
+
+    /// Calculate the size referenced by each snapshot and the size exclusive to it, i.e. referenced
+    /// by no other selected snapshot. Adds columns to the table and fields to the json output
+    #[clap(long, conflicts_with = "html")]
+    calculate_sizes: bool,
+
     /// Show snapshots in json format
     #[clap(long, conflicts_with = "long")]
     json: bool,
 
+    /// Show snapshots as standalone HTML page
+    #[clap(long, conflicts_with_all = &["long", "json"])]
+    html: bool,
+
+    /// Add repository statistics (see `repoinfo`) to the HTML page
+    #[clap(long, requires = "html")]
+    with_repoinfo: bool,
+
+    /// Mark groups in the HTML page as warning if their latest snapshot is older than the given
+    /// duration
+    #[clap(long, value_name = "DURATION", requires = "html")]
+    warn_age: Option<humantime::Duration>,
+
+    /// Mark groups in the HTML page as critical if their latest snapshot is older than the given
+    /// duration
+    #[clap(long, value_name = "DURATION", requires = "html")]
+    crit_age: Option<humantime::Duration>,
+
     /// Show all snapshots instead of summarizing identical follow-up snapshots
     #[clap(long, conflicts_with_all = &["long", "json"])]
     all: bool,
 
+    /// Output format: "table", "json" or a template rendered for each snapshot, e.g.
+    /// "{{.Id}} {{.Time}} {{.Host}}". Available fields are Id, LongId, Time, Host, User, Label,
+    /// Tags, Paths, Description, Parent, Tree, Files, Dirs and Size
+    #[clap(long, value_name = "TEMPLATE", conflicts_with_all = &["long", "json", "html"])]
+    format: Option<SnapshotFormat>,
+
     #[cfg(feature = "tui")]
     /// Run in interactive UI mode
     #[clap(long, short)]
     pub interactive: bool,
+
+    #[clap(subcommand)]
+    cmd: Option<SnapshotSubCmd>,
+}
+
+/// Subcommands of the `snapshot` command
+#[derive(clap::Subcommand, Debug, Runnable)]
+enum SnapshotSubCmd {
+    /// Export snapshot metadata to a SQLite database
+    #[cfg(feature = "sqlite")]
+    Export(export::ExportCmd),
+    /// Verify that all blobs of snapshots are readable and match their checksums
+    Verify(verify::VerifyCmd),
 }
 
 impl Runnable for SnapshotCmd {
     fn run(&self) {
-        if let Err(err) = self.inner_run() {
+        if let Some(cmd) = &self.cmd {
+            return cmd.run();
+        }
+
+        if let Err(err) = self.inner_run(&mut Output::stdout()) {
             status_err!("{}", err);
             RUSTIC_APP.shutdown(Shutdown::Crash);
         };
@@ -64,7 +133,7 @@ impl Runnable for SnapshotCmd {
 }
 
 impl SnapshotCmd {
-    fn inner_run(&self) -> Result<()> {
+    fn inner_run(&self, out: &mut Output<impl Write>) -> Result<()> {
         #[cfg(feature = "tui")]
         if self.interactive {
             return tui::run(self.group_by);
@@ -77,16 +146,83 @@ impl SnapshotCmd {
             config.snapshot_filter.matches(sn)
         })?;
 
+        let sizes = self
+            .calculate_sizes
+            .then(|| -> Result<_> {
+                let repo = open_repository_indexed(&config.repository)?;
+                let snapshots: Vec<_> = groups
+                    .iter()
+                    .flat_map(|(_, snapshots)| snapshots.iter().cloned())
+                    .collect();
+                let sizes = calculate_sizes(&repo, &snapshots)?;
+                Ok(snapshots.iter().map(|sn| sn.id).zip(sizes).collect())
+            })
+            .transpose()?;
+
+        match &self.format {
+            Some(SnapshotFormat::Json) => return print_json(out, groups, sizes.as_ref()),
+            Some(SnapshotFormat::Template(template)) => {
+                let snapshots = groups
+                    .into_iter()
+                    .flat_map(|(_, mut snapshots)| {
+                        snapshots.sort_unstable();
+                        snapshots
+                    })
+                    .collect_vec();
+                write!(out, "{}", template::render(template, &snapshots)?)?;
+                return Ok(());
+            }
+            Some(SnapshotFormat::Table) | None => {}
+        }
+
         if self.json {
-            let mut stdout = std::io::stdout();
-            serde_json::to_writer_pretty(&mut stdout, &groups)?;
+            return print_json(out, groups, sizes.as_ref());
+        }
+
+        if self.html {
+            let infos = self
+                .with_repoinfo
+                .then(|| -> Result<_> {
+                    Ok(Infos {
+                        files: Some(repo.infos_files()?),
+                        index: Some(repo.infos_index()?),
+                        compression_ratio: None,
+                        suggested_pack_size: None,
+                        snapshots: None,
+                    })
+                })
+                .transpose()?;
+            let opts = ReportOptions {
+                all: self.all,
+                warn_age: self.warn_age.map(Into::into),
+                crit_age: self.crit_age.map(Into::into),
+            };
+            let mut report = String::new();
+            html::write_report(&mut report, groups, infos.as_ref(), &opts, Local::now())?;
+            write!(out, "{report}")?;
             return Ok(());
         }
 
+        self.print_groups(out, groups, sizes.as_ref())
+    }
+
+    /// Print the snapshot groups as tables
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - the output to print to
+    /// * `groups` - the snapshot groups to print
+    /// * `sizes` - the sizes of the snapshots, if calculated
+    fn print_groups(
+        &self,
+        out: &mut Output<impl Write>,
+        groups: Vec<(SnapshotGroup, Vec<SnapshotFile>)>,
+        sizes: Option<&HashMap<Id, SnapshotSizes>>,
+    ) -> Result<()> {
         let mut total_count = 0;
         for (group, mut snapshots) in groups {
             if !group.is_empty() {
-                println!("\nsnapshots for {group}");
+                writeln!(out, "\nsnapshots for {group}")?;
             }
             snapshots.sort_unstable();
             let count = snapshots.len();
@@ -99,37 +235,64 @@ impl SnapshotCmd {
                         _ = table.add_row([bold_cell(title), Cell::new(value)]);
                     };
                     fill_table(&snap, add_entry);
+                    if let Some(sizes) = sizes.and_then(|sizes| sizes.get(&snap.id)) {
+                        let (referenced, exclusive) = sizes_to_strings(sizes);
+                        _ = table.add_row([bold_cell("Referenced size"), Cell::new(referenced)]);
+                        _ = table.add_row([bold_cell("Exclusive size"), Cell::new(exclusive)]);
+                    }
 
-                    println!("{table}");
-                    println!();
+                    out.table(table)?;
+                    writeln!(out)?;
                 }
             } else {
-                let mut table = table_right_from(
-                    6,
-                    [
-                        "ID", "Time", "Host", "Label", "Tags", "Paths", "Files", "Dirs", "Size",
-                    ],
-                );
+                let mut header = SNAPSHOT_HEADER.to_vec();
+                let mut right_from = 6;
+                if self.show_parent {
+                    header.insert(1, "Parent");
+                    right_from += 1;
+                }
+                if sizes.is_some() {
+                    header.extend(["Referenced", "Exclusive"]);
+                }
+                let mut table = table_right_from(right_from, header);
 
                 let snapshots: Vec<_> = snapshots
                     .into_iter()
                     .chunk_by(|sn| if self.all { sn.id } else { sn.tree })
                     .into_iter()
-                    .map(|(_, mut g)| snap_to_table(&g.next().unwrap(), g.count()))
+                    .map(|(_, mut g)| {
+                        let sn = g.next().unwrap();
+                        let mut row = snap_to_table(&sn, g.count()).to_vec();
+                        if self.show_parent {
+                            row.insert(1, parent_to_string(&sn));
+                        }
+                        if let Some(sizes) = sizes {
+                            let (referenced, exclusive) = sizes
+                                .get(&sn.id)
+                                .map_or_else(Default::default, sizes_to_strings);
+                            row.extend([referenced, exclusive]);
+                        }
+                        row
+                    })
                     .collect();
                 _ = table.add_rows(snapshots);
-                println!("{table}");
+                out.table(table)?;
             }
-            println!("{count} snapshot(s)");
+            writeln!(out, "{count} snapshot(s)")?;
             total_count += count;
         }
-        println!();
-        println!("total: {total_count} snapshot(s)");
+        writeln!(out)?;
+        writeln!(out, "total: {total_count} snapshot(s)")?;
 
         Ok(())
     }
 }
 
+/// Column titles of the snapshot table, see [`snap_to_table`]
+pub(crate) const SNAPSHOT_HEADER: [&str; 9] = [
+    "ID", "Time", "Host", "Label", "Tags", "Paths", "Files", "Dirs", "Size",
+];
+
 pub fn snap_to_table(sn: &SnapshotFile, count: usize) -> [String; 9] {
     let tags = sn.tags.formatln();
     let paths = sn.paths.formatln();
@@ -161,6 +324,55 @@ pub fn snap_to_table(sn: &SnapshotFile, count: usize) -> [String; 9] {
     ]
 }
 
+/// A snapshot together with its sizes, see `--calculate-sizes`
+#[derive(Serialize)]
+struct SnapshotWithSizes {
+    /// The snapshot
+    #[serde(flatten)]
+    snapshot: SnapshotFile,
+    /// The sizes of the snapshot
+    sizes: SnapshotSizes,
+}
+
+/// Print the snapshot groups in json format, adding the sizes of the snapshots if calculated
+fn print_json(
+    out: &mut Output<impl Write>,
+    groups: Vec<(SnapshotGroup, Vec<SnapshotFile>)>,
+    sizes: Option<&HashMap<Id, SnapshotSizes>>,
+) -> Result<()> {
+    let Some(sizes) = sizes else {
+        return out.json(&groups);
+    };
+    let groups: Vec<_> = groups
+        .into_iter()
+        .map(|(group, snapshots)| {
+            let snapshots: Vec<_> = snapshots
+                .into_iter()
+                .map(|snapshot| SnapshotWithSizes {
+                    sizes: sizes.get(&snapshot.id).copied().unwrap_or_default(),
+                    snapshot,
+                })
+                .collect();
+            (group, snapshots)
+        })
+        .collect();
+    out.json(&groups)
+}
+
+/// Referenced and exclusive size of a snapshot for display
+fn sizes_to_strings(sizes: &SnapshotSizes) -> (String, String) {
+    (
+        bytes_size_to_string(sizes.size),
+        bytes_size_to_string(sizes.exclusive_size),
+    )
+}
+
+/// Short ID of the parent snapshot or "none" if the snapshot has no parent
+fn parent_to_string(sn: &SnapshotFile) -> String {
+    sn.parent
+        .map_or_else(|| "none".to_string(), |p| p.to_string())
+}
+
 pub fn fill_table(snap: &SnapshotFile, mut add_entry: impl FnMut(&str, String)) {
     add_entry("Snapshot", snap.id.to_hex().to_string());
     // note that if original was not set, it is set to snap.id by the load process
@@ -181,10 +393,9 @@ pub fn fill_table(snap: &SnapshotFile, mut add_entry: impl FnMut(&str, String))
     };
     add_entry("Delete", delete);
     add_entry("Paths", snap.paths.formatln());
-    let parent = snap.parent.map_or_else(
-        || "no parent snapshot".to_string(),
-        |p| p.to_hex().to_string(),
-    );
+    let parent = snap
+        .parent
+        .map_or_else(|| "none".to_string(), |p| p.to_hex().to_string());
     add_entry("Parent", parent);
     if let Some(ref summary) = snap.summary {
         add_entry("", String::new());
@@ -242,3 +453,102 @@ pub fn fill_table(snap: &SnapshotFile, mut add_entry: impl FnMut(&str, String))
         add_entry("Description", description.clone());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::helpers::test_snapshot;
+
+    use clap::Parser;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_print_groups_passes() -> Result<()> {
+        let snaps: Vec<_> = [('1', 10), ('2', 5)]
+            .into_iter()
+            .map(|(id, day)| SnapshotFile {
+                hostname: "host1".to_string(),
+                paths: "/home".parse().unwrap(),
+                ..test_snapshot(id, day)
+            })
+            .collect();
+        let group = SnapshotGroup::from_snapshot(&snaps[0], "host".parse()?);
+
+        let cmd = SnapshotCmd::try_parse_from(["snapshots"])?;
+        let mut buf = Vec::new();
+        cmd.print_groups(&mut Output::new(&mut buf), vec![(group, snaps)], None)?;
+
+        // identical follow-up snapshots are summarized
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "\n\
+            snapshots for (host [host1])\n\
+            | ID            | Time                | Host  | Label | Tags | Paths | Files | Dirs | Size |\n\
+            |---------------|---------------------|-------|-------|------|-------|-------|------|------|\n\
+            | 22222222 (+1) | 2024-01-05 12:00:00 | host1 |       |      | /home |     ? |    ? |    ? |\n\
+            2 snapshot(s)\n\
+            \n\
+            total: 2 snapshot(s)\n"
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_print_groups_with_parent_passes() -> Result<()> {
+        let first = SnapshotFile {
+            tree: Id::from_hex(&"a".repeat(64)).unwrap(),
+            hostname: "host1".to_string(),
+            ..test_snapshot('1', 5)
+        };
+        let second = SnapshotFile {
+            tree: Id::from_hex(&"b".repeat(64)).unwrap(),
+            hostname: "host1".to_string(),
+            parent: Some(first.id),
+            ..test_snapshot('2', 6)
+        };
+        let group = SnapshotGroup::from_snapshot(&first, "host".parse()?);
+
+        let cmd = SnapshotCmd::try_parse_from(["snapshots", "--show-parent"])?;
+        let mut buf = Vec::new();
+        cmd.print_groups(
+            &mut Output::new(&mut buf),
+            vec![(group, vec![first, second])],
+        )?;
+
+        let out = String::from_utf8(buf)?;
+        assert!(out.contains("| ID       | Parent   | Time "));
+        assert!(out.contains("| 11111111 | none     | 2024-01-05 12:00:00 |"));
+        assert!(out.contains("| 22222222 | 11111111 | 2024-01-06 12:00:00 |"));
+        Ok(())
+    }
+    #[test]
+    fn test_print_groups_with_sizes_passes() -> Result<()> {
+        let sn = SnapshotFile {
+            hostname: "host1".to_string(),
+            ..test_snapshot('1', 5)
+        };
+        let sizes = HashMap::from([(
+            sn.id,
+            SnapshotSizes {
+                blobs: 3,
+                size: 2048,
+                exclusive_blobs: 1,
+                exclusive_size: 1024,
+            },
+        )]);
+        let group = SnapshotGroup::from_snapshot(&sn, "host".parse()?);
+
+        let cmd = SnapshotCmd::try_parse_from(["snapshots", "--calculate-sizes"])?;
+        let mut buf = Vec::new();
+        cmd.print_groups(
+            &mut Output::new(&mut buf),
+            vec![(group, vec![sn])],
+            Some(&sizes),
+        )?;
+
+        let out = String::from_utf8(buf)?;
+        assert!(out.contains("| Size | Referenced | Exclusive |"));
+        assert!(out.contains("|    ? |    2.0 KiB |   1.0 KiB |"));
+        Ok(())
+    }
+}
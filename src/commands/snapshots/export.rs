@@ -0,0 +1,218 @@
+//! `snapshots export` subcommand: export snapshot metadata to a SQLite database
+
+use std::path::PathBuf;
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local};
+use clap::ValueHint;
+use log::info;
+use rusqlite::{named_params, Connection};
+use rustic_core::repofile::SnapshotFile;
+
+use crate::{
+    commands::open_repository, helpers::parse_datetime, status_err, Application, RUSTIC_APP,
+};
+
+/// Schema of the exported database
+///
+/// There is one row per snapshot, keyed by the snapshot id; exporting again updates existing rows.
+/// Times are given in RFC 3339 format, tags and paths are JSON arrays and the summary columns are
+/// `NULL` for snapshots without summary.
+pub(crate) const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS snapshots (
+    id TEXT PRIMARY KEY NOT NULL,
+    time TEXT NOT NULL,
+    program_version TEXT NOT NULL,
+    hostname TEXT NOT NULL,
+    username TEXT NOT NULL,
+    label TEXT NOT NULL,
+    tags TEXT NOT NULL,
+    paths TEXT NOT NULL,
+    description TEXT,
+    tree TEXT NOT NULL,
+    parent TEXT,
+    original TEXT,
+    files_new INTEGER,
+    files_changed INTEGER,
+    files_unmodified INTEGER,
+    dirs_new INTEGER,
+    dirs_changed INTEGER,
+    dirs_unmodified INTEGER,
+    total_files_processed INTEGER,
+    total_dirs_processed INTEGER,
+    total_bytes_processed INTEGER,
+    data_added INTEGER,
+    data_added_packed INTEGER,
+    backup_start TEXT,
+    backup_end TEXT,
+    backup_duration REAL
+);
+";
+
+/// Statement to insert or update a snapshot
+const UPSERT: &str = "INSERT OR REPLACE INTO snapshots VALUES (
+    :id, :time, :program_version, :hostname, :username, :label, :tags, :paths, :description,
+    :tree, :parent, :original, :files_new, :files_changed, :files_unmodified, :dirs_new,
+    :dirs_changed, :dirs_unmodified, :total_files_processed, :total_dirs_processed,
+    :total_bytes_processed, :data_added, :data_added_packed, :backup_start, :backup_end,
+    :backup_duration
+)";
+
+/// `snapshots export` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct ExportCmd {
+    /// SQLite database to export to; it is created if it doesn't exist
+    #[clap(long, value_name = "PATH", value_hint = ValueHint::FilePath, required_unless_present = "schema")]
+    db: Option<PathBuf>,
+
+    /// Only print the schema of the database
+    #[clap(long)]
+    schema: bool,
+
+    /// Only export snapshots newer than the given time, e.g. "2024-01-31" or "2024-01-31 12:00:00"
+    #[clap(long, value_name = "DATETIME", value_parser = parse_datetime)]
+    since: Option<DateTime<Local>>,
+
+    /// Snapshots to export. If none is given, use filter options to filter from all snapshots
+    #[clap(value_name = "ID")]
+    ids: Vec<String>,
+}
+
+impl Runnable for ExportCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl ExportCmd {
+    fn inner_run(&self) -> Result<()> {
+        if self.schema {
+            print!("{SCHEMA}");
+            return Ok(());
+        }
+        let Some(db) = &self.db else {
+            bail!("please specify the database to export to using --db");
+        };
+
+        let config = RUSTIC_APP.config();
+        let repo = open_repository(&config.repository)?;
+        let snapshots: Vec<_> = if self.ids.is_empty() {
+            repo.get_matching_snapshots(|sn| config.snapshot_filter.matches(sn))?
+        } else {
+            repo.get_snapshots(&self.ids)?
+        }
+        .into_iter()
+        .filter(|sn| self.since.map_or(true, |since| sn.time > since))
+        .collect();
+
+        let mut conn = Connection::open(db)?;
+        let count = export(&mut conn, &snapshots)?;
+        info!("exported {count} snapshot(s) to {}.", db.display());
+        Ok(())
+    }
+}
+
+/// Write the snapshots to the database, replacing existing rows of the same snapshots
+///
+/// # Arguments
+///
+/// * `conn` - the database connection
+/// * `snapshots` - the snapshots to export
+///
+/// # Returns
+///
+/// The number of exported snapshots
+fn export(conn: &mut Connection, snapshots: &[SnapshotFile]) -> Result<usize> {
+    conn.execute_batch(SCHEMA)?;
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(UPSERT)?;
+        for sn in snapshots {
+            let summary = sn.summary.as_ref();
+            _ = stmt.execute(named_params! {
+                ":id": sn.id.to_hex().as_str(),
+                ":time": sn.time.to_rfc3339(),
+                ":program_version": sn.program_version,
+                ":hostname": sn.hostname,
+                ":username": sn.username,
+                ":label": sn.label,
+                ":tags": serde_json::to_string(&sn.tags)?,
+                ":paths": serde_json::to_string(&sn.paths)?,
+                ":description": sn.description,
+                ":tree": sn.tree.to_hex().as_str(),
+                ":parent": sn.parent.map(|id| id.to_hex().to_string()),
+                ":original": sn.original.map(|id| id.to_hex().to_string()),
+                ":files_new": summary.map(|s| s.files_new),
+                ":files_changed": summary.map(|s| s.files_changed),
+                ":files_unmodified": summary.map(|s| s.files_unmodified),
+                ":dirs_new": summary.map(|s| s.dirs_new),
+                ":dirs_changed": summary.map(|s| s.dirs_changed),
+                ":dirs_unmodified": summary.map(|s| s.dirs_unmodified),
+                ":total_files_processed": summary.map(|s| s.total_files_processed),
+                ":total_dirs_processed": summary.map(|s| s.total_dirs_processed),
+                ":total_bytes_processed": summary.map(|s| s.total_bytes_processed),
+                ":data_added": summary.map(|s| s.data_added),
+                ":data_added_packed": summary.map(|s| s.data_added_packed),
+                ":backup_start": summary.map(|s| s.backup_start.to_rfc3339()),
+                ":backup_end": summary.map(|s| s.backup_end.to_rfc3339()),
+                ":backup_duration": summary.map(|s| s.backup_duration),
+            })?;
+        }
+    }
+    tx.commit()?;
+    Ok(snapshots.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::helpers::test_snapshot;
+
+    use rustic_core::repofile::SnapshotSummary;
+
+    #[test]
+    fn test_export_upserts_passes() -> Result<()> {
+        let mut snap = SnapshotFile {
+            hostname: "host1".to_string(),
+            paths: "/home".parse().unwrap(),
+            tags: "a,b".parse().unwrap(),
+            ..test_snapshot('1', 5)
+        };
+        let other = SnapshotFile {
+            summary: Some(SnapshotSummary {
+                total_files_processed: 3,
+                ..Default::default()
+            }),
+            ..test_snapshot('2', 5)
+        };
+
+        let mut conn = Connection::open_in_memory()?;
+        assert_eq!(export(&mut conn, &[snap.clone(), other])?, 2);
+        snap.hostname = "host2".to_string();
+        assert_eq!(export(&mut conn, &[snap])?, 1);
+
+        let rows: Vec<(String, String, String, Option<u64>)> = conn
+            .prepare("SELECT id, hostname, tags, total_files_processed FROM snapshots ORDER BY id")?
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        assert_eq!(
+            rows,
+            vec![
+                (
+                    "1".repeat(64),
+                    "host2".to_string(),
+                    r#"["a","b"]"#.to_string(),
+                    None
+                ),
+                ("2".repeat(64), String::new(), "[]".to_string(), Some(3)),
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,315 @@
+//! HTML report for the `snapshots` command
+
+use std::{
+    fmt::{Result, Write},
+    time::Duration,
+};
+
+use chrono::{DateTime, Local};
+use humantime::format_duration;
+use itertools::Itertools;
+use rustic_core::{repofile::SnapshotFile, SnapshotGroup};
+
+use crate::{
+    commands::{
+        repoinfo::Infos,
+        snapshots::{snap_to_table, SNAPSHOT_HEADER},
+    },
+    helpers::bytes_size_to_string,
+};
+
+/// Inline style sheet, the report must not reference external assets
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 0.5em; }
+th, td { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; vertical-align: top; }
+th { background: #eee; }
+td.num { text-align: right; }
+.badge { border-radius: 0.8em; color: #fff; font-size: 0.7em; padding: 0.2em 0.8em; vertical-align: middle; }
+.badge.ok { background: #2e7d32; }
+.badge.warn { background: #f9a825; }
+.badge.crit { background: #c62828; }";
+
+/// Options for the HTML report
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct ReportOptions {
+    /// Show all snapshots instead of summarizing identical follow-up snapshots
+    pub(crate) all: bool,
+    /// Age of the latest snapshot of a group from which on the group is marked as warning
+    pub(crate) warn_age: Option<Duration>,
+    /// Age of the latest snapshot of a group from which on the group is marked as critical
+    pub(crate) crit_age: Option<Duration>,
+}
+
+impl ReportOptions {
+    /// CSS class of the age badge for the given age of the latest snapshot
+    fn age_class(&self, age: Duration) -> &'static str {
+        if self.crit_age.is_some_and(|crit| age >= crit) {
+            "crit"
+        } else if self.warn_age.is_some_and(|warn| age >= warn) {
+            "warn"
+        } else {
+            "ok"
+        }
+    }
+}
+
+/// Write the snapshot groups as standalone HTML page
+///
+/// # Arguments
+///
+/// * `out` - where to write the page to
+/// * `groups` - the snapshot groups, as used for the `json` output
+/// * `infos` - repository statistics to add to the report
+/// * `opts` - the report options
+/// * `now` - the time used to compute the age of the snapshots
+pub(crate) fn write_report(
+    out: &mut impl Write,
+    groups: Vec<(SnapshotGroup, Vec<SnapshotFile>)>,
+    infos: Option<&Infos>,
+    opts: &ReportOptions,
+    now: DateTime<Local>,
+) -> Result {
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html lang=\"en\">")?;
+    writeln!(out, "<head>")?;
+    writeln!(out, "<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>rustic snapshots</title>")?;
+    writeln!(out, "<style>\n{STYLE}\n</style>")?;
+    writeln!(out, "</head>")?;
+    writeln!(out, "<body>")?;
+    writeln!(out, "<h1>rustic snapshots</h1>")?;
+    writeln!(
+        out,
+        "<p>generated at {}</p>",
+        now.format("%Y-%m-%d %H:%M:%S")
+    )?;
+
+    let mut total_count = 0;
+    for (group, mut snapshots) in groups {
+        snapshots.sort_unstable();
+        let count = snapshots.len();
+
+        writeln!(out, "<section>")?;
+        write!(out, "<h2>snapshots")?;
+        if !group.is_empty() {
+            write!(out, " for {}", escape(&group.to_string()))?;
+        }
+        if let Some(latest) = snapshots.last() {
+            let age = now
+                .signed_duration_since(latest.time)
+                .to_std()
+                .unwrap_or_default();
+            // only show full seconds
+            let age = Duration::from_secs(age.as_secs());
+            write!(
+                out,
+                " <span class=\"badge {}\">latest {} ago</span>",
+                opts.age_class(age),
+                format_duration(age)
+            )?;
+        }
+        writeln!(out, "</h2>")?;
+
+        let rows = snapshots
+            .into_iter()
+            .chunk_by(|sn| if opts.all { sn.id } else { sn.tree })
+            .into_iter()
+            .map(|(_, mut g)| snap_to_table(&g.next().unwrap(), g.count()))
+            .collect_vec();
+        write_table(out, &SNAPSHOT_HEADER, 6, rows)?;
+        writeln!(out, "<p>{count} snapshot(s)</p>")?;
+        writeln!(out, "</section>")?;
+        total_count += count;
+    }
+    writeln!(
+        out,
+        "<p><strong>total: {total_count} snapshot(s)</strong></p>"
+    )?;
+
+    if let Some(infos) = infos {
+        write_infos(out, infos)?;
+    }
+
+    writeln!(out, "</body>")?;
+    writeln!(out, "</html>")
+}
+
+/// Write the repository statistics as tables, see the `repoinfo` command
+fn write_infos(out: &mut impl Write, infos: &Infos) -> Result {
+    if let Some(files) = &infos.files {
+        writeln!(out, "<section>")?;
+        writeln!(out, "<h2>repository files</h2>")?;
+        let rows = files
+            .repo
+            .iter()
+            .map(|row| {
+                [
+                    format!("{:?}", row.tpe),
+                    row.count.to_string(),
+                    bytes_size_to_string(row.size),
+                ]
+            })
+            .chain(std::iter::once([
+                "Total".to_string(),
+                files
+                    .repo
+                    .iter()
+                    .map(|row| row.count)
+                    .sum::<u64>()
+                    .to_string(),
+                bytes_size_to_string(files.repo.iter().map(|row| row.size).sum()),
+            ]))
+            .collect_vec();
+        write_table(out, &["File type", "Count", "Total Size"], 1, rows)?;
+        writeln!(out, "</section>")?;
+    }
+
+    if let Some(index) = &infos.index {
+        writeln!(out, "<section>")?;
+        writeln!(out, "<h2>index</h2>")?;
+        let rows = index
+            .blobs
+            .iter()
+            .map(|blobs| {
+                [
+                    format!("{:?}", blobs.blob_type),
+                    blobs.count.to_string(),
+                    bytes_size_to_string(blobs.data_size),
+                    bytes_size_to_string(blobs.size),
+                ]
+            })
+            .collect_vec();
+        write_table(
+            out,
+            &["Blob type", "Count", "Total Size", "Total Size in Packs"],
+            1,
+            rows,
+        )?;
+        let rows = index
+            .packs
+            .iter()
+            .map(|packs| {
+                [
+                    format!("{:?} packs", packs.blob_type),
+                    packs.count.to_string(),
+                    packs.min_size.map_or("-".to_string(), bytes_size_to_string),
+                    packs.max_size.map_or("-".to_string(), bytes_size_to_string),
+                ]
+            })
+            .collect_vec();
+        write_table(
+            out,
+            &["Blob type", "Pack Count", "Minimum Size", "Maximum Size"],
+            1,
+            rows,
+        )?;
+        writeln!(out, "</section>")?;
+    }
+    Ok(())
+}
+
+/// Write a table; all columns starting from `right_from` are right aligned
+fn write_table<const N: usize>(
+    out: &mut impl Write,
+    titles: &[&str; N],
+    right_from: usize,
+    rows: Vec<[String; N]>,
+) -> Result {
+    writeln!(out, "<table>")?;
+    write!(out, "<tr>")?;
+    for title in titles {
+        write!(out, "<th>{}</th>", escape(title))?;
+    }
+    writeln!(out, "</tr>")?;
+    for row in rows {
+        write!(out, "<tr>")?;
+        for (i, cell) in row.iter().enumerate() {
+            let class = if i >= right_from {
+                " class=\"num\""
+            } else {
+                ""
+            };
+            // multi-line cells, e.g. tags or paths
+            let cell = escape(cell).replace('\n', "<br>");
+            write!(out, "<td{class}>{cell}</td>")?;
+        }
+        writeln!(out, "</tr>")?;
+    }
+    writeln!(out, "</table>")
+}
+
+/// Escape text for use in HTML
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::helpers::test_snapshot;
+
+    use chrono::TimeZone;
+    use rustic_core::{repofile::SnapshotSummary, Id, SnapshotGroupCriterion};
+
+    use pretty_assertions::assert_eq;
+
+    fn snap(id: char, tree: char, day: u32, hostname: &str, paths: &str) -> SnapshotFile {
+        SnapshotFile {
+            tree: Id::from_hex(&tree.to_string().repeat(64)).unwrap(),
+            hostname: hostname.to_string(),
+            paths: paths.parse().unwrap(),
+            ..test_snapshot(id, day)
+        }
+    }
+
+    #[test]
+    fn test_write_report_passes() -> anyhow::Result<()> {
+        let mut snaps = vec![
+            snap('1', 'a', 5, "host1", "/home"),
+            snap('2', 'a', 10, "host1", "/home"),
+            snap('3', 'b', 18, "host1", "/home"),
+            snap('4', 'c', 15, "host<2>", "/etc"),
+            snap('5', 'd', 1, "host3", "/srv/a&b"),
+        ];
+        snaps[2].summary = Some(SnapshotSummary {
+            total_files_processed: 3,
+            total_dirs_processed: 1,
+            total_bytes_processed: 512,
+            ..Default::default()
+        });
+        let criterion: SnapshotGroupCriterion = "host".parse()?;
+        let groups = snaps
+            .into_iter()
+            .chunk_by(|sn| SnapshotGroup::from_snapshot(sn, criterion))
+            .into_iter()
+            .map(|(group, snaps)| (group, snaps.collect()))
+            .collect();
+
+        let opts = ReportOptions {
+            all: false,
+            warn_age: Some(Duration::from_secs(3 * 86400)),
+            crit_age: Some(Duration::from_secs(14 * 86400)),
+        };
+        let now = Local.with_ymd_and_hms(2024, 1, 20, 12, 0, 0).unwrap();
+
+        let mut report = String::new();
+        write_report(&mut report, groups, None, &opts, now)?;
+        assert_eq!(
+            report,
+            include_str!("../../../tests/snapshots-fixtures/report.html")
+        );
+        Ok(())
+    }
+}
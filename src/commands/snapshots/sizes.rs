@@ -0,0 +1,215 @@
+//! Calculation of the sizes referenced by snapshots
+//!
+//! To compute which blobs are exclusive to a snapshot, the trees of all snapshots have to be
+//! walked. To keep this feasible for many snapshots, each tree blob is read only once and
+//! snapshots with identical root trees are walked only once. The costs are hence proportional to
+//! the number of distinct root trees times the number of blobs they reference and not to the
+//! number of snapshots times the size of a full tree walk.
+
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use rustic_core::{
+    repofile::{BlobType, SnapshotFile},
+    Id, IndexedFull, Progress, ProgressBars, Repository,
+};
+
+use crate::{Application, RUSTIC_APP};
+
+/// Sizes referenced by a snapshot
+#[derive(Clone, Copy, Debug, Default, Serialize, PartialEq, Eq)]
+pub(crate) struct SnapshotSizes {
+    /// Number of blobs referenced by the snapshot
+    pub(crate) blobs: u64,
+    /// Size in packs of the blobs referenced by the snapshot
+    pub(crate) size: u64,
+    /// Number of blobs referenced by no other of the given snapshots
+    pub(crate) exclusive_blobs: u64,
+    /// Size in packs of the blobs referenced by no other of the given snapshots, i.e. the size
+    /// which is freed by forgetting the snapshot and pruning
+    pub(crate) exclusive_size: u64,
+}
+
+/// Calculate the referenced and exclusive sizes of snapshots
+///
+/// Exclusiveness is relative to the given snapshots, i.e. blobs referenced by other snapshots of
+/// the repository count as exclusive unless those snapshots are given, too.
+///
+/// # Arguments
+///
+/// * `repo` - the indexed repository
+/// * `snapshots` - the snapshots to calculate the sizes for
+///
+/// # Returns
+///
+/// The sizes in the order of the given snapshots
+pub(crate) fn calculate_sizes<P: ProgressBars, S: IndexedFull>(
+    repo: &Repository<P, S>,
+    snapshots: &[SnapshotFile],
+) -> Result<Vec<SnapshotSizes>> {
+    // snapshots with identical root trees share their blob set
+    let mut roots: HashMap<Id, usize> = HashMap::new();
+    let mut tree_ids = Vec::new();
+    let indices: Vec<_> = snapshots
+        .iter()
+        .map(|sn| {
+            *roots.entry(sn.tree).or_insert_with(|| {
+                tree_ids.push(sn.tree);
+                tree_ids.len() - 1
+            })
+        })
+        .collect();
+    let mut multiplicity = vec![0; tree_ids.len()];
+    for &i in &indices {
+        multiplicity[i] += 1;
+    }
+
+    let p = RUSTIC_APP
+        .config()
+        .global
+        .progress_options
+        .progress_counter("calculating snapshot sizes...");
+    p.set_length(tree_ids.len() as u64);
+    let mut walker = TreeWalker::default();
+    let sets = tree_ids
+        .iter()
+        .zip(multiplicity)
+        .map(|(id, count)| {
+            let blobs = walker.blobs(repo, *id)?;
+            p.inc(1);
+            Ok((blobs, count))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    p.finish();
+
+    let blob_size = |(tpe, id): &(BlobType, Id)| -> Result<u64> {
+        Ok(u64::from(repo.get_index_entry(*tpe, id)?.length))
+    };
+    let exclusive = exclusive_blobs(&sets);
+    let sizes = sets
+        .iter()
+        .zip(exclusive)
+        .map(|((blobs, _), exclusive)| {
+            Ok(SnapshotSizes {
+                blobs: blobs.len() as u64,
+                size: blobs.iter().map(blob_size).sum::<Result<u64>>()?,
+                exclusive_blobs: exclusive.len() as u64,
+                exclusive_size: exclusive.iter().map(blob_size).sum::<Result<u64>>()?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(indices.into_iter().map(|i| sizes[i]).collect())
+}
+
+/// Get the packs containing the blobs referenced by snapshots
+///
+/// # Arguments
+///
+/// * `repo` - the indexed repository
+/// * `snapshots` - the snapshots to get the packs for
+///
+/// # Returns
+///
+/// The ids of the packs, each one only once
+pub(crate) fn snapshot_packs<P, S: IndexedFull>(
+    repo: &Repository<P, S>,
+    snapshots: &[SnapshotFile],
+) -> Result<Vec<Id>> {
+    let mut walker = TreeWalker::default();
+    let mut packs = HashSet::new();
+    for sn in snapshots {
+        for (tpe, id) in walker.blobs(repo, sn.tree)? {
+            _ = packs.insert(repo.get_index_entry(tpe, &id)?.pack);
+        }
+    }
+    Ok(packs.into_iter().collect())
+}
+
+/// Walks trees, reading each tree blob only once
+#[derive(Default)]
+struct TreeWalker {
+    /// The data blobs and subtrees of the trees read so far
+    trees: HashMap<Id, (Vec<Id>, Vec<Id>)>,
+}
+
+impl TreeWalker {
+    /// Get all tree and data blobs referenced by a tree, including the tree itself
+    fn blobs<P, S: IndexedFull>(
+        &mut self,
+        repo: &Repository<P, S>,
+        tree: Id,
+    ) -> Result<HashSet<(BlobType, Id)>> {
+        let mut blobs = HashSet::new();
+        let mut stack = vec![tree];
+        while let Some(id) = stack.pop() {
+            if !blobs.insert((BlobType::Tree, id)) {
+                continue;
+            }
+            if !self.trees.contains_key(&id) {
+                let mut content = Vec::new();
+                let mut subtrees = Vec::new();
+                for node in repo.get_tree(&id)?.nodes {
+                    content.extend(node.content.into_iter().flatten());
+                    subtrees.extend(node.subtree);
+                }
+                _ = self.trees.insert(id, (content, subtrees));
+            }
+            let (content, subtrees) = &self.trees[&id];
+            blobs.extend(content.iter().map(|id| (BlobType::Data, *id)));
+            stack.extend(subtrees);
+        }
+        Ok(blobs)
+    }
+}
+
+/// Determine the blobs which are referenced by exactly one set
+///
+/// # Arguments
+///
+/// * `sets` - the sets together with the number of times they are referenced
+///
+/// # Returns
+///
+/// For each set, the blobs which are referenced by no other set; sets which are referenced more
+/// than once have no exclusive blobs
+fn exclusive_blobs<T: Clone + Eq + Hash>(sets: &[(HashSet<T>, usize)]) -> Vec<Vec<T>> {
+    let mut references: HashMap<&T, usize> = HashMap::new();
+    for (set, count) in sets {
+        for blob in set {
+            *references.entry(blob).or_default() += count;
+        }
+    }
+    sets.iter()
+        .map(|(set, _)| {
+            set.iter()
+                .filter(|blob| references[blob] == 1)
+                .cloned()
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_exclusive_blobs_passes() {
+        let sets = vec![
+            (HashSet::from([1, 2, 3]), 1),
+            (HashSet::from([2, 3, 4]), 1),
+            (HashSet::from([3]), 1),
+            (HashSet::from([5]), 2),
+        ];
+        let mut exclusive = exclusive_blobs(&sets);
+        exclusive.iter_mut().for_each(|blobs| blobs.sort_unstable());
+        assert_eq!(exclusive, vec![vec![1], vec![4], vec![], vec![]]);
+    }
+}
@@ -0,0 +1,213 @@
+//! User-defined output format of the `snapshots` command
+
+use std::str::FromStr;
+
+use anyhow::{Error, Result};
+use handlebars::{no_escape, Handlebars, Template};
+use itertools::Itertools;
+use rustic_core::repofile::SnapshotFile;
+use serde::Serialize;
+
+/// Name of the registered template
+const TEMPLATE_NAME: &str = "snapshot";
+
+/// Output format given by `--format`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum SnapshotFormat {
+    /// The default table output
+    Table,
+    /// The output of `--json`
+    Json,
+    /// A template rendered for each snapshot, already converted to handlebars syntax
+    Template(String),
+}
+
+impl FromStr for SnapshotFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "table" => Self::Table,
+            "json" => Self::Json,
+            template => {
+                let template = convert_template(template);
+                // check the syntax already when parsing the arguments
+                _ = Template::compile(&template)?;
+                Self::Template(template)
+            }
+        })
+    }
+}
+
+/// Fields of a snapshot available in templates, e.g. `{{.Host}}`
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct TemplateData {
+    /// Short snapshot id
+    id: String,
+    /// Full snapshot id
+    long_id: String,
+    /// Snapshot time
+    time: String,
+    /// Host name
+    host: String,
+    /// User name
+    user: String,
+    /// Label
+    label: String,
+    /// Comma-separated tags
+    tags: String,
+    /// Comma-separated paths
+    paths: String,
+    /// Description
+    description: Option<String>,
+    /// Short id of the parent snapshot
+    parent: Option<String>,
+    /// Short id of the root tree
+    tree: String,
+    /// Number of files, if the snapshot has a summary
+    files: Option<u64>,
+    /// Number of directories, if the snapshot has a summary
+    dirs: Option<u64>,
+    /// Size in bytes, if the snapshot has a summary
+    size: Option<u64>,
+}
+
+impl From<&SnapshotFile> for TemplateData {
+    fn from(sn: &SnapshotFile) -> Self {
+        let summary = sn.summary.as_ref();
+        Self {
+            id: sn.id.to_string(),
+            long_id: sn.id.to_hex().to_string(),
+            time: sn.time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            host: sn.hostname.clone(),
+            user: sn.username.clone(),
+            label: sn.label.clone(),
+            tags: sn.tags.formatln().lines().join(","),
+            paths: sn.paths.formatln().lines().join(","),
+            description: sn.description.clone(),
+            parent: sn.parent.map(|id| id.to_string()),
+            tree: sn.tree.to_string(),
+            files: summary.map(|s| s.total_files_processed),
+            dirs: summary.map(|s| s.total_dirs_processed),
+            size: summary.map(|s| s.total_bytes_processed),
+        }
+    }
+}
+
+/// Convert a Go-like template, i.e. using `{{.Field}}`, to handlebars syntax, i.e. `{{Field}}`
+///
+/// Within expressions, the leading dot of each field name is removed.
+fn convert_template(template: &str) -> String {
+    let mut converted = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let (text, expr) = rest.split_at(start);
+        converted.push_str(text);
+        let end = expr.find("}}").map_or(expr.len(), |end| end + 2);
+        let (expr, tail) = expr.split_at(end);
+        let mut prev = '{';
+        for (i, c) in expr.char_indices() {
+            let starts_field = c == '.'
+                && (prev == '{' || prev == '(' || prev.is_whitespace())
+                && expr[i + 1..].starts_with(char::is_alphabetic);
+            if !starts_field {
+                converted.push(c);
+            }
+            prev = c;
+        }
+        rest = tail;
+    }
+    converted.push_str(rest);
+    converted
+}
+
+/// Render the template for each snapshot, each followed by a newline
+///
+/// # Arguments
+///
+/// * `template` - the template, as parsed by [`SnapshotFormat`]
+/// * `snapshots` - the snapshots to render
+///
+/// # Errors
+///
+/// * If the template uses unknown fields or can't be rendered
+pub(crate) fn render<'a>(
+    template: &str,
+    snapshots: impl IntoIterator<Item = &'a SnapshotFile>,
+) -> Result<String> {
+    let mut handlebars = Handlebars::new();
+    handlebars.set_strict_mode(true);
+    handlebars.register_escape_fn(no_escape);
+    handlebars.register_template_string(TEMPLATE_NAME, template)?;
+
+    let mut rendered = String::new();
+    for sn in snapshots {
+        rendered.push_str(&handlebars.render(TEMPLATE_NAME, &TemplateData::from(sn))?);
+        rendered.push('\n');
+    }
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::helpers::test_snapshot;
+
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("{{.Id}}", "{{Id}}")]
+    #[case("{{ .Id }} {{.Host}}", "{{ Id }} {{Host}}")]
+    #[case("{{#if .Parent}}{{.Parent}}{{/if}}", "{{#if Parent}}{{Parent}}{{/if}}")]
+    #[case("{{.}} {{ .Files }}", "{{.}} {{ Files }}")]
+    #[case("no fields", "no fields")]
+    fn test_convert_template_passes(#[case] template: &str, #[case] expected: &str) {
+        assert_eq!(convert_template(template), expected);
+    }
+
+    #[test]
+    fn test_render_passes() -> Result<()> {
+        let snaps: Vec<_> = [('1', "a,b"), ('2', "")]
+            .into_iter()
+            .map(|(id, tags)| SnapshotFile {
+                hostname: "host<1>".to_string(),
+                tags: tags.parse().unwrap(),
+                ..test_snapshot(id, 5)
+            })
+            .collect();
+
+        let SnapshotFormat::Template(template) =
+            "{{.Id}} {{.Time}} {{.Host}} [{{.Tags}}]".parse()?
+        else {
+            panic!("not parsed as template");
+        };
+        assert_eq!(
+            render(&template, &snaps)?,
+            "11111111 2024-01-05 12:00:00 host<1> [a,b]\n\
+            22222222 2024-01-05 12:00:00 host<1> []\n"
+        );
+
+        // unknown fields are an error
+        let SnapshotFormat::Template(template) = "{{.Hostname}}".parse()? else {
+            panic!("not parsed as template");
+        };
+        assert!(render(&template, &snaps).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_format_passes() {
+        assert_eq!(
+            "table".parse::<SnapshotFormat>().unwrap(),
+            SnapshotFormat::Table
+        );
+        assert_eq!(
+            "json".parse::<SnapshotFormat>().unwrap(),
+            SnapshotFormat::Json
+        );
+        assert!("{{#if .Id}}".parse::<SnapshotFormat>().is_err());
+    }
+}
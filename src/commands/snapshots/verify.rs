@@ -0,0 +1,178 @@
+//! `snapshots verify` subcommand: check that the contents of snapshots can be restored
+
+use std::{collections::HashSet, io::Write};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{anyhow, bail, Result};
+use log::{error, info};
+use rustic_core::{
+    repofile::{BlobType, Node, SnapshotFile},
+    Id, IndexedFull, LsOptions, Progress, ProgressBars, Repository,
+};
+
+use crate::{
+    commands::open_repository_indexed, config::progress_options::RusticProgress, output::Output,
+    status_err, Application, RUSTIC_APP,
+};
+
+/// `snapshots verify` subcommand
+///
+/// In contrast to `check --read-data`, only the data belonging to the given snapshots is read.
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct VerifyCmd {
+    /// Snapshots to verify. If none is given, use filter options to filter from all snapshots
+    #[clap(value_name = "ID")]
+    ids: Vec<String>,
+
+    /// Only check that all blobs are contained in the index, don't read any pack data
+    #[clap(long)]
+    quick: bool,
+}
+
+impl Runnable for VerifyCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run(&mut Output::stdout()) {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl VerifyCmd {
+    fn inner_run(&self, out: &mut Output<impl Write>) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let repo = open_repository_indexed(&config.repository)?;
+        let snapshots = if self.ids.is_empty() {
+            repo.get_matching_snapshots(|sn| config.snapshot_filter.matches(sn))?
+        } else {
+            repo.get_snapshots(&self.ids)?
+        };
+
+        // the summary is only an estimate for the progress, identical blobs are read only once
+        let p = config
+            .global
+            .progress_options
+            .progress_bytes("verifying snapshots...");
+        p.set_length(
+            snapshots
+                .iter()
+                .filter_map(|sn| sn.summary.as_ref())
+                .map(|s| s.total_bytes_processed)
+                .sum(),
+        );
+
+        let mut verified = HashSet::new();
+        let mut failed = 0;
+        let mut results = Vec::new();
+        for sn in &snapshots {
+            let errors = self.verify_snapshot(&repo, sn, &mut verified, &p);
+            if errors > 0 {
+                failed += 1;
+            }
+            results.push((sn.id, errors));
+        }
+        p.finish();
+
+        for (id, errors) in results {
+            if errors == 0 {
+                writeln!(out, "snapshot {id}: ok")?;
+            } else {
+                writeln!(out, "snapshot {id}: {errors} error(s)")?;
+            }
+        }
+        if failed > 0 {
+            bail!(
+                "{failed} of {} snapshot(s) failed verification.",
+                snapshots.len()
+            );
+        }
+        info!("{} snapshot(s) verified successfully.", snapshots.len());
+        Ok(())
+    }
+
+    /// Verify all files of a snapshot, reporting the errors
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - the indexed repository
+    /// * `sn` - the snapshot to verify
+    /// * `verified` - the data blobs which have already been read successfully
+    /// * `p` - the progress bar
+    ///
+    /// # Returns
+    ///
+    /// The number of files and directories which failed verification
+    fn verify_snapshot<P, S: IndexedFull>(
+        &self,
+        repo: &Repository<P, S>,
+        sn: &SnapshotFile,
+        verified: &mut HashSet<Id>,
+        p: &RusticProgress,
+    ) -> usize {
+        let node = match repo.node_from_snapshot_and_path(sn, "") {
+            Ok(node) => node,
+            Err(err) => {
+                error!("snapshot {}: error reading the root tree: {err}", sn.id);
+                return 1;
+            }
+        };
+        let mut opts = LsOptions::default();
+        opts.recursive = true;
+        let entries = match repo.ls(&node, &opts) {
+            Ok(entries) => entries,
+            Err(err) => {
+                error!("snapshot {}: error reading trees: {err}", sn.id);
+                return 1;
+            }
+        };
+
+        let mut errors = 0;
+        for item in entries {
+            let result = item.map_err(anyhow::Error::from).and_then(|(path, node)| {
+                self.verify_file(repo, &node, verified, p)
+                    .map_err(|err| anyhow!("{}: {err}", path.display()))
+            });
+            if let Err(err) = result {
+                error!("snapshot {}: {err}", sn.id);
+                errors += 1;
+            }
+        }
+        errors
+    }
+
+    /// Verify the content of a file; other entries are always fine
+    ///
+    /// # Errors
+    ///
+    /// * If a blob is not contained in the index
+    /// * If a blob can't be read or doesn't match its checksum (not with `--quick`)
+    fn verify_file<P, S: IndexedFull>(
+        &self,
+        repo: &Repository<P, S>,
+        node: &Node,
+        verified: &mut HashSet<Id>,
+        p: &RusticProgress,
+    ) -> Result<()> {
+        if !node.is_file() {
+            return Ok(());
+        }
+        let open_file = (!self.quick).then(|| repo.open_file(node)).transpose()?;
+
+        let mut offset = 0;
+        for id in node.content.iter().flatten() {
+            let length = repo.get_index_entry(BlobType::Data, id)?.data_length() as usize;
+            if let Some(open_file) = &open_file {
+                if !verified.contains(id) {
+                    let data = repo.read_file_at(open_file, offset, length)?;
+                    if !id.blob_matches_reader(length, &mut data.as_ref()) {
+                        bail!("blob {id} doesn't match its checksum");
+                    }
+                    _ = verified.insert(*id);
+                }
+            }
+            offset += length;
+            p.inc(length as u64);
+        }
+        Ok(())
+    }
+}
@@ -1,12 +1,22 @@
 //! `tag` subcommand
 
-use crate::{commands::open_repository, status_err, Application, RUSTIC_APP};
+use std::path::PathBuf;
+
+use crate::{
+    commands::open_repository, config::hooks::is_saved_as, status_err, Application, RUSTIC_APP,
+};
 
 use abscissa_core::{Command, Runnable, Shutdown};
 
+use anyhow::Context;
 use chrono::{Duration, Local};
+use clap::ValueHint;
+use itertools::Itertools;
 
-use rustic_core::{repofile::DeleteOption, StringList};
+use rustic_core::{
+    repofile::{DeleteOption, SnapshotFile},
+    StringList,
+};
 
 /// `tag` subcommand
 #[derive(clap::Parser, Command, Debug)]
@@ -57,6 +67,29 @@ pub(crate) struct TagCmd {
     /// Mark snapshot to be deleted after given duration (e.g. 10d)
     #[clap(long, value_name = "DURATION", help_heading = "Delete mark options")]
     set_delete_after: Option<humantime::Duration>,
+
+    /// Set the description
+    #[clap(
+        long,
+        value_name = "DESCRIPTION",
+        conflicts_with_all = &["set_description_from", "remove_description"],
+        help_heading = "Description options"
+    )]
+    set_description: Option<String>,
+
+    /// Set the description to the contents of the given file
+    #[clap(
+        long,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        conflicts_with = "remove_description",
+        help_heading = "Description options"
+    )]
+    set_description_from: Option<PathBuf>,
+
+    /// Remove the description
+    #[clap(long, help_heading = "Description options")]
+    remove_description: bool,
 }
 
 impl Runnable for TagCmd {
@@ -90,25 +123,118 @@ impl TagCmd {
             (false, false, None) => None,
         };
 
-        let snapshots: Vec<_> = snapshots
-            .into_iter()
-            .filter_map(|mut sn| {
-                sn.modify_sn(self.set.clone(), self.add.clone(), &self.remove, &delete)
-            })
-            .collect();
-        let old_snap_ids: Vec<_> = snapshots.iter().map(|sn| sn.id).collect();
+        // `None` keeps the description, `Some(None)` removes it
+        let description = match (&self.set_description, &self.set_description_from) {
+            (Some(description), _) => Some(Some(description.clone())),
+            (_, Some(file)) => Some(Some(std::fs::read_to_string(file).with_context(|| {
+                format!("error reading description from {}", file.display())
+            })?)),
+            (None, None) => self.remove_description.then_some(None),
+        };
+
+        let mut changes = Vec::new();
+        for mut sn in snapshots {
+            let old = sn.clone();
+            let mut changed = sn
+                .modify_sn(self.set.clone(), self.add.clone(), &self.remove, &delete)
+                .is_some();
+            if let Some(description) = &description {
+                if sn.description != *description {
+                    sn.description.clone_from(description);
+                    changed = true;
+                }
+            }
+            // unchanged snapshots are not rewritten
+            if changed {
+                // the modified snapshot references the first snapshot it was derived from
+                _ = sn.original.get_or_insert(old.id);
+                changes.push((old, sn));
+            }
+        }
+        let old_snaps: Vec<_> = changes.iter().map(|(old, _)| old.clone()).collect();
+        let old_snap_ids: Vec<_> = old_snaps.iter().map(|sn| sn.id).collect();
+        let snapshots: Vec<_> = changes.iter().map(|(_, sn)| sn.clone()).collect();
 
         match (old_snap_ids.is_empty(), config.global.dry_run) {
             (true, _) => println!("no snapshot changed."),
             (false, true) => {
-                println!("would have modified the following snapshots:\n {old_snap_ids:?}");
+                println!("would have modified the following snapshots:");
+                for (old, new) in &changes {
+                    print!("{}", describe_change(old, new));
+                }
             }
             (false, false) => {
-                repo.save_snapshots(snapshots)?;
-                repo.delete_snapshots(&old_snap_ids)?;
+                let is_created =
+                    |saved: &SnapshotFile| snapshots.iter().any(|sn| is_saved_as(saved, sn));
+                config
+                    .hooks
+                    .track_snapshots(&repo, is_created, &old_snaps, || {
+                        // old snapshots are only removed once the modified ones have been saved
+                        repo.save_snapshots(snapshots.clone())?;
+                        repo.delete_snapshots(&old_snap_ids)?;
+                        Ok(())
+                    })?;
             }
         }
 
         Ok(())
     }
 }
+
+/// Describe the changes of a snapshot, one line per changed property
+///
+/// # Arguments
+///
+/// * `old` - the snapshot before the change
+/// * `new` - the snapshot after the change
+fn describe_change(old: &SnapshotFile, new: &SnapshotFile) -> String {
+    let tags = |sn: &SnapshotFile| sn.tags.formatln().lines().join(",");
+    let mut description = format!("snapshot {}:\n", old.id);
+    let (old_tags, new_tags) = (tags(old), tags(new));
+    if old_tags != new_tags {
+        description.push_str(&format!("  tags: [{old_tags}] -> [{new_tags}]\n"));
+    }
+    if old.description != new.description {
+        let show = |d: &Option<String>| {
+            d.as_ref()
+                .map_or_else(|| "none".to_string(), |d| format!("{d:?}"))
+        };
+        description.push_str(&format!(
+            "  description: {} -> {}\n",
+            show(&old.description),
+            show(&new.description)
+        ));
+    }
+    if old.delete != new.delete {
+        description.push_str(&format!(
+            "  delete mark: {:?} -> {:?}\n",
+            old.delete, new.delete
+        ));
+    }
+    description
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::helpers::test_snapshot;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_describe_change_passes() {
+        let old = SnapshotFile {
+            tags: "a,b".parse().unwrap(),
+            ..test_snapshot('1', 5)
+        };
+        let mut new = old.clone();
+        new.tags = "a,c".parse().unwrap();
+        new.description = Some("new".to_string());
+
+        assert_eq!(
+            describe_change(&old, &new),
+            "snapshot 11111111:\n  tags: [a,b] -> [a,c]\n  description: none -> \"new\"\n"
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! `version` subcommand
+
+use crate::{status_err, Application, RUSTIC_APP};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::Result;
+use serde::Serialize;
+
+/// Version of rustic, can be overwritten at build time using `PROJECT_VERSION`
+pub(crate) const VERSION: &str = match option_env!("PROJECT_VERSION") {
+    Some(version) => version,
+    None => env!("CARGO_PKG_VERSION"),
+};
+
+/// Version of the config profile schema. Increase this when the config format changes incompatibly.
+pub(crate) const CONFIG_SCHEMA_VERSION: u32 = 1;
+
+/// `version` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct VersionCmd {
+    /// Show version information in json format
+    #[clap(long)]
+    json: bool,
+}
+
+impl Runnable for VersionCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+/// Version and build information
+///
+/// This struct is used to serialize the infos in `json` format. Its fields are considered
+/// to be a stable interface; only add new fields, never rename or remove existing ones.
+#[derive(Debug, Serialize)]
+struct VersionInfo {
+    version: &'static str,
+    git_commit: Option<&'static str>,
+    build_date: Option<&'static str>,
+    rustic_core_version: Option<&'static str>,
+    features: Vec<&'static str>,
+    backends: Vec<&'static str>,
+    config_schema_version: u32,
+}
+
+impl VersionInfo {
+    fn new() -> Self {
+        Self {
+            version: VERSION,
+            git_commit: option_env!("RUSTIC_GIT_COMMIT"),
+            build_date: option_env!("RUSTIC_BUILD_DATE"),
+            rustic_core_version: option_env!("RUSTIC_CORE_VERSION"),
+            features: enabled_features(),
+            backends: enabled_backends(),
+            config_schema_version: CONFIG_SCHEMA_VERSION,
+        }
+    }
+}
+
+/// Get the cargo features rustic has been compiled with
+pub(crate) fn enabled_features() -> Vec<&'static str> {
+    [
+        ("jemallocator", cfg!(feature = "jemallocator")),
+        ("keyring", cfg!(feature = "keyring")),
+        ("mimalloc", cfg!(feature = "mimalloc")),
+        ("mount", cfg!(feature = "mount")),
+        ("self-update", cfg!(feature = "self-update")),
+        ("sqlite", cfg!(feature = "sqlite")),
+        ("tui", cfg!(feature = "tui")),
+        ("webdav", cfg!(feature = "webdav")),
+    ]
+    .into_iter()
+    .filter_map(|(feature, enabled)| enabled.then_some(feature))
+    .collect()
+}
+
+/// Get the backends rustic has been compiled with, i.e. the possible prefixes for `--repository`
+fn enabled_backends() -> Vec<&'static str> {
+    [
+        ("local", true),
+        ("opendal", cfg!(feature = "opendal")),
+        ("rclone", cfg!(feature = "rclone")),
+        ("rest", cfg!(feature = "rest")),
+    ]
+    .into_iter()
+    .filter_map(|(backend, enabled)| enabled.then_some(backend))
+    .collect()
+}
+
+impl VersionCmd {
+    fn inner_run(&self) -> Result<()> {
+        let info = VersionInfo::new();
+
+        if self.json {
+            let mut stdout = std::io::stdout();
+            serde_json::to_writer_pretty(&mut stdout, &info)?;
+            return Ok(());
+        }
+
+        println!("rustic v{}", info.version);
+        println!("git commit:    {}", info.git_commit.unwrap_or("unknown"));
+        println!("build date:    {}", info.build_date.unwrap_or("unknown"));
+        println!(
+            "rustic_core:   {}",
+            info.rustic_core_version.unwrap_or("unknown")
+        );
+        println!("features:      {}", info.features.join(", "));
+        println!("backends:      {}", info.backends.join(", "));
+        println!("config schema: {}", info.config_schema_version);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_json_schema_is_stable() {
+        let value = serde_json::to_value(VersionInfo::new()).unwrap();
+        let mut keys: Vec<_> = value.as_object().unwrap().keys().cloned().collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                "backends",
+                "build_date",
+                "config_schema_version",
+                "features",
+                "git_commit",
+                "rustic_core_version",
+                "version",
+            ]
+        );
+        assert_eq!(value["version"], VERSION);
+        assert_eq!(value["config_schema_version"], CONFIG_SCHEMA_VERSION);
+        assert_eq!(value["backends"][0], "local");
+    }
+}
@@ -3,24 +3,61 @@
 // ignore markdown clippy lints as we use doc-comments to generate clap help texts
 #![allow(clippy::doc_markdown)]
 
-use std::{net::ToSocketAddrs, str::FromStr};
+use std::{
+    net::{SocketAddr, ToSocketAddrs},
+    path::{Path, PathBuf},
+    process::Stdio,
+    str::FromStr,
+    sync::{Arc, PoisonError, RwLock},
+    thread,
+    time::Duration,
+};
 
 use crate::{commands::open_repository_indexed, status_err, Application, RusticConfig, RUSTIC_APP};
-use abscissa_core::{config::Override, Command, FrameworkError, Runnable, Shutdown};
-use anyhow::{anyhow, Result};
-use dav_server::{warp::dav_handler, DavHandler};
+use abscissa_core::{
+    config::Override, Command, FrameworkError, FrameworkErrorKind, Runnable, Shutdown,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use dav_server::{
+    davpath::DavPath,
+    fs::{
+        DavDirEntry, DavFile, DavFileSystem, DavMetaData, FsFuture, FsStream, OpenOptions,
+        ReadDirMeta,
+    },
+    warp::dav_handler,
+    DavHandler,
+};
+use log::{debug, info, warn};
 use merge::Merge;
+use rcgen::CertifiedKey;
 use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use warp::{http::StatusCode, reject::Reject, Filter, Rejection, Reply};
 
-use rustic_core::vfs::{FilePolicy, IdenticalSnapshot, Latest, Vfs};
+use rustic_core::{
+    vfs::{FilePolicy, IdenticalSnapshot, Latest, Vfs},
+    CommandInput,
+};
 
+/// Address the webdav server binds to if none is given
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+
+#[serde_as]
 #[derive(Clone, Command, Default, Debug, clap::Parser, Serialize, Deserialize, Merge)]
 #[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
 pub struct WebDavCmd {
-    /// Address to bind the webdav server to. [default: "localhost:8000"]
-    #[clap(long, value_name = "ADDRESS")]
+    /// Address to bind the webdav server to, e.g. "0.0.0.0:8080" for all interfaces. Use port 0 to
+    /// get a random free port. [default: "127.0.0.1:8080"]
+    #[clap(long, visible_alias = "bind", value_name = "ADDRESS")]
     address: Option<String>,
 
+    /// The address to bind to, resolved from `address` when the options are merged
+    #[clap(skip)]
+    #[serde(skip)]
+    #[merge(skip)]
+    bind_addr: Option<SocketAddr>,
+
     /// The path template to use for snapshots. {id}, {id_long}, {time}, {username}, {hostname}, {label}, {tags}, {backup_start}, {backup_end} are replaced. [default: "[{hostname}]/[{label}]/{time}"]
     #[clap(long)]
     path_template: Option<String>,
@@ -38,6 +75,64 @@ pub struct WebDavCmd {
     #[clap(long)]
     file_access: Option<String>,
 
+    /// Serve via HTTPS using the certificate from this PEM file (requires --tls-key)
+    #[clap(long, value_name = "PATH")]
+    tls_cert: Option<PathBuf>,
+
+    /// Private key (PEM file) for the certificate given by --tls-cert
+    #[clap(long, value_name = "PATH")]
+    tls_key: Option<PathBuf>,
+
+    /// Serve via HTTPS using an ephemeral self-signed certificate. Only meant for local use!
+    #[clap(long, conflicts_with_all = ["tls_cert", "tls_key"])]
+    #[merge(strategy = merge::bool::overwrite_false)]
+    tls_self_signed: bool,
+
+    /// Require HTTP Basic Authentication with this user (requires --auth-password,
+    /// --auth-password-file or --auth-password-command)
+    #[clap(long, value_name = "USER")]
+    auth_user: Option<String>,
+
+    /// Password for HTTP Basic Authentication
+    #[clap(
+        long,
+        value_name = "PASSWORD",
+        env = "RUSTIC_WEBDAV_AUTH_PASSWORD",
+        hide_env_values = true
+    )]
+    auth_password: Option<String>,
+
+    /// File to read the password for HTTP Basic Authentication from
+    #[clap(
+        long,
+        value_name = "FILE",
+        env = "RUSTIC_WEBDAV_AUTH_PASSWORD_FILE",
+        conflicts_with = "auth_password"
+    )]
+    auth_password_file: Option<PathBuf>,
+
+    /// Command to get the password for HTTP Basic Authentication from (first line of its output).
+    /// Like the repository password command, it is parsed using shell quoting rules
+    #[clap(
+        long,
+        value_name = "COMMAND",
+        env = "RUSTIC_WEBDAV_AUTH_PASSWORD_COMMAND",
+        conflicts_with_all = ["auth_password", "auth_password_file"]
+    )]
+    auth_password_command: Option<CommandInput>,
+
+    /// Allow serving on a non-loopback address without authentication, i.e. to anyone who can
+    /// reach the address
+    #[clap(long)]
+    #[merge(strategy = merge::bool::overwrite_false)]
+    no_auth_i_know_what_i_do: bool,
+
+    /// Re-read the snapshots from the repository after this interval (e.g. "10m"), such that new
+    /// snapshots are served without restarting. Not used if a snapshot path is given
+    #[clap(long, value_name = "DURATION")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    refresh_interval: Option<humantime::Duration>,
+
     /// Specify directly which snapshot/path to serve
     #[clap(value_name = "SNAPSHOT[:PATH]")]
     snapshot_path: Option<String>,
@@ -51,6 +146,12 @@ impl Override<RusticConfig> for WebDavCmd {
         let mut self_config = self.clone();
         // merge "webdav" section from config file, if given
         self_config.merge(config.webdav);
+        // validate the address at startup, not when binding to it
+        self_config.bind_addr = Some(
+            self_config
+                .resolve_address()
+                .map_err(|err| FrameworkErrorKind::ConfigError.context(err))?,
+        );
         config.webdav = self_config;
         Ok(config)
     }
@@ -71,65 +172,416 @@ impl WebDavCmd {
     /// see https://github.com/rustic-rs/rustic/issues/1242
     fn inner_run(&self) -> Result<()> {
         let config = RUSTIC_APP.config();
-        let repo = open_repository_indexed(&config.repository)?;
 
-        let path_template = config
-            .webdav
-            .path_template
-            .clone()
-            .unwrap_or_else(|| "[{hostname}]/[{label}]/{time}".to_string());
-        let time_template = config
-            .webdav
-            .time_template
+        // check the server options before opening the repository
+        let addr = match config.webdav.bind_addr {
+            Some(addr) => addr,
+            None => config.webdav.resolve_address()?,
+        };
+        let address = config.webdav.address();
+        let tls = config.webdav.tls_cert_and_key(&address)?;
+        let credentials = config.webdav.auth_credentials()?;
+        if credentials.is_none() && !addr.ip().is_loopback() {
+            if !config.webdav.no_auth_i_know_what_i_do {
+                bail!("refusing to serve on non-loopback address {addr} without authentication. Please set auth-user or use --no-auth-i-know-what-i-do.");
+            }
+            warn!("serving on non-loopback address {addr} without authentication!");
+        }
+
+        let fs = webdav_fs(&config)?;
+        let fs = match (config.webdav.refresh_interval, &config.webdav.snapshot_path) {
+            (Some(interval), None) => RefreshingFs::spawn(fs, *interval),
+            (Some(_), Some(_)) => {
+                warn!("refresh-interval is ignored as a snapshot path is given.");
+                fs
+            }
+            (None, _) => fs,
+        };
+
+        let dav_server = DavHandler::builder().filesystem(fs).build_handler();
+
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let routes = warp::header::optional::<String>("authorization")
+                    .and_then(move |header: Option<String>| {
+                        let authorized = credentials.as_deref().map_or(true, |credentials| {
+                            is_authorized(header.as_deref(), credentials)
+                        });
+                        async move {
+                            if authorized {
+                                Ok(())
+                            } else {
+                                Err(warp::reject::custom(Unauthorized))
+                            }
+                        }
+                    })
+                    .untuple_one()
+                    .and(dav_handler(dav_server))
+                    .recover(handle_unauthorized);
+                let server = warp::serve(routes);
+                if let Some((cert, key)) = tls {
+                    // log the bound address, which contains the actual port if port 0 is given
+                    let (addr, server) = server.tls().cert(cert).key(key).bind_ephemeral(addr);
+                    info!("serving webdav on https://{addr}");
+                    server.await;
+                } else {
+                    let (addr, server) = server.bind_ephemeral(addr);
+                    info!("serving webdav on http://{addr}");
+                    server.await;
+                }
+            });
+
+        Ok(())
+    }
+
+    /// Get the address to bind to as given
+    fn address(&self) -> String {
+        self.address
             .clone()
-            .unwrap_or_else(|| "%Y-%m-%d_%H-%M-%S".to_string());
+            .unwrap_or_else(|| DEFAULT_ADDRESS.to_string())
+    }
 
-        let sn_filter = |sn: &_| config.snapshot_filter.matches(sn);
+    /// Resolve the address to bind to
+    ///
+    /// # Errors
+    ///
+    /// * If the address is invalid or doesn't resolve to any address
+    fn resolve_address(&self) -> Result<SocketAddr> {
+        let address = self.address();
+        address
+            .to_socket_addrs()
+            .with_context(|| format!("invalid address {address}, please use ADDRESS:PORT"))?
+            .next()
+            .ok_or_else(|| anyhow!("address {address} doesn't resolve to any address"))
+    }
+
+    /// Get the credentials `user:password` for HTTP Basic Authentication
+    ///
+    /// # Returns
+    ///
+    /// `None` if no authentication is configured
+    fn auth_credentials(&self) -> Result<Option<String>> {
+        let Some(user) = &self.auth_user else {
+            if self.auth_password.is_some()
+                || self.auth_password_file.is_some()
+                || self.auth_password_command.is_some()
+            {
+                bail!("auth-password, auth-password-file and auth-password-command require auth-user to be set.");
+            }
+            return Ok(None);
+        };
+        if user.contains(':') {
+            bail!("auth-user must not contain ':'.");
+        }
+        // CLI options take precedence, as the config file has been merged into them
+        let password = match (
+            &self.auth_password,
+            &self.auth_password_file,
+            &self.auth_password_command,
+        ) {
+            (Some(password), _, _) => password.clone(),
+            (None, Some(file), _) => {
+                let content = std::fs::read_to_string(file).with_context(|| {
+                    format!("error reading auth-password-file {}", file.display())
+                })?;
+                // use the first line, like for repository password files
+                content.lines().next().unwrap_or_default().to_string()
+            }
+            (None, None, Some(command)) => read_password_from_command(command)?,
+            (None, None, None) => bail!(
+                "auth-user requires auth-password, auth-password-file or auth-password-command."
+            ),
+        };
+        Ok(Some(format!("{user}:{password}")))
+    }
 
-        let vfs = if let Some(snap) = &config.webdav.snapshot_path {
-            let node = repo.node_from_snapshot_path(snap, sn_filter)?;
-            Vfs::from_dir_node(&node)
+    /// Get the PEM-encoded certificate and private key to serve via HTTPS
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the address to serve on, used as name for a self-signed certificate
+    ///
+    /// # Returns
+    ///
+    /// `None` if HTTPS is not configured
+    fn tls_cert_and_key(&self, address: &str) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        match (&self.tls_cert, &self.tls_key, self.tls_self_signed) {
+            (None, None, false) => Ok(None),
+            (None, None, true) => {
+                warn!(
+                    "using an ephemeral self-signed certificate, this is only meant for local use!"
+                );
+                let host = address
+                    .rsplit_once(':')
+                    .map_or(address, |(host, _)| host)
+                    .trim_matches(['[', ']']);
+                let mut names = vec!["localhost".to_string()];
+                if host != "localhost" {
+                    names.push(host.to_string());
+                }
+                let CertifiedKey { cert, key_pair } = rcgen::generate_simple_self_signed(names)
+                    .context("error generating self-signed certificate")?;
+                Ok(Some((
+                    cert.pem().into_bytes(),
+                    key_pair.serialize_pem().into_bytes(),
+                )))
+            }
+            (Some(cert), Some(key), false) => Ok(Some((read_cert(cert)?, read_key(key)?))),
+            (Some(_), Some(_), true) => {
+                bail!("tls-self-signed cannot be used together with tls-cert and tls-key.")
+            }
+            _ => bail!("both tls-cert and tls-key must be given to serve via HTTPS."),
+        }
+    }
+}
+
+/// Run a command and use the first line of its output as password
+fn read_password_from_command(command: &CommandInput) -> Result<String> {
+    if !command.is_set() {
+        bail!("auth-password-command is empty.");
+    }
+    let program = command.command();
+    let output = std::process::Command::new(program)
+        .args(command.args())
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| format!("error running auth-password-command {program}"))?;
+    if !output.status.success() {
+        bail!(
+            "auth-password-command {program} failed with {}",
+            output.status
+        );
+    }
+    let stdout = String::from_utf8(output.stdout)
+        .with_context(|| format!("output of auth-password-command {program} is no UTF-8"))?;
+    Ok(stdout.lines().next().unwrap_or_default().to_string())
+}
+
+/// Open the repository and create the filesystem to serve
+///
+/// # Arguments
+///
+/// * `config` - the config, containing the merged webdav options
+fn webdav_fs(config: &RusticConfig) -> Result<Box<dyn DavFileSystem>> {
+    let webdav = &config.webdav;
+    let repo = open_repository_indexed(&config.repository)?;
+
+    let path_template = webdav
+        .path_template
+        .clone()
+        .unwrap_or_else(|| "[{hostname}]/[{label}]/{time}".to_string());
+    let time_template = webdav
+        .time_template
+        .clone()
+        .unwrap_or_else(|| "%Y-%m-%d_%H-%M-%S".to_string());
+
+    let sn_filter = |sn: &_| config.snapshot_filter.matches(sn);
+
+    let vfs = if let Some(snap) = &webdav.snapshot_path {
+        let node = repo.node_from_snapshot_path(snap, sn_filter)?;
+        Vfs::from_dir_node(&node)
+    } else {
+        let snapshots = repo.get_matching_snapshots(sn_filter)?;
+        let (latest, identical) = if webdav.symlinks {
+            (Latest::AsLink, IdenticalSnapshot::AsLink)
         } else {
-            let snapshots = repo.get_matching_snapshots(sn_filter)?;
-            let (latest, identical) = if config.webdav.symlinks {
-                (Latest::AsLink, IdenticalSnapshot::AsLink)
-            } else {
-                (Latest::AsDir, IdenticalSnapshot::AsDir)
-            };
-            Vfs::from_snapshots(snapshots, &path_template, &time_template, latest, identical)?
+            (Latest::AsDir, IdenticalSnapshot::AsDir)
         };
+        Vfs::from_snapshots(snapshots, &path_template, &time_template, latest, identical)?
+    };
 
-        let addr = config
-            .webdav
-            .address
-            .clone()
-            .unwrap_or_else(|| "localhost:8000".to_string())
-            .to_socket_addrs()?
-            .next()
-            .ok_or_else(|| anyhow!("no address given"))?;
+    let file_access = webdav.file_access.as_ref().map_or_else(
+        || {
+            if repo.config().is_hot == Some(true) {
+                Ok(FilePolicy::Forbidden)
+            } else {
+                Ok(FilePolicy::Read)
+            }
+        },
+        |s| FilePolicy::from_str(s),
+    )?;
 
-        let file_access = config.webdav.file_access.as_ref().map_or_else(
-            || {
-                if repo.config().is_hot == Some(true) {
-                    Ok(FilePolicy::Forbidden)
-                } else {
-                    Ok(FilePolicy::Read)
+    Ok(vfs.into_webdav_fs(repo, file_access))
+}
+
+/// Filesystem which is re-created regularly, such that new snapshots are served
+#[derive(Clone)]
+struct RefreshingFs {
+    /// the current filesystem
+    current: Arc<RwLock<Box<dyn DavFileSystem>>>,
+}
+
+impl RefreshingFs {
+    /// Serve `fs` and re-create it from the repository after each `interval`
+    ///
+    /// Errors when refreshing are logged and the previous filesystem is kept.
+    fn spawn(fs: Box<dyn DavFileSystem>, interval: Duration) -> Box<dyn DavFileSystem> {
+        let current = Arc::new(RwLock::new(fs));
+        let refreshed = current.clone();
+        _ = thread::spawn(move || loop {
+            thread::sleep(interval);
+            match webdav_fs(&RUSTIC_APP.config()) {
+                Ok(fs) => {
+                    *refreshed.write().unwrap_or_else(PoisonError::into_inner) = fs;
+                    debug!("refreshed the snapshots to serve.");
                 }
-            },
-            |s| FilePolicy::from_str(s),
-        )?;
+                Err(err) => warn!("error refreshing the snapshots, keeping the old ones: {err}"),
+            }
+        });
+        Box::new(Self { current })
+    }
 
-        let dav_server = DavHandler::builder()
-            .filesystem(vfs.into_webdav_fs(repo, file_access))
-            .build_handler();
+    /// Get the current filesystem
+    fn current(&self) -> Box<dyn DavFileSystem> {
+        self.current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
 
-        tokio::runtime::Builder::new_current_thread()
-            .enable_all()
-            .build()?
-            .block_on(async {
-                warp::serve(dav_handler(dav_server)).run(addr).await;
-            });
+impl DavFileSystem for RefreshingFs {
+    fn open<'a>(
+        &'a self,
+        path: &'a DavPath,
+        options: OpenOptions,
+    ) -> FsFuture<'a, Box<dyn DavFile>> {
+        let fs = self.current();
+        Box::pin(async move { fs.open(path, options).await })
+    }
+
+    fn read_dir<'a>(
+        &'a self,
+        path: &'a DavPath,
+        meta: ReadDirMeta,
+    ) -> FsFuture<'a, FsStream<Box<dyn DavDirEntry>>> {
+        let fs = self.current();
+        Box::pin(async move { fs.read_dir(path, meta).await })
+    }
+
+    fn metadata<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, Box<dyn DavMetaData>> {
+        let fs = self.current();
+        Box::pin(async move { fs.metadata(path).await })
+    }
+}
+
+/// Rejection for requests without valid HTTP Basic Authentication
+#[derive(Debug)]
+struct Unauthorized;
+
+impl Reject for Unauthorized {}
+
+/// Check if the given `Authorization` header contains the expected credentials
+///
+/// # Arguments
+///
+/// * `header` - the value of the `Authorization` header, if given
+/// * `credentials` - the expected credentials as `user:password`
+fn is_authorized(header: Option<&str>, credentials: &str) -> bool {
+    header
+        .and_then(|header| header.strip_prefix("Basic "))
+        .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())
+        .is_some_and(|decoded| constant_time_eq(&decoded, credentials.as_bytes()))
+}
+
+/// Compare two byte slices in a time which only depends on their length
+///
+/// This avoids leaking how many leading bytes of the credentials are correct.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Answer requests rejected due to missing authentication with 401
+async fn handle_unauthorized(rejection: Rejection) -> Result<impl Reply, Rejection> {
+    if rejection.find::<Unauthorized>().is_some() {
+        Ok(warp::reply::with_header(
+            warp::reply::with_status("unauthorized", StatusCode::UNAUTHORIZED),
+            "WWW-Authenticate",
+            "Basic realm=\"rustic\"",
+        ))
+    } else {
+        Err(rejection)
+    }
+}
+
+/// Read and check a PEM-encoded TLS certificate (chain)
+fn read_cert(path: &Path) -> Result<Vec<u8>> {
+    let pem = std::fs::read(path)
+        .with_context(|| format!("error reading TLS certificate {}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut pem.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("invalid TLS certificate {}", path.display()))?;
+    if certs.is_empty() {
+        bail!(
+            "{} contains no PEM-encoded certificate, please check --tls-cert.",
+            path.display()
+        );
+    }
+    Ok(pem)
+}
+
+/// Read and check a PEM-encoded private key
+fn read_key(path: &Path) -> Result<Vec<u8>> {
+    let pem = std::fs::read(path)
+        .with_context(|| format!("error reading TLS private key {}", path.display()))?;
+    if rustls_pemfile::private_key(&mut pem.as_slice())
+        .with_context(|| format!("invalid TLS private key {}", path.display()))?
+        .is_none()
+    {
+        bail!(
+            "{} contains no PEM-encoded private key, please check --tls-key.",
+            path.display()
+        );
+    }
+    Ok(pem)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_authorized_passes() {
+        let header = format!("Basic {}", STANDARD.encode("user:secret"));
+        assert!(is_authorized(Some(&header), "user:secret"));
+        assert!(!is_authorized(Some(&header), "user:secreT"));
+        assert!(!is_authorized(Some(&header), "user:secret2"));
+        assert!(!is_authorized(Some("Bearer token"), "user:secret"));
+        assert!(!is_authorized(None, "user:secret"));
+    }
+
+    #[test]
+    fn test_resolve_address_passes() -> Result<()> {
+        let mut cmd = WebDavCmd::default();
+        assert_eq!(cmd.resolve_address()?, "127.0.0.1:8080".parse()?);
+
+        cmd.address = Some("0.0.0.0:0".to_string());
+        assert_eq!(cmd.resolve_address()?, "0.0.0.0:0".parse()?);
+
+        cmd.address = Some("no-port".to_string());
+        assert!(cmd.resolve_address().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_auth_credentials_passes() -> Result<()> {
+        let mut cmd = WebDavCmd {
+            auth_user: Some("user".to_string()),
+            auth_password_command: Some("echo 'secret with spaces'".parse()?),
+            ..Default::default()
+        };
+        assert_eq!(
+            cmd.auth_credentials()?.as_deref(),
+            Some("user:secret with spaces")
+        );
+
+        cmd.auth_password = Some("other".to_string());
+        assert_eq!(cmd.auth_credentials()?.as_deref(), Some("user:other"));
 
+        cmd.auth_user = None;
+        assert!(cmd.auth_credentials().is_err());
         Ok(())
     }
 }
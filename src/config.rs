@@ -4,29 +4,41 @@
 //! application's configuration file and/or command-line options
 //! for specifying it.
 
+pub(crate) mod env_expansion;
+pub(crate) mod hooks;
+pub(crate) mod password_keyring;
 pub(crate) mod progress_options;
 
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    path::{Path, PathBuf},
+};
 
-use abscissa_core::config::Config;
-use abscissa_core::path::AbsPathBuf;
-use abscissa_core::FrameworkError;
+use abscissa_core::{FrameworkError, FrameworkErrorKind};
+use anyhow::{anyhow, bail, Context, Result};
+use bytesize::ByteSize;
 use clap::{Parser, ValueHint};
 use directories::ProjectDirs;
 use itertools::Itertools;
-use log::Level;
+use log::{warn, Level};
 use merge::Merge;
 use rustic_backend::BackendOptions;
-use rustic_core::RepositoryOptions;
+use rustic_core::{ConfigOptions, RepositoryOptions};
 use serde::{Deserialize, Serialize};
-use serde_with::{serde_as, OneOrMany};
+use serde_with::{serde_as, DisplayFromStr, OneOrMany};
 
+#[cfg(feature = "mount")]
+use crate::commands::mount::MountCmd;
 #[cfg(feature = "webdav")]
 use crate::commands::webdav::WebDavCmd;
 use crate::{
-    commands::{backup::BackupCmd, copy::CopyCmd, forget::ForgetOptions},
-    config::progress_options::ProgressOptions,
+    commands::{
+        backup::BackupCmd, copy::CopyCmd, forget::ForgetOptions, prune::PruneProfileOptions,
+    },
+    config::{hooks::Hooks, progress_options::ProgressOptions},
     filtering::SnapshotFilter,
+    logging::{LogFormat, SyslogFacility},
+    throttle::Rate,
 };
 
 /// Rustic Configuration
@@ -46,6 +58,11 @@ pub struct RusticConfig {
     #[clap(flatten, next_help_heading = "Repository options")]
     pub repository: AllRepositoryOptions,
 
+    /// Named repositories, selected by --repo-name or `use` in `[repository]`
+    #[clap(skip)]
+    #[merge(strategy = merge_repositories)]
+    pub repositories: HashMap<String, AllRepositoryOptions>,
+
     /// Snapshot filter options
     #[clap(flatten, next_help_heading = "Snapshot filter options")]
     pub snapshot_filter: SnapshotFilter,
@@ -62,12 +79,26 @@ pub struct RusticConfig {
     #[clap(skip)]
     pub forget: ForgetOptions,
 
+    /// Prune options
+    #[clap(skip)]
+    pub prune: PruneProfileOptions,
+
+    /// Hooks for repository events
+    #[clap(skip)]
+    pub hooks: Hooks,
+
+    #[cfg(feature = "mount")]
+    /// mount options
+    #[clap(skip)]
+    pub mount: MountCmd,
+
     #[cfg(feature = "webdav")]
     /// webdav options
     #[clap(skip)]
     pub webdav: WebDavCmd,
 }
 
+#[serde_as]
 #[derive(Clone, Default, Debug, Parser, Serialize, Deserialize, Merge)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct AllRepositoryOptions {
@@ -80,15 +111,272 @@ pub struct AllRepositoryOptions {
     #[clap(flatten)]
     #[serde(flatten)]
     pub repo: RepositoryOptions,
+
+    /// Keyring entry (SERVICE/ACCOUNT) to read the password from the keychain of the operating
+    /// system. Only used if none of --password, --password-file and --password-command is given.
+    /// Store the password using `rustic key store-password`.
+    #[clap(
+        long,
+        global = true,
+        value_name = "SERVICE/ACCOUNT",
+        env = "RUSTIC_PASSWORD_KEYRING"
+    )]
+    pub password_keyring: Option<String>,
+
+    /// Use the repository defined as `[repositories.<NAME>]` in the config file. Its options take
+    /// precedence over the other options in `[repository]`.
+    #[clap(
+        long = "repo-name",
+        global = true,
+        value_name = "NAME",
+        env = "RUSTIC_REPO_NAME"
+    )]
+    #[serde(rename = "use")]
+    pub repo_name: Option<String>,
+
+    /// File to read the repository from (first line). Ignored if the repository is given by
+    /// --repository or RUSTIC_REPOSITORY, but overrides the repository of the config file.
+    #[clap(long, global = true, value_name = "FILE", value_hint = ValueHint::FilePath, env = "RUSTIC_REPOSITORY_FILE")]
+    pub repository_file: Option<PathBuf>,
+
+    /// Limit the download rate from the backend, e.g. "5MiB/s". This limits the backend (network)
+    /// layer only, not reading local files. [default: 0, i.e. unlimited]
+    #[clap(
+        long,
+        global = true,
+        value_name = "RATE",
+        env = "RUSTIC_LIMIT_DOWNLOAD"
+    )]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub limit_download: Option<Rate>,
+
+    /// Limit the upload rate to the backend, e.g. "5MiB/s". This limits the backend (network)
+    /// layer only, not writing local files. [default: 0, i.e. unlimited]
+    #[clap(long, global = true, value_name = "RATE", env = "RUSTIC_LIMIT_UPLOAD")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub limit_upload: Option<Rate>,
+
+    /// Number of times a backend operation (read, write, remove, ...) failing with a transient I/O
+    /// error, e.g. a timeout or a connection reset, is retried. Retries are off by default, as the
+    /// rest, rclone and opendal backends already retry failed requests [default: 0]
+    #[clap(
+        long,
+        alias = "retry",
+        global = true,
+        value_name = "N",
+        env = "RUSTIC_BACKEND_RETRIES"
+    )]
+    #[serde(alias = "retry")]
+    pub backend_retries: Option<usize>,
+
+    /// Wait time before the first retry of a backend operation, e.g. "2s"; doubled for each
+    /// further retry [default: 1s]
+    #[clap(
+        long,
+        alias = "retry-delay",
+        global = true,
+        value_name = "DURATION",
+        env = "RUSTIC_BACKEND_RETRY_BACKOFF"
+    )]
+    #[serde(alias = "retry-delay")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub backend_retry_backoff: Option<humantime::Duration>,
+
+    /// Maximum wait time between retries of a backend operation, e.g. "1min" [default: no limit]
+    #[clap(
+        long,
+        alias = "retry-delay-max",
+        global = true,
+        value_name = "DURATION",
+        env = "RUSTIC_BACKEND_RETRY_BACKOFF_MAX"
+    )]
+    #[serde(alias = "retry-delay-max")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub backend_retry_backoff_max: Option<humantime::Duration>,
+
+    /// Maximum duration of a single request of the rest and rclone backends, e.g. "5min"; passed
+    /// to them as `timeout` option and ignored for other backends. [default: the default of the
+    /// backend]
+    #[clap(
+        long,
+        global = true,
+        alias = "timeout",
+        value_name = "DURATION",
+        env = "RUSTIC_BACKEND_TIMEOUT"
+    )]
+    #[serde(alias = "timeout")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub backend_timeout: Option<humantime::Duration>,
+
+    /// Target size of data packs when initializing the repository, e.g. "128MiB" (1MiB - 2GiB).
+    /// Use `rustic config --set-pack-size-data` to change it for an existing repository.
+    #[clap(long, global = true, value_name = "SIZE", env = "RUSTIC_PACK_SIZE")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub pack_size: Option<ByteSize>,
+}
+
+impl AllRepositoryOptions {
+    /// Get the config options to initialize the repository with
+    ///
+    /// The pack size is used as data pack size, unless a data pack size is given explicitly.
+    ///
+    /// # Arguments
+    ///
+    /// * `config_opts` - the config options given to the command
+    ///
+    /// # Errors
+    ///
+    /// * If the pack size is not between 1 MiB and 2 GiB
+    pub fn config_options(&self, config_opts: &ConfigOptions) -> Result<ConfigOptions> {
+        let mut config_opts = config_opts.clone();
+        if let Some(size) = self.pack_size {
+            if !(MIN_PACK_SIZE..=MAX_PACK_SIZE).contains(&size.as_u64()) {
+                bail!(
+                    "pack size {size} is out of range, it must be between {} and {}",
+                    ByteSize::b(MIN_PACK_SIZE),
+                    ByteSize::b(MAX_PACK_SIZE)
+                );
+            }
+            if config_opts.set_datapack_size.is_none() {
+                config_opts.set_datapack_size = Some(size);
+            }
+        }
+        Ok(config_opts)
+    }
+
+    /// Set the repository from the repository file, if one is given
+    ///
+    /// # Arguments
+    ///
+    /// * `repository_given` - whether the repository is given by the command line or environment,
+    ///   in which case the repository file is not used
+    ///
+    /// # Errors
+    ///
+    /// * If the repository file can't be read or contains no repository
+    pub fn apply_repository_file(&mut self, repository_given: bool) -> Result<()> {
+        let Some(file) = &self.repository_file else {
+            return Ok(());
+        };
+        if repository_given {
+            return Ok(());
+        }
+        let content = std::fs::read_to_string(file)
+            .with_context(|| format!("error reading repository file {}", file.display()))?;
+        let repository = content.lines().next().unwrap_or_default().trim_end();
+        if repository.is_empty() {
+            bail!("repository file {} is empty", file.display());
+        }
+        self.be.repository = Some(repository.to_string());
+        Ok(())
+    }
+
+    /// Get the repository options, reading the password from the keyring if needed
+    ///
+    /// The password is taken from the first given of `password`, `password-file`,
+    /// `password-command` and `password-keyring`.
+    ///
+    /// # Errors
+    ///
+    /// * If the password is read from the keyring and this fails
+    pub fn repository_options(&self) -> Result<RepositoryOptions> {
+        self.repository_options_with(password_keyring::read_password)
+    }
+
+    /// Get the repository options using the given function to read from the keyring
+    fn repository_options_with(
+        &self,
+        read_keyring: impl FnOnce(&str) -> Result<String>,
+    ) -> Result<RepositoryOptions> {
+        let mut opts = self.repo.clone();
+        if let Some(entry) = &self.password_keyring {
+            if opts.password.is_none()
+                && opts.password_file.is_none()
+                && !opts.password_command.is_set()
+            {
+                opts.password = Some(read_keyring(entry)?);
+            }
+        }
+        Ok(opts)
+    }
+
+    /// Pass the timeout to the backends
+    ///
+    /// `backend-timeout` is given as `timeout` option to the repository as well as to the hot
+    /// repository, if they support it. For other backends, a warning is shown.
+    pub fn apply_backend_options(&mut self) {
+        if let Some(timeout) = self.backend_timeout {
+            for (location, options) in [
+                (&self.be.repository, &mut self.be.options),
+                (&self.be.repo_hot, &mut self.be.options_hot),
+            ] {
+                let Some(location) = location else {
+                    continue;
+                };
+                if HTTP_BACKENDS
+                    .iter()
+                    .any(|prefix| location.starts_with(prefix))
+                {
+                    _ = options.insert("timeout".to_string(), timeout.to_string());
+                } else {
+                    // the location isn't shown as it may contain credentials
+                    warn!("backend-timeout is only supported by the rest and rclone backends, ignoring it for another backend.");
+                }
+            }
+        }
+    }
 }
 
 impl RusticConfig {
+    /// Use the named repository given by --repo-name or `use` in `[repository]`
+    ///
+    /// The options of the named repository take precedence over the options in `[repository]`,
+    /// but not over the options given on the command line or by environment variables.
+    ///
+    /// # Arguments
+    ///
+    /// * `given` - the repository options given on the command line or by environment variables
+    ///
+    /// # Errors
+    ///
+    /// * If the named repository is not defined
+    pub fn apply_repository_name(&mut self, given: &AllRepositoryOptions) -> Result<()> {
+        let Some(name) = &self.repository.repo_name else {
+            return Ok(());
+        };
+        let mut repository = given.clone();
+        repository.merge(self.named_repository(name)?);
+        repository.merge(std::mem::take(&mut self.repository));
+        self.repository = repository;
+        Ok(())
+    }
+
+    /// Get the options of a named repository defined in `[repositories]`
+    ///
+    /// # Errors
+    ///
+    /// * If no repository with this name is defined
+    pub fn named_repository(&self, name: &str) -> Result<AllRepositoryOptions> {
+        self.repositories.get(name).cloned().ok_or_else(|| {
+            let names = if self.repositories.is_empty() {
+                "none".to_string()
+            } else {
+                self.repositories.keys().sorted().join(", ")
+            };
+            anyhow!("repository {name} is not defined. Available repositories: {names}")
+        })
+    }
+
     /// Merge a profile into the current config by reading the corresponding config file.
     /// Also recursively merge all profiles given within this config file.
     ///
+    /// A profile containing a path separator is the path to the config file, otherwise the file
+    /// `<profile>.toml` is searched in the config directories. Environment variables referenced in
+    /// the config files are expanded, see [`env_expansion`].
+    ///
     /// # Arguments
     ///
-    /// * `profile` - name of the profile to merge
+    /// * `profile` - name of or path to the profile to merge
     /// * `merge_logs` - Vector to collect logs during merging
     /// * `level_missing` - The log level to use if this profile is missing. Recursive calls will produce a Warning.
     pub fn merge_profile(
@@ -97,15 +385,67 @@ impl RusticConfig {
         merge_logs: &mut Vec<(Level, String)>,
         level_missing: Level,
     ) -> Result<(), FrameworkError> {
-        let profile_filename = profile.to_string() + ".toml";
-        let paths = get_config_paths(&profile_filename);
+        let config_dir = self.global.config_dir.clone();
+        self.merge_profile_relative_to(
+            profile,
+            None,
+            config_dir.as_deref(),
+            merge_logs,
+            level_missing,
+        )
+    }
+
+    /// Load a config file and expand the environment variables referenced in it, see
+    /// [`env_expansion`]
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the path to the config file
+    fn load_file(path: &Path) -> Result<Self, FrameworkError> {
+        let content =
+            std::fs::read_to_string(path).map_err(|e| FrameworkErrorKind::IoError.context(e))?;
+        let mut table: toml::Table =
+            toml::from_str(&content).map_err(|e| FrameworkErrorKind::ConfigError.context(e))?;
+        env_expansion::expand_table(&mut table)
+            .and_then(|()| Ok(table.try_into()?))
+            .map_err(|e| {
+                FrameworkErrorKind::ConfigError
+                    .context(e.context(format!("error in config file {}", path.display())))
+                    .into()
+            })
+    }
+
+    /// Merge a profile, resolving a relative path to the profile relative to `base`
+    ///
+    /// # Arguments
+    ///
+    /// * `profile` - name of or path to the profile to merge
+    /// * `base` - the directory of the config file referencing the profile, if any
+    /// * `config_dir` - the config directory given by `--config-dir`, if any
+    /// * `merge_logs` - Vector to collect logs during merging
+    /// * `level_missing` - The log level to use if this profile is missing
+    fn merge_profile_relative_to(
+        &mut self,
+        profile: &str,
+        base: Option<&Path>,
+        config_dir: Option<&Path>,
+        merge_logs: &mut Vec<(Level, String)>,
+        level_missing: Level,
+    ) -> Result<(), FrameworkError> {
+        let paths = get_profile_paths(profile, base, config_dir);
 
         if let Some(path) = paths.iter().find(|path| path.exists()) {
             merge_logs.push((Level::Info, format!("using config {}", path.display())));
-            let mut config = Self::load_toml_file(AbsPathBuf::canonicalize(path)?)?;
+            let mut config = Self::load_file(path)?;
             // if "use_profile" is defined in config file, merge the referenced profiles first
             for profile in &config.global.use_profile.clone() {
-                config.merge_profile(profile, merge_logs, Level::Warn)?;
+                config.merge_profile_relative_to(
+                    profile,
+                    path.parent(),
+                    config_dir,
+                    merge_logs,
+                    Level::Warn,
+                )?;
             }
             self.merge(config);
         } else {
@@ -129,8 +469,8 @@ impl RusticConfig {
 #[derive(Default, Debug, Parser, Clone, Deserialize, Serialize, Merge)]
 #[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
 pub struct GlobalOptions {
-    /// Config profile to use. This parses the file `<PROFILE>.toml` in the config directory.
-    /// [default: "rustic"]
+    /// Config profile to use. This parses the file `<PROFILE>.toml` in the config directory or, if
+    /// PROFILE contains a path separator, the file PROFILE. [default: "rustic"]
     #[clap(
         short = 'P',
         long,
@@ -142,6 +482,18 @@ pub struct GlobalOptions {
     #[serde_as(as = "OneOrMany<_>")]
     pub use_profile: Vec<String>,
 
+    /// Directory to search for config profiles first, before the default config directories
+    #[clap(long, global = true, env = "RUSTIC_CONFIG_DIR", value_name = "DIR")]
+    #[serde(skip)]
+    #[merge(skip)]
+    pub config_dir: Option<PathBuf>,
+
+    /// Don't load the default profile "rustic" if no profile is given
+    #[clap(long, global = true, env = "RUSTIC_NO_DEFAULT_CONFIG")]
+    #[serde(skip)]
+    #[merge(skip)]
+    pub no_default_config: bool,
+
     /// Only show what would be done without modifying anything. Does not affect read-only commands.
     #[clap(long, short = 'n', global = true, env = "RUSTIC_DRY_RUN")]
     #[merge(strategy = merge::bool::overwrite_false)]
@@ -164,6 +516,85 @@ pub struct GlobalOptions {
     #[clap(long, global = true, env = "RUSTIC_LOG_FILE", value_name = "LOGFILE", value_hint = ValueHint::FilePath)]
     pub log_file: Option<PathBuf>,
 
+    /// Format of log messages written to the log file or, without --log-file, to stderr
+    /// [default: text]
+    #[clap(
+        long,
+        global = true,
+        env = "RUSTIC_LOG_FORMAT",
+        value_name = "FORMAT",
+        value_enum
+    )]
+    pub log_format: Option<LogFormat>,
+
+    /// Rotate the log file at startup if it has at least this size, e.g. "10MiB". Requires
+    /// --log-file.
+    #[clap(
+        long,
+        alias = "log-file-max-size",
+        global = true,
+        env = "RUSTIC_LOG_MAX_SIZE",
+        value_name = "SIZE"
+    )]
+    #[serde(alias = "log-file-max-size")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub log_max_size: Option<ByteSize>,
+
+    /// Number of rotated log files (`<LOGFILE>.1` to `<LOGFILE>.N`) to keep. If given without
+    /// --log-max-size, the log file is rotated at each startup. Requires --log-file.
+    /// [default: 5]
+    #[clap(
+        long,
+        alias = "log-file-keep",
+        global = true,
+        env = "RUSTIC_LOG_MAX_FILES",
+        value_name = "N"
+    )]
+    #[serde(alias = "log-file-keep")]
+    pub log_max_files: Option<usize>,
+
+    /// Send log messages to the local syslog daemon (Unix only). Can be combined with --log-file.
+    #[clap(long, global = true, env = "RUSTIC_LOG_SYSLOG")]
+    #[merge(strategy = merge::bool::overwrite_false)]
+    pub log_syslog: bool,
+
+    /// Syslog facility to use with --log-syslog [default: user]
+    #[clap(
+        long,
+        global = true,
+        env = "RUSTIC_SYSLOG_FACILITY",
+        value_name = "FACILITY",
+        value_enum
+    )]
+    pub syslog_facility: Option<SyslogFacility>,
+
+    /// Write log messages to the Windows Application event log (Windows only). Can be combined
+    /// with --log-file.
+    #[clap(long, global = true, env = "RUSTIC_LOG_EVENTLOG")]
+    #[merge(strategy = merge::bool::overwrite_false)]
+    pub log_eventlog: bool,
+
+    /// Write events of this invocation (e.g. phases, progress, warnings) as newline-delimited
+    /// JSON to the given file or to an open file descriptor given as fd:N
+    #[clap(
+        long,
+        global = true,
+        env = "RUSTIC_EVENT_STREAM",
+        value_name = "PATH|fd:N"
+    )]
+    pub event_stream: Option<String>,
+
+    /// Limit the size of the cache directory, e.g. "1GiB". After each command, the least recently
+    /// used cached files are removed until the cache meets the limit. [default: no limit]
+    #[clap(
+        long,
+        global = true,
+        env = "RUSTIC_CACHE_SIZE_LIMIT",
+        value_name = "SIZE"
+    )]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub cache_size_limit: Option<ByteSize>,
+
     /// Settings to customize progress bars
     #[clap(flatten)]
     #[serde(flatten)]
@@ -181,32 +612,134 @@ fn extend(left: &mut HashMap<String, String>, right: HashMap<String, String>) {
     left.extend(right);
 }
 
+/// Merge named repositories: Repositories defined in both are merged, `left` taking precedence
+fn merge_repositories(
+    left: &mut HashMap<String, AllRepositoryOptions>,
+    right: HashMap<String, AllRepositoryOptions>,
+) {
+    for (name, opts) in right {
+        match left.entry(name) {
+            Entry::Occupied(mut entry) => entry.get_mut().merge(opts),
+            Entry::Vacant(entry) => {
+                _ = entry.insert(opts);
+            }
+        }
+    }
+}
+
+/// Get the possible paths to the config file of a profile
+///
+/// # Arguments
+///
+/// * `profile` - name of or path to the profile
+/// * `base` - the directory relative paths are resolved against; the current directory if not given
+/// * `config_dir` - the config directory given by `--config-dir`, if any
+///
+/// # Returns
+///
+/// The path to the config file if the profile contains a path separator, otherwise the paths in
+/// the config directories, see [`get_config_paths`]
+fn get_profile_paths(
+    profile: &str,
+    base: Option<&Path>,
+    config_dir: Option<&Path>,
+) -> Vec<PathBuf> {
+    if profile.contains('/') || profile.contains(std::path::MAIN_SEPARATOR) {
+        let path = Path::new(profile);
+        return vec![base.map_or_else(|| path.to_path_buf(), |base| base.join(path))];
+    }
+    get_config_paths(&(profile.to_string() + ".toml"), config_dir)
+}
+
 /// Get the paths to the config file
 ///
 /// # Arguments
 ///
 /// * `filename` - name of the config file
+/// * `config_dir` - the config directory given by `--config-dir`, which is searched first
 ///
 /// # Returns
 ///
 /// A vector of [`PathBuf`]s to the config files
-fn get_config_paths(filename: &str) -> Vec<PathBuf> {
+fn get_config_paths(filename: &str, config_dir: Option<&Path>) -> Vec<PathBuf> {
+    get_config_dirs(config_dir)
+        .into_iter()
+        .map(|mut p| {
+            p.push(filename);
+            p
+        })
+        .collect()
+}
+
+/// Get the directories to search for config files, in the order they are searched
+///
+/// # Arguments
+///
+/// * `config_dir` - the config directory given by `--config-dir`, which is searched first
+fn get_config_dirs(config_dir: Option<&Path>) -> Vec<PathBuf> {
     [
+        config_dir.map(Path::to_path_buf),
         ProjectDirs::from("", "", "rustic")
             .map(|project_dirs| project_dirs.config_dir().to_path_buf()),
         get_global_config_path(),
         Some(PathBuf::from(".")),
     ]
     .into_iter()
-    .filter_map(|path| {
-        path.map(|mut p| {
-            p.push(filename);
-            p
-        })
-    })
+    .flatten()
     .collect()
 }
 
+/// Get the names of the profiles found in the config directories
+///
+/// These are the names of all `*.toml` files, sorted and without duplicates. Directories which
+/// can't be read are ignored.
+///
+/// # Arguments
+///
+/// * `config_dir` - the config directory given by `--config-dir`, which is searched first
+pub fn get_profile_names(config_dir: Option<&Path>) -> Vec<String> {
+    get_config_dirs(config_dir)
+        .into_iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "toml" || !path.is_file() {
+                return None;
+            }
+            path.file_stem()?.to_str().map(ToString::to_string)
+        })
+        .sorted()
+        .dedup()
+        .collect()
+}
+
+/// Prefixes of the backends which use an HTTP client and support the `timeout` option
+const HTTP_BACKENDS: [&str; 2] = ["rest:", "rclone:"];
+
+/// Smallest allowed pack size
+const MIN_PACK_SIZE: u64 = 1024 * 1024;
+
+/// Largest allowed pack size
+const MAX_PACK_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+
+/// Suggest a data pack size for a repository holding the given amount of data
+///
+/// The suggestion aims at about 10,000 data packs, as bigger packs mean fewer files and requests
+/// to the backend: the data size divided by 10,000, rounded up to a power of two, but at least
+/// 32 MiB and at most 2 GiB.
+///
+/// # Arguments
+///
+/// * `data_size` - the (expected) total size of the data
+pub fn suggested_pack_size(data_size: u64) -> ByteSize {
+    ByteSize::b(
+        (data_size / 10_000)
+            .next_power_of_two()
+            .clamp(32 * 1024 * 1024, MAX_PACK_SIZE),
+    )
+}
+
 /// Get the path to the global config directory on Windows.
 ///
 /// # Returns
@@ -242,3 +775,265 @@ fn get_global_config_path() -> Option<PathBuf> {
 fn get_global_config_path() -> Option<PathBuf> {
     Some(PathBuf::from("/etc/rustic"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_apply_repository_file_passes() -> Result<()> {
+        let dir = tempdir()?;
+        let file = dir.path().join("repository");
+        fs::write(&file, "/tmp/repo  \nignored\n")?;
+
+        let mut opts = AllRepositoryOptions {
+            repository_file: Some(file.clone()),
+            ..Default::default()
+        };
+        opts.be.repository = Some("/from/config".to_string());
+        opts.apply_repository_file(false)?;
+        assert_eq!(opts.be.repository.as_deref(), Some("/tmp/repo"));
+
+        // a repository given on the command line takes precedence
+        opts.be.repository = Some("/from/cli".to_string());
+        opts.apply_repository_file(true)?;
+        assert_eq!(opts.be.repository.as_deref(), Some("/from/cli"));
+
+        fs::write(&file, "\n")?;
+        assert!(opts.apply_repository_file(false).is_err());
+        opts.repository_file = Some(dir.path().join("missing"));
+        assert!(opts.apply_repository_file(false).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_profile_by_path_passes() -> Result<()> {
+        let dir = tempdir()?;
+        fs::create_dir(dir.path().join("sub"))?;
+        let main = dir.path().join("main.toml");
+        fs::write(&main, "[global]\nuse-profile = \"sub/base.toml\"\n")?;
+        fs::write(
+            dir.path().join("sub").join("base.toml"),
+            "[global]\nlog-level = \"debug\"\n",
+        )?;
+
+        // the relative path is resolved relative to main.toml, not to the current directory
+        let mut config = RusticConfig::default();
+        let mut merge_logs = Vec::new();
+        config.merge_profile(&main.to_string_lossy(), &mut merge_logs, Level::Error)?;
+        assert_eq!(config.global.log_level.as_deref(), Some("debug"));
+        assert!(merge_logs.iter().all(|(level, _)| *level == Level::Info));
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("other", None, None)]
+    #[case("/etc/rustic/other.toml", None, Some("/etc/rustic/other.toml"))]
+    #[case(
+        "/etc/rustic/other.toml",
+        Some("/base"),
+        Some("/etc/rustic/other.toml")
+    )]
+    #[case("sub/other.toml", None, Some("sub/other.toml"))]
+    #[case("sub/other.toml", Some("/base"), Some("/base/sub/other.toml"))]
+    fn test_get_profile_paths_passes(
+        #[case] profile: &str,
+        #[case] base: Option<&str>,
+        #[case] expected: Option<&str>,
+    ) {
+        let paths = get_profile_paths(profile, base.map(Path::new), None);
+        match expected {
+            Some(expected) => assert_eq!(paths, vec![PathBuf::from(expected)]),
+            None => assert!(paths.iter().all(|path| path.ends_with("other.toml"))),
+        }
+    }
+
+    #[test]
+    fn test_get_config_paths_with_config_dir_passes() {
+        let paths = get_config_paths("rustic.toml", Some(Path::new("/config")));
+        assert_eq!(paths[0], PathBuf::from("/config/rustic.toml"));
+        assert_eq!(paths.last(), Some(&PathBuf::from("./rustic.toml")));
+        // a path to the profile is not searched in the config directories
+        let paths = get_profile_paths("sub/other.toml", None, Some(Path::new("/config")));
+        assert_eq!(paths, vec![PathBuf::from("sub/other.toml")]);
+    }
+
+    #[test]
+    fn test_merge_profile_from_config_dir_passes() -> Result<()> {
+        let dir = tempdir()?;
+        fs::write(
+            dir.path().join("custom.toml"),
+            "[global]\nlog-level = \"debug\"\n",
+        )?;
+        let mut config = RusticConfig::default();
+        config.global.config_dir = Some(dir.path().to_path_buf());
+        let mut merge_logs = Vec::new();
+        config.merge_profile("custom", &mut merge_logs, Level::Error)?;
+        assert_eq!(config.global.log_level.as_deref(), Some("debug"));
+        assert_eq!(config.global.config_dir.as_deref(), Some(dir.path()));
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(0, 32 * 1024 * 1024)]
+    #[case(1_000_000_000_000, 128 * 1024 * 1024)]
+    #[case(u64::MAX, MAX_PACK_SIZE)]
+    fn test_suggested_pack_size_passes(#[case] data_size: u64, #[case] expected: u64) {
+        assert_eq!(suggested_pack_size(data_size).as_u64(), expected);
+    }
+
+    #[test]
+    fn test_config_options_pack_size_passes() -> Result<()> {
+        let mut opts = AllRepositoryOptions {
+            pack_size: Some(ByteSize::mib(128)),
+            ..Default::default()
+        };
+        let config_opts = opts.config_options(&ConfigOptions::default())?;
+        assert_eq!(config_opts.set_datapack_size, Some(ByteSize::mib(128)));
+
+        // an explicitly given data pack size is kept
+        let given = ConfigOptions {
+            set_datapack_size: Some(ByteSize::mib(64)),
+            ..Default::default()
+        };
+        let config_opts = opts.config_options(&given)?;
+        assert_eq!(config_opts.set_datapack_size, Some(ByteSize::mib(64)));
+
+        opts.pack_size = Some(ByteSize::kib(512));
+        assert!(opts.config_options(&ConfigOptions::default()).is_err());
+        opts.pack_size = Some(ByteSize::gib(3));
+        assert!(opts.config_options(&ConfigOptions::default()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_profile_names_passes() -> Result<()> {
+        let dir = tempdir()?;
+        fs::write(dir.path().join("offsite.toml"), "")?;
+        fs::write(dir.path().join("daily.toml"), "")?;
+        fs::write(dir.path().join("notes.txt"), "")?;
+        fs::create_dir(dir.path().join("dir.toml"))?;
+        let names = get_profile_names(Some(dir.path()));
+        assert!(names.contains(&"offsite".to_string()));
+        assert!(names.contains(&"daily".to_string()));
+        assert!(!names.iter().any(|name| name == "notes" || name == "dir"));
+        assert!(names.windows(2).all(|w| w[0] < w[1]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_repository_name_passes() -> Result<()> {
+        let mut config: RusticConfig = toml::from_str(
+            r#"
+            [repository]
+            use = "offsite"
+            password = "common"
+            no-cache = true
+
+            [repositories.local]
+            repository = "/srv/backup"
+
+            [repositories.offsite]
+            repository = "rclone:offsite:backup"
+            password = "offsite"
+            "#,
+        )?;
+        let mut given = AllRepositoryOptions::default();
+        given.be.repo_hot = Some("/from/cli".to_string());
+        config.apply_repository_name(&given)?;
+
+        let repository = &config.repository;
+        assert_eq!(
+            repository.be.repository.as_deref(),
+            Some("rclone:offsite:backup")
+        );
+        assert_eq!(repository.repo.password.as_deref(), Some("offsite"));
+        assert!(repository.repo.no_cache);
+        assert_eq!(repository.be.repo_hot.as_deref(), Some("/from/cli"));
+
+        config.repository.repo_name = Some("missing".to_string());
+        let err = config.apply_repository_name(&given).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Available repositories: local, offsite"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_repositories_passes() {
+        let named = |repository: Option<&str>, password: Option<&str>| {
+            let mut opts = AllRepositoryOptions::default();
+            opts.be.repository = repository.map(ToString::to_string);
+            opts.repo.password = password.map(ToString::to_string);
+            opts
+        };
+        let mut left = HashMap::from([("a".to_string(), named(Some("/left"), None))]);
+        let right = HashMap::from([
+            ("a".to_string(), named(Some("/right"), Some("pw"))),
+            ("b".to_string(), named(Some("/b"), None)),
+        ]);
+        merge_repositories(&mut left, right);
+        assert_eq!(left["a"].be.repository.as_deref(), Some("/left"));
+        assert_eq!(left["a"].repo.password.as_deref(), Some("pw"));
+        assert_eq!(left["b"].be.repository.as_deref(), Some("/b"));
+    }
+
+    #[rstest]
+    #[case("password = \"pw\"", Some("pw"))]
+    #[case("password-file = \"/root/pw\"", None)]
+    #[case("password-command = \"pass rustic\"", None)]
+    #[case("", Some("from keyring"))]
+    fn test_password_keyring_precedence_passes(
+        #[case] password: &str,
+        #[case] expected: Option<&str>,
+    ) -> Result<()> {
+        let opts: AllRepositoryOptions =
+            toml::from_str(&format!("{password}\npassword-keyring = \"rustic/repo\""))?;
+        let repo_opts = opts.repository_options_with(|entry| {
+            assert_eq!(entry, "rustic/repo");
+            Ok("from keyring".to_string())
+        })?;
+        assert_eq!(repo_opts.password.as_deref(), expected);
+
+        // the keyring is only accessed if no other password option is given
+        let read = opts.repository_options_with(|_| bail!("keyring not available"));
+        assert_eq!(read.is_err(), expected == Some("from keyring"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_backend_options_passes() -> Result<()> {
+        let mut opts = AllRepositoryOptions {
+            backend_timeout: Some("30s".parse()?),
+            ..Default::default()
+        };
+        opts.be.repository = Some("rest:http://localhost:8000".to_string());
+        opts.be.repo_hot = Some("rclone:hot".to_string());
+        opts.apply_backend_options();
+        assert_eq!(
+            opts.be.options.get("timeout").map(String::as_str),
+            Some("30s")
+        );
+        assert_eq!(
+            opts.be.options_hot.get("timeout").map(String::as_str),
+            Some("30s")
+        );
+
+        // the timeout is only passed to backends supporting it
+        opts.be.options_hot.clear();
+        opts.be.repo_hot = Some("/srv/hot".to_string());
+        opts.apply_backend_options();
+        assert_eq!(
+            opts.be.options.get("timeout").map(String::as_str),
+            Some("30s")
+        );
+        assert!(opts.be.options_hot.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,210 @@
+//! Expansion of environment variables in config files
+//!
+//! String values in config files may reference environment variables as `${VAR}` or `$VAR`; `$$`
+//! gives a literal `$`. Variables are looked up in `[global.env]` of the same config file first
+//! and then in the environment of the rustic process. Values given on the command line or by
+//! environment variables are never expanded.
+//!
+//! The variables are expanded in the parsed TOML file before it is deserialized, such that values
+//! which are parsed from strings, e.g. sizes, are not changed by converting them back to strings.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Context, Result};
+use toml::{Table, Value};
+
+/// Keys whose values (and all values below them) are not expanded
+///
+/// Hooks are run with additional environment variables, e.g. `RUSTIC_SNAPSHOT_ID`, which are
+/// expanded by the commands they run. Globs may contain a literal `$`.
+const NO_EXPANSION: [&str; 5] = ["hooks", "glob", "iglob", "glob-file", "iglob-file"];
+
+/// Expand environment variables in all string values of a config file
+///
+/// # Arguments
+///
+/// * `table` - the parsed config file
+///
+/// # Errors
+///
+/// * If a referenced environment variable is not set
+pub(crate) fn expand_table(table: &mut Table) -> Result<()> {
+    // variables in `[global.env]` can only reference the environment of the process
+    let mut env = HashMap::new();
+    if let Some(Value::Table(env_table)) = table
+        .get_mut("global")
+        .and_then(|global| global.get_mut("env"))
+    {
+        for (key, value) in env_table {
+            if let Value::String(s) = value {
+                *s = expand(s, |name| std::env::var(name).ok()).with_context(|| {
+                    format!("error expanding environment variables in global.env.{key}")
+                })?;
+                _ = env.insert(key.clone(), s.clone());
+            }
+        }
+    }
+    let lookup = |name: &str| env.get(name).cloned().or_else(|| std::env::var(name).ok());
+    for (key, value) in table {
+        if !NO_EXPANSION.contains(&key.as_str()) {
+            expand_value(value, key, &lookup)?;
+        }
+    }
+    Ok(())
+}
+
+/// Recursively expand environment variables in all strings of a TOML value
+///
+/// # Arguments
+///
+/// * `value` - the value to expand
+/// * `path` - the key path of the value, used in error messages
+/// * `lookup` - function to get the value of an environment variable
+fn expand_value(
+    value: &mut Value,
+    path: &str,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> Result<()> {
+    match value {
+        // don't show the value in the error message, it may be a password
+        Value::String(s) => {
+            *s = expand(s, lookup)
+                .with_context(|| format!("error expanding environment variables in {path}"))?;
+        }
+        Value::Array(values) => {
+            for value in values {
+                expand_value(value, path, lookup)?;
+            }
+        }
+        Value::Table(table) => {
+            for (key, value) in table {
+                if NO_EXPANSION.contains(&key.as_str()) {
+                    continue;
+                }
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                // already expanded
+                if path == "global.env" {
+                    continue;
+                }
+                expand_value(value, &path, lookup)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Expand `${VAR}` and `$VAR` references in a string; `$$` is a literal `$`
+///
+/// A `$` which is not followed by `{` or a variable name is kept as it is.
+///
+/// # Errors
+///
+/// * If a referenced environment variable is not set
+/// * If a `${` is not terminated by `}`
+fn expand(s: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let is_name_start = |c: char| c.is_ascii_alphabetic() || c == '_';
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(pos) = rest.find('$') {
+        result.push_str(&rest[..pos]);
+        rest = &rest[pos + 1..];
+        let (name, tail) = if let Some(tail) = rest.strip_prefix('$') {
+            result.push('$');
+            rest = tail;
+            continue;
+        } else if let Some(braced) = rest.strip_prefix('{') {
+            let Some(end) = braced.find('}') else {
+                bail!("missing }} after ${{");
+            };
+            (&braced[..end], &braced[end + 1..])
+        } else if rest.starts_with(is_name_start) {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        } else {
+            result.push('$');
+            continue;
+        };
+        let value = lookup(name).ok_or_else(|| {
+            anyhow!("environment variable {name} is not set (use $$ for a literal $)")
+        })?;
+        result.push_str(&value);
+        rest = tail;
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    use crate::RusticConfig;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "VAR" => Some("value".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[rstest]
+    #[case("plain", "plain")]
+    #[case("${VAR}", "value")]
+    #[case("$VAR", "value")]
+    #[case("a/$VAR/b", "a/value/b")]
+    #[case("a${VAR}b", "avalueb")]
+    #[case("$VAR-$EMPTY.", "value-.")]
+    #[case("$$VAR", "$VAR")]
+    #[case("5$ or $1", "5$ or $1")]
+    fn test_expand_passes(#[case] s: &str, #[case] expected: &str) -> Result<()> {
+        assert_eq!(expand(s, lookup)?, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("$UNSET")]
+    #[case("${UNSET}")]
+    #[case("${VAR")]
+    fn test_expand_fails(#[case] s: &str) {
+        assert!(expand(s, lookup).is_err());
+    }
+
+    #[test]
+    fn test_expand_table_passes() -> Result<()> {
+        let mut table: Table = toml::from_str(
+            r#"
+            [global.env]
+            RUSTIC_TEST_SECRET = "secret$$"
+
+            [repository]
+            password = "${RUSTIC_TEST_SECRET}"
+            pack-size = "128MiB"
+
+            [hooks]
+            on-snapshot-created = ["echo $RUSTIC_SNAPSHOT_ID"]
+            "#,
+        )?;
+        expand_table(&mut table)?;
+        let config: RusticConfig = table.try_into()?;
+        assert_eq!(config.global.env["RUSTIC_TEST_SECRET"], "secret$");
+        assert_eq!(config.repository.repo.password.as_deref(), Some("secret$"));
+        // sizes are not converted back to strings, which would round them
+        assert_eq!(
+            config.repository.pack_size.map(|size| size.as_u64()),
+            Some(128 * 1024 * 1024)
+        );
+        let hooks = toml::to_string(&config.hooks)?;
+        assert!(hooks.contains("echo $RUSTIC_SNAPSHOT_ID"));
+        Ok(())
+    }
+}
@@ -0,0 +1,282 @@
+//! Hooks for repository events
+
+use std::{
+    collections::BTreeSet,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::{bail, Context, Result};
+use log::{debug, warn};
+use merge::Merge;
+use serde::{Deserialize, Serialize};
+
+use rustic_core::{
+    repofile::{FileType, SnapshotFile},
+    CommandInput, Id, Open, ProgressBars, Repository,
+};
+
+/// Hooks which are called on repository events, regardless of the command causing the event
+///
+/// Commands called for a single snapshot get the environment variables `RUSTIC_SNAPSHOT_ID`,
+/// `RUSTIC_SNAPSHOT_HOSTNAME`, `RUSTIC_SNAPSHOT_LABEL`, `RUSTIC_SNAPSHOT_TAGS` and
+/// `RUSTIC_SNAPSHOT_TIME`.
+#[derive(Default, Debug, Clone, Deserialize, Serialize, Merge)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Hooks {
+    /// Command to call for each created snapshot
+    #[merge(strategy = overwrite_unset)]
+    pub on_snapshot_created: CommandInput,
+
+    /// Command to call for each removed snapshot
+    #[merge(strategy = overwrite_unset)]
+    pub on_snapshot_removed: CommandInput,
+
+    /// Command to call once for all removed snapshots. The ids are given on stdin, one per line.
+    #[merge(strategy = overwrite_unset)]
+    pub on_snapshots_removed: CommandInput,
+}
+
+/// Merge strategy for commands: `left` is only overwritten if it is not set
+fn overwrite_unset(left: &mut CommandInput, right: CommandInput) {
+    if !left.is_set() {
+        *left = right;
+    }
+}
+
+impl Hooks {
+    /// Call the `on-snapshot-created` hook for the given snapshots
+    pub fn snapshots_created<'a>(&self, snaps: impl IntoIterator<Item = &'a SnapshotFile>) {
+        for sn in snaps {
+            call_hook(
+                "on-snapshot-created",
+                &self.on_snapshot_created,
+                Some(sn),
+                None,
+            );
+        }
+    }
+
+    /// Call the `on-snapshot-removed` hook for the given snapshots and the
+    /// `on-snapshots-removed` hook once for all of them
+    pub fn snapshots_removed(&self, snaps: &[SnapshotFile]) {
+        if snaps.is_empty() {
+            return;
+        }
+        for sn in snaps {
+            call_hook(
+                "on-snapshot-removed",
+                &self.on_snapshot_removed,
+                Some(sn),
+                None,
+            );
+        }
+        let ids: String = snaps.iter().map(|sn| format!("{}\n", sn.id)).collect();
+        call_hook(
+            "on-snapshots-removed",
+            &self.on_snapshots_removed,
+            None,
+            Some(&ids),
+        );
+    }
+
+    /// Run an action which creates or removes snapshots and call the hooks for the snapshots
+    /// created or removed by it
+    ///
+    /// Snapshots only get their id when they are saved, so the created snapshots are found among
+    /// the snapshots which are new after the action using `is_created`. Of `to_remove`, the
+    /// snapshots which are gone after the action are reported as removed. Snapshots created or
+    /// removed concurrently by other processes are thus not reported. The snapshots are only
+    /// listed if hooks are configured.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - the repository the action works on
+    /// * `is_created` - whether a new snapshot has been created by the action
+    /// * `to_remove` - the snapshots the action may remove
+    /// * `action` - the action to run
+    pub fn track_snapshots<P: ProgressBars, S: Open, T>(
+        &self,
+        repo: &Repository<P, S>,
+        is_created: impl Fn(&SnapshotFile) -> bool,
+        to_remove: &[SnapshotFile],
+        action: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        let track_created = self.on_snapshot_created.is_set();
+        let track_removed = self.on_snapshot_removed.is_set() || self.on_snapshots_removed.is_set();
+        if !track_created && !track_removed {
+            return action();
+        }
+
+        let before: BTreeSet<_> = if track_created {
+            repo.list(FileType::Snapshot)?.collect()
+        } else {
+            BTreeSet::new()
+        };
+
+        let result = action()?;
+
+        let after: BTreeSet<_> = repo.list(FileType::Snapshot)?.collect();
+        if track_created {
+            let new: Vec<_> = after.difference(&before).map(Id::to_string).collect();
+            if !new.is_empty() {
+                let created: Vec<_> = repo
+                    .get_snapshots(&new)?
+                    .into_iter()
+                    .filter(|sn| is_created(sn))
+                    .collect();
+                self.snapshots_created(&created);
+            }
+        }
+        let removed: Vec<_> = to_remove
+            .iter()
+            .filter(|sn| !after.contains(&sn.id))
+            .cloned()
+            .collect();
+        self.snapshots_removed(&removed);
+
+        Ok(result)
+    }
+}
+
+/// Check if a snapshot read from the repository is a saved version of the given snapshot, i.e.
+/// equal apart from the id, parent and original which may be set when saving
+pub fn is_saved_as(saved: &SnapshotFile, snap: &SnapshotFile) -> bool {
+    let contents = |sn: &SnapshotFile| {
+        let mut sn = sn.clone();
+        sn.id = Id::default();
+        sn.parent = None;
+        sn.original = None;
+        serde_json::to_value(sn).ok()
+    };
+    let saved = contents(saved);
+    saved.is_some() && saved == contents(snap)
+}
+
+/// Call a hook. Failures are only reported as warnings as the event already happened.
+///
+/// # Arguments
+///
+/// * `name` - name of the hook, used for messages
+/// * `command` - the command to call; nothing is done if it is not set
+/// * `snap` - the snapshot to pass as environment variables
+/// * `input` - the input to pass on stdin
+fn call_hook(name: &str, command: &CommandInput, snap: Option<&SnapshotFile>, input: Option<&str>) {
+    if !command.is_set() {
+        return;
+    }
+    debug!("calling {name} hook: {command:?}");
+    if let Err(err) = run_command(command.command(), command.args(), snap, input) {
+        warn!("{name} hook failed: {err}");
+    }
+}
+
+/// Run a hook command and wait for it to finish
+fn run_command(
+    program: &str,
+    args: &[String],
+    snap: Option<&SnapshotFile>,
+    input: Option<&str>,
+) -> Result<()> {
+    let mut command = Command::new(program);
+    _ = command.args(args);
+    if let Some(sn) = snap {
+        _ = command
+            .env("RUSTIC_SNAPSHOT_ID", sn.id.to_string())
+            .env("RUSTIC_SNAPSHOT_HOSTNAME", &sn.hostname)
+            .env("RUSTIC_SNAPSHOT_LABEL", &sn.label)
+            .env("RUSTIC_SNAPSHOT_TAGS", sn.tags.to_string())
+            .env("RUSTIC_SNAPSHOT_TIME", sn.time.to_rfc3339());
+    }
+    _ = command.stdin(if input.is_some() {
+        Stdio::piped()
+    } else {
+        Stdio::null()
+    });
+
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("error starting {program}"))?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        // stdin is closed when dropped at the end of this block
+        stdin
+            .write_all(input.as_bytes())
+            .with_context(|| format!("error writing to stdin of {program}"))?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("{program} returned {status}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[cfg(not(windows))]
+mod tests {
+    use super::*;
+
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_snapshots_removed_passes() -> Result<()> {
+        let dir = tempdir()?;
+        let single = dir.path().join("single");
+        let batch = dir.path().join("batch");
+        let hooks = Hooks {
+            on_snapshot_removed: CommandInput::from(vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                format!(
+                    "echo \"$RUSTIC_SNAPSHOT_ID $RUSTIC_SNAPSHOT_HOSTNAME\" >> {}",
+                    single.display()
+                ),
+            ]),
+            on_snapshots_removed: CommandInput::from(vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                format!("cat > {}", batch.display()),
+            ]),
+            ..Default::default()
+        };
+
+        let snaps: Vec<_> = ["host1", "host2"]
+            .into_iter()
+            .map(|hostname| SnapshotFile {
+                id: Id::random(),
+                hostname: hostname.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        hooks.snapshots_removed(&snaps);
+
+        assert_eq!(
+            std::fs::read_to_string(single)?,
+            format!("{} host1\n{} host2\n", snaps[0].id, snaps[1].id)
+        );
+        assert_eq!(
+            std::fs::read_to_string(batch)?,
+            format!("{}\n{}\n", snaps[0].id, snaps[1].id)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_saved_as_passes() {
+        let snap = SnapshotFile {
+            hostname: "host".to_string(),
+            ..Default::default()
+        };
+        let saved = SnapshotFile {
+            id: Id::random(),
+            original: Some(Id::random()),
+            ..snap.clone()
+        };
+        let other = SnapshotFile {
+            id: saved.id,
+            hostname: "other".to_string(),
+            ..Default::default()
+        };
+        assert!(is_saved_as(&saved, &snap));
+        assert!(!is_saved_as(&other, &snap));
+    }
+}
@@ -0,0 +1,93 @@
+//! Repository passwords stored in the keychain of the operating system
+//!
+//! The entry is given as `SERVICE/ACCOUNT`. Passwords are read from and stored to the Secret
+//! Service on Linux, the Keychain on macOS and the Credential Manager on Windows. This requires the
+//! `keyring` feature.
+
+use anyhow::{anyhow, Result};
+
+/// Split a keyring entry given as `SERVICE/ACCOUNT`
+///
+/// The service may contain `/`, the account is the part after the last `/`.
+///
+/// # Errors
+///
+/// * If the entry is not of the form `SERVICE/ACCOUNT`
+pub(crate) fn split_entry(entry: &str) -> Result<(&str, &str)> {
+    match entry.rsplit_once('/') {
+        Some((service, account)) if !service.is_empty() && !account.is_empty() => {
+            Ok((service, account))
+        }
+        _ => Err(anyhow!(
+            "invalid keyring entry {entry:?}, expected SERVICE/ACCOUNT"
+        )),
+    }
+}
+
+/// Read the password from the keyring entry
+///
+/// # Errors
+///
+/// * If the entry is invalid or cannot be read from the keyring
+#[cfg(feature = "keyring")]
+pub(crate) fn read_password(entry: &str) -> Result<String> {
+    let (service, account) = split_entry(entry)?;
+    keyring::Entry::new(service, account)
+        .and_then(|entry| entry.get_password())
+        .map_err(|err| anyhow!("error reading password from keyring {entry}: {err}"))
+}
+
+/// Store the password in the keyring entry, replacing an existing password
+///
+/// # Errors
+///
+/// * If the entry is invalid or the password cannot be stored in the keyring
+#[cfg(feature = "keyring")]
+pub(crate) fn store_password(entry: &str, password: &str) -> Result<()> {
+    let (service, account) = split_entry(entry)?;
+    keyring::Entry::new(service, account)
+        .and_then(|entry| entry.set_password(password))
+        .map_err(|err| anyhow!("error storing password in keyring {entry}: {err}"))
+}
+
+/// Read the password from the keyring entry; not supported without the `keyring` feature
+#[cfg(not(feature = "keyring"))]
+pub(crate) fn read_password(entry: &str) -> Result<String> {
+    _ = split_entry(entry)?;
+    Err(unsupported(entry))
+}
+
+/// Store the password in the keyring entry; not supported without the `keyring` feature
+#[cfg(not(feature = "keyring"))]
+pub(crate) fn store_password(entry: &str, _password: &str) -> Result<()> {
+    _ = split_entry(entry)?;
+    Err(unsupported(entry))
+}
+
+#[cfg(not(feature = "keyring"))]
+fn unsupported(entry: &str) -> anyhow::Error {
+    anyhow!("cannot access keyring {entry}: rustic was built without the keyring feature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("rustic/backup", ("rustic", "backup"))]
+    #[case("rustic/nas/backup", ("rustic/nas", "backup"))]
+    fn test_split_entry_passes(#[case] entry: &str, #[case] expected: (&str, &str)) -> Result<()> {
+        assert_eq!(split_entry(entry)?, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("rustic")]
+    #[case("rustic/")]
+    #[case("/backup")]
+    fn test_split_entry_fails(#[case] entry: &str) {
+        assert!(split_entry(entry).is_err());
+    }
+}
@@ -1,52 +1,206 @@
 //! Progress Bar Config
 
-use std::{borrow::Cow, fmt::Write, time::Duration};
+use std::{
+    borrow::Cow,
+    fmt::Write,
+    io::{self, IsTerminal, Write as _},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
-use indicatif::{HumanDuration, ProgressBar, ProgressState, ProgressStyle};
+use console::Term;
+use indicatif::{HumanDuration, ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle};
 
 use clap::Parser;
 use merge::Merge;
 
+use bytesize::ByteSize;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 
 use rustic_core::{Progress, ProgressBars};
 
+use crate::events::{self, Event};
+
+/// Default interval to update progress bars
+const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Default interval to print plain status lines
+const DEFAULT_PLAIN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Minimum interval between two progress events of a progress bar
+const EVENT_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Progress Bar Config
 #[serde_as]
 #[derive(Default, Debug, Parser, Clone, Copy, Deserialize, Serialize, Merge)]
 #[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
 pub struct ProgressOptions {
-    /// Don't show any progress bar
+    /// Don't show any progress bar. Unlike redirecting stderr, warnings and other log messages
+    /// are still shown. This overrides all other progress options.
     #[clap(long, global = true, env = "RUSTIC_NO_PROGRESS")]
     #[merge(strategy=merge::bool::overwrite_false)]
     pub no_progress: bool,
 
-    /// Interval to update progress bars
+    /// When to show progress indicators. "auto" only shows them if stderr is a terminal
+    /// [default: auto]
+    #[clap(
+        long,
+        global = true,
+        env = "RUSTIC_PROGRESS",
+        value_name = "MODE",
+        value_enum
+    )]
+    pub progress: Option<ProgressMode>,
+
+    /// Interval to update progress bars, e.g. "500ms" or "1s". Progress bars are not redrawn more
+    /// often than this. [default: 100ms, 10s for plain status lines]
     #[clap(
         long,
         global = true,
         env = "RUSTIC_PROGRESS_INTERVAL",
-        value_name = "DURATION",
-        conflicts_with = "no_progress"
+        value_name = "DURATION"
     )]
     #[serde_as(as = "Option<DisplayFromStr>")]
     pub progress_interval: Option<humantime::Duration>,
+
+    /// Style of the progress indicators [default: bar]
+    #[clap(
+        long,
+        global = true,
+        env = "RUSTIC_PROGRESS_STYLE",
+        value_name = "STYLE",
+        value_enum
+    )]
+    pub progress_style: Option<ProgressIndicatorStyle>,
+
+    /// Emit the progress as newline-delimited JSON events on stderr instead of drawing progress
+    /// indicators, e.g. for frontends. Events are emitted at most once per progress interval.
+    #[clap(long, global = true, env = "RUSTIC_PROGRESS_JSON")]
+    #[merge(strategy=merge::bool::overwrite_false)]
+    pub progress_json: bool,
+}
+
+/// When to show progress indicators
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProgressMode {
+    /// Show progress indicators if stderr is a terminal, e.g. no progress when run from cron
+    #[default]
+    Auto,
+    /// Always show progress indicators, even if stderr is not a terminal
+    Always,
+    /// Never show progress indicators, same as --no-progress
+    Never,
+    /// Print a plain status line per progress interval instead of progress indicators
+    Plain,
+}
+
+/// Style of the progress indicators
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProgressIndicatorStyle {
+    /// Full progress bars
+    #[default]
+    Bar,
+    /// Animated spinner with the title and the current position
+    Spinner,
+    /// Plain text with the current position, e.g. "N/M items"
+    Counter,
+    /// No progress indicators, same as --no-progress
+    None,
 }
 
 impl ProgressOptions {
     /// Get the progress interval
+    fn progress_interval(&self) -> Duration {
+        let default = if self.progress == Some(ProgressMode::Plain) {
+            DEFAULT_PLAIN_INTERVAL
+        } else {
+            DEFAULT_PROGRESS_INTERVAL
+        };
+        self.progress_interval.map_or(default, |i| *i)
+    }
+
+    /// Get the style of the progress indicators
+    fn progress_style(&self) -> ProgressIndicatorStyle {
+        if self.no_progress || self.progress == Some(ProgressMode::Never) {
+            ProgressIndicatorStyle::None
+        } else {
+            self.progress_style.unwrap_or_default()
+        }
+    }
+
+    /// Get the progress mode, resolving "auto" by checking if stderr is a terminal
     ///
-    /// # Returns
+    /// # Arguments
     ///
-    /// `Duration::ZERO` if no progress is enabled
-    fn progress_interval(&self) -> Duration {
-        self.progress_interval.map_or(Duration::ZERO, |i| *i)
+    /// * `is_terminal` - whether stderr is a terminal
+    fn progress_mode(&self, is_terminal: bool) -> ProgressMode {
+        match self.progress.unwrap_or_default() {
+            _ if self.progress_style() == ProgressIndicatorStyle::None => ProgressMode::Never,
+            ProgressMode::Auto if is_terminal => ProgressMode::Always,
+            ProgressMode::Auto => ProgressMode::Never,
+            mode => mode,
+        }
+    }
+
+    /// Create a progress indicator of the given type in the configured style
+    ///
+    /// The indicator is redrawn at most once per progress interval.
+    fn progress(&self, prefix: impl Into<Cow<'static, str>>, tpe: ProgressType) -> RusticProgress {
+        let style = self.progress_style();
+        if style == ProgressIndicatorStyle::None {
+            return Self::hidden(prefix, tpe);
+        }
+        let interval = self.progress_interval();
+        // JSON events are meant for frontends, which usually don't attach a terminal
+        let format = if self.progress_json {
+            LineFormat::Json
+        } else {
+            match self.progress_mode(io::stderr().is_terminal()) {
+                ProgressMode::Never | ProgressMode::Auto => return Self::hidden(prefix, tpe),
+                ProgressMode::Plain => LineFormat::Plain,
+                ProgressMode::Always => {
+                    // draw to the terminal even if it is not attended
+                    let target = ProgressDrawTarget::term_like_with_hz(
+                        Box::new(Term::stderr()),
+                        refresh_rate(interval),
+                    );
+                    let p = ProgressBar::with_draw_target(Some(0), target)
+                        .with_style(progress_style(style, tpe, false));
+                    p.set_prefix(prefix);
+                    p.enable_steady_tick(interval);
+                    return RusticProgress::new(p, tpe, style);
+                }
+            }
+        };
+        let p = ProgressBar::hidden();
+        p.set_prefix(prefix);
+        let mut p = RusticProgress::new(p, tpe, ProgressIndicatorStyle::None);
+        p.4 = Some(Arc::new(LineProgress::start(&p, format, interval)));
+        p
     }
 
     /// Create a hidden progress bar
     pub fn no_progress() -> RusticProgress {
-        RusticProgress(ProgressBar::hidden(), ProgressType::Hidden)
+        RusticProgress(
+            ProgressBar::hidden(),
+            ProgressType::Hidden,
+            ProgressIndicatorStyle::None,
+            None,
+            None,
+        )
+    }
+
+    /// Create a hidden progress bar for the given phase, which still emits events
+    fn hidden(prefix: impl Into<Cow<'static, str>>, tpe: ProgressType) -> RusticProgress {
+        if !events::is_enabled() {
+            return Self::no_progress();
+        }
+        let p = ProgressBar::hidden();
+        p.set_prefix(prefix);
+        RusticProgress::new(p, tpe, ProgressIndicatorStyle::None)
     }
 }
 
@@ -54,31 +208,11 @@ impl ProgressBars for ProgressOptions {
     type P = RusticProgress;
 
     fn progress_spinner(&self, prefix: impl Into<Cow<'static, str>>) -> RusticProgress {
-        if self.no_progress {
-            return Self::no_progress();
-        }
-        let p = ProgressBar::new(0).with_style(
-            ProgressStyle::default_bar()
-                .template("[{elapsed_precise}] {prefix:30} {spinner}")
-                .unwrap(),
-        );
-        p.set_prefix(prefix);
-        p.enable_steady_tick(self.progress_interval());
-        RusticProgress(p, ProgressType::Spinner)
+        self.progress(prefix, ProgressType::Spinner)
     }
 
     fn progress_counter(&self, prefix: impl Into<Cow<'static, str>>) -> RusticProgress {
-        if self.no_progress {
-            return Self::no_progress();
-        }
-        let p = ProgressBar::new(0).with_style(
-            ProgressStyle::default_bar()
-                .template("[{elapsed_precise}] {prefix:30} {bar:40.cyan/blue} {pos:>10}")
-                .unwrap(),
-        );
-        p.set_prefix(prefix);
-        p.enable_steady_tick(self.progress_interval());
-        RusticProgress(p, ProgressType::Counter)
+        self.progress(prefix, ProgressType::Counter)
     }
 
     fn progress_hidden(&self) -> RusticProgress {
@@ -86,21 +220,93 @@ impl ProgressBars for ProgressOptions {
     }
 
     fn progress_bytes(&self, prefix: impl Into<Cow<'static, str>>) -> RusticProgress {
-        if self.no_progress {
-            return Self::no_progress();
+        self.progress(prefix, ProgressType::Bytes)
+    }
+}
+
+/// Get the template of a progress indicator
+///
+/// # Arguments
+///
+/// * `style` - the style of the progress indicator
+/// * `tpe` - the type of the progress indicator
+/// * `with_len` - whether the total length is known
+fn template(style: ProgressIndicatorStyle, tpe: ProgressType, with_len: bool) -> &'static str {
+    match (style, tpe, with_len) {
+        (ProgressIndicatorStyle::None, _, _) | (_, ProgressType::Hidden, _) => "",
+        (ProgressIndicatorStyle::Bar, ProgressType::Spinner, _) => {
+            "[{elapsed_precise}] {prefix:30} {spinner}"
+        }
+        (ProgressIndicatorStyle::Bar, ProgressType::Counter, false) => {
+            "[{elapsed_precise}] {prefix:30} {bar:40.cyan/blue} {pos:>10}"
+        }
+        (ProgressIndicatorStyle::Bar, ProgressType::Counter, true) => {
+            "[{elapsed_precise}] {prefix:30} {bar:40.cyan/blue} {pos:>10}/{len:10}"
+        }
+        (ProgressIndicatorStyle::Bar, ProgressType::Bytes, false) => {
+            "[{elapsed_precise}] {prefix:30} {bar:40.cyan/blue} {bytes:>10}            {bytes_per_sec:12}"
+        }
+        (ProgressIndicatorStyle::Bar, ProgressType::Bytes, true) => {
+            "[{elapsed_precise}] {prefix:30} {bar:40.cyan/blue} {bytes:>10}/{total_bytes:10} {bytes_per_sec:12} (ETA {my_eta})"
+        }
+        (ProgressIndicatorStyle::Spinner, ProgressType::Spinner, _) => "{spinner} {prefix}",
+        (ProgressIndicatorStyle::Spinner, ProgressType::Counter, false) => {
+            "{spinner} {prefix} {pos}"
+        }
+        (ProgressIndicatorStyle::Spinner, ProgressType::Counter, true) => {
+            "{spinner} {prefix} {pos}/{len}"
+        }
+        (ProgressIndicatorStyle::Spinner, ProgressType::Bytes, false) => {
+            "{spinner} {prefix} {bytes}"
+        }
+        (ProgressIndicatorStyle::Spinner, ProgressType::Bytes, true) => {
+            "{spinner} {prefix} {bytes}/{total_bytes}"
+        }
+        (ProgressIndicatorStyle::Counter, ProgressType::Spinner, _) => "{prefix} {elapsed}",
+        (ProgressIndicatorStyle::Counter, ProgressType::Counter, false) => "{prefix} {pos} items",
+        (ProgressIndicatorStyle::Counter, ProgressType::Counter, true) => {
+            "{prefix} {pos}/{len} items"
+        }
+        (ProgressIndicatorStyle::Counter, ProgressType::Bytes, false) => "{prefix} {bytes}",
+        (ProgressIndicatorStyle::Counter, ProgressType::Bytes, true) => {
+            "{prefix} {bytes}/{total_bytes}"
         }
-        let p = ProgressBar::new(0).with_style(
-            ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] {prefix:30} {bar:40.cyan/blue} {bytes:>10}            {bytes_per_sec:12}")
-            .unwrap()
-            );
-        p.set_prefix(prefix);
-        p.enable_steady_tick(self.progress_interval());
-        RusticProgress(p, ProgressType::Bytes)
     }
 }
 
-#[derive(Debug, Clone)]
+/// Get the style of a progress indicator, see [`template`]
+fn progress_style(
+    style: ProgressIndicatorStyle,
+    tpe: ProgressType,
+    with_len: bool,
+) -> ProgressStyle {
+    ProgressStyle::default_bar()
+        .with_key("my_eta", |s: &ProgressState, w: &mut dyn Write| {
+            let _ = match (s.pos(), s.len()) {
+                // Extra checks to prevent panics from dividing by zero or subtract overflow
+                (pos, Some(len)) if pos != 0 && len > pos => write!(
+                    w,
+                    "{:#}",
+                    HumanDuration(Duration::from_secs(
+                        s.elapsed().as_secs() * (len - pos) / pos
+                    ))
+                ),
+                (_, _) => write!(w, "-"),
+            };
+        })
+        .template(template(style, tpe, with_len))
+        .unwrap()
+}
+
+/// Get the refresh rate of the draw target for the given progress interval
+///
+/// The refresh rate is given in Hz and must be between 1 and 255.
+fn refresh_rate(interval: Duration) -> u8 {
+    let rate = 1000 / interval.as_millis().max(1);
+    u8::try_from(rate.clamp(1, u128::from(u8::MAX))).unwrap_or(u8::MAX)
+}
+
+#[derive(Debug, Clone, Copy)]
 enum ProgressType {
     Hidden,
     Spinner,
@@ -108,41 +314,182 @@ enum ProgressType {
     Bytes,
 }
 
+/// A progress event, written to stderr as one line of JSON with `--progress-json`
+///
+/// Positions are given as `bytes_*` for byte progress and as `items_*` for counters; spinners
+/// have neither.
+#[serde_with::apply(Option => #[serde(skip_serializing_if = "Option::is_none")])]
+#[derive(Debug, Serialize, PartialEq, Eq)]
+struct ProgressEvent<'a> {
+    /// Type of the event: "start", "progress" or "finish"
+    #[serde(rename = "type")]
+    tpe: &'static str,
+    /// Title of the task
+    task: &'a str,
+    /// Number of bytes processed
+    bytes_done: Option<u64>,
+    /// Total number of bytes, if known
+    bytes_total: Option<u64>,
+    /// Number of items processed
+    items_done: Option<u64>,
+    /// Total number of items, if known
+    items_total: Option<u64>,
+}
+
+impl<'a> ProgressEvent<'a> {
+    /// Create an event for the current state of a progress bar
+    fn new(tpe: &'static str, task: &'a str, p: &RusticProgress) -> Self {
+        let (done, total) = (Some(p.0.position()), p.0.length());
+        let (bytes, items) = match p.1 {
+            ProgressType::Bytes => ((done, total), (None, None)),
+            ProgressType::Counter => ((None, None), (done, total)),
+            ProgressType::Hidden | ProgressType::Spinner => ((None, None), (None, None)),
+        };
+        Self {
+            tpe,
+            task,
+            bytes_done: bytes.0,
+            bytes_total: bytes.1,
+            items_done: items.0,
+            items_total: items.1,
+        }
+    }
+}
+
+/// Format of progress reported as lines on stderr
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LineFormat {
+    /// A JSON event per line, see [`ProgressEvent`]
+    Json,
+    /// A plain status line, see [`plain_status`]
+    Plain,
+}
+
+/// Get a plain status line for the current state of a progress bar, e.g.
+/// "backing up... 1.0 GiB / 4.0 GiB (25%)"
+fn plain_status(p: &RusticProgress) -> String {
+    let (pos, len) = (p.0.position(), p.0.length());
+    let fmt = |n| match p.1 {
+        ProgressType::Bytes => ByteSize(n).to_string_as(true),
+        _ => n.to_string(),
+    };
+    let mut status = p.0.prefix();
+    match (p.1, len) {
+        (ProgressType::Hidden | ProgressType::Spinner, _) => {}
+        (_, Some(len)) if len > 0 => {
+            _ = write!(
+                status,
+                " {} / {} ({}%)",
+                fmt(pos),
+                fmt(len),
+                pos * 100 / len
+            );
+        }
+        (_, _) => _ = write!(status, " {}", fmt(pos)),
+    }
+    if matches!(p.1, ProgressType::Counter) {
+        status.push_str(" items");
+    }
+    status
+}
+
+/// Write a line to stderr
+///
+/// The line is written at once and flushed, so it is not interleaved with log messages.
+/// Errors writing stderr are ignored.
+fn write_line(mut line: Vec<u8>) {
+    line.push(b'\n');
+    let mut stderr = io::stderr().lock();
+    _ = stderr.write_all(&line);
+    _ = stderr.flush();
+}
+
+/// State of a progress bar reporting its progress as lines on stderr
+#[derive(Debug)]
+struct LineProgress {
+    /// Format of the lines
+    format: LineFormat,
+    /// Minimum interval between two progress lines
+    interval: Duration,
+    /// Time of the last progress line
+    last: Mutex<Instant>,
+}
+
+impl LineProgress {
+    /// Report the start of a progress bar
+    fn start(p: &RusticProgress, format: LineFormat, interval: Duration) -> Self {
+        let line = Self {
+            format,
+            interval,
+            last: Mutex::new(Instant::now()),
+        };
+        if format == LineFormat::Json {
+            line.emit("start", p);
+        }
+        line
+    }
+
+    /// Report the progress, at most once per interval
+    fn inc(&self, p: &RusticProgress) {
+        if let Ok(mut last) = self.last.lock() {
+            if last.elapsed() >= self.interval {
+                *last = Instant::now();
+                self.emit("progress", p);
+            }
+        }
+    }
+
+    /// Write a line of the given event type for the current state of the progress bar
+    fn emit(&self, tpe: &'static str, p: &RusticProgress) {
+        match self.format {
+            LineFormat::Json => {
+                if let Ok(line) = serde_json::to_vec(&ProgressEvent::new(tpe, &p.0.prefix(), p)) {
+                    write_line(line);
+                }
+            }
+            LineFormat::Plain if tpe == "finish" => {
+                write_line(format!("{} done", plain_status(p)).into_bytes());
+            }
+            LineFormat::Plain => write_line(plain_status(p).into_bytes()),
+        }
+    }
+}
+
 /// A default progress bar
+///
+/// The progress bar is shown in the configured style. If the event stream is enabled, the time of
+/// the last progress event is kept to limit the number of events. With `--progress-json` or
+/// `--progress plain`, the progress is reported as lines on stderr instead of drawing the bar.
 #[derive(Debug, Clone)]
-pub struct RusticProgress(ProgressBar, ProgressType);
+pub struct RusticProgress(
+    ProgressBar,
+    ProgressType,
+    ProgressIndicatorStyle,
+    Option<Arc<Mutex<Instant>>>,
+    Option<Arc<LineProgress>>,
+);
+
+impl RusticProgress {
+    /// Create a new progress bar and emit the start of its phase
+    fn new(p: ProgressBar, tpe: ProgressType, style: ProgressIndicatorStyle) -> Self {
+        let last_event = events::is_enabled().then(|| {
+            events::emit(&Event::PhaseStarted { phase: &p.prefix() });
+            Arc::new(Mutex::new(Instant::now()))
+        });
+        Self(p, tpe, style, last_event, None)
+    }
+}
 
 impl Progress for RusticProgress {
     fn is_hidden(&self) -> bool {
-        self.0.is_hidden()
+        self.0.is_hidden() && self.4.is_none()
     }
 
     fn set_length(&self, len: u64) {
-        match self.1 {
-            ProgressType::Counter => {
-                self.0.set_style(
-                    ProgressStyle::default_bar()
-                        .template(
-                            "[{elapsed_precise}] {prefix:30} {bar:40.cyan/blue} {pos:>10}/{len:10}",
-                        )
-                        .unwrap(),
-                );
-            }
-            ProgressType::Bytes => {
-                self.0.set_style(
-                    ProgressStyle::default_bar()
-                        .with_key("my_eta", |s: &ProgressState, w: &mut dyn Write| {
-                            let _ = match (s.pos(), s.len()){
-                                // Extra checks to prevent panics from dividing by zero or subtract overflow
-                                (pos,Some(len)) if pos != 0 && len > pos => write!(w,"{:#}", HumanDuration(Duration::from_secs(s.elapsed().as_secs() * (len-pos)/pos))),
-                                (_, _) => write!(w,"-"),
-                            };
-                        })
-                        .template("[{elapsed_precise}] {prefix:30} {bar:40.cyan/blue} {bytes:>10}/{total_bytes:10} {bytes_per_sec:12} (ETA {my_eta})")
-                        .unwrap()
-                );
-            }
-            _ => {}
+        if matches!(self.1, ProgressType::Counter | ProgressType::Bytes)
+            && self.2 != ProgressIndicatorStyle::None
+        {
+            self.0.set_style(progress_style(self.2, self.1, true));
         }
         self.0.set_length(len);
     }
@@ -153,9 +500,141 @@ impl Progress for RusticProgress {
 
     fn inc(&self, inc: u64) {
         self.0.inc(inc);
+        if let Some(Ok(mut last_event)) = self.3.as_ref().map(|l| l.lock()) {
+            if last_event.elapsed() >= EVENT_INTERVAL {
+                *last_event = Instant::now();
+                events::emit(&Event::Progress {
+                    phase: &self.0.prefix(),
+                    position: self.0.position(),
+                    length: self.0.length(),
+                });
+            }
+        }
+        if let Some(line) = &self.4 {
+            line.inc(self);
+        }
     }
 
     fn finish(&self) {
         self.0.finish_with_message("done");
+        if self.3.is_some() {
+            events::emit(&Event::PhaseFinished {
+                phase: &self.0.prefix(),
+                position: self.0.position(),
+                length: self.0.length(),
+            });
+        }
+        if let Some(line) = &self.4 {
+            line.emit("finish", self);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(Duration::from_millis(100), 10)]
+    #[case(Duration::from_millis(500), 2)]
+    #[case(Duration::from_secs(1), 1)]
+    #[case(Duration::from_secs(60), 1)]
+    #[case(Duration::from_millis(1), 255)]
+    #[case(Duration::ZERO, 255)]
+    fn test_refresh_rate_passes(#[case] interval: Duration, #[case] expected: u8) {
+        assert_eq!(refresh_rate(interval), expected);
+    }
+
+    #[rstest]
+    fn test_templates_are_valid_passes(
+        #[values(
+            ProgressIndicatorStyle::Bar,
+            ProgressIndicatorStyle::Spinner,
+            ProgressIndicatorStyle::Counter
+        )]
+        style: ProgressIndicatorStyle,
+        #[values(ProgressType::Spinner, ProgressType::Counter, ProgressType::Bytes)]
+        tpe: ProgressType,
+        #[values(false, true)] with_len: bool,
+    ) {
+        assert!(ProgressStyle::default_bar()
+            .template(template(style, tpe, with_len))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_no_progress_overrides_style_passes() {
+        let opts = ProgressOptions {
+            no_progress: true,
+            progress_style: Some(ProgressIndicatorStyle::Bar),
+            ..Default::default()
+        };
+        assert_eq!(opts.progress_style(), ProgressIndicatorStyle::None);
+        assert!(opts.progress_counter("counting").is_hidden());
+    }
+
+    #[test]
+    fn test_progress_event_passes() -> serde_json::Result<()> {
+        let opts = ProgressOptions {
+            progress_json: true,
+            ..Default::default()
+        };
+        let p = opts.progress_bytes("backing up...");
+        assert!(!p.is_hidden());
+        p.set_length(100);
+        p.inc(10);
+        assert_eq!(
+            serde_json::to_string(&ProgressEvent::new("progress", "backing up...", &p))?,
+            r#"{"type":"progress","task":"backing up...","bytes_done":10,"bytes_total":100}"#
+        );
+
+        let p = opts.progress_spinner("starting...");
+        assert_eq!(
+            serde_json::to_string(&ProgressEvent::new("finish", "starting...", &p))?,
+            r#"{"type":"finish","task":"starting..."}"#
+        );
+        Ok(())
+    }
+    #[rstest]
+    #[case(None, true, ProgressMode::Always)]
+    #[case(None, false, ProgressMode::Never)]
+    #[case(Some(ProgressMode::Always), false, ProgressMode::Always)]
+    #[case(Some(ProgressMode::Plain), true, ProgressMode::Plain)]
+    #[case(Some(ProgressMode::Never), true, ProgressMode::Never)]
+    fn test_progress_mode_passes(
+        #[case] mode: Option<ProgressMode>,
+        #[case] is_terminal: bool,
+        #[case] expected: ProgressMode,
+    ) {
+        let opts = ProgressOptions {
+            progress: mode,
+            ..Default::default()
+        };
+        assert_eq!(opts.progress_mode(is_terminal), expected);
+        let opts = ProgressOptions {
+            no_progress: true,
+            ..opts
+        };
+        assert_eq!(opts.progress_mode(is_terminal), ProgressMode::Never);
+    }
+
+    #[test]
+    fn test_plain_status_passes() {
+        let opts = ProgressOptions {
+            progress: Some(ProgressMode::Plain),
+            ..Default::default()
+        };
+        assert_eq!(opts.progress_interval(), DEFAULT_PLAIN_INTERVAL);
+
+        let p = opts.progress_bytes("backing up...");
+        p.set_length(4 * 1024 * 1024 * 1024);
+        p.inc(1024 * 1024 * 1024);
+        assert_eq!(plain_status(&p), "backing up... 1.0 GiB / 4.0 GiB (25%)");
+
+        let p = opts.progress_counter("reading index...");
+        p.inc(3);
+        assert_eq!(plain_status(&p), "reading index... 3 items");
     }
 }
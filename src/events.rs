@@ -0,0 +1,289 @@
+//! Machine-readable event stream
+//!
+//! If `--event-stream` is given, events of the whole invocation are written to it as
+//! newline-delimited JSON, e.g. for frontends wrapping rustic. Events are emitted by the command
+//! entry point, when opening repositories, by the progress bars (phases and progress ticks) and by
+//! the logger (warnings and errors), so every command using these is covered.
+//!
+//! Every event contains the `schema` version, the `time` and the `event` type. The version is
+//! increased if fields are removed or their meaning is changed.
+
+use std::{fs::File, io::Write, path::PathBuf, sync::Mutex};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use simplelog::{Config, SharedLogger};
+
+/// Version of the event schema
+pub(crate) const SCHEMA_VERSION: u32 = 1;
+
+/// The event stream, if enabled
+static EVENT_STREAM: OnceCell<Mutex<File>> = OnceCell::new();
+
+/// Errors logged while collecting them, see [`collect_errors`]
+static COLLECTED_ERRORS: Mutex<Option<Vec<LoggedError>>> = Mutex::new(None);
+
+/// An error which has been logged
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LoggedError {
+    /// Category of the message, i.e. the module which logged it
+    pub(crate) category: String,
+    /// The message
+    pub(crate) message: String,
+}
+
+/// An event of the event stream
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum Event<'a> {
+    /// A command has been started
+    CommandStarted {
+        /// Name of the command
+        command: &'a str,
+    },
+    /// A repository has been opened
+    RepositoryOpened {
+        /// Name of the repository
+        repository: &'a str,
+    },
+    /// A phase, i.e. a progress bar, has been started
+    PhaseStarted {
+        /// Title of the phase
+        phase: &'a str,
+    },
+    /// Progress within a phase, emitted at most once per second for each phase
+    Progress {
+        /// Title of the phase
+        phase: &'a str,
+        /// Current position, i.e. the number of items or bytes processed
+        position: u64,
+        /// Total length, if known
+        length: Option<u64>,
+    },
+    /// A phase has been finished
+    PhaseFinished {
+        /// Title of the phase
+        phase: &'a str,
+        /// Final position
+        position: u64,
+        /// Total length, if known
+        length: Option<u64>,
+    },
+    /// A warning or an error has been logged
+    Log {
+        /// Log level, either `warn` or `error`
+        level: &'a str,
+        /// Category of the message, i.e. the module which logged it
+        category: &'a str,
+        /// The message
+        message: String,
+    },
+    /// Final summary of a command
+    Summary {
+        /// Name of the command
+        command: &'a str,
+        /// The summary, depending on the command
+        summary: serde_json::Value,
+    },
+    /// The invocation has finished
+    Finished {
+        /// Exit code of the process
+        exit_code: i32,
+    },
+}
+
+/// An event together with the schema version and time, as written to the event stream
+#[derive(Debug, Serialize)]
+struct EventRecord<'a> {
+    /// Version of the event schema
+    schema: u32,
+    /// Time of the event
+    time: DateTime<Local>,
+    /// The event
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+/// Enable the event stream
+///
+/// # Arguments
+///
+/// * `target` - file to write the events to, or `fd:N` to use an open file descriptor (unix only)
+///
+/// # Errors
+///
+/// * If the target cannot be opened
+pub(crate) fn init(target: &str) -> Result<()> {
+    let path = match target.strip_prefix("fd:") {
+        Some(fd) if cfg!(unix) => PathBuf::from(format!("/dev/fd/{}", fd.parse::<u32>()?)),
+        Some(_) => bail!("event stream to file descriptors is only supported on unix"),
+        None => PathBuf::from(target),
+    };
+    let file = File::options().create(true).append(true).open(&path)?;
+    if EVENT_STREAM.set(Mutex::new(file)).is_err() {
+        bail!("event stream is already enabled");
+    }
+    Ok(())
+}
+
+/// Check if the event stream is enabled
+pub(crate) fn is_enabled() -> bool {
+    EVENT_STREAM.get().is_some()
+}
+
+/// Emit an event, if the event stream is enabled
+///
+/// Errors writing the event stream are ignored; they can't be logged as logging emits events.
+pub(crate) fn emit(event: &Event<'_>) {
+    let Some(stream) = EVENT_STREAM.get() else {
+        return;
+    };
+    let record = EventRecord {
+        schema: SCHEMA_VERSION,
+        time: Local::now(),
+        event,
+    };
+    if let Ok(mut line) = serde_json::to_vec(&record) {
+        line.push(b'\n');
+        if let Ok(mut stream) = stream.lock() {
+            _ = stream.write_all(&line);
+        }
+    }
+}
+
+/// Run `f` and collect all errors logged while running it
+///
+/// The errors are still logged as usual. Needs the [`EventLogger`] to be installed.
+pub(crate) fn collect_errors<T>(f: impl FnOnce() -> T) -> (T, Vec<LoggedError>) {
+    if let Ok(mut collected) = COLLECTED_ERRORS.lock() {
+        *collected = Some(Vec::new());
+    }
+    let result = f();
+    let errors = COLLECTED_ERRORS
+        .lock()
+        .ok()
+        .and_then(|mut collected| collected.take())
+        .unwrap_or_default();
+    (result, errors)
+}
+
+/// Logger which emits warnings and errors to the event stream and collects errors, see
+/// [`collect_errors`]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct EventLogger;
+
+impl Log for EventLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= Level::Warn
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if record.level() == Level::Error {
+            if let Ok(mut collected) = COLLECTED_ERRORS.lock() {
+                if let Some(errors) = collected.as_mut() {
+                    errors.push(LoggedError {
+                        category: record.target().to_string(),
+                        message: record.args().to_string(),
+                    });
+                }
+            }
+        }
+        if self.enabled(record.metadata()) {
+            emit(&Event::Log {
+                level: &record.level().as_str().to_lowercase(),
+                category: record.target(),
+                message: record.args().to_string(),
+            });
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+impl SharedLogger for EventLogger {
+    fn level(&self) -> LevelFilter {
+        LevelFilter::Warn
+    }
+
+    fn config(&self) -> Option<&Config> {
+        None
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+    use rstest::rstest;
+    use serde_json::json;
+
+    #[rstest]
+    #[case(Event::CommandStarted { command: "backup" }, r#""event":"command_started","command":"backup""#)]
+    #[case(Event::RepositoryOpened { repository: "repo" }, r#""event":"repository_opened","repository":"repo""#)]
+    #[case(Event::PhaseStarted { phase: "backing up..." }, r#""event":"phase_started","phase":"backing up...""#)]
+    #[case(
+        Event::Progress { phase: "backing up...", position: 5, length: Some(10) },
+        r#""event":"progress","phase":"backing up...","position":5,"length":10"#
+    )]
+    #[case(
+        Event::PhaseFinished { phase: "backing up...", position: 10, length: None },
+        r#""event":"phase_finished","phase":"backing up...","position":10,"length":null"#
+    )]
+    #[case(
+        Event::Log { level: "warn", category: "rustic", message: "careful".to_string() },
+        r#""event":"log","level":"warn","category":"rustic","message":"careful""#
+    )]
+    #[case(
+        Event::Summary { command: "forget", summary: json!({"removed": 2}) },
+        r#""event":"summary","command":"forget","summary":{"removed":2}"#
+    )]
+    #[case(Event::Finished { exit_code: 1 }, r#""event":"finished","exit_code":1"#)]
+    fn test_event_schema_passes(#[case] event: Event<'_>, #[case] fields: &str) -> Result<()> {
+        let record = EventRecord {
+            schema: SCHEMA_VERSION,
+            time: Local.with_ymd_and_hms(2024, 1, 5, 12, 0, 0).unwrap(),
+            event: &event,
+        };
+        let time = serde_json::to_string(&record.time)?;
+        assert_eq!(
+            serde_json::to_string(&record)?,
+            format!(r#"{{"schema":1,"time":{time},{fields}}}"#)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_collect_errors_passes() {
+        let log = |level: Level, message: &str| {
+            EventLogger.log(
+                &Record::builder()
+                    .level(level)
+                    .target("rustic_core")
+                    .args(format_args!("{message}"))
+                    .build(),
+            );
+        };
+
+        log(Level::Error, "before");
+        let ((), errors) = collect_errors(|| {
+            log(Level::Error, "damaged");
+            log(Level::Warn, "careful");
+        });
+        log(Level::Error, "after");
+        assert_eq!(
+            errors,
+            vec![LoggedError {
+                category: "rustic_core".to_string(),
+                message: "damaged".to_string(),
+            }]
+        );
+    }
+}
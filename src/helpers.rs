@@ -1,7 +1,17 @@
+use std::{
+    fs::OpenOptions,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
 use bytesize::ByteSize;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
 use comfy_table::{
     presets::ASCII_MARKDOWN, Attribute, Cell, CellAlignment, ContentArrangement, Table,
 };
+use log::info;
 
 /// Helpers for table output
 
@@ -46,3 +56,300 @@ pub fn table_right_from<I: IntoIterator<Item = T>, T: ToString>(start: usize, ti
 pub fn bytes_size_to_string(b: u64) -> String {
     ByteSize(b).to_string_as(true)
 }
+
+/// Kind of a local output destination
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputKind {
+    /// A file which will be written
+    File,
+    /// A directory into which will be written
+    Dir,
+}
+
+/// Validate a local output destination before writing to it
+///
+/// The destination is resolved to an absolute path which is logged.
+///
+/// # Arguments
+///
+/// * `path` - the destination given by the user
+/// * `kind` - whether the destination is a file or a directory
+/// * `force` - allow overwriting an existing file
+/// * `create_parents` - create missing parent directories
+///
+/// # Errors
+///
+/// * If the destination exists but has the wrong type
+/// * If the destination is an existing file and `force` is not set
+/// * If the parent directory doesn't exist and `create_parents` is not set
+///
+/// # Returns
+///
+/// The absolute path of the destination
+pub fn prepare_output_path(
+    path: &Path,
+    kind: OutputKind,
+    force: bool,
+    create_parents: bool,
+) -> Result<PathBuf> {
+    let mut absolute = std::env::current_dir()
+        .context("cannot determine the current directory")?
+        .join(path);
+    if let Ok(canonical) = absolute.canonicalize() {
+        absolute = canonical;
+    } else if let (Some(parent), Some(name)) = (absolute.parent(), absolute.file_name()) {
+        if let Ok(parent) = parent.canonicalize() {
+            absolute = parent.join(name);
+        }
+    }
+
+    match (absolute.metadata(), kind) {
+        (Ok(meta), OutputKind::File) if meta.is_dir() => {
+            bail!("{} is a directory, expected a file.", absolute.display())
+        }
+        (Ok(_), OutputKind::File) if !force => {
+            bail!(
+                "{} already exists, use --force to overwrite it.",
+                absolute.display()
+            )
+        }
+        (Ok(meta), OutputKind::Dir) if !meta.is_dir() => {
+            bail!("{} is not a directory.", absolute.display())
+        }
+        (Ok(_), _) => {}
+        (Err(_), _) => {
+            if let Some(parent) = absolute.parent() {
+                if !parent.exists() {
+                    if !create_parents {
+                        bail!(
+                            "parent directory of {} doesn't exist, use --create-parents to create it.",
+                            absolute.display()
+                        );
+                    }
+                    std::fs::create_dir_all(parent).with_context(|| {
+                        format!("error creating directory {}", parent.display())
+                    })?;
+                }
+            }
+        }
+    }
+
+    info!("writing to {}", absolute.display());
+    Ok(absolute)
+}
+
+/// Number of kept log files if only `--log-max-size` is given
+pub const DEFAULT_LOG_MAX_FILES: usize = 5;
+
+/// Age after which a lock file for log rotation is considered stale
+pub const LOG_LOCK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Lock file guarding the rotation of a log file; removed when dropped
+#[derive(Debug)]
+struct LogLock(PathBuf);
+
+impl LogLock {
+    /// Try to acquire the lock for rotating the given log file
+    ///
+    /// # Returns
+    ///
+    /// The lock or `None` if another process holds it
+    ///
+    /// # Errors
+    ///
+    /// * If the lock file cannot be created for other reasons than being present
+    fn acquire(path: &Path) -> Result<Option<Self>> {
+        let mut name = path.as_os_str().to_os_string();
+        name.push(".lock");
+        let lock = PathBuf::from(name);
+        for _ in 0..2 {
+            match OpenOptions::new().write(true).create_new(true).open(&lock) {
+                Ok(_) => return Ok(Some(Self(lock))),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                    let stale = lock
+                        .metadata()
+                        .and_then(|meta| meta.modified())
+                        .ok()
+                        .and_then(|modified| modified.elapsed().ok())
+                        .is_some_and(|age| age > LOG_LOCK_TIMEOUT);
+                    if !stale {
+                        return Ok(None);
+                    }
+                    _ = std::fs::remove_file(&lock);
+                }
+                Err(err) => {
+                    return Err(err)
+                        .with_context(|| format!("error creating lock file {}", lock.display()))
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Drop for LogLock {
+    fn drop(&mut self) {
+        _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Rotate the log file before it is opened
+///
+/// The log file is renamed to `<file>.1`, `<file>.1` to `<file>.2` and so on; the file
+/// `<file>.<max_files>` is removed. Rotating only at startup ensures that the log entries of one
+/// run are not split across files.
+///
+/// Rotation is guarded by the lock file `<file>.lock`, so if several processes start at the same
+/// time, only one of them rotates and the others append to the (new) log file. Lock files older
+/// than [`LOG_LOCK_TIMEOUT`] are left over from crashed processes and are removed.
+///
+/// # Arguments
+///
+/// * `path` - the log file
+/// * `max_size` - only rotate if the log file has at least this size; rotate always if not given
+/// * `max_files` - the number of rotated log files to keep
+///
+/// # Errors
+///
+/// * If a log file cannot be renamed or removed
+pub fn rotate_log_file(path: &Path, max_size: Option<ByteSize>, max_files: usize) -> Result<()> {
+    let needs_rotation = || {
+        path.metadata()
+            .is_ok_and(|meta| !max_size.is_some_and(|max_size| meta.len() < max_size.as_u64()))
+    };
+    if !needs_rotation() {
+        return Ok(());
+    }
+    let Some(_lock) = LogLock::acquire(path)? else {
+        // another process is rotating right now
+        return Ok(());
+    };
+    // the log file may have been rotated while we were waiting for the lock
+    if !needs_rotation() {
+        return Ok(());
+    }
+
+    let rotated = |n: usize| {
+        let mut name = path.as_os_str().to_os_string();
+        name.push(format!(".{n}"));
+        PathBuf::from(name)
+    };
+    if max_files == 0 {
+        return std::fs::remove_file(path)
+            .with_context(|| format!("error removing log file {}", path.display()));
+    }
+    let oldest = rotated(max_files);
+    if oldest.exists() {
+        std::fs::remove_file(&oldest)
+            .with_context(|| format!("error removing log file {}", oldest.display()))?;
+    }
+    for n in (1..max_files).rev() {
+        let from = rotated(n);
+        if from.exists() {
+            std::fs::rename(&from, rotated(n + 1))
+                .with_context(|| format!("error rotating log file {}", from.display()))?;
+        }
+    }
+    std::fs::rename(path, rotated(1))
+        .with_context(|| format!("error rotating log file {}", path.display()))
+}
+
+/// Parse a date and time given as RFC 3339, "YYYY-MM-DD HH:MM:SS" or "YYYY-MM-DD" in local time
+pub fn parse_datetime(s: &str) -> Result<DateTime<Local>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.with_timezone(&Local));
+    }
+    let naive = match NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S") {
+        Ok(naive) => naive,
+        Err(_) => NaiveDate::parse_from_str(s, "%Y-%m-%d")?
+            .and_hms_opt(0, 0, 0)
+            .unwrap_or_default(),
+    };
+    match Local.from_local_datetime(&naive).earliest() {
+        Some(time) => Ok(time),
+        None => bail!("invalid local time {s}"),
+    }
+}
+
+/// Create a snapshot for tests
+///
+/// The id consists of the given hex digit and the snapshot is taken at 12:00 on the given day of
+/// January 2024; all other fields are set to their defaults.
+#[cfg(test)]
+pub(crate) fn test_snapshot(id: char, day: u32) -> rustic_core::repofile::SnapshotFile {
+    rustic_core::repofile::SnapshotFile {
+        id: rustic_core::Id::from_hex(&id.to_string().repeat(64)).unwrap(),
+        time: Local.with_ymd_and_hms(2024, 1, day, 12, 0, 0).unwrap(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    #[test]
+    fn test_rotate_log_file_passes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let log = dir.path().join("rustic.log");
+        let read = |name: &str| fs::read_to_string(dir.path().join(name)).ok();
+
+        // nothing to rotate
+        rotate_log_file(&log, None, 2)?;
+
+        fs::write(&log, "first")?;
+        // too small to be rotated
+        rotate_log_file(&log, Some(ByteSize::b(10)), 2)?;
+        assert_eq!(read("rustic.log").as_deref(), Some("first"));
+
+        rotate_log_file(&log, Some(ByteSize::b(5)), 2)?;
+        assert_eq!(read("rustic.log"), None);
+        assert_eq!(read("rustic.log.1").as_deref(), Some("first"));
+
+        fs::write(&log, "second")?;
+        rotate_log_file(&log, None, 2)?;
+        fs::write(&log, "third")?;
+        rotate_log_file(&log, None, 2)?;
+        assert_eq!(read("rustic.log"), None);
+        assert_eq!(read("rustic.log.1").as_deref(), Some("third"));
+        assert_eq!(read("rustic.log.2").as_deref(), Some("second"));
+        assert_eq!(read("rustic.log.3"), None);
+        assert_eq!(read("rustic.log.lock"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rotate_log_file_skips_locked_passes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let log = dir.path().join("rustic.log");
+        let lock = dir.path().join("rustic.log.lock");
+        fs::write(&log, "first")?;
+        fs::write(&lock, "")?;
+
+        // another process is rotating
+        rotate_log_file(&log, None, 2)?;
+        assert!(log.exists());
+        assert!(lock.exists());
+
+        fs::remove_file(&lock)?;
+        rotate_log_file(&log, None, 2)?;
+        assert!(!log.exists());
+        assert!(!lock.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_datetime_passes() -> Result<()> {
+        let expected = Local.with_ymd_and_hms(2024, 1, 31, 12, 30, 0).unwrap();
+        assert_eq!(parse_datetime("2024-01-31 12:30:00")?, expected);
+        assert_eq!(parse_datetime(&expected.to_rfc3339())?, expected);
+        assert_eq!(
+            parse_datetime("2024-01-31")?,
+            Local.with_ymd_and_hms(2024, 1, 31, 0, 0, 0).unwrap()
+        );
+        assert!(parse_datetime("yesterday").is_err());
+        Ok(())
+    }
+}
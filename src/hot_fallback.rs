@@ -0,0 +1,107 @@
+//! Fallback to the cold repository for files missing in the hot repository
+//!
+//! With a hot repository, metadata files and tree packs are read from the hot repository. If such
+//! a file can't be read from the hot repository, e.g. because a write to it failed, it is read
+//! from the (cold) repository instead and a warning is shown. `rustic repair hotcopy` copies the
+//! missing files to the hot repository.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use bytes::Bytes;
+use log::warn;
+
+use rustic_core::{repofile::FileType, Id, ReadBackend, RepositoryBackends, WriteBackend};
+
+/// A hot backend which reads files it doesn't contain from the cold backend
+struct HotFallbackBackend {
+    /// the hot backend
+    hot: Arc<dyn WriteBackend>,
+    /// the cold backend, only used for reading
+    cold: Arc<dyn WriteBackend>,
+}
+
+impl HotFallbackBackend {
+    /// Read from the hot backend, falling back to the cold backend on errors
+    ///
+    /// If the cold backend fails, too, the error of the hot backend is returned.
+    fn read_with_fallback(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        read: impl Fn(&dyn WriteBackend) -> Result<Bytes>,
+    ) -> Result<Bytes> {
+        read(self.hot.as_ref()).or_else(|err| {
+            let data = read(self.cold.as_ref()).map_err(|_| err)?;
+            warn!(
+                "{tpe:?} file {id} can't be read from the hot repository, using the cold repository. Run `rustic repair hotcopy` to re-sync."
+            );
+            Ok(data)
+        })
+    }
+}
+
+impl ReadBackend for HotFallbackBackend {
+    fn location(&self) -> String {
+        self.hot.location()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> Result<Vec<(Id, u32)>> {
+        self.hot.list_with_size(tpe)
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> Result<Bytes> {
+        self.read_with_fallback(tpe, id, |be| be.read_full(tpe, id))
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> Result<Bytes> {
+        self.read_with_fallback(tpe, id, |be| {
+            be.read_partial(tpe, id, cacheable, offset, length)
+        })
+    }
+
+    fn needs_warm_up(&self) -> bool {
+        self.hot.needs_warm_up()
+    }
+
+    fn warm_up(&self, tpe: FileType, id: &Id) -> Result<()> {
+        self.hot.warm_up(tpe, id)
+    }
+}
+
+impl WriteBackend for HotFallbackBackend {
+    fn create(&self) -> Result<()> {
+        self.hot.create()
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> Result<()> {
+        self.hot.write_bytes(tpe, id, cacheable, buf)
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> Result<()> {
+        self.hot.remove(tpe, id, cacheable)
+    }
+}
+
+/// Let the hot repository read files it doesn't contain from the cold repository
+///
+/// # Arguments
+///
+/// * `backends` - the backends; nothing is changed if there is no hot repository
+pub(crate) fn with_hot_fallback(backends: RepositoryBackends) -> RepositoryBackends {
+    let cold = backends.repository();
+    let hot = backends.repo_hot().map(|hot| -> Arc<dyn WriteBackend> {
+        Arc::new(HotFallbackBackend {
+            hot,
+            cold: cold.clone(),
+        })
+    });
+    RepositoryBackends::new(cold, hot)
+}
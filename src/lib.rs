@@ -60,8 +60,14 @@ pub mod application;
 pub(crate) mod commands;
 pub(crate) mod config;
 pub(crate) mod error;
+pub(crate) mod events;
 pub(crate) mod filtering;
 pub(crate) mod helpers;
+pub(crate) mod hot_fallback;
+pub(crate) mod logging;
+pub(crate) mod output;
+pub(crate) mod retry;
+pub(crate) mod throttle;
 
 // rustic_cli Public API
 
@@ -0,0 +1,301 @@
+//! Structured log output
+//!
+//! With `--log-format json`, every log record is written as a single line of JSON containing the
+//! `timestamp`, `level`, `target` and `message`, e.g. for log aggregators.
+//!
+//! With `--log-syslog` (Unix) or `--log-eventlog` (Windows), log records are additionally sent to
+//! the system log.
+
+use std::{io::Write, sync::Mutex};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+use simplelog::{Config, SharedLogger};
+
+use crate::config::GlobalOptions;
+
+/// Format of log messages
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogFormat {
+    /// Human readable text
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// A log record, as written in JSON format
+#[derive(Debug, Serialize)]
+struct JsonRecord<'a> {
+    /// Time of the record
+    timestamp: DateTime<Local>,
+    /// Log level, e.g. `info`
+    level: &'a str,
+    /// Target of the record, i.e. the module which logged it
+    target: &'a str,
+    /// The message
+    message: String,
+}
+
+/// Format a log record as a line of JSON, including the trailing newline
+fn json_line(record: &Record<'_>, timestamp: DateTime<Local>) -> serde_json::Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(&JsonRecord {
+        timestamp,
+        level: &record.level().as_str().to_lowercase(),
+        target: record.target(),
+        message: record.args().to_string(),
+    })?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+/// A logger writing log records as JSON lines
+#[derive(Debug)]
+pub(crate) struct JsonLogger<W> {
+    /// The maximum level to log
+    level: LevelFilter,
+    /// The writer to write the records to
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send + 'static> JsonLogger<W> {
+    /// Create a new logger writing to the given writer
+    pub(crate) fn new(level: LevelFilter, writer: W) -> Box<Self> {
+        Box::new(Self {
+            level,
+            writer: Mutex::new(writer),
+        })
+    }
+}
+
+impl<W: Write + Send + 'static> Log for JsonLogger<W> {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        // each record is written at once, so records of different threads are not interleaved
+        if let (Ok(line), Ok(mut writer)) = (json_line(record, Local::now()), self.writer.lock()) {
+            _ = writer.write_all(&line);
+            _ = writer.flush();
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut writer) = self.writer.lock() {
+            _ = writer.flush();
+        }
+    }
+}
+
+impl<W: Write + Send + 'static> SharedLogger for JsonLogger<W> {
+    fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    fn config(&self) -> Option<&Config> {
+        None
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        self
+    }
+}
+
+/// Syslog facility
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyslogFacility {
+    /// Generic user-level messages
+    #[default]
+    User,
+    /// System daemons
+    Daemon,
+    /// Reserved for local use
+    Local0,
+    /// Reserved for local use
+    Local1,
+    /// Reserved for local use
+    Local2,
+    /// Reserved for local use
+    Local3,
+    /// Reserved for local use
+    Local4,
+    /// Reserved for local use
+    Local5,
+    /// Reserved for local use
+    Local6,
+    /// Reserved for local use
+    Local7,
+}
+
+#[cfg(unix)]
+impl From<SyslogFacility> for syslog::Facility {
+    fn from(facility: SyslogFacility) -> Self {
+        match facility {
+            SyslogFacility::User => Self::LOG_USER,
+            SyslogFacility::Daemon => Self::LOG_DAEMON,
+            SyslogFacility::Local0 => Self::LOG_LOCAL0,
+            SyslogFacility::Local1 => Self::LOG_LOCAL1,
+            SyslogFacility::Local2 => Self::LOG_LOCAL2,
+            SyslogFacility::Local3 => Self::LOG_LOCAL3,
+            SyslogFacility::Local4 => Self::LOG_LOCAL4,
+            SyslogFacility::Local5 => Self::LOG_LOCAL5,
+            SyslogFacility::Local6 => Self::LOG_LOCAL6,
+            SyslogFacility::Local7 => Self::LOG_LOCAL7,
+        }
+    }
+}
+
+/// A logger passing the records up to a given level to another logger
+#[derive(Debug)]
+pub(crate) struct LevelLogger<L> {
+    /// The maximum level to log
+    level: LevelFilter,
+    /// The logger to pass the records to
+    logger: L,
+}
+
+impl<L: Log + 'static> LevelLogger<L> {
+    /// Create a new logger passing records up to `level` to `logger`
+    pub(crate) fn new(level: LevelFilter, logger: L) -> Box<Self> {
+        Box::new(Self { level, logger })
+    }
+}
+
+impl<L: Log + 'static> Log for LevelLogger<L> {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= self.level && self.logger.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if self.enabled(record.metadata()) {
+            self.logger.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.logger.flush();
+    }
+}
+
+impl<L: Log + 'static> SharedLogger for LevelLogger<L> {
+    fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    fn config(&self) -> Option<&Config> {
+        None
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        self
+    }
+}
+
+/// Create the logger for the system log, if requested by `--log-syslog` or `--log-eventlog`
+///
+/// # Errors
+///
+/// * If the system log is not available on this platform or cannot be connected to
+pub(crate) fn system_logger(
+    global: &GlobalOptions,
+    level: LevelFilter,
+) -> Result<Option<Box<dyn SharedLogger>>> {
+    if global.log_syslog {
+        #[cfg(unix)]
+        {
+            let formatter = syslog::Formatter3164 {
+                facility: global.syslog_facility.unwrap_or_default().into(),
+                hostname: None,
+                process: "rustic".to_string(),
+                pid: std::process::id(),
+            };
+            let logger = syslog::unix(formatter)
+                .map_err(|err| anyhow!("error connecting to syslog: {err}"))?;
+            return Ok(Some(LevelLogger::new(
+                level,
+                syslog::BasicLogger::new(logger),
+            )));
+        }
+        #[cfg(not(unix))]
+        return Err(anyhow!("--log-syslog is only supported on Unix"));
+    }
+    if global.log_eventlog {
+        #[cfg(windows)]
+        {
+            let logger = eventlog::EventLog::new("rustic", log::Level::Trace)
+                .map_err(|err| anyhow!("error opening the event log: {err}"))?;
+            return Ok(Some(LevelLogger::new(level, logger)));
+        }
+        #[cfg(not(windows))]
+        return Err(anyhow!("--log-eventlog is only supported on Windows"));
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+    use log::Level;
+
+    #[test]
+    fn test_json_line_passes() -> serde_json::Result<()> {
+        let timestamp = Local.with_ymd_and_hms(2024, 1, 5, 12, 0, 0).unwrap();
+        let line = json_line(
+            &Record::builder()
+                .level(Level::Warn)
+                .target("rustic::commands")
+                .args(format_args!("a \"quoted\" message"))
+                .build(),
+            timestamp,
+        )?;
+        let line = String::from_utf8(line).unwrap();
+        assert!(line.ends_with(
+            r#""level":"warn","target":"rustic::commands","message":"a \"quoted\" message"}
+"#
+        ));
+        assert_eq!(line.lines().count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_level_logger_filters_passes() {
+        #[derive(Default)]
+        struct Counter(Mutex<usize>);
+        impl Log for Counter {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn log(&self, _: &Record<'_>) {
+                *self.0.lock().unwrap() += 1;
+            }
+            fn flush(&self) {}
+        }
+
+        let logger = LevelLogger::new(LevelFilter::Warn, Counter::default());
+        for level in [Level::Error, Level::Warn, Level::Info, Level::Debug] {
+            logger.log(
+                &Record::builder()
+                    .level(level)
+                    .args(format_args!(""))
+                    .build(),
+            );
+        }
+        assert_eq!(*logger.logger.0.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_system_logger_not_requested_passes() -> Result<()> {
+        assert!(system_logger(&GlobalOptions::default(), LevelFilter::Info)?.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+//! Output of commands
+//!
+//! Commands write their user-facing output (listings, tables, json) to an [`Output`] instead of
+//! printing to stdout directly, such that the output can be captured, e.g. in tests. Log messages
+//! are not part of the output.
+
+use std::io::{self, IsTerminal, Stdout, Write};
+
+use anyhow::Result;
+use comfy_table::Table;
+use serde::Serialize;
+
+/// Sink for the user-facing output of a command
+#[derive(Debug)]
+pub(crate) struct Output<W = Stdout> {
+    /// Where the output is written to
+    writer: W,
+    /// Whether the output is written to a terminal
+    is_terminal: bool,
+}
+
+impl Output {
+    /// Create an output writing to stdout
+    pub(crate) fn stdout() -> Self {
+        let stdout = io::stdout();
+        Self {
+            is_terminal: stdout.is_terminal(),
+            writer: stdout,
+        }
+    }
+}
+
+impl<W: Write> Output<W> {
+    /// Create an output writing to the given writer, which is not treated as terminal
+    pub(crate) fn new(writer: W) -> Self {
+        Self {
+            writer,
+            is_terminal: false,
+        }
+    }
+
+    /// Write a table, followed by a newline
+    ///
+    /// Tables are only styled and fitted to the terminal width if the output is a terminal.
+    pub(crate) fn table(&mut self, mut table: Table) -> Result<()> {
+        if !self.is_terminal {
+            _ = table.force_no_tty();
+        }
+        writeln!(self, "{table}")?;
+        Ok(())
+    }
+
+    /// Write a value in json format
+    pub(crate) fn json(&mut self, value: &impl Serialize) -> Result<()> {
+        serde_json::to_writer_pretty(&mut self.writer, value)?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
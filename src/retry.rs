@@ -0,0 +1,463 @@
+//! Retries for the backends
+//!
+//! Like the bandwidth limits, retries are applied by wrapping the backends of a repository. If
+//! enabled, each backend operation failing with a transient I/O error is retried with exponential
+//! backoff; every retry is logged as a warning. The rest, rclone and opendal backends have their
+//! own retries of failed requests, see their `retry` option.
+//!
+//! The backup command additionally retries the upload of each pack file, such that a single
+//! failed upload doesn't abort a long running backup, see [`retry_pack_uploads`].
+
+use std::{
+    io::{self, ErrorKind},
+    sync::{Arc, Mutex},
+    thread::sleep,
+    time::Duration,
+};
+
+use anyhow::Result;
+use bytes::Bytes;
+use humantime::format_duration;
+use log::warn;
+
+use rustic_core::{repofile::FileType, Id, ReadBackend, RepositoryBackends, WriteBackend};
+
+/// Default wait time before the first retry
+const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Number of retries of pack uploads during a backup if no backend retries are configured
+pub(crate) const DEFAULT_PACK_UPLOAD_RETRIES: usize = 3;
+
+/// Kinds of I/O errors which are transient, i.e. the operation may succeed when retried
+const TRANSIENT_ERRORS: [ErrorKind; 8] = [
+    ErrorKind::TimedOut,
+    ErrorKind::ConnectionReset,
+    ErrorKind::ConnectionAborted,
+    ErrorKind::ConnectionRefused,
+    ErrorKind::NotConnected,
+    ErrorKind::BrokenPipe,
+    ErrorKind::Interrupted,
+    ErrorKind::UnexpectedEof,
+];
+
+/// When and how often failed operations are retried
+#[derive(Clone, Copy, Debug)]
+struct RetryPolicy {
+    /// number of retries after the first attempt
+    retries: usize,
+    /// wait time before the first retry, doubled for each further retry
+    backoff: Duration,
+    /// maximum wait time between retries
+    max_backoff: Option<Duration>,
+}
+
+impl RetryPolicy {
+    /// Create a [`RetryPolicy`]
+    ///
+    /// # Arguments
+    ///
+    /// * `retries` - the number of retries
+    /// * `backoff` - the wait time before the first retry; `None` means the default of 1s
+    /// * `max_backoff` - the maximum wait time between retries
+    fn new(retries: usize, backoff: Option<Duration>, max_backoff: Option<Duration>) -> Self {
+        Self {
+            retries,
+            backoff: backoff.unwrap_or(DEFAULT_RETRY_BACKOFF),
+            max_backoff,
+        }
+    }
+
+    /// Run an operation, retrying it on errors
+    ///
+    /// # Arguments
+    ///
+    /// * `what` - description of the operation for log and error messages
+    /// * `op` - the operation
+    ///
+    /// # Errors
+    ///
+    /// * If the last attempt fails or the error is not transient, reporting the number of attempts
+    fn run<T>(&self, what: impl Fn() -> String, op: impl Fn() -> Result<T>) -> Result<T> {
+        let attempts = self.retries + 1;
+        let mut backoff = self.backoff;
+        let mut attempt = 1;
+        loop {
+            match op() {
+                Ok(result) => return Ok(result),
+                Err(err) if attempt < attempts && is_transient(&err) => {
+                    warn!(
+                        "{} failed (attempt {attempt} of {attempts}): {err:#}, retrying in {}",
+                        what(),
+                        format_duration(backoff)
+                    );
+                    sleep(backoff);
+                    backoff = backoff.saturating_mul(2);
+                    if let Some(max_backoff) = self.max_backoff {
+                        backoff = backoff.min(max_backoff);
+                    }
+                    attempt += 1;
+                }
+                Err(err) => {
+                    return Err(err.context(format!("{} failed after {attempt} attempt(s)", what())))
+                }
+            }
+        }
+    }
+}
+
+/// A backend retrying failed operations
+struct RetryBackend {
+    /// the wrapped backend
+    be: Arc<dyn WriteBackend>,
+    /// how failed operations are retried
+    policy: RetryPolicy,
+}
+
+impl RetryBackend {
+    /// Run an operation on the wrapped backend, retrying it on errors, see [`RetryPolicy::run`]
+    fn retry<T>(
+        &self,
+        what: impl Fn() -> String,
+        op: impl Fn(&dyn WriteBackend) -> Result<T>,
+    ) -> Result<T> {
+        self.policy.run(what, || op(self.be.as_ref()))
+    }
+}
+
+impl ReadBackend for RetryBackend {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> Result<Vec<(Id, u32)>> {
+        self.retry(
+            || format!("listing {tpe:?} files"),
+            |be| be.list_with_size(tpe),
+        )
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> Result<Bytes> {
+        self.retry(
+            || format!("reading {tpe:?} file {id}"),
+            |be| be.read_full(tpe, id),
+        )
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> Result<Bytes> {
+        self.retry(
+            || format!("reading {tpe:?} file {id}"),
+            |be| be.read_partial(tpe, id, cacheable, offset, length),
+        )
+    }
+
+    fn needs_warm_up(&self) -> bool {
+        self.be.needs_warm_up()
+    }
+
+    fn warm_up(&self, tpe: FileType, id: &Id) -> Result<()> {
+        self.retry(
+            || format!("warming up {tpe:?} file {id}"),
+            |be| be.warm_up(tpe, id),
+        )
+    }
+}
+
+impl WriteBackend for RetryBackend {
+    fn create(&self) -> Result<()> {
+        self.retry(|| "creating the repository".to_string(), |be| be.create())
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> Result<()> {
+        self.retry(
+            || format!("writing {tpe:?} file {id}"),
+            |be| be.write_bytes(tpe, id, cacheable, buf.clone()),
+        )
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> Result<()> {
+        self.retry(
+            || format!("removing {tpe:?} file {id}"),
+            |be| be.remove(tpe, id, cacheable),
+        )
+    }
+}
+
+/// A backend retrying failed uploads of pack files and recording the packs which finally failed
+struct PackUploadBackend {
+    /// the wrapped backend
+    be: Arc<dyn WriteBackend>,
+    /// how failed uploads are retried
+    policy: RetryPolicy,
+    /// the packs which couldn't be uploaded
+    failed: Arc<Mutex<Vec<Id>>>,
+}
+
+impl ReadBackend for PackUploadBackend {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> Result<Vec<(Id, u32)>> {
+        self.be.list_with_size(tpe)
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> Result<Bytes> {
+        self.be.read_full(tpe, id)
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> Result<Bytes> {
+        self.be.read_partial(tpe, id, cacheable, offset, length)
+    }
+
+    fn needs_warm_up(&self) -> bool {
+        self.be.needs_warm_up()
+    }
+
+    fn warm_up(&self, tpe: FileType, id: &Id) -> Result<()> {
+        self.be.warm_up(tpe, id)
+    }
+}
+
+impl WriteBackend for PackUploadBackend {
+    fn create(&self) -> Result<()> {
+        self.be.create()
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> Result<()> {
+        if tpe != FileType::Pack {
+            return self.be.write_bytes(tpe, id, cacheable, buf);
+        }
+        self.policy
+            .run(
+                || format!("uploading pack {id}"),
+                || self.be.write_bytes(tpe, id, cacheable, buf.clone()),
+            )
+            .inspect_err(|_| {
+                if let Ok(mut failed) = self.failed.lock() {
+                    failed.push(*id);
+                }
+            })
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> Result<()> {
+        self.be.remove(tpe, id, cacheable)
+    }
+}
+
+/// Check if an error is transient, i.e. the operation may succeed when retried
+///
+/// Only I/O errors like timeouts or connection resets are transient. All other errors, e.g. a
+/// missing file, a failed authentication or a malformed response, are not retried.
+fn is_transient(err: &anyhow::Error) -> bool {
+    err.chain()
+        .find_map(|cause| cause.downcast_ref::<io::Error>())
+        .is_some_and(|err| TRANSIENT_ERRORS.contains(&err.kind()))
+}
+
+/// Retry failed operations of the backends
+///
+/// The options apply to the repository and the hot repository.
+///
+/// # Arguments
+///
+/// * `backends` - the backends to retry operations of
+/// * `retries` - the number of retries; `None` means no retries
+/// * `backoff` - the wait time before the first retry, doubled for each further retry
+/// * `max_backoff` - the maximum wait time between retries
+pub(crate) fn retry_backends(
+    backends: RepositoryBackends,
+    retries: Option<usize>,
+    backoff: Option<Duration>,
+    max_backoff: Option<Duration>,
+) -> RepositoryBackends {
+    let retries = retries.unwrap_or_default();
+    if retries == 0 {
+        return backends;
+    }
+    let policy = RetryPolicy::new(retries, backoff, max_backoff);
+    let retry = |be: Arc<dyn WriteBackend>| -> Arc<dyn WriteBackend> {
+        Arc::new(RetryBackend { be, policy })
+    };
+    RepositoryBackends::new(retry(backends.repository()), backends.repo_hot().map(retry))
+}
+
+/// Retry failed uploads of pack files to the repository and record the packs which finally failed
+///
+/// Pack files are only written to the repository, the hot repository is not wrapped.
+///
+/// # Arguments
+///
+/// * `backends` - the backends to retry pack uploads of
+/// * `retries` - the number of retries
+/// * `backoff` - the wait time before the first retry, doubled for each further retry
+/// * `max_backoff` - the maximum wait time between retries
+/// * `failed` - the list to add the packs which couldn't be uploaded to
+pub(crate) fn retry_pack_uploads(
+    backends: RepositoryBackends,
+    retries: usize,
+    backoff: Option<Duration>,
+    max_backoff: Option<Duration>,
+    failed: &Arc<Mutex<Vec<Id>>>,
+) -> RepositoryBackends {
+    RepositoryBackends::new(
+        Arc::new(PackUploadBackend {
+            be: backends.repository(),
+            policy: RetryPolicy::new(retries, backoff, max_backoff),
+            failed: failed.clone(),
+        }),
+        backends.repo_hot(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use anyhow::anyhow;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    /// A backend whose reads and writes fail a given number of times and whose removes always fail
+    struct FailingBackend {
+        failures: AtomicUsize,
+    }
+
+    impl FailingBackend {
+        fn fail(&self) -> Result<()> {
+            let remaining = self.failures.load(Ordering::SeqCst);
+            if remaining > 0 {
+                self.failures.store(remaining - 1, Ordering::SeqCst);
+                return Err(io::Error::from(ErrorKind::ConnectionReset).into());
+            }
+            Ok(())
+        }
+    }
+
+    impl ReadBackend for FailingBackend {
+        fn location(&self) -> String {
+            "failing".to_string()
+        }
+
+        fn list_with_size(&self, _tpe: FileType) -> Result<Vec<(Id, u32)>> {
+            Ok(Vec::new())
+        }
+
+        fn read_full(&self, _tpe: FileType, _id: &Id) -> Result<Bytes> {
+            self.fail()?;
+            Ok(Bytes::from_static(b"data"))
+        }
+
+        fn read_partial(
+            &self,
+            _tpe: FileType,
+            _id: &Id,
+            _cacheable: bool,
+            _offset: u32,
+            _length: u32,
+        ) -> Result<Bytes> {
+            Ok(Bytes::new())
+        }
+    }
+
+    impl WriteBackend for FailingBackend {
+        fn create(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn write_bytes(
+            &self,
+            _tpe: FileType,
+            _id: &Id,
+            _cacheable: bool,
+            _buf: Bytes,
+        ) -> Result<()> {
+            self.fail()
+        }
+
+        fn remove(&self, _tpe: FileType, _id: &Id, _cacheable: bool) -> Result<()> {
+            Err(io::Error::from(ErrorKind::NotFound).into())
+        }
+    }
+
+    fn failing_backend(failures: usize) -> Arc<dyn WriteBackend> {
+        Arc::new(FailingBackend {
+            failures: AtomicUsize::new(failures),
+        })
+    }
+
+    fn retry_backend(failures: usize, retries: usize) -> RetryBackend {
+        RetryBackend {
+            be: failing_backend(failures),
+            policy: RetryPolicy::new(retries, Some(Duration::from_millis(1)), None),
+        }
+    }
+
+    #[test]
+    fn test_retry_passes() -> Result<()> {
+        let be = retry_backend(2, 2);
+        assert_eq!(be.read_full(FileType::Pack, &Id::default())?, "data");
+        Ok(())
+    }
+
+    #[test]
+    fn test_retry_reports_attempts_fails() {
+        let be = retry_backend(3, 2);
+        let err = be.read_full(FileType::Pack, &Id::default()).unwrap_err();
+        assert!(err.to_string().ends_with("failed after 3 attempt(s)"));
+    }
+
+    #[test]
+    fn test_permanent_error_is_not_retried_fails() {
+        let be = retry_backend(0, 2);
+        let err = be
+            .remove(FileType::Pack, &Id::default(), false)
+            .unwrap_err();
+        assert!(err.to_string().ends_with("failed after 1 attempt(s)"));
+    }
+
+    #[rstest]
+    #[case(2, true)]
+    #[case(3, false)]
+    fn test_pack_upload_records_failed_packs_passes(
+        #[case] failures: usize,
+        #[case] succeeds: bool,
+    ) {
+        let failed = Arc::new(Mutex::new(Vec::new()));
+        let be = PackUploadBackend {
+            be: failing_backend(failures),
+            policy: RetryPolicy::new(2, Some(Duration::from_millis(1)), None),
+            failed: failed.clone(),
+        };
+        let id = Id::random();
+        let result = be.write_bytes(FileType::Pack, &id, false, Bytes::new());
+        assert_eq!(result.is_ok(), succeeds);
+        let expected = if succeeds { Vec::new() } else { vec![id] };
+        assert_eq!(*failed.lock().unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case(io::Error::from(ErrorKind::TimedOut).into(), true)]
+    #[case(io::Error::from(ErrorKind::ConnectionReset).into(), true)]
+    #[case(anyhow::Error::from(io::Error::from(ErrorKind::NotFound)).context("reading"), false)]
+    #[case(io::Error::from(ErrorKind::PermissionDenied).into(), false)]
+    #[case(anyhow!("HTTP status 401"), false)]
+    fn test_is_transient_passes(#[case] err: anyhow::Error, #[case] expected: bool) {
+        assert_eq!(is_transient(&err), expected);
+    }
+}
@@ -0,0 +1,238 @@
+//! Bandwidth limits for the backends
+//!
+//! The limits are applied by wrapping the backends of a repository, i.e. they apply to the data
+//! transferred by the backend (network) layer and not to reading or writing local files, e.g. the
+//! backup sources or restore destinations. Downloads are throttled after the data has been read,
+//! uploads before the data is written, such that the average rate doesn't exceed the limit.
+
+use std::{fmt, num::NonZeroU32, str::FromStr, sync::Arc, thread::sleep, time::Duration};
+
+use anyhow::{Error, Result};
+use bytes::Bytes;
+use bytesize::ByteSize;
+use governor::{
+    clock::{Clock, DefaultClock},
+    middleware::NoOpMiddleware,
+    state::{InMemoryState, NotKeyed},
+    Quota, RateLimiter,
+};
+
+use rustic_core::{repofile::FileType, Id, ReadBackend, RepositoryBackends, WriteBackend};
+
+/// A transfer rate in bytes per second, e.g. "5MiB/s"; 0 means unlimited
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rate(pub u64);
+
+impl FromStr for Rate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let size = s.trim().trim_end_matches("/s");
+        let size = ByteSize::from_str(size).map_err(Error::msg)?;
+        Ok(Self(size.as_u64()))
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/s", ByteSize(self.0).to_string_as(true))
+    }
+}
+
+/// Rate limiter for a transfer direction
+struct Limiter<C: Clock = DefaultClock> {
+    /// the rate limiter with one cell per byte
+    limiter: RateLimiter<NotKeyed, InMemoryState, C, NoOpMiddleware<C::Instant>>,
+    /// the clock of the rate limiter
+    clock: C,
+    /// the maximum number of bytes which can be acquired at once
+    burst: NonZeroU32,
+}
+
+impl Limiter {
+    /// Create a limiter for the given rate; `None` if the rate is unlimited
+    fn new(rate: Option<Rate>) -> Option<Self> {
+        Self::with_clock(rate, DefaultClock::default())
+    }
+
+    /// Wait until `bytes` may be transferred
+    fn wait(&self, bytes: usize) {
+        self.wait_with(bytes, sleep);
+    }
+}
+
+impl<C: Clock> Limiter<C> {
+    /// Create a limiter for the given rate using the given clock; `None` if the rate is unlimited
+    fn with_clock(rate: Option<Rate>, clock: C) -> Option<Self> {
+        let rate = rate?.0;
+        // rates beyond u32::MAX bytes per second are not limited more precisely
+        let burst = NonZeroU32::new(rate.try_into().unwrap_or(u32::MAX))?;
+        Some(Self {
+            limiter: RateLimiter::direct_with_clock(Quota::per_second(burst), &clock),
+            clock,
+            burst,
+        })
+    }
+
+    /// Wait until `bytes` may be transferred, using `sleep` to wait for the given durations
+    fn wait_with(&self, bytes: usize, mut sleep: impl FnMut(Duration)) {
+        let mut remaining = bytes;
+        while remaining > 0 {
+            let n = remaining.min(self.burst.get() as usize);
+            remaining -= n;
+            // n is positive and at most burst
+            let n = NonZeroU32::new(n.try_into().unwrap_or(u32::MAX)).unwrap_or(self.burst);
+            while let Ok(Err(not_until)) = self.limiter.check_n(n) {
+                sleep(not_until.wait_time_from(self.clock.now()));
+            }
+        }
+    }
+}
+
+/// A backend with limited download and/or upload rate
+struct ThrottledBackend {
+    /// the wrapped backend
+    be: Arc<dyn WriteBackend>,
+    /// limiter for downloads
+    download: Option<Arc<Limiter>>,
+    /// limiter for uploads
+    upload: Option<Arc<Limiter>>,
+}
+
+impl ThrottledBackend {
+    /// Wait after `bytes` have been downloaded
+    fn downloaded(&self, bytes: &Bytes) {
+        if let Some(limiter) = &self.download {
+            limiter.wait(bytes.len());
+        }
+    }
+}
+
+impl ReadBackend for ThrottledBackend {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> Result<Vec<(Id, u32)>> {
+        self.be.list_with_size(tpe)
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> Result<Bytes> {
+        let data = self.be.read_full(tpe, id)?;
+        self.downloaded(&data);
+        Ok(data)
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> Result<Bytes> {
+        let data = self.be.read_partial(tpe, id, cacheable, offset, length)?;
+        self.downloaded(&data);
+        Ok(data)
+    }
+
+    fn needs_warm_up(&self) -> bool {
+        self.be.needs_warm_up()
+    }
+
+    fn warm_up(&self, tpe: FileType, id: &Id) -> Result<()> {
+        self.be.warm_up(tpe, id)
+    }
+}
+
+impl WriteBackend for ThrottledBackend {
+    fn create(&self) -> Result<()> {
+        self.be.create()
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> Result<()> {
+        if let Some(limiter) = &self.upload {
+            limiter.wait(buf.len());
+        }
+        self.be.write_bytes(tpe, id, cacheable, buf)
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> Result<()> {
+        self.be.remove(tpe, id, cacheable)
+    }
+}
+
+/// Limit the download and upload rate of the backends
+///
+/// The limits are shared by the repository and the hot repository.
+///
+/// # Arguments
+///
+/// * `backends` - the backends to limit
+/// * `download` - the download limit; `None` or 0 means unlimited
+/// * `upload` - the upload limit; `None` or 0 means unlimited
+pub(crate) fn limit_backends(
+    backends: RepositoryBackends,
+    download: Option<Rate>,
+    upload: Option<Rate>,
+) -> RepositoryBackends {
+    let download = Limiter::new(download).map(Arc::new);
+    let upload = Limiter::new(upload).map(Arc::new);
+    if download.is_none() && upload.is_none() {
+        return backends;
+    }
+    let throttle = |be: Arc<dyn WriteBackend>| -> Arc<dyn WriteBackend> {
+        Arc::new(ThrottledBackend {
+            be,
+            download: download.clone(),
+            upload: upload.clone(),
+        })
+    };
+    RepositoryBackends::new(
+        throttle(backends.repository()),
+        backends.repo_hot().map(throttle),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use governor::clock::FakeRelativeClock;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("5MiB/s", 5 * 1024 * 1024)]
+    #[case("100kB", 100_000)]
+    #[case("0", 0)]
+    fn test_parse_rate_passes(#[case] rate: &str, #[case] expected: u64) {
+        assert_eq!(rate.parse::<Rate>().unwrap(), Rate(expected));
+    }
+
+    #[test]
+    fn test_parse_rate_fails() {
+        assert!("fast".parse::<Rate>().is_err());
+    }
+
+    #[test]
+    fn test_limiter_passes() {
+        assert!(Limiter::new(None).is_none());
+        assert!(Limiter::new(Some(Rate(0))).is_none());
+
+        let clock = FakeRelativeClock::default();
+        let limiter = Limiter::with_clock(Some(Rate(1000)), clock.clone()).unwrap();
+
+        // the first second is available as burst
+        let mut waited = Vec::new();
+        limiter.wait_with(1000, |duration| waited.push(duration));
+        assert!(waited.is_empty());
+
+        // more than the burst is acquired in parts, each waiting for its share of the rate
+        limiter.wait_with(1500, |duration| {
+            waited.push(duration);
+            clock.advance(duration);
+        });
+        assert_eq!(waited, [Duration::from_secs(1), Duration::from_millis(500)]);
+    }
+}
@@ -138,3 +138,1762 @@ fn test_backup_and_restore_passes() -> TestResult<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_restore_with_include_pattern_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let restore_dir = temp_dir.path().join("restore");
+    let backup = "src/";
+
+    // actual repository root to backup
+    let backup_files = std::env::current_dir()?.join(backup);
+
+    {
+        // Run `backup`
+        rustic_runner(&temp_dir)?
+            .arg("backup")
+            .arg(&backup_files)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("successfully saved."));
+    }
+    {
+        // Run `restore` only for a nested subtree
+        rustic_runner(&temp_dir)?
+            .arg("restore")
+            .args(["--include", "**/commands/restore"])
+            .arg("latest")
+            .arg(&restore_dir)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("restore done"));
+    }
+
+    let restored = restore_dir.join(backup_files.strip_prefix("/")?);
+
+    // the matching subtree is restored completely
+    let compare_result = Comparison::default().compare(
+        &backup_files.join("commands/restore"),
+        &restored.join("commands/restore"),
+    )?;
+    assert!(compare_result.is_empty());
+
+    // unmatched siblings are absent
+    assert!(restored.join("commands").is_dir());
+    assert!(!restored.join("commands/restore.rs").exists());
+    assert!(!restored.join("commands/backup.rs").exists());
+    assert!(!restored.join("lib.rs").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_restore_without_matching_pattern_fails() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let restore_dir = temp_dir.path().join("restore");
+    let backup = "src/";
+
+    // actual repository root to backup
+    let backup_files = std::env::current_dir()?.join(backup);
+
+    {
+        // Run `backup`
+        rustic_runner(&temp_dir)?
+            .arg("backup")
+            .arg(&backup_files)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("successfully saved."));
+    }
+    {
+        // Run `restore` with a pattern matching nothing
+        rustic_runner(&temp_dir)?
+            .arg("restore")
+            .args(["--include", "**/*.does-not-exist"])
+            .arg("latest")
+            .arg(&restore_dir)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("no entries match"));
+    }
+
+    assert!(!restore_dir.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_restore_with_delete_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let restore_dir = temp_dir.path().join("restore");
+    let backup = "src/";
+
+    // actual repository root to backup
+    let backup_files = std::env::current_dir()?.join(backup);
+
+    {
+        // Run `backup`
+        rustic_runner(&temp_dir)?
+            .arg("backup")
+            .arg(&backup_files)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("successfully saved."));
+    }
+
+    // add entries to the restore destination which are not in the snapshot
+    let restored = restore_dir.join(backup_files.strip_prefix("/")?);
+    std::fs::create_dir_all(restored.join("commands"))?;
+    std::fs::write(restored.join("extra.txt"), "extra")?;
+    std::fs::write(restored.join("commands/extra.txt"), "extra")?;
+
+    {
+        // Run `restore --delete` in dry-run mode
+        rustic_runner(&temp_dir)?
+            .args(["restore", "--dry-run", "--delete"])
+            .arg("latest")
+            .arg(&restore_dir)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("would delete:"))
+            .stdout(predicate::str::contains("extra.txt"));
+    }
+    assert!(restored.join("extra.txt").exists());
+
+    {
+        // Run `restore --delete` only for the `commands` subtree
+        rustic_runner(&temp_dir)?
+            .args(["restore", "--delete", "--include", "**/commands"])
+            .arg("latest")
+            .arg(&restore_dir)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("restore done"));
+    }
+    // entries outside of the included subtree are not deleted
+    assert!(restored.join("extra.txt").exists());
+    assert!(!restored.join("commands/extra.txt").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_restore_in_place_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let restore_dir = temp_dir.path().join("restore");
+    let backup = "src/";
+
+    // actual repository root to backup
+    let backup_files = std::env::current_dir()?.join(backup);
+
+    {
+        // Run `backup`
+        rustic_runner(&temp_dir)?
+            .arg("backup")
+            .arg(&backup_files)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("successfully saved."));
+    }
+    {
+        // Run `restore` twice, the second run skips all files
+        rustic_runner(&temp_dir)?
+            .args(["restore", "latest"])
+            .arg(&restore_dir)
+            .assert()
+            .success();
+        rustic_runner(&temp_dir)?
+            .args(["restore", "latest"])
+            .arg(&restore_dir)
+            .assert()
+            .success()
+            .stderr(predicate::str::contains(
+                "files which already match the snapshot",
+            ));
+    }
+    {
+        // Run `restore --no-in-place`, which rewrites all files
+        rustic_runner(&temp_dir)?
+            .args(["restore", "--no-in-place", "latest"])
+            .arg(&restore_dir)
+            .assert()
+            .success()
+            .stderr(predicate::str::contains("existing files to rewrite them"));
+    }
+
+    // Compare the backup and the restored directory
+    let compare_result =
+        Comparison::default().compare(&backup_files, &restore_dir.join(&backup_files))?;
+    assert!(compare_result.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_restore_verify_only_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let restore_dir = temp_dir.path().join("restore");
+    let backup = "src/";
+
+    // actual repository root to backup
+    let backup_files = std::env::current_dir()?.join(backup);
+
+    {
+        // Run `backup`
+        rustic_runner(&temp_dir)?
+            .arg("backup")
+            .arg(&backup_files)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("successfully saved."));
+    }
+    {
+        // Run `restore --verify`
+        rustic_runner(&temp_dir)?
+            .args(["restore", "--verify"])
+            .arg("latest")
+            .arg(&restore_dir)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("verification successful."));
+    }
+
+    // modify a restored file without changing its size
+    let restored = restore_dir.join(backup_files.strip_prefix("/")?.join("lib.rs"));
+    let mut content = std::fs::read(&restored)?;
+    content[0] ^= 1;
+    std::fs::write(&restored, content)?;
+
+    {
+        // Run `restore --verify-only`, which reports the modified file
+        rustic_runner(&temp_dir)?
+            .args(["restore", "--verify-only"])
+            .arg("latest")
+            .arg(&restore_dir)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("lib.rs"))
+            .stderr(predicate::str::contains("1 files don't match the snapshot"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_prune_with_foreign_locks_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+
+    // simulate a lock file of another client
+    let locks = temp_dir.path().join("repo").join("locks");
+    std::fs::create_dir_all(&locks)?;
+    std::fs::write(locks.join("0123456789abcdef"), "{}")?;
+
+    rustic_runner(&temp_dir)?
+        .arg("prune")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("lock files of other clients"));
+
+    rustic_runner(&temp_dir)?
+        .args(["prune", "--ignore-foreign-locks"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("ignoring 1 lock files"));
+
+    Ok(())
+}
+
+#[test]
+fn test_prune_with_grace_period_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    rustic_runner(&temp_dir)?
+        .args(["backup", "src/"])
+        .assert()
+        .success();
+
+    // the grace period is opt-in
+    rustic_runner(&temp_dir)?
+        .arg("prune")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("grace period").not());
+
+    rustic_runner(&temp_dir)?
+        .args(["prune", "--grace-period", "1h"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "have been written within the grace period of 1h",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_prune_below_thresholds_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+
+    // a fresh repository has no unused data, so pruning is skipped
+    rustic_runner(&temp_dir)?
+        .args([
+            "prune",
+            "--max-unused-size",
+            "1GiB",
+            "--max-unused-percent",
+            "10",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("no pruning needed"));
+
+    rustic_runner(&temp_dir)?
+        .args(["prune", "--max-unused-percent", "101"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("must be within 0-100"));
+
+    Ok(())
+}
+
+#[test]
+fn test_prune_dry_run_report_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    rustic_runner(&temp_dir)?
+        .args(["backup", "src/"])
+        .assert()
+        .success();
+
+    rustic_runner(&temp_dir)?
+        .args(["--dry-run", "prune", "--verbose"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("dry-run summary:"))
+        .stdout(predicate::str::contains(
+            "rewrite selection: packs containing unused data",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_check_with_tiny_memory_budget_passes() -> TestResult<()> {
+    let temp_dir = tempdir()?;
+    rustic_runner(&temp_dir)?
+        .args(["init", "--set-datapack-size-limit", "64kiB"])
+        .assert()
+        .success();
+
+    {
+        // Run `backup`, creating multiple packs
+        rustic_runner(&temp_dir)?
+            .args(["backup", "src/"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("successfully saved."));
+    }
+
+    {
+        // Run `check --read-data` with a budget too small for a single pack
+        rustic_runner(&temp_dir)?
+            .args(["check", "--read-data", "--max-memory", "1kiB"])
+            .assert()
+            .success()
+            .stderr(predicate::str::contains(
+                "reading up to 1 packs concurrently",
+            ))
+            .stderr(predicate::str::contains("ERROR").not());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_check_read_data_subset_passes() -> TestResult<()> {
+    let temp_dir = tempdir()?;
+    rustic_runner(&temp_dir)?
+        .args(["init", "--set-datapack-size-limit", "64kiB"])
+        .assert()
+        .success();
+
+    {
+        // Run `backup`, creating multiple packs
+        rustic_runner(&temp_dir)?
+            .args(["backup", "src/"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("successfully saved."));
+    }
+
+    {
+        // Run `check --read-data-subset` reading all packs
+        rustic_runner(&temp_dir)?
+            .args(["check", "--read-data-subset", "100%"])
+            .assert()
+            .success()
+            .stderr(predicate::str::contains("ERROR").not());
+    }
+
+    {
+        // Run `check --read-data-subset` reading the packs in tiny chunks
+        rustic_runner(&temp_dir)?
+            .args([
+                "check",
+                "--read-data-subset",
+                "100%",
+                "--max-memory",
+                "1kiB",
+            ])
+            .assert()
+            .success()
+            .stderr(predicate::str::contains("0 damaged."));
+    }
+
+    {
+        // Run `check --read-data-subset` with a bucket containing all packs
+        rustic_runner(&temp_dir)?
+            .args(["check", "--read-data-subset", "1/1"])
+            .args(["--read-data-subset-seed", "3"])
+            .assert()
+            .success()
+            .stderr(predicate::str::contains("0 damaged."))
+            .stdout(predicate::str::contains("no errors found."));
+    }
+
+    // damage a pack file
+    let packs = temp_dir.path().join("repo").join("data");
+    let pack = walkdir(&packs)?
+        .into_iter()
+        .next()
+        .ok_or("no pack file found")?;
+    let id = pack
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or("invalid pack file name")?
+        .to_string();
+    let mut content = std::fs::read(&pack)?;
+    content[0] ^= 1;
+    std::fs::write(&pack, content)?;
+
+    {
+        rustic_runner(&temp_dir)?
+            .args(["check", "--read-data-subset", "100"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("hash mismatch"));
+    }
+
+    {
+        // errors are reported with exit code 1
+        rustic_runner(&temp_dir)?
+            .args(["check", "--read-data-subset", "100", "--json"])
+            .assert()
+            .code(1)
+            .stdout(predicate::str::contains("\"ok\": false"))
+            .stdout(predicate::str::contains("\"kind\": \"pack-hash-mismatch\""))
+            .stdout(predicate::str::contains(format!("\"id\": \"{id}\"")));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_copy_with_modified_snapshots_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    rustic_runner(&temp_dir)?
+        .args(["backup", "--host", "source", "src/"])
+        .assert()
+        .success();
+
+    let target_repo = temp_dir.path().join("target");
+    let target_profile = temp_dir.path().join("target-profile");
+    std::fs::write(
+        target_profile.with_extension("toml"),
+        format!("[repository]\nrepository = {target_repo:?}\npassword = \"test\"\n"),
+    )?;
+
+    let read_snapshots = || -> TestResult<Vec<_>> {
+        let mut snapshots = Vec::new();
+        for path in walkdir(&temp_dir.path().join("repo").join("snapshots"))? {
+            let content = std::fs::read(&path)?;
+            snapshots.push((path, content));
+        }
+        Ok(snapshots)
+    };
+    let before = read_snapshots()?;
+    let id = before[0]
+        .0
+        .file_name()
+        .ok_or("no snapshot file")?
+        .to_string_lossy()
+        .to_string();
+
+    let copy = || -> TestResult<Command> {
+        let mut runner = rustic_runner(&temp_dir)?;
+        _ = runner
+            .args(["copy", "--init", "--filter-host", "source", "--target"])
+            .arg(&target_profile)
+            .args(["--add-tag", "copied", "--set-hostname", "offsite"]);
+        Ok(runner)
+    };
+    copy()?.assert().success();
+
+    // the source snapshots are untouched
+    assert_eq!(read_snapshots()?, before);
+
+    Command::new(env!("CARGO_BIN_EXE_rustic"))
+        .arg("-r")
+        .arg(&target_repo)
+        .args(["--password", "test", "--no-progress", "snapshots", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"hostname\": \"offsite\""))
+        .stdout(predicate::str::contains("\"copied\""))
+        .stdout(predicate::str::contains(format!("\"original\": \"{id}\"")));
+
+    // the modified copies don't match the filter, but are still found as existing
+    copy()?
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("nothing to copy."));
+
+    Ok(())
+}
+
+#[test]
+fn test_repair_needs_confirmation_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    rustic_runner(&temp_dir)?
+        .args(["backup", "src/"])
+        .assert()
+        .success();
+
+    rustic_runner(&temp_dir)?
+        .args(["repair", "index"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--yes-really"));
+    rustic_runner(&temp_dir)?
+        .args(["--dry-run", "repair", "index"])
+        .assert()
+        .success();
+    rustic_runner(&temp_dir)?
+        .args(["repair", "index", "--yes-really"])
+        .assert()
+        .success();
+
+    rustic_runner(&temp_dir)?
+        .args(["repair", "snapshots", "--delete"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--yes-really"));
+    rustic_runner(&temp_dir)?
+        .args(["--dry-run", "repair", "snapshots", "--delete"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("0 of 1 snapshots are damaged."));
+
+    Ok(())
+}
+
+#[test]
+fn test_log_rotation_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let log_file = temp_dir.path().join("rustic.log");
+    let log = |args: &[&str]| -> TestResult<()> {
+        rustic_runner(&temp_dir)?
+            .arg("--log-file")
+            .arg(&log_file)
+            .args(args)
+            .arg("snapshots")
+            .assert()
+            .success();
+        Ok(())
+    };
+
+    log(&[])?;
+    // the log file is rotated at startup
+    log(&["--log-max-files", "1"])?;
+    log(&["--log-max-files", "1"])?;
+    assert!(log_file.exists());
+    assert!(temp_dir.path().join("rustic.log.1").exists());
+    assert!(!temp_dir.path().join("rustic.log.2").exists());
+
+    rustic_runner(&temp_dir)?
+        .args(["--log-max-size", "1MiB", "snapshots"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("require log-file"));
+
+    Ok(())
+}
+
+#[test]
+fn test_event_stream_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let events = temp_dir.path().join("events.ndjson");
+
+    rustic_runner(&temp_dir)?
+        .arg("--event-stream")
+        .arg(&events)
+        .args(["backup", "src/"])
+        .assert()
+        .success();
+
+    let events = std::fs::read_to_string(events)?;
+    let lines: Vec<_> = events.lines().collect();
+    assert!(lines
+        .iter()
+        .all(|line| line.starts_with("{\"schema\":1,\"time\":")));
+    assert!(lines[0].ends_with("\"event\":\"command_started\",\"command\":\"backup\"}"));
+    assert!(lines
+        .iter()
+        .any(|line| line.contains("\"event\":\"repository_opened\"")));
+    assert!(lines
+        .iter()
+        .any(|line| line.contains("\"event\":\"phase_finished\"")));
+    assert!(lines
+        .iter()
+        .any(|line| line.contains("\"event\":\"summary\",\"command\":\"backup\"")));
+    assert!(lines[lines.len() - 1].ends_with("\"event\":\"finished\",\"exit_code\":0}"));
+
+    Ok(())
+}
+
+/// List all files below the given directory
+fn walkdir(dir: &std::path::Path) -> TestResult<Vec<std::path::PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(walkdir(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_restore_with_user_mapping_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let restore_dir = temp_dir.path().join("restore");
+    let backup = "src/";
+
+    // actual repository root to backup
+    let backup_files = std::env::current_dir()?.join(backup);
+    let uid = nix::unistd::geteuid();
+
+    {
+        // Run `backup`
+        rustic_runner(&temp_dir)?
+            .arg("backup")
+            .arg(&backup_files)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("successfully saved."));
+    }
+    {
+        // Run `restore` mapping the current user to itself
+        rustic_runner(&temp_dir)?
+            .args(["restore", "--numeric-uid-gid", "--map-user"])
+            .arg(format!("{uid}={uid}"))
+            .arg("latest")
+            .arg(&restore_dir)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("restore done"))
+            .stderr(predicate::str::contains("has not been restored").not());
+    }
+
+    // Compare the backup and the restored directory
+    let compare_result =
+        Comparison::default().compare(&backup_files, &restore_dir.join(&backup_files))?;
+    assert!(compare_result.is_empty());
+
+    if !uid.is_root() {
+        // Run `restore` mapping the current user to another user
+        rustic_runner(&temp_dir)?
+            .args(["restore", "--map-user"])
+            .arg(format!("{uid}={}", uid.as_raw() + 1))
+            .arg("latest")
+            .arg(&restore_dir)
+            .assert()
+            .success()
+            .stderr(predicate::str::contains("has not been restored"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_output_path_validation_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let backup = "src/";
+
+    // actual repository root to backup
+    let backup_files = std::env::current_dir()?.join(backup);
+
+    {
+        // Run `backup`
+        rustic_runner(&temp_dir)?
+            .arg("backup")
+            .arg(&backup_files)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("successfully saved."));
+    }
+
+    let nested_dir = temp_dir.path().join("missing").join("restore");
+    {
+        // `restore` doesn't create missing parents without --create-parents
+        rustic_runner(&temp_dir)?
+            .args(["restore", "latest"])
+            .arg(&nested_dir)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("use --create-parents"));
+        rustic_runner(&temp_dir)?
+            .args(["restore", "--create-parents", "latest"])
+            .arg(&nested_dir)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("restore done"));
+    }
+
+    let existing_file = temp_dir.path().join("existing");
+    std::fs::write(&existing_file, "existing")?;
+    {
+        // `restore` refuses to restore a directory into a file
+        rustic_runner(&temp_dir)?
+            .args(["restore", "latest"])
+            .arg(&existing_file)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("is not a directory"));
+    }
+
+    let snapshot_file = format!("latest:{}", backup_files.join("lib.rs").display());
+    {
+        // `dump --file` doesn't overwrite existing files without --force
+        rustic_runner(&temp_dir)?
+            .args(["dump", &snapshot_file, "--file"])
+            .arg(&existing_file)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("use --force"));
+        assert_eq!(std::fs::read_to_string(&existing_file)?, "existing");
+
+        rustic_runner(&temp_dir)?
+            .args(["dump", &snapshot_file, "--force", "--file"])
+            .arg(&existing_file)
+            .assert()
+            .success();
+        assert_eq!(
+            std::fs::read(&existing_file)?,
+            std::fs::read(backup_files.join("lib.rs"))?
+        );
+    }
+
+    {
+        // `restore --manifest` doesn't overwrite existing files without --force
+        rustic_runner(&temp_dir)?
+            .args(["restore", "--manifest"])
+            .arg(&existing_file)
+            .arg("latest")
+            .arg(temp_dir.path().join("restore"))
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("use --force"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_ls_max_depth_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let backup_dir = std::env::current_dir()?.join("src");
+    rustic_runner(&temp_dir)?
+        .arg("backup")
+        .arg(&backup_dir)
+        .assert()
+        .success();
+
+    let snapshot_path = format!("latest:{}", backup_dir.display());
+    rustic_runner(&temp_dir)?
+        .args(["ls", &snapshot_path, "--max-depth", "1", "--print0"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("lib.rs\0"))
+        .stdout(predicate::str::contains("commands\0"))
+        .stdout(predicate::str::contains("commands/ls.rs").not());
+    rustic_runner(&temp_dir)?
+        .args([
+            "ls",
+            &snapshot_path,
+            "--max-depth",
+            "2",
+            "--glob",
+            "**/ls.rs",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("commands/ls.rs"))
+        .stdout(predicate::str::contains("lib.rs").not());
+
+    Ok(())
+}
+
+#[test]
+fn test_find_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    rustic_runner(&temp_dir)?
+        .args(["backup", "src/"])
+        .assert()
+        .success();
+
+    rustic_runner(&temp_dir)?
+        .args(["find", "--glob", "lib.rs", "--show-blobs"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("found in"))
+        .stdout(predicate::str::contains("lib.rs"))
+        .stdout(predicate::str::contains("    blob "));
+    rustic_runner(&temp_dir)?
+        .args(["find", "--glob", "lib.rs", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""matches": ["#))
+        .stdout(predicate::str::contains("blobs").not());
+    // no snapshot is within the time bounds
+    rustic_runner(&temp_dir)?
+        .args(["find", "--glob", "lib.rs", "--newest", "2000-01-01"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("found in").not());
+
+    Ok(())
+}
+
+#[test]
+fn test_dump_archive_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let backup_files = std::env::current_dir()?.join("src/");
+    rustic_runner(&temp_dir)?
+        .arg("backup")
+        .arg(&backup_files)
+        .assert()
+        .success();
+
+    let snapshot_path = format!("latest:{}", backup_files.display());
+    rustic_runner(&temp_dir)?
+        .args(["dump", &snapshot_path])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("use --archive"));
+
+    for format in ["tar", "tar.gz", "zip"] {
+        let archive = temp_dir.path().join(format!("src.{format}"));
+        let extract_dir = temp_dir.path().join(format!("extract-{format}"));
+        rustic_runner(&temp_dir)?
+            .args(["dump", &snapshot_path, "--archive", format, "--file"])
+            .arg(&archive)
+            .assert()
+            .success();
+
+        let file = std::fs::File::open(&archive)?;
+        match format {
+            "tar" => tar::Archive::new(file).unpack(&extract_dir)?,
+            "tar.gz" => {
+                tar::Archive::new(flate2::read::GzDecoder::new(file)).unpack(&extract_dir)?
+            }
+            _ => zip::ZipArchive::new(file)?.extract(&extract_dir)?,
+        }
+        assert!(Comparison::default()
+            .compare(&backup_files, &extract_dir)?
+            .is_empty());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_init_existing_repository_fails() -> TestResult<()> {
+    let temp_dir = setup()?;
+
+    rustic_runner(&temp_dir)?
+        .arg("init")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already exists"));
+
+    Ok(())
+}
+
+#[test]
+fn test_key_management_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+
+    rustic_runner(&temp_dir)?
+        .args(["key", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 key(s)"));
+    rustic_runner(&temp_dir)?
+        .args(["key", "add", "--new-password", "other"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("successfully added."));
+    rustic_runner(&temp_dir)?
+        .args(["key", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2 key(s)"));
+
+    // the key in use is marked
+    rustic_runner(&temp_dir)?
+        .args(["key", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("*"));
+
+    // change the password of the key in use; the other key still works
+    rustic_runner(&temp_dir)?
+        .args(["key", "passwd", "--new-password", "changed"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("successfully replaced"));
+    rustic_runner(&temp_dir)?
+        .args(["key", "list"])
+        .assert()
+        .failure();
+    Command::new(env!("CARGO_BIN_EXE_rustic"))
+        .arg("-r")
+        .arg(temp_dir.path().join("repo"))
+        .args(["--password", "changed", "--no-progress", "key", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2 key(s)"));
+
+    Ok(())
+}
+
+#[test]
+fn test_key_passwd_with_hot_repository_passes() -> TestResult<()> {
+    let temp_dir = tempdir()?;
+    let hot = temp_dir.path().join("repo-hot");
+    let hot_runner = || -> TestResult<Command> {
+        let mut runner = rustic_runner(&temp_dir)?;
+        _ = runner.arg("--repo-hot").arg(&hot);
+        Ok(runner)
+    };
+    hot_runner()?.arg("init").assert().success();
+    hot_runner()?
+        .args(["key", "passwd", "--new-password", "changed"])
+        .assert()
+        .success();
+
+    // the old key is removed from both repositories
+    for keys in [temp_dir.path().join("repo").join("keys"), hot.join("keys")] {
+        assert_eq!(std::fs::read_dir(keys)?.count(), 1);
+    }
+    hot_runner()?.args(["key", "list"]).assert().failure();
+
+    Ok(())
+}
+
+#[test]
+fn test_config_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+
+    rustic_runner(&temp_dir)?
+        .arg("config")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("version"));
+    rustic_runner(&temp_dir)?
+        .args(["--dry-run", "config", "--set-compression", "10"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("would save the following config:"));
+    rustic_runner(&temp_dir)?
+        .args([
+            "config",
+            "--set-compression",
+            "10",
+            "--set-pack-size-data",
+            "64MiB",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("compression"))
+        .stdout(predicate::str::contains("datapack_size"));
+    // the config has been saved, so there is nothing left to change
+    rustic_runner(&temp_dir)?
+        .args(["config", "--set-compression", "10"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Old").not());
+
+    Ok(())
+}
+
+#[test]
+fn test_backup_compression_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+
+    rustic_runner(&temp_dir)?
+        .args(["backup", "--compression", "23", "src/"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid compression level"));
+    rustic_runner(&temp_dir)?
+        .args(["backup", "--compression", "best", "src/"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("successfully saved."));
+    // the level has been saved in the repository config
+    rustic_runner(&temp_dir)?
+        .args(["config", "--set-compression", "19"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Old").not());
+
+    Ok(())
+}
+
+#[test]
+fn test_init_pack_size_passes() -> TestResult<()> {
+    let temp_dir = tempdir()?;
+    rustic_runner(&temp_dir)?
+        .args(["init", "--pack-size", "512KiB"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("out of range"));
+    rustic_runner(&temp_dir)?
+        .args(["init", "--pack-size", "128MiB"])
+        .assert()
+        .success();
+    rustic_runner(&temp_dir)?
+        .arg("config")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("134217728"));
+
+    Ok(())
+}
+
+#[test]
+fn test_merge_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    for source in ["src/", "tests/"] {
+        rustic_runner(&temp_dir)?
+            .args(["backup", source])
+            .assert()
+            .success();
+    }
+
+    rustic_runner(&temp_dir)?
+        .args(["--dry-run", "merge"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("merged snapshot would contain:"))
+        .stdout(predicate::str::contains("conflict:").not());
+
+    rustic_runner(&temp_dir)?
+        .args(["merge", "--conflict", "error", "--json", "--delete-inputs"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("merged from snapshots"));
+
+    let output = rustic_runner(&temp_dir)?
+        .args(["snapshots", "--json"])
+        .output()?;
+    let snapshots: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    // the inputs are deleted, only the merged snapshot is left
+    assert_eq!(snapshots.as_array().map(Vec::len), Some(1));
+    assert_eq!(snapshots[0][1].as_array().map(Vec::len), Some(1));
+
+    Ok(())
+}
+
+#[test]
+fn test_bench_passes() -> TestResult<()> {
+    let temp_dir = tempdir()?;
+    for operation in ["backup", "restore", "prune"] {
+        let output = rustic_runner(&temp_dir)?
+            .args([
+                "bench", operation, "--size", "1MiB", "--files", "4", "--json",
+            ])
+            .output()?;
+        assert!(output.status.success());
+        let result: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+        assert_eq!(result["operation"], operation);
+        assert!(result["bytes_read"].as_u64().is_some_and(|bytes| bytes > 0));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_password_command_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+
+    // the trailing newline of the command output is not part of the password
+    Command::new(env!("CARGO_BIN_EXE_rustic"))
+        .arg("-r")
+        .arg(temp_dir.path().join("repo"))
+        .args(["--no-progress", "snapshots"])
+        .env("RUSTIC_PASSWORD_COMMAND", "echo test")
+        .assert()
+        .success();
+    Command::new(env!("CARGO_BIN_EXE_rustic"))
+        .arg("-r")
+        .arg(temp_dir.path().join("repo"))
+        .args([
+            "--no-progress",
+            "--password-command",
+            "echo wrong",
+            "snapshots",
+        ])
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_file_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let file = temp_dir.path().join("repository");
+    std::fs::write(
+        &file,
+        format!("{}\n", temp_dir.path().join("repo").display()),
+    )?;
+
+    Command::new(env!("CARGO_BIN_EXE_rustic"))
+        .arg("--repository-file")
+        .arg(&file)
+        .args(["--password", "test", "--no-progress", "snapshots"])
+        .assert()
+        .success();
+    Command::new(env!("CARGO_BIN_EXE_rustic"))
+        .arg("--repository-file")
+        .arg(temp_dir.path().join("missing"))
+        .args(["--password", "test", "--no-progress", "snapshots"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error reading repository file"));
+
+    Ok(())
+}
+
+#[test]
+fn test_acl_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    rustic_runner(&temp_dir)?
+        .args(["backup", "src/"])
+        .assert()
+        .success();
+
+    rustic_runner(&temp_dir)?
+        .args(["--dry-run", "acl", "set", "--allow", "alice"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[] -> [alice]"))
+        .stderr(predicate::str::contains("advisory only"));
+    rustic_runner(&temp_dir)?
+        .args(["acl", "get"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("(no ACL)"));
+
+    rustic_runner(&temp_dir)?
+        .args(["acl", "set", "--allow", "alice", "--allow", "@admins"])
+        .assert()
+        .success();
+    rustic_runner(&temp_dir)?
+        .args(["acl", "get"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("alice"))
+        .stdout(predicate::str::contains("@admins"));
+
+    rustic_runner(&temp_dir)?
+        .args(["acl", "clear"])
+        .assert()
+        .success();
+    rustic_runner(&temp_dir)?
+        .args(["acl", "get"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("(no ACL)"));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(not(feature = "mount"))]
+fn test_mount_without_feature_fails() -> TestResult<()> {
+    let temp_dir = setup()?;
+
+    rustic_runner(&temp_dir)?
+        .args(["mount", "mnt"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("without the `mount` feature"));
+
+    Ok(())
+}
+
+#[test]
+fn test_repoinfo_blobs_per_snapshot_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    rustic_runner(&temp_dir)?
+        .args(["backup", "src/"])
+        .assert()
+        .success();
+
+    rustic_runner(&temp_dir)?
+        .args(["repoinfo"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Compression Ratio"));
+
+    rustic_runner(&temp_dir)?
+        .args(["repoinfo", "--blobs-per-snapshot", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"exclusive_blobs\""))
+        .stdout(predicate::str::contains("\"compression_ratio\""));
+
+    Ok(())
+}
+
+#[test]
+fn test_snapshots_verify_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    rustic_runner(&temp_dir)?
+        .args(["backup", "src/"])
+        .assert()
+        .success();
+
+    rustic_runner(&temp_dir)?
+        .args(["snapshots", "verify"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(": ok"));
+    rustic_runner(&temp_dir)?
+        .args(["snapshots", "verify", "--quick"])
+        .assert()
+        .success();
+
+    // without the pack files, the index is still complete but no data can be read
+    std::fs::remove_dir_all(temp_dir.path().join("repo").join("data"))?;
+    rustic_runner(&temp_dir)?
+        .args(["--no-cache", "snapshots", "verify"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("failed verification"));
+
+    Ok(())
+}
+
+#[test]
+fn test_snapshots_calculate_sizes_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    for _ in 0..2 {
+        rustic_runner(&temp_dir)?
+            .args(["backup", "src/"])
+            .assert()
+            .success();
+    }
+
+    // both snapshots have the same tree, so nothing is exclusive
+    rustic_runner(&temp_dir)?
+        .args(["snapshots", "--calculate-sizes", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"exclusive_size\": 0"));
+    rustic_runner(&temp_dir)?
+        .args(["snapshots", "--calculate-sizes", "--all"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Exclusive"));
+
+    Ok(())
+}
+
+#[test]
+fn test_repair_index_rebuilds_lost_index_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    rustic_runner(&temp_dir)?
+        .args(["backup", "src/"])
+        .assert()
+        .success();
+
+    let index_dir = temp_dir.path().join("repo").join("index");
+    for entry in std::fs::read_dir(&index_dir)? {
+        std::fs::remove_file(entry?.path())?;
+    }
+
+    rustic_runner(&temp_dir)?
+        .args(["--no-cache", "repair", "index", "--yes-really"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("all 1 snapshots are complete."));
+    rustic_runner(&temp_dir)?
+        .args(["--no-cache", "check"])
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+#[test]
+fn test_progress_json_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rustic"))
+        .arg("-r")
+        .arg(temp_dir.path().join("repo"))
+        .args(["--password", "test", "--progress-json", "backup", "src/"])
+        .output()?;
+    assert!(output.status.success());
+
+    let stderr = String::from_utf8(output.stderr)?;
+    let events: Vec<_> = stderr
+        .lines()
+        .filter(|line| line.starts_with('{'))
+        .collect();
+    assert!(events
+        .iter()
+        .all(|line| line.starts_with("{\"type\":") && line.ends_with('}')));
+    assert!(events
+        .iter()
+        .any(|line| line.starts_with("{\"type\":\"start\"")));
+    assert!(events
+        .iter()
+        .any(|line| line.starts_with("{\"type\":\"finish\"") && line.contains("\"bytes_done\":")));
+
+    Ok(())
+}
+
+#[test]
+fn test_repair_snapshots_lists_damage_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    rustic_runner(&temp_dir)?
+        .args(["backup", "src/"])
+        .assert()
+        .success();
+
+    rustic_runner(&temp_dir)?
+        .args(["repair", "snapshots", "--delete", "--yes"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("0 of 1 snapshots are damaged."));
+
+    // without the pack files, no tree can be read anymore
+    std::fs::remove_dir_all(temp_dir.path().join("repo").join("data"))?;
+    rustic_runner(&temp_dir)?
+        .args(["--no-cache", "--dry-run", "repair", "snapshots"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 of 1 snapshots are damaged."));
+
+    Ok(())
+}
+
+#[test]
+fn test_progress_plain_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let repo = temp_dir.path().join("repo");
+
+    // stderr is no terminal, so there is no progress by default
+    Command::new(env!("CARGO_BIN_EXE_rustic"))
+        .arg("-r")
+        .arg(&repo)
+        .args(["--password", "test", "backup", "src/"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("\u{1b}[").not());
+
+    Command::new(env!("CARGO_BIN_EXE_rustic"))
+        .arg("-r")
+        .arg(&repo)
+        .args(["--password", "test", "--progress", "plain", "check"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("done\n"))
+        .stderr(predicate::str::contains("\u{1b}[").not());
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "sqlite")]
+fn test_catalog_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let catalog = temp_dir.path().join("catalog.db");
+    rustic_runner(&temp_dir)?
+        .args(["backup", "src/"])
+        .assert()
+        .success();
+
+    rustic_runner(&temp_dir)?
+        .arg("catalog")
+        .arg("build")
+        .arg(&catalog)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("added 1 snapshot(s)"));
+    rustic_runner(&temp_dir)?
+        .arg("catalog")
+        .arg("build")
+        .arg(&catalog)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("catalog update"));
+
+    rustic_runner(&temp_dir)?
+        .args(["backup", "src/"])
+        .assert()
+        .success();
+    rustic_runner(&temp_dir)?
+        .args(["catalog", "update", "--catalog"])
+        .arg(&catalog)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("added 1 snapshot(s)"));
+
+    rustic_runner(&temp_dir)?
+        .args(["catalog", "search", "--catalog"])
+        .arg(&catalog)
+        .arg("rustic.rs")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2 match(es)"));
+
+    Ok(())
+}
+
+#[test]
+fn test_log_format_json_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let log = temp_dir.path().join("rustic.log");
+
+    rustic_runner(&temp_dir)?
+        .arg("--log-format")
+        .arg("json")
+        .arg("--log-file")
+        .arg(&log)
+        .args(["backup", "src/"])
+        .assert()
+        .success();
+
+    let content = std::fs::read_to_string(&log)?;
+    assert!(content.lines().count() > 0);
+    assert!(content
+        .lines()
+        .all(|line| line.starts_with("{\"timestamp\":") && line.ends_with('}')));
+    assert!(content.contains("\"level\":\"info\""));
+
+    Ok(())
+}
+
+#[test]
+fn test_complete_snapshot_ids_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    rustic_runner(&temp_dir)?
+        .args(["backup", "src/"])
+        .assert()
+        .success();
+
+    let output = rustic_runner(&temp_dir)?
+        .args(["__complete", "snapshot-ids"])
+        .output()?;
+    assert!(output.status.success());
+    let ids = String::from_utf8(output.stdout)?;
+    let ids: Vec<_> = ids.lines().collect();
+    assert_eq!(ids.len(), 2);
+    assert_eq!(ids[0], "latest");
+
+    rustic_runner(&temp_dir)?
+        .args(["__complete", "snapshot-ids", &ids[1][..3]])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", ids[1]));
+
+    Ok(())
+}
+
+#[test]
+fn test_complete_tags_and_profiles_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    rustic_runner(&temp_dir)?
+        .args(["backup", "src/", "--tag", "daily,offsite"])
+        .assert()
+        .success();
+
+    let output = rustic_runner(&temp_dir)?
+        .args(["__complete", "snapshot-ids", "--describe"])
+        .output()?;
+    assert!(output.status.success());
+    let lines = String::from_utf8(output.stdout)?;
+    assert!(lines.lines().all(|line| line.contains('\t')));
+
+    rustic_runner(&temp_dir)?
+        .args(["__complete", "tags", "daily,o"])
+        .assert()
+        .success()
+        .stdout("daily,offsite\n");
+
+    let config_dir = tempdir()?;
+    std::fs::write(config_dir.path().join("my-profile.toml"), "")?;
+    rustic_runner(&temp_dir)?
+        .args(["__complete", "profiles", "my-"])
+        .arg("--config-dir")
+        .arg(config_dir.path())
+        .assert()
+        .success()
+        .stdout("my-profile\n");
+
+    Ok(())
+}
+
+#[test]
+#[cfg(not(windows))]
+fn test_unavailable_system_log_falls_back_to_stderr_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+
+    rustic_runner(&temp_dir)?
+        .args(["--log-eventlog", "backup", "src/"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "--log-eventlog is only supported on Windows, logging to stderr instead",
+        ));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(not(feature = "keyring"))]
+fn test_password_keyring_unsupported_fails() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let repo = temp_dir.path().join("repo");
+
+    // the keyring is only used if no other password is given
+    Command::new(env!("CARGO_BIN_EXE_rustic"))
+        .arg("-r")
+        .arg(&repo)
+        .args(["--no-progress", "--password-keyring", "rustic/test"])
+        .arg("snapshots")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "rustic was built without the keyring feature",
+        ));
+
+    rustic_runner(&temp_dir)?
+        .args(["--password-keyring", "rustic/test", "snapshots"])
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+#[test]
+fn test_warm_up_dry_run_lists_packs_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let restore_dir = temp_dir.path().join("restore");
+    rustic_runner(&temp_dir)?
+        .args(["backup", "src/"])
+        .assert()
+        .success();
+
+    // the warm-up command fails, so it must not be run in dry-run mode
+    rustic_runner(&temp_dir)?
+        .args(["--dry-run", "--warm-up-command", "false %id"])
+        .args(["restore", "latest"])
+        .arg(&restore_dir)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("packs queued for warm-up."))
+        .stdout(predicate::str::contains("would warm up pack: "));
+
+    Ok(())
+}
+
+#[test]
+fn test_repair_hotcopy_passes() -> TestResult<()> {
+    let temp_dir = tempdir()?;
+    let hot = temp_dir.path().join("repo-hot");
+    let hot_runner = || -> TestResult<Command> {
+        let mut runner = rustic_runner(&temp_dir)?;
+        _ = runner.arg("--repo-hot").arg(&hot);
+        Ok(runner)
+    };
+    hot_runner()?.arg("init").assert().success();
+    hot_runner()?.args(["backup", "src/"]).assert().success();
+
+    // remove the snapshot from the hot repository
+    let snapshots = hot.join("snapshots");
+    for entry in std::fs::read_dir(&snapshots)? {
+        std::fs::remove_file(entry?.path())?;
+    }
+
+    hot_runner()?
+        .args(["snapshots", "--all"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("rustic repair hotcopy"));
+
+    hot_runner()?.args(["repair", "hotcopy"]).assert().success();
+    assert_eq!(std::fs::read_dir(&snapshots)?.count(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_no_default_config_passes() -> TestResult<()> {
+    let temp_dir = tempdir()?;
+    std::fs::write(
+        temp_dir.path().join("rustic.toml"),
+        "[global]\nlog-level = \"debug\"\n",
+    )?;
+    let show_config = |args: &[&str]| -> TestResult<String> {
+        let output = Command::new(env!("CARGO_BIN_EXE_rustic"))
+            .current_dir(temp_dir.path())
+            .args(args)
+            .arg("show-config")
+            .output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    assert!(show_config(&[])?.contains("log-level = \"debug\""));
+    assert!(!show_config(&["--no-default-config"])?.contains("log-level = \"debug\""));
+
+    Ok(())
+}
+
+#[test]
+fn test_cache_stats_and_clear_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let cache = temp_dir.path().join("cache");
+    let cache_runner = || -> TestResult<Command> {
+        let mut runner = rustic_runner(&temp_dir)?;
+        _ = runner.arg("--cache-dir").arg(&cache);
+        Ok(runner)
+    };
+    cache_runner()?.args(["backup", "src/"]).assert().success();
+
+    cache_runner()?
+        .args(["cache", "location"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(cache.to_string_lossy()));
+    cache_runner()?
+        .args(["cache", "stats"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("total: 0 files").not());
+
+    // files not created by rustic are kept
+    let other = cache.join("notes.txt");
+    std::fs::write(&other, "keep")?;
+    cache_runner()?.args(["cache", "clear"]).assert().success();
+    cache_runner()?
+        .args(["cache", "stats"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("total: 0 files"));
+    assert!(other.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_init_non_empty_location_fails() -> TestResult<()> {
+    let temp_dir = tempdir()?;
+    let keys = temp_dir.path().join("repo").join("keys");
+    std::fs::create_dir_all(&keys)?;
+    std::fs::write(
+        keys.join("0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef"),
+        "{}",
+    )?;
+
+    rustic_runner(&temp_dir)?
+        .arg("init")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is not empty"));
+
+    Ok(())
+}
+
+#[test]
+fn test_init_non_empty_hot_location_fails() -> TestResult<()> {
+    let temp_dir = tempdir()?;
+    let hot = temp_dir.path().join("repo-hot");
+    let keys = hot.join("keys");
+    std::fs::create_dir_all(&keys)?;
+    std::fs::write(
+        keys.join("0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef"),
+        "{}",
+    )?;
+
+    rustic_runner(&temp_dir)?
+        .arg("--repo-hot")
+        .arg(&hot)
+        .arg("init")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("hot repository location"))
+        .stderr(predicate::str::contains("is not empty"));
+
+    Ok(())
+}
+
+#[test]
+fn test_init_from_repo_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let profile = temp_dir.path().join("profile.toml");
+    std::fs::write(
+        &profile,
+        format!(
+            "[repositories.source]\nrepository = {:?}\npassword = \"test\"\n",
+            temp_dir.path().join("repo")
+        ),
+    )?;
+    let target = temp_dir.path().join("target");
+    let runner = |repo: &std::path::Path| {
+        let mut runner = Command::new(env!("CARGO_BIN_EXE_rustic"));
+        _ = runner
+            .arg("-r")
+            .arg(repo)
+            .args(["--password", "test", "--no-progress"]);
+        runner
+    };
+    let cat_config = |repo: &std::path::Path| -> TestResult<serde_json::Value> {
+        let output = runner(repo).args(["cat", "config"]).output()?;
+        assert!(output.status.success());
+        Ok(serde_json::from_slice(&output.stdout)?)
+    };
+
+    runner(&target)
+        .arg("-P")
+        .arg(&profile)
+        .args(["init", "--from-repo", "source"])
+        .assert()
+        .success();
+
+    let source = cat_config(&temp_dir.path().join("repo"))?;
+    let target = cat_config(&target)?;
+    assert_eq!(source["chunker_polynomial"], target["chunker_polynomial"]);
+    assert_ne!(source["id"], target["id"]);
+
+    Ok(())
+}
@@ -0,0 +1,195 @@
+//! Rustic Integration Test for the WebDAV server
+//!
+//! Starts the `webdav` command as a subprocess and sends raw HTTP requests to it.
+//!
+//! You can run them with 'nextest':
+//! `cargo nextest run -E 'test(webdav)'`.
+
+#![cfg(feature = "webdav")]
+
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    process::{Child, Stdio},
+    thread::sleep,
+    time::Duration,
+};
+
+use predicates::prelude::predicate;
+use tempfile::{tempdir, TempDir};
+
+use rustic_testing::TestResult;
+
+/// Kills the server when dropped
+struct Server(Child);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        _ = self.0.kill();
+        _ = self.0.wait();
+    }
+}
+
+fn rustic_runner(temp_dir: &TempDir) -> TestResult<std::process::Command> {
+    let mut runner = std::process::Command::new(env!("CARGO_BIN_EXE_rustic"));
+    _ = runner
+        .arg("-r")
+        .arg(temp_dir.path().join("repo"))
+        .arg("--password")
+        .arg("test")
+        .arg("--no-progress");
+    Ok(runner)
+}
+
+fn setup() -> TestResult<TempDir> {
+    let temp_dir = tempdir()?;
+    assert_cmd::Command::from_std(rustic_runner(&temp_dir)?)
+        .arg("init")
+        .assert()
+        .success();
+    assert_cmd::Command::from_std(rustic_runner(&temp_dir)?)
+        .arg("backup")
+        .arg(std::env::current_dir()?.join("src"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("successfully saved."));
+    Ok(temp_dir)
+}
+
+/// Send a `PROPFIND` request for the root and return the status code of the response
+fn propfind(port: u16, authorization: Option<&str>) -> TestResult<u16> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port))?;
+    let mut request =
+        String::from("PROPFIND / HTTP/1.1\r\nHost: localhost\r\nDepth: 0\r\nConnection: close\r\n");
+    if let Some(authorization) = authorization {
+        request.push_str(&format!("Authorization: {authorization}\r\n"));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes())?;
+
+    let mut response = String::new();
+    _ = stream.read_to_string(&mut response)?;
+    let status = response
+        .split_whitespace()
+        .nth(1)
+        .ok_or("invalid response")?
+        .parse()?;
+    Ok(status)
+}
+
+/// Wait until the server accepts connections
+fn wait_for_server(port: u16) {
+    let mut retries = 0;
+    while TcpStream::connect(("127.0.0.1", port)).is_err() {
+        retries += 1;
+        assert!(retries < 100, "webdav server didn't start");
+        sleep(Duration::from_millis(100));
+    }
+}
+
+#[test]
+fn test_webdav_basic_auth_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+
+    let _server = Server(
+        rustic_runner(&temp_dir)?
+            .arg("webdav")
+            .arg("--address")
+            .arg(format!("127.0.0.1:{port}"))
+            .args(["--auth-user", "user", "--auth-password", "secret"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?,
+    );
+
+    wait_for_server(port);
+
+    // no or wrong credentials; "user:wrong" and "user:secret" base64-encoded
+    assert_eq!(propfind(port, None)?, 401);
+    assert_eq!(propfind(port, Some("Basic dXNlcjp3cm9uZw=="))?, 401);
+    assert_eq!(propfind(port, Some("Basic dXNlcjpzZWNyZXQ="))?, 207);
+
+    Ok(())
+}
+
+#[test]
+fn test_webdav_non_loopback_without_auth_fails() -> TestResult<()> {
+    let temp_dir = setup()?;
+
+    assert_cmd::Command::from_std(rustic_runner(&temp_dir)?)
+        .args(["webdav", "--address", "0.0.0.0:0"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--no-auth-i-know-what-i-do"));
+
+    Ok(())
+}
+
+#[test]
+fn test_webdav_tls_rejects_plaintext_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+
+    let _server = Server(
+        rustic_runner(&temp_dir)?
+            .arg("webdav")
+            .arg("--address")
+            .arg(format!("127.0.0.1:{port}"))
+            .arg("--tls-self-signed")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?,
+    );
+    wait_for_server(port);
+
+    // a plain HTTP request doesn't get a HTTP response
+    assert!(propfind(port, None).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_webdav_tls_cert_without_key_fails() -> TestResult<()> {
+    // the options are checked before the repository is opened, so no repository is needed
+    let temp_dir = tempdir()?;
+
+    assert_cmd::Command::from_std(rustic_runner(&temp_dir)?)
+        .args(["webdav", "--tls-cert", "cert.pem"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("both tls-cert and tls-key"));
+
+    Ok(())
+}
+
+#[test]
+fn test_webdav_refresh_interval_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+
+    let _server = Server(
+        rustic_runner(&temp_dir)?
+            .arg("webdav")
+            .arg("--address")
+            .arg(format!("127.0.0.1:{port}"))
+            .args(["--path-template", "hosts/{hostname}/{time}"])
+            .args(["--refresh-interval", "500ms"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?,
+    );
+    wait_for_server(port);
+    assert_eq!(propfind(port, None)?, 207);
+
+    // a new snapshot is picked up while serving
+    assert_cmd::Command::from_std(rustic_runner(&temp_dir)?)
+        .arg("backup")
+        .arg(std::env::current_dir()?.join("src"))
+        .assert()
+        .success();
+    sleep(Duration::from_secs(2));
+    assert_eq!(propfind(port, None)?, 207);
+
+    Ok(())
+}